@@ -21,13 +21,26 @@ def create_filename_base_2(prefix, lambda_val, j_val, mesh_x, mesh_y, div, thres
     )
     return filename
 
+# sweepコマンドが出力するマニフェスト（各点のパラメーターと出力ファイルの一覧）
+manifest = "./compare_6_spinmodel/sweep_manifest.dat"
+
+def load_manifest(path):
+    df = pd.read_csv(path, comment='#', header=None, names=["index", "lambda", "jj", "filling", "status", "path"])
+    df = df[df["status"] != "failed"]
+    # マニフェストのパスは実行ディレクトリ(リポジトリのルート)からの相対パス
+    file_list = [os.path.relpath(os.path.join("..", p)) for p in df["path"]]
+    return file_list, [float(j) for j in df["jj"]]
+
 file_list = []
 j_values_for_loop = []
-for i in range(50):
-    j_val = float("{:.2f}".format(0.01 * i))
-    lambda_val = "0p30" # 元のlam変数を使うように修正
-    j_values_for_loop.append(j_val)
-    file_list.append(create_filename_base_2("stable", lambda_val, j_val, mesh, mesh, div, threshold_berry, main_mesh, "compare_6_spinmodel"))
+if os.path.exists(manifest):
+    file_list, j_values_for_loop = load_manifest(manifest)
+else:
+    for i in range(50):
+        j_val = float("{:.2f}".format(0.01 * i))
+        lambda_val = "0p30" # 元のlam変数を使うように修正
+        j_values_for_loop.append(j_val)
+        file_list.append(create_filename_base_2("stable", lambda_val, j_val, mesh, mesh, div, threshold_berry, main_mesh, "compare_6_spinmodel"))
 
 # --- データ収集部分は変更なし ---
 n_points = []
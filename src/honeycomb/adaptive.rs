@@ -0,0 +1,222 @@
+use crate::honeycomb::{
+    height_map::AllHeightMaps,
+    honeycomb_grids::Grids,
+    setting::CalcSetting,
+    tanzaku::Tanzakus,
+    util::GridInfo,
+};
+
+use crate::system::model::System;
+
+use rayon::prelude::*;
+use std::io::Write;
+
+//----------------------------------------------------------------
+// 適応的なメッシュ細分化の設定
+//----------------------------------------------------------------
+#[derive(Debug, Clone, Copy)]
+pub struct AdaptiveSetting{
+    pub max_depth : usize,  // 細分化の最大の深さ
+    pub tol_berry : f64,    // 領域内の ∫|Ω| d²k がこれを超えたら細分化する
+    pub tol_energy : f64,   // 隣り合うメッシュ点のエネルギー差がこれを超えたら細分化する
+}
+
+impl AdaptiveSetting{
+    pub fn standard() -> Self{
+        AdaptiveSetting {
+            max_depth : 3,
+            tol_berry : 0.05,
+            tol_energy : 0.05,
+        }
+    }
+}
+
+//----------------------------------------------------------------
+// 細分化された領域（タイル）
+//----------------------------------------------------------------
+#[derive(Debug, Clone, Copy)]
+pub struct Tile{
+    pub grid_info : GridInfo,
+    pub depth : usize,
+    pub berry_indicator : f64,
+    pub energy_indicator : f64,
+    pub error : f64,        // メッシュを半分に間引いた場合との Berry 曲率の積分の差
+}
+
+pub struct AdaptiveTanzakus{
+    pub tanzakus : Tanzakus,
+    pub tiles : Vec<Tile>,      // 最終的に計算に用いたタイル
+    pub error_estimate : f64,   // 各タイルの誤差の和
+}
+
+impl AdaptiveTanzakus{
+    /// タイルの配置を.datファイルに出力する（細分化の様子の可視化用）
+    pub fn write_tiles_to_dat(&self, path : &str) -> std::io::Result<()> {
+        let mut file = std::fs::File::create(path)?;
+
+        writeln!(file, "# ini_x,ini_y,delta_x,delta_y,depth,berry_indicator,energy_indicator,error")?;
+        for tile in &self.tiles {
+            writeln!(
+                file,
+                "{},{},{},{},{},{},{},{}",
+                tile.grid_info.ini.x, tile.grid_info.ini.y,
+                tile.grid_info.dxy.x, tile.grid_info.dxy.y,
+                tile.depth, tile.berry_indicator, tile.energy_indicator, tile.error
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Berry 曲率やエネルギーの変化が大きい領域を再帰的に細分化して Tanzakus を計算する
+///
+/// 初期のタイルは `main_mesh` x `main_mesh` に等分したもので、各タイルには
+/// `mesh_kx` x `mesh_ky` のメッシュを張る。細分化の判定を満たしたタイルは
+/// `GridInfo::subdivide` で 2x2 に分割し、同じメッシュ数で計算し直す。
+///
+/// 全てのタイルで共通のエネルギー範囲を用いるので、等高線のエネルギーは
+/// タイルの境界をまたいでも一致する。タイルは重複なく BZ を覆うので、
+/// 各等高線の線分と各 k 点はちょうど一度ずつ数えられる。
+pub fn adaptive_calculate_tanzaku(
+    calc_setting : CalcSetting,
+    system : System,
+    adaptive_setting : AdaptiveSetting,
+) -> AdaptiveTanzakus {
+
+    //エネルギーの範囲を取得するための事前処理
+    let grids = Grids::build(calc_setting, system.clone(), GridInfo::no_divide());
+    let energy_range = grids.energy_range();
+
+    let main_grid = calc_setting.main_mesh;
+    let mut frontier: Vec<(GridInfo, usize)> = (0..(main_grid * main_grid))
+        .map(|ij| (GridInfo::new_ijn(ij % main_grid, ij / main_grid, main_grid, main_grid, Some(energy_range)), 0))
+        .collect();
+
+    let mut tanzakus = Tanzakus::new(calc_setting, system.clone());
+    let mut tiles = Vec::new();
+
+    while !frontier.is_empty() {
+        let evaluated: Vec<(Tile, Option<Tanzakus>)> = frontier
+            .par_iter()
+            .map(|(grid_info, depth)| evaluate_tile(calc_setting, &system, *grid_info, *depth, &adaptive_setting))
+            .collect();
+
+        frontier = Vec::new();
+        for (tile, partial_tanzakus) in evaluated {
+            match partial_tanzakus {
+                Some(partial_tanzakus) => {
+                    tanzakus.merge(&partial_tanzakus);
+                    tiles.push(tile);
+                }
+                None => {
+                    frontier.extend(tile.grid_info.subdivide().into_iter().map(|sub| (sub, tile.depth + 1)));
+                }
+            }
+        }
+    }
+
+    let error_estimate = tiles.iter().map(|tile| tile.error).sum();
+
+    AdaptiveTanzakus { tanzakus, tiles, error_estimate }
+}
+
+//タイルを評価し、細分化が不要であれば Tanzakus を計算して返す
+fn evaluate_tile(
+    calc_setting : CalcSetting,
+    system : &System,
+    grid_info : GridInfo,
+    depth : usize,
+    adaptive_setting : &AdaptiveSetting,
+) -> (Tile, Option<Tanzakus>) {
+    let grids = Grids::build(calc_setting, system.clone(), grid_info);
+
+    let berry_indicator = berry_indicator(&grids);
+    let energy_indicator = energy_indicator(&grids);
+
+    let refine = depth < adaptive_setting.max_depth
+        && (berry_indicator > adaptive_setting.tol_berry || energy_indicator > adaptive_setting.tol_energy);
+
+    let tile = Tile { grid_info, depth, berry_indicator, energy_indicator, error : error_estimate(&grids) };
+
+    if refine {
+        return (tile, None);
+    }
+
+    // 全バンドの等高線データを作成
+    let all_height_maps = AllHeightMaps::build(&grids);
+
+    let mut partial_tanzakus = Tanzakus::new(calc_setting, system.clone());
+    partial_tanzakus.write_energy_n_bc_sum_to_tanzaku(&grids);
+    partial_tanzakus.write_bcd_sum_to_tanzakus(&all_height_maps);
+
+    (tile, Some(partial_tanzakus))
+}
+
+//----------------------------------------------------------------
+// 細分化の判定と誤差評価
+//----------------------------------------------------------------
+
+//タイル内の ∫|Ω| d²k（全スピン・全バンドの和）
+fn berry_indicator(grids : &Grids) -> f64 {
+    let (mesh_kx, mesh_ky) = grids.calc_setting.meshes();
+
+    let mut sum = 0.0;
+    for spin in 0..2 {
+        for grid in grids.index(spin) {
+            for row in grid.0.iter().take(mesh_kx) {
+                sum += row.iter().take(mesh_ky).filter_map(|band_info| band_info.berry).map(f64::abs).sum::<f64>();
+            }
+        }
+    }
+
+    sum * grids.area_fraction()
+}
+
+//隣り合うメッシュ点のエネルギー差の最大値
+fn energy_indicator(grids : &Grids) -> f64 {
+    let (mesh_kx, mesh_ky) = grids.calc_setting.meshes();
+
+    let mut max_diff: f64 = 0.0;
+    for spin in 0..2 {
+        for grid in grids.index(spin) {
+            for i in 0..mesh_kx {
+                for j in 0..mesh_ky {
+                    let here = grid.0[i][j].eigen;
+                    max_diff = max_diff
+                        .max((grid.0[i + 1][j].eigen - here).abs())
+                        .max((grid.0[i][j + 1].eigen - here).abs());
+                }
+            }
+        }
+    }
+
+    max_diff
+}
+
+//各スピン・バンドの Berry 曲率の積分を、全メッシュ点と一つおきの点で計算した差
+fn error_estimate(grids : &Grids) -> f64 {
+    let (mesh_kx, mesh_ky) = grids.calc_setting.meshes();
+    let n_all = (mesh_kx * mesh_ky) as f64;
+    let n_half = (mesh_kx.div_ceil(2) * mesh_ky.div_ceil(2)) as f64;
+
+    let mut error = 0.0;
+    for spin in 0..2 {
+        for grid in grids.index(spin) {
+            let mut flux_all = 0.0;
+            let mut flux_half = 0.0;
+            for (i, row) in grid.0.iter().enumerate().take(mesh_kx) {
+                for (j, band_info) in row.iter().enumerate().take(mesh_ky) {
+                    let berry = band_info.berry.unwrap_or(0.0);
+                    flux_all += berry;
+                    if i % 2 == 0 && j % 2 == 0 {
+                        flux_half += berry * n_all / n_half;
+                    }
+                }
+            }
+            error += (flux_all - flux_half).abs();
+        }
+    }
+
+    error * grids.area_fraction()
+}
@@ -0,0 +1,129 @@
+use crate::consts::{gamma, k, kp, kpp, m};
+use crate::system::{
+    diag::{diag, SEudEnum},
+    model::System,
+    supercell::{Supercell, SupercellSystem},
+};
+
+use nalgebra::Vector2;
+use std::io::Write;
+
+//----------------------------------------------------------------
+// 高対称点を結ぶ経路
+//----------------------------------------------------------------
+
+/// 高対称点の名前からk点を求める
+/// "Gamma"(または"G"), "K", "K'"(または"Kp"), "K''"(または"Kpp"), "M" を受け付ける
+pub fn high_symmetry_point(label : &str, size : usize) -> Option<Vector2<f64>> {
+    let kk = match label {
+        "Gamma" | "G" => gamma(size),
+        "K" => k(size),
+        "K'" | "Kp" => kp(size),
+        "K''" | "Kpp" => kpp(size),
+        "M" => m(size),
+        _ => return None,
+    };
+    Some(kk)
+}
+
+#[derive(Debug, Clone)]
+pub struct BandPath{
+    pub labels : Vec<String>,
+    pub points : Vec<Vector2<f64>>,     // 経路上のk点
+    pub distance : Vec<f64>,            // 経路の始点からの長さ
+    pub label_distance : Vec<f64>,      // 各高対称点での経路の長さ
+}
+
+impl BandPath{
+    /// 高対称点を順に直線で結んだ経路を作る（各区間を `points_per_segment` 等分する）
+    pub fn from_labels(labels : &[String], size : usize, points_per_segment : usize) -> Option<Self> {
+        let corners = labels.iter()
+            .map(|label| high_symmetry_point(label, size))
+            .collect::<Option<Vec<_>>>()?;
+
+        Some(BandPath::from_corners(labels, &corners, points_per_segment))
+    }
+    /// 与えたk点（labels はその名前）を順に直線で結んだ経路を作る
+    pub fn from_corners(labels : &[String], corners : &[Vector2<f64>], points_per_segment : usize) -> Self {
+        let mut points = vec![corners[0]];
+        let mut distance = vec![0.0];
+        let mut label_distance = vec![0.0];
+
+        for pair in corners.windows(2) {
+            let (start, end) = (pair[0], pair[1]);
+            let offset = *distance.last().unwrap();
+            for step in 1..=points_per_segment {
+                let frac = step as f64 / points_per_segment as f64;
+                points.push(start + (end - start) * frac);
+                distance.push(offset + (end - start).norm() * frac);
+            }
+            label_distance.push(*distance.last().unwrap());
+        }
+
+        BandPath { labels : labels.to_vec(), points, distance, label_distance }
+    }
+    /// 超格子の高対称点（Supercell::high_symmetry_point）の名前を順に結んだ経路（from_labels の超格子版）
+    pub fn from_supercell_labels(labels : &[String], supercell : &Supercell, points_per_segment : usize) -> Option<Self> {
+        let corners = labels.iter()
+            .map(|label| supercell.high_symmetry_point(label))
+            .collect::<Option<Vec<_>>>()?;
+
+        Some(BandPath::from_corners(labels, &corners, points_per_segment))
+    }
+}
+
+//----------------------------------------------------------------
+// バンド構造
+//----------------------------------------------------------------
+
+#[derive(Debug, Clone)]
+pub struct BandPoint{
+    pub distance : f64,
+    pub kk : Vector2<f64>,
+    pub energies : [Vec<f64>; 2],   // [spin][band]
+}
+
+/// 経路上の各k点でスピンごとの固有値を計算する
+pub fn band_structure(system : &System, path : &BandPath) -> Vec<BandPoint> {
+    path.points.iter().zip(path.distance.iter())
+        .map(|(&kk, &distance)| {
+            let energies = match diag(system, kk, false) {
+                SEudEnum::SEud2(seud) => [seud.u.eigenvalues.as_slice().to_vec(), seud.d.eigenvalues.as_slice().to_vec()],
+                SEudEnum::SEud6(seud) => [seud.u.eigenvalues.as_slice().to_vec(), seud.d.eigenvalues.as_slice().to_vec()],
+            };
+            BandPoint { distance, kk, energies }
+        })
+        .collect()
+}
+
+/// 経路上の各k点で超格子のスピンごとの固有値を計算する
+pub fn supercell_band_structure(system : &SupercellSystem, path : &BandPath) -> Vec<BandPoint> {
+    path.points.iter().zip(path.distance.iter())
+        .map(|(&kk, &distance)| {
+            let seud = system.diag(kk);
+            let energies = [seud.u.eigenvalues.as_slice().to_vec(), seud.d.eigenvalues.as_slice().to_vec()];
+            BandPoint { distance, kk, energies }
+        })
+        .collect()
+}
+
+/// バンド構造を.datファイルに出力する（高対称点の位置はヘッダーに記す）
+pub fn write_bands_to_dat(bands : &[BandPoint], path : &BandPath, file_path : &str) -> std::io::Result<()> {
+    let mut file = std::fs::File::create(file_path)?;
+
+    let ticks: Vec<String> = path.labels.iter().zip(path.label_distance.iter())
+        .map(|(label, distance)| format!("{}={}", label, distance))
+        .collect();
+    writeln!(file, "# high_symmetry_points: {}", ticks.join(","))?;
+    writeln!(file, "# distance,kx,ky,spin,band_index,energy")?;
+
+    for point in bands {
+        for (spin, energies) in point.energies.iter().enumerate() {
+            for (band_index, energy) in energies.iter().enumerate() {
+                writeln!(file, "{},{},{},{},{},{}", point.distance, point.kk.x, point.kk.y, spin, band_index, energy)?;
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -2,7 +2,7 @@ use std::vec;
 
 use crate::{
     honeycomb::setting, system::{
-        diag::SEudEnum, hamiltonian::{hamiltonian_2_dxi,hamiltonian_6_dxi}, model::System
+        diag::SEudEnum, hamiltonian::{hamiltonian_2_jet,hamiltonian_6_jet}, model::System
     }
 };
 
@@ -13,7 +13,7 @@ pub enum Tensor{
     YY,
 }
 
-use nalgebra::{Complex, ComplexField, Vector2, Vector6};
+use nalgebra::{Complex, ComplexField, Const, DMatrix, Dim, DimMin, Matrix, ArrayStorage, SymmetricEigen, Vector2, Vector6};
 /// SEudEnumからspin,bandごとのBerry曲率を効率的に計算する関数
 /// 
 /// この関数は対角化結果（SEudEnum）を不変借用し、Kubo公式に基づいてBerry曲率を計算します。
@@ -32,6 +32,9 @@ use nalgebra::{Complex, ComplexField, Vector2, Vector6};
 /// # 計算式
 /// Berry曲率は以下のKubo公式で計算されます：
 /// Ω_n(k) = -2 * Im[Σ_{m≠n} <u_n|∂H/∂kx|u_m><u_m|∂H/∂ky|u_n> / (ε_n - ε_m)²]
+/// 
+/// 縮退した多重項Sに属するバンドでは和をm∉Sに制限し、多重項全体の
+/// ゲージ不変なトレース Tr Ω_S をバンド数で等分した値を返す（`non_abelian_berry_curvature`参照）。
 pub fn calculate_berry_curvature_from_seud(
     seud_enum: &SEudEnum,
     system: &System,
@@ -45,8 +48,8 @@ pub fn calculate_berry_curvature_from_seud(
     match seud_enum {
         SEudEnum::SEud2(seud) => {
             // ハミルトニアンの微分を一度だけ計算
-            let dhdx_all = hamiltonian_2_dxi(system, kk, 0);
-            let dhdy_all = hamiltonian_2_dxi(system, kk, 1);
+            let jet = hamiltonian_2_jet(system, kk, 0, 1);
+            let (dhdx_all, dhdy_all) = (jet.dxi, jet.dxj);
             
             for spin in 0..2 {
                 let dhdx = dhdx_all.index(spin);
@@ -59,13 +62,17 @@ pub fn calculate_berry_curvature_from_seud(
                 
                 let eigenvalues = &seud.index(spin).eigenvalues;
                 
+                // 縮退した多重項を検出する（多重項内部の寄与は含めない）
+                let multiplets = find_multiplets(eigenvalues.as_slice(), setting.threshold_berry);
+                let labels = multiplet_labels(&multiplets, size);
+
                 for ei in 0..size {
                     let mut berry = 0.0;
                     let u_ei = &eigenvectors[ei];
                     let eps_i = eigenvalues[ei];
                     
                     for ej in 0..size {
-                        if ei != ej {
+                        if labels[ei] != labels[ej] {
                             let u_ej = &eigenvectors[ej];
                             let eps_j = eigenvalues[ej];
                             
@@ -83,12 +90,15 @@ pub fn calculate_berry_curvature_from_seud(
                     
                     berry_results[spin][ei] = berry;
                 }
+
+                // 多重項の各バンドにはゲージ不変なトレースを等分して割り当てる
+                average_over_multiplets(&mut berry_results[spin], &multiplets);
             }
         }
         SEudEnum::SEud6(seud) => {
             // ハミルトニアンの微分を一度だけ計算
-            let dhdx_all = hamiltonian_6_dxi(system, kk, 0);
-            let dhdy_all = hamiltonian_6_dxi(system, kk, 1);
+            let jet = hamiltonian_6_jet(system, kk, 0, 1);
+            let (dhdx_all, dhdy_all) = (jet.dxi, jet.dxj);
             
             for spin in 0..2 {
                 let dhdx = dhdx_all.index(spin);
@@ -101,13 +111,17 @@ pub fn calculate_berry_curvature_from_seud(
                 
                 let eigenvalues = &seud.index(spin).eigenvalues;
                 
+                // 縮退した多重項を検出する（多重項内部の寄与は含めない）
+                let multiplets = find_multiplets(eigenvalues.as_slice(), setting.threshold_berry);
+                let labels = multiplet_labels(&multiplets, size);
+
                 for ei in 0..size {
                     let mut berry = 0.0;
                     let u_ei = &eigenvectors[ei];
                     let eps_i = eigenvalues[ei];
                     
                     for ej in 0..size {
-                        if ei != ej {
+                        if labels[ei] != labels[ej] {
                             let u_ej = &eigenvectors[ej];
                             let eps_j = eigenvalues[ej];
                             
@@ -125,6 +139,9 @@ pub fn calculate_berry_curvature_from_seud(
                     
                     berry_results[spin][ei] = berry;
                 }
+
+                // 多重項の各バンドにはゲージ不変なトレースを等分して割り当てる
+                average_over_multiplets(&mut berry_results[spin], &multiplets);
             }
         }
     }
@@ -147,8 +164,8 @@ pub fn calculate_quantum_metric_from_seud(
     match seud_enum {
         SEudEnum::SEud2(seud) => {
             // ハミルトニアンの微分を一度だけ計算
-            let dhdx_all = hamiltonian_2_dxi(system, kk, 0);
-            let dhdy_all = hamiltonian_2_dxi(system, kk, 1);
+            let jet = hamiltonian_2_jet(system, kk, 0, 1);
+            let (dhdx_all, dhdy_all) = (jet.dxi, jet.dxj);
             
             for spin in 0..2 {
                 let dhdx = dhdx_all.index(spin);
@@ -161,13 +178,17 @@ pub fn calculate_quantum_metric_from_seud(
                 
                 let eigenvalues = &seud.index(spin).eigenvalues;
                 
+                // 縮退した多重項を検出する（多重項内部の寄与は含めない）
+                let multiplets = find_multiplets(eigenvalues.as_slice(), setting.threshold_berry);
+                let labels = multiplet_labels(&multiplets, size);
+
                 for ei in 0..size {
                     let mut berry = 0.0;
                     let u_ei = &eigenvectors[ei];
                     let eps_i = eigenvalues[ei];
                     
                     for ej in 0..size {
-                        if ei != ej {
+                        if labels[ei] != labels[ej] {
                             let u_ej = &eigenvectors[ej];
                             let eps_j = eigenvalues[ej];
                             
@@ -195,12 +216,15 @@ pub fn calculate_quantum_metric_from_seud(
                     
                     berry_results[spin][ei] = berry;
                 }
+
+                // 多重項の各バンドにはゲージ不変なトレースを等分して割り当てる
+                average_over_multiplets(&mut berry_results[spin], &multiplets);
             }
         }
         SEudEnum::SEud6(seud) => {
             // ハミルトニアンの微分を一度だけ計算
-            let dhdx_all = hamiltonian_6_dxi(system, kk, 0);
-            let dhdy_all = hamiltonian_6_dxi(system, kk, 1);
+            let jet = hamiltonian_6_jet(system, kk, 0, 1);
+            let (dhdx_all, dhdy_all) = (jet.dxi, jet.dxj);
             
             for spin in 0..2 {
                 let dhdx = dhdx_all.index(spin);
@@ -213,13 +237,17 @@ pub fn calculate_quantum_metric_from_seud(
                 
                 let eigenvalues = &seud.index(spin).eigenvalues;
                 
+                // 縮退した多重項を検出する（多重項内部の寄与は含めない）
+                let multiplets = find_multiplets(eigenvalues.as_slice(), setting.threshold_berry);
+                let labels = multiplet_labels(&multiplets, size);
+
                 for ei in 0..size {
                     let mut berry = 0.0;
                     let u_ei = &eigenvectors[ei];
                     let eps_i = eigenvalues[ei];
                     
                     for ej in 0..size {
-                        if ei != ej {
+                        if labels[ei] != labels[ej] {
                             let u_ej = &eigenvectors[ej];
                             let eps_j = eigenvalues[ej];
                             
@@ -247,6 +275,9 @@ pub fn calculate_quantum_metric_from_seud(
                     
                     berry_results[spin][ei] = berry;
                 }
+
+                // 多重項の各バンドにはゲージ不変なトレースを等分して割り当てる
+                average_over_multiplets(&mut berry_results[spin], &multiplets);
             }
         }
     }
@@ -264,8 +295,8 @@ pub fn cal_anomaly_velocity(
 ) -> Vector2<f64> {
     match seud_enum {
         SEudEnum::SEud2(seud) => {
-            let dhdx_all = hamiltonian_2_dxi(system, kk, 0);
-            let dhdy_all = hamiltonian_2_dxi(system, kk, 1);
+            let jet = hamiltonian_2_jet(system, kk, 0, 1);
+            let (dhdx_all, dhdy_all) = (jet.dxi, jet.dxj);
 
             let dhdx = dhdx_all.index(spin);
             let dhdy = dhdy_all.index(spin);
@@ -278,8 +309,8 @@ pub fn cal_anomaly_velocity(
             Vector2::new(av_x[(0,0)].real(), av_y[(0,0)].real())
         }
         SEudEnum::SEud6(seud) => {
-            let dhdx_all = hamiltonian_6_dxi(system, kk, 0);
-            let dhdy_all = hamiltonian_6_dxi(system, kk, 1);
+            let jet = hamiltonian_6_jet(system, kk, 0, 1);
+            let (dhdx_all, dhdy_all) = (jet.dxi, jet.dxj);
 
             let dhdx = dhdx_all.index(spin);
             let dhdy = dhdy_all.index(spin);
@@ -293,4 +324,153 @@ pub fn cal_anomaly_velocity(
         }
     }
 
-}
\ No newline at end of file
+}
+//----------------------------------------------------------------
+// 縮退したバンドの組（多重項）を扱う部分
+//----------------------------------------------------------------
+
+/// 昇順に並んだ固有値のうち、互いに縮退しているバンドの組
+/// 
+/// `start`から`start + len - 1`までのバンドが一つの多重項をなす。
+/// 縮退していないバンドは`len == 1`の多重項として扱う。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Multiplet{
+    pub start : usize,
+    pub len : usize,
+}
+
+impl Multiplet{
+    pub fn contains(&self, band_num : usize) -> bool{
+        band_num >= self.start && band_num < self.start + self.len
+    }
+    pub fn is_degenerate(&self) -> bool{
+        self.len > 1
+    }
+}
+
+/// 昇順に並んだ固有値を多重項に分ける
+/// 
+/// 隣り合う固有値の差の2乗が`threshold`以下であれば縮退しているとみなす。
+/// これはKubo公式で分母を無視する条件（`threshold_berry`）と同じ基準である。
+pub fn find_multiplets(eigenvalues: &[f64], threshold: f64) -> Vec<Multiplet> {
+    let mut multiplets: Vec<Multiplet> = Vec::new();
+
+    for (band_num, &eps) in eigenvalues.iter().enumerate() {
+        match multiplets.last_mut() {
+            Some(last) if (eps - eigenvalues[band_num - 1]).powi(2) <= threshold => {
+                last.len += 1;
+            }
+            _ => multiplets.push(Multiplet { start: band_num, len: 1 }),
+        }
+    }
+
+    multiplets
+}
+
+/// 指定したバンドが縮退した多重項に属していればその多重項を返す
+pub fn degenerate_multiplet_of(multiplets: &[Multiplet], band_num : usize) -> Option<Multiplet> {
+    multiplets
+        .iter()
+        .find(|multiplet| multiplet.contains(band_num))
+        .filter(|multiplet| multiplet.is_degenerate())
+        .copied()
+}
+
+//各バンドがどの多重項に属するかのラベル
+fn multiplet_labels(multiplets: &[Multiplet], size : usize) -> Vec<usize> {
+    let mut labels = vec![0; size];
+    for (label, multiplet) in multiplets.iter().enumerate() {
+        labels[multiplet.start..multiplet.start + multiplet.len].fill(label);
+    }
+    labels
+}
+
+//多重項内のバンドの値をその平均（= トレース / 次元）で置き換える
+fn average_over_multiplets(values: &mut [f64], multiplets: &[Multiplet]) {
+    for multiplet in multiplets.iter().filter(|m| m.is_degenerate()) {
+        let range = multiplet.start..multiplet.start + multiplet.len;
+        let mean = values[range.clone()].iter().sum::<f64>() / multiplet.len as f64;
+        values[range].iter_mut().for_each(|v| *v = mean);
+    }
+}
+
+/// 多重項に対する非可換（U(N)）量子幾何テンソルを計算する
+/// 
+/// # Returns
+/// * `DMatrix<Complex<f64>>` - 多重項内のバンドを添字とする N x N 行列
+/// 
+/// # 計算式
+/// Q^{ab}_{nn'} = Σ_{m∉S} <u_n|∂H/∂k_a|u_m><u_m|∂H/∂k_b|u_n'> / ((ε_n - ε_m)(ε_n' - ε_m))
+/// 
+/// 多重項内部のユニタリ変換に対して共変であり、トレースはゲージ不変である。
+/// 量子計量は Re[Tr Q]、Berry曲率のトレースは -2 Im[Tr Q^{xy}] で与えられる。
+pub fn non_abelian_qgt(
+    seud_enum: &SEudEnum,
+    system: &System,
+    kk: Vector2<f64>,
+    spin : usize,
+    multiplet : Multiplet,
+    tensor : Tensor,
+) -> DMatrix<Complex<f64>> {
+    let (a, b) = match tensor {
+        Tensor::XX => (0, 0),
+        Tensor::XY => (0, 1),
+        Tensor::YY => (1, 1),
+    };
+
+    match seud_enum {
+        SEudEnum::SEud2(seud) => {
+            let jet = hamiltonian_2_jet(system, kk, a, b);
+            multiplet_qgt(seud.index(spin), jet.dxi.index(spin), jet.dxj.index(spin), multiplet)
+        }
+        SEudEnum::SEud6(seud) => {
+            let jet = hamiltonian_6_jet(system, kk, a, b);
+            multiplet_qgt(seud.index(spin), jet.dxi.index(spin), jet.dxj.index(spin), multiplet)
+        }
+    }
+}
+
+/// 多重項に対する非可換Berry曲率 F^{xy} = i (Q^{xy} - Q^{xy†}) を計算する
+/// 
+/// 返り値はエルミート行列で、そのトレースは多重項全体のBerry曲率に等しい。
+pub fn non_abelian_berry_curvature(
+    seud_enum: &SEudEnum,
+    system: &System,
+    kk: Vector2<f64>,
+    spin : usize,
+    multiplet : Multiplet,
+) -> DMatrix<Complex<f64>> {
+    let qgt = non_abelian_qgt(seud_enum, system, kk, spin, multiplet, Tensor::XY);
+    (&qgt - qgt.adjoint()) * Complex::new(0.0, 1.0)
+}
+
+type SquareMatrix<const N: usize> = Matrix<Complex<f64>, Const<N>, Const<N>, ArrayStorage<Complex<f64>, N, N>>;
+
+fn multiplet_qgt<const N: usize>(
+    eigen : &SymmetricEigen<Complex<f64>, Const<N>>,
+    dha : &SquareMatrix<N>,
+    dhb : &SquareMatrix<N>,
+    multiplet : Multiplet,
+) -> DMatrix<Complex<f64>>
+where
+    Const<N>: Dim + DimMin<Const<N>, Output = Const<N>>,
+{
+    // 固有ベクトル基底での速度行列
+    let dha_eigen = eigen.eigenvectors.adjoint() * dha * eigen.eigenvectors;
+    let dhb_eigen = eigen.eigenvectors.adjoint() * dhb * eigen.eigenvectors;
+
+    let eigenvalues = &eigen.eigenvalues;
+
+    DMatrix::from_fn(multiplet.len, multiplet.len, |n, np| {
+        let n = multiplet.start + n;
+        let np = multiplet.start + np;
+
+        (0..N)
+            .filter(|m| !multiplet.contains(*m))
+            .map(|m| {
+                let bunbo = (eigenvalues[n] - eigenvalues[m]) * (eigenvalues[np] - eigenvalues[m]);
+                dha_eigen[(n, m)] * dhb_eigen[(m, np)] / bunbo
+            })
+            .sum()
+    })
+}
@@ -0,0 +1,115 @@
+use crate::consts::PI;
+use crate::honeycomb::{
+    honeycomb_grids::Grids,
+    thermodynamics::ThermoSpectrum,
+};
+use crate::system::{
+    model::System,
+    ribbon::{Edge, EdgeModeCount},
+};
+
+use nalgebra::{Complex, DMatrix};
+use std::io::Write;
+
+//----------------------------------------------------------------
+// スピンごとの Chern 数（Fukui-Hatsugai-Suzuki の格子ゲージの方法）
+//
+// Grids の (mesh_kx + 1) × (mesh_ky + 1) 点の固有ベクトルから、エネルギー e_fermi より下にある
+// バンドの部分空間の重なり行列式 U = det⟨ψ_m(k)|ψ_n(k')⟩ を小さな平行四辺形のまわりで掛けて
+//   C_σ = -(1/2π) Σ arg(U_1 U_2 U_3 U_4)
+// を求める（縮退があっても部分空間ごとに扱うのでゲージによらない）。
+// hamiltonian_2, hamiltonian_6 は結合ベクトルの位相を使うが、k と k + G の固有ベクトルの違いは
+// サイトごとの位相だけなので、境界の辺の寄与は打ち消し合い整数になる。
+//----------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy)]
+pub struct ChernNumber{
+    pub occupied : usize,   // e_fermi より完全に下にあるバンドの数
+    pub gapped : bool,      // e_fermi をまたぐバンドがない
+    pub chern : f64,        // 格子の上での値（ギャップがあれば整数に近い）
+}
+
+impl ChernNumber{
+    pub fn rounded(&self) -> i64{
+        self.chern.round() as i64
+    }
+}
+
+/// 電子数 n のバルクの μ の下の最も高い準位と上の最も低い準位（温度 0）
+pub fn bulk_gap(system : &System, n : f64, energy_mesh : usize) -> (f64, f64){
+    let spectrum = ThermoSpectrum::new(system, energy_mesh);
+    let mu = spectrum.chemical_potential(n, 0.0);
+    let top = spectrum.eigenvalues.iter().copied().filter(|&e| e <= mu).fold(f64::NEG_INFINITY, f64::max);
+    let bottom = spectrum.eigenvalues.iter().copied().filter(|&e| e > mu).fold(f64::INFINITY, f64::min);
+    (top, bottom)
+}
+
+/// e_fermi より下のバンドのスピンごとの Chern 数
+pub fn chern_numbers(grids : &Grids, e_fermi : f64) -> [ChernNumber; 2]{
+    let (mesh_kx, mesh_ky) = grids.calc_setting.meshes();
+    let size = grids.system.size();
+
+    [0, 1].map(|spin| {
+        let bands = grids.index(spin);
+        let range = |band : usize| {
+            bands[band].0.iter().flatten()
+                .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), info| (min.min(info.eigen), max.max(info.eigen)))
+        };
+        let ranges: Vec<(f64, f64)> = (0..size).map(range).collect();
+        let occupied = ranges.iter().filter(|(_, max)| *max < e_fermi).count();
+        let gapped = ranges.iter().all(|&(min, max)| max < e_fermi || min > e_fermi);
+        if occupied == 0 {
+            return ChernNumber { occupied, gapped, chern : 0.0 };
+        }
+
+        //k点ごとの占有状態を並べた行列（size × occupied）
+        let states: Vec<Vec<DMatrix<Complex<f64>>>> = (0..=mesh_kx)
+            .map(|i| (0..=mesh_ky)
+                .map(|j| {
+                    let columns: Vec<Vec<Complex<f64>>> = (0..occupied).map(|band| bands[band].0[i][j].eigen_vector.to_vec()).collect();
+                    DMatrix::from_fn(size, occupied, |a, band| columns[band][a])
+                })
+                .collect())
+            .collect();
+        let link = |a : &DMatrix<Complex<f64>>, b : &DMatrix<Complex<f64>>| (a.adjoint() * b).determinant();
+
+        //k1, k2 の向きが右手系でなければ符号を反転する
+        let kk = |i : usize, j : usize| bands[0].0[i][j].kk;
+        let (dk1, dk2) = (kk(1, 0) - kk(0, 0), kk(0, 1) - kk(0, 0));
+        let orientation = (dk1.x * dk2.y - dk1.y * dk2.x).signum();
+
+        let mut flux = 0.0;
+        for i in 0..mesh_kx {
+            for j in 0..mesh_ky {
+                let loop_product = link(&states[i][j], &states[i + 1][j])
+                    * link(&states[i + 1][j], &states[i + 1][j + 1])
+                    * link(&states[i + 1][j + 1], &states[i][j + 1])
+                    * link(&states[i][j + 1], &states[i][j]);
+                flux += loop_product.arg();
+            }
+        }
+
+        ChernNumber { occupied, gapped, chern : -orientation * flux / (2. * PI) }
+    })
+}
+
+/// 端ごとの状態の数とバルクの Chern 数を比べて.datファイルに出力する（matches は N_upper = -N_lower = C、ribbon::Ribbon::edge_modes）
+pub fn write_edge_modes_to_dat(counts : &[(Edge, [EdgeModeCount; 2])], chern : &[ChernNumber; 2], e_fermi : f64, file_path : &str) -> std::io::Result<()>{
+    let mut file = std::fs::File::create(file_path)?;
+
+    writeln!(file, "# e_fermi={}", e_fermi)?;
+    writeln!(file, "# edge,spin,occupied,gapped,chern,lower,upper,lower_modes,upper_modes,bulk,matches")?;
+    for (edge, count) in counts {
+        for spin in 0..2 {
+            let (count, chern) = (count[spin], chern[spin]);
+            let matches = chern.gapped && count.upper == chern.rounded() && count.lower == -chern.rounded();
+            writeln!(
+                file, "{},{},{},{},{},{},{},{},{},{},{}",
+                edge.name(), spin, chern.occupied, chern.gapped, chern.chern,
+                count.lower, count.upper, count.lower_modes, count.upper_modes, count.bulk, matches
+            )?;
+        }
+    }
+
+    Ok(())
+}
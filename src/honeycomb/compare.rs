@@ -1,127 +1,134 @@
 use crate::honeycomb::{
-    parallelization::parallel_calculate_tanzaku, setting::CalcSetting, tanzaku::Tanzakus, util::GridInfo
+    parallelization::parallel_calculate_tanzaku, setting::CalcSetting, tanzaku::{Tanzaku, Tanzakus}, util::GridInfo
 };
 
 use crate::system::{model::Param,model::System};
 
-pub fn compare_6_spinmodel(param : Param, main_mesh : usize) {
-    // 計算設定
-    let calc_setting = CalcSetting{
-        mesh_kx : 400,
-        mesh_ky : 400,
-        height_map_div : 307,   // 等高線の分割数
-        threshold_berry : 1e-12, // Berry曲率計算の際の閾値
-        main_mesh : main_mesh,
-    };
-
-    let systems = vec![
-        System::FmTmd(param),
-        System::One1Tmd(param),
-        System::One2Tmd(param),
-        System::TwinTmd(param),
-        System::Tri1Tmd(param),
-        System::UuudddTmd(param),
-        System::Tri2Tmd(param),
-        System::SatoTmd(param),
-    ];
-
-    let n_div = 300;
-
-    let cal_e_vs_ns : Vec<Vec<f64>> = systems.iter().map(|system|{
-        cal_e_vs_n(system, 100, n_div)
-    }).collect();
+use rayon::prelude::*;
+use std::io::Write;
 
-    let tanzakuss : Vec<Tanzakus> = systems.iter().map(|system|{
-        // ハニカム格子の構築
-        let tanzakus = parallel_calculate_tanzaku(calc_setting, *system);
-        tanzakus.interpolate_by_n(n_div)
-    }).collect();
+//6サイトのスピン配置（8通り）
+const SIX_SITE_SPINS : [&str; 8] = ["fm", "one1", "one2", "twin", "tri1", "uuuddd", "tri2", "afm"];
 
-    let mut tanzakus_most_stable = Tanzakus::new(calc_setting,System::Stable(param));
+/// family（"tmd" または "kanemele"）の6サイトのスピン配置を全て並べる
+pub fn six_site_candidates(family : &str, param : Param) -> Vec<System> {
+    SIX_SITE_SPINS.iter()
+        .filter_map(|spin| System::from_family(family, spin, param))
+        .collect()
+}
 
-    for i in 0..n_div{
-        tanzakus_most_stable.data[i] = {
-            let mut tanzaku = tanzakuss[0].data[i];
-            tanzaku.stable = Some(systems[0]);
-            let mut min_energy = cal_e_vs_ns[0][i];
+//----------------------------------------------------------------
+// スピン配置の比較
+//----------------------------------------------------------------
+#[derive(Debug, Clone, Copy)]
+pub struct CompareOptions{
+    pub n_div : usize,          // 電子数 0..2 の分割数
+    pub energy_mesh : usize,    // 全エネルギーを求めるk点のメッシュ
+}
 
-            for j in 1..tanzakuss.len(){
-                if cal_e_vs_ns[j][i] < min_energy{
-                    tanzaku = tanzakuss[j].data[i];
-                    min_energy = cal_e_vs_ns[j][i];
-                    tanzaku.stable = Some(systems[j]);
-                }
-            }
-            tanzaku
-        }
+impl CompareOptions{
+    pub fn standard() -> Self{
+        CompareOptions { n_div : 300, energy_mesh : 100 }
+    }
+}
+
+/// ある電子数での比較の結果
+#[derive(Clone)]
+pub struct FillingResult{
+    pub n : f64,
+    pub winner : usize,         // 全エネルギーが最も低い候補（candidates の添字）
+    pub energies : Vec<f64>,    // 各候補の全エネルギー
+    pub tanzaku : Tanzaku,      // 最も安定な候補のTanzaku
+}
+
+pub struct CompareResult{
+    pub candidates : Vec<System>,
+    pub calc_setting : CalcSetting,
+    pub tanzakuss : Vec<Tanzakus>,      // 各候補のTanzakus（電子数について等間隔に補間済み）
+    pub fillings : Vec<FillingResult>,  // 電子数ごとの結果
+}
+
+impl CompareResult{
+    pub fn winner(&self, i : usize) -> System{
+        self.candidates[self.fillings[i].winner].clone()
     }
+    /// 最も安定なスピン配置を集めた表のパス
+    pub fn stable_path(dir : &str, param : &Param, setting : &CalcSetting) -> String{
+        format!("{}/data_Stable_{}_{}.dat", dir, param.debug(), setting.debug())
+    }
+    /// 各候補のTanzakusと、最も安定なスピン配置を集めた表を出力する
+    pub fn write_to_dat(&self, dir : &str) -> std::io::Result<()>{
+        let dir = dir.to_string();
+        for tanzakus in &self.tanzakuss{
+            tanzakus.write_to_dat(Some(&dir))?;
+        }
+
+        let first = self.candidates.first()
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "no candidates to write"))?;
+        let path = Self::stable_path(&dir, first.param(), &self.calc_setting);
+        let mut file = std::fs::File::create(path)?;
+
+        let energy_names: Vec<String> = self.candidates.iter().map(|system| format!("e_{}", system.debug_only_name())).collect();
+        writeln!(file, "# n,energy,berry,bcd_x,bcd_y,qmd_x,qmd_y,stable,{}", energy_names.join(","))?;
+
+        for (i, filling) in self.fillings.iter().enumerate(){
+            let tanzaku = &filling.tanzaku;
+            let energies: Vec<String> = filling.energies.iter().map(|energy| energy.to_string()).collect();
+            writeln!(
+                file, "{},{},{},{},{},{},{},{},{}",
+                filling.n, tanzaku.energy, tanzaku.berry, tanzaku.bcd.x, tanzaku.bcd.y, tanzaku.qmd.x, tanzaku.qmd.y,
+                self.winner(i).debug_only_name(), energies.join(",")
+            )?;
+        }
 
-    //出力
-    let dir = "./out_tanzaku/compare_6_spinmodel".to_string();
-    for tanzakus in tanzakuss{
-        tanzakus.write_to_dat(Some(&dir),false).unwrap();
+        Ok(())
     }
-    tanzakus_most_stable.write_to_dat(Some(&dir),true).unwrap();
 }
 
-pub fn compare_6_spinmodel_kanemele(param : Param, main_mesh : usize) {
-    // 計算設定
-    let calc_setting = CalcSetting{
-        mesh_kx : 400,
-        mesh_ky : 400,
-        height_map_div : 307,   // 等高線の分割数
-        threshold_berry : 1e-12, // Berry曲率計算の際の閾値
-        main_mesh : main_mesh,
-    };
-
-    let systems = vec![
-        System::FmKanemele(param),
-        System::One1Kanemele(param),
-        System::One2Kanemele(param),
-        System::TwinKanemele(param),
-        System::Tri1Kanemele(param),
-        System::UuudddKanemele(param),
-        System::Tri2Kanemele(param),
-        System::AfmKanemele(param),
-    ];
-
-    let n_div = 300;
-
-    let cal_e_vs_ns : Vec<Vec<f64>> = systems.iter().map(|system|{
-        cal_e_vs_n(system, 100, n_div)
-    }).collect();
+/// 同じパラメーターの複数のスピン配置でTanzakusを計算し、
+/// 各電子数で全エネルギーが最も低いスピン配置を選ぶ（候補が空ならエラー）
+pub fn compare_candidates(candidates : &[System], calc_setting : CalcSetting, options : CompareOptions) -> Result<CompareResult, String> {
+    if candidates.is_empty() {
+        return Err("compare_candidates needs at least one candidate".to_string());
+    }
+    let energies = candidate_energies(candidates, options);
 
-    let tanzakuss : Vec<Tanzakus> = systems.iter().map(|system|{
+    let tanzakuss : Vec<Tanzakus> = candidates.iter().map(|system|{
         // ハニカム格子の構築
-        let tanzakus = parallel_calculate_tanzaku(calc_setting, *system);
-        tanzakus.interpolate_by_n(n_div)
+        let tanzakus = parallel_calculate_tanzaku(calc_setting, system.clone());
+        tanzakus.interpolate_by_n(options.n_div)
     }).collect();
 
-    let mut tanzakus_most_stable = Tanzakus::new(calc_setting,System::Stable(param));
-
-    for i in 0..n_div{
-        tanzakus_most_stable.data[i] = {
-            let mut tanzaku = tanzakuss[0].data[i];
-            tanzaku.stable = Some(systems[0]);
-            let mut min_energy = cal_e_vs_ns[0][i];
-
-            for j in 1..tanzakuss.len(){
-                if cal_e_vs_ns[j][i] < min_energy{
-                    tanzaku = tanzakuss[j].data[i];
-                    min_energy = cal_e_vs_ns[j][i];
-                    tanzaku.stable = Some(systems[j]);
-                }
+    let fillings = (0..options.n_div).map(|i|{
+        let mut winner = 0;
+        for j in 1..candidates.len(){
+            if energies[j][i] < energies[winner][i]{
+                winner = j;
             }
-            tanzaku
         }
-    }
 
-    //出力
-    let dir = "./out_tanzaku/compare_6_spinmodel_kanemele".to_string();
-    for tanzakus in tanzakuss{
-        tanzakus.write_to_dat(Some(&dir),false).unwrap();
-    }
-    tanzakus_most_stable.write_to_dat(Some(&dir),true).unwrap();
+        let tanzaku = tanzakuss[winner].data[i];
+        FillingResult {
+            n : 2.0 * i as f64 / options.n_div as f64,
+            winner,
+            energies : energies.iter().map(|energy| energy[i]).collect(),
+            tanzaku,
+        }
+    }).collect();
+
+    Ok(CompareResult {
+        candidates : candidates.to_vec(),
+        calc_setting,
+        tanzakuss,
+        fillings,
+    })
+}
+
+/// 各候補の電子数ごとの全エネルギー [candidate][n]（n は 0 から 2 を options.n_div 等分）
+pub fn candidate_energies(candidates : &[System], options : CompareOptions) -> Vec<Vec<f64>> {
+    candidates.par_iter()
+        .map(|system| cal_e_vs_n(system, options.energy_mesh, options.n_div))
+        .collect()
 }
 
 use crate::{
@@ -130,7 +137,8 @@ use crate::{
 };
 
 
-pub fn cal_e_vs_n(system : &System, graph_mesh: usize, n_div: usize) -> Vec<f64> {
+/// graph_mesh × graph_mesh のk点での（6サイトの単位胞で見た）全固有値を昇順に並べる
+pub fn all_eigenvalues(system : &System, graph_mesh: usize) -> Vec<f64> {
     let mesh_kx = graph_mesh;
     let mesh_ky = graph_mesh;
 
@@ -145,6 +153,14 @@ pub fn cal_e_vs_n(system : &System, graph_mesh: usize, n_div: usize) -> Vec<f64>
     }
 
     all_eigens.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    all_eigens
+}
+
+pub fn cal_e_vs_n(system : &System, graph_mesh: usize, n_div: usize) -> Vec<f64> {
+    let mesh_kx = graph_mesh;
+    let mesh_ky = graph_mesh;
+
+    let all_eigens = all_eigenvalues(system, graph_mesh);
 
     let total_kpoints = mesh_kx * mesh_ky;
     let total_states = all_eigens.len();
@@ -0,0 +1,154 @@
+use crate::consts::{gamma, kp, kpp};
+use crate::honeycomb::{
+    cal_berry::find_multiplets,
+    setting::CalcSetting,
+};
+use crate::system::{
+    diag::{diag, SEudEnum},
+    hamiltonian::{hamiltonian_2_jet, hamiltonian_6_jet, HamiltonianJet},
+    model::System,
+};
+
+use nalgebra::{Complex, Const, Dim, DimMin, Matrix2, SymmetricEigen, Vector2};
+use std::io::Write;
+
+//----------------------------------------------------------------
+// 逆有効質量テンソル
+//----------------------------------------------------------------
+
+/// ある点でのあるバンドの逆有効質量テンソル ∂²ε_n/∂k_a∂k_b を計算する
+///
+/// # 計算式
+/// (M⁻¹)_ab = <u_n|∂²H/∂k_a∂k_b|u_n> + Σ_{m∉S} 2 Re[<u_n|∂H/∂k_a|u_m><u_m|∂H/∂k_b|u_n>] / (ε_n - ε_m)
+///
+/// 第2項がバンド間の寄与である。縮退した多重項Sの内部の寄与は
+/// `threshold_berry`の基準で除外する（縮退点では有効質量は一意に定まらない）。
+pub fn inverse_effective_mass(
+    seud_enum: &SEudEnum,
+    system: &System,
+    kk: Vector2<f64>,
+    spin : usize,
+    band_num : usize,
+    setting : &CalcSetting,
+) -> Matrix2<f64> {
+    // テンソルは対称なので (a, b) = (0,0), (0,1), (1,1) の3つのジェットだけを作る
+    let element = |a : usize, b : usize| match seud_enum {
+        SEudEnum::SEud2(seud) if system.size() == 2 => {
+            mass_element(seud.index(spin), &hamiltonian_2_jet(system, kk, a, b), spin, band_num, setting.threshold_berry)
+        }
+        SEudEnum::SEud6(seud) => {
+            mass_element(seud.index(spin), &hamiltonian_6_jet(system, kk, a, b), spin, band_num, setting.threshold_berry)
+        }
+        _ => panic!("size of eigen system and hamiltonian should be the same"),
+    };
+
+    let xy = element(0, 1);
+    Matrix2::new(
+        element(0, 0), xy,
+        xy, element(1, 1),
+    )
+}
+
+fn mass_element<const N: usize>(
+    eigen : &SymmetricEigen<Complex<f64>, Const<N>>,
+    jet : &HamiltonianJet<N>,
+    spin : usize,
+    band_num : usize,
+    threshold : f64,
+) -> f64
+where
+    Const<N>: Dim + DimMin<Const<N>, Output = Const<N>>,
+{
+    let eigenvalues = &eigen.eigenvalues;
+    let multiplets = find_multiplets(eigenvalues.as_slice(), threshold);
+    let own = multiplets.iter().find(|m| m.contains(band_num)).unwrap();
+
+    let u_n = eigen.eigenvectors.column(band_num);
+
+    // バンド内の寄与
+    let intra = (u_n.adjoint() * jet.dxidxj.index(spin) * u_n)[(0,0)].re;
+
+    // バンド間の寄与
+    let inter: f64 = (0..N)
+        .filter(|m| !own.contains(*m))
+        .map(|m| {
+            let u_m = eigen.eigenvectors.column(m);
+            let braket = (u_n.adjoint() * jet.dxi.index(spin) * u_m)[(0,0)] * (u_m.adjoint() * jet.dxj.index(spin) * u_n)[(0,0)];
+            2.0 * braket.re / (eigenvalues[band_num] - eigenvalues[m])
+        })
+        .sum();
+
+    intra + inter
+}
+
+//----------------------------------------------------------------
+// 高対称点でのバンドの曲率
+//----------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy)]
+pub struct BandCurvature{
+    pub point : &'static str,
+    pub kk : Vector2<f64>,
+    pub spin : usize,
+    pub band_num : usize,
+    pub energy : f64,
+    pub inverse_mass : Matrix2<f64>,
+}
+
+impl BandCurvature{
+    /// 逆有効質量テンソルの主値（昇順）
+    pub fn principal_inverse_masses(&self) -> (f64, f64){
+        let eigen = self.inverse_mass.symmetric_eigenvalues();
+        (eigen.min(), eigen.max())
+    }
+}
+
+/// K, K', Γ 点での全スピン・全バンドの逆有効質量テンソルを計算する
+pub fn band_curvature_at_extrema(system : &System, setting : &CalcSetting) -> Vec<BandCurvature> {
+    let size = system.size();
+    let points = [("K", kp(size)), ("K'", kpp(size)), ("Gamma", gamma(size))];
+
+    let mut out = Vec::new();
+
+    for (point, kk) in points {
+        let seud_enum = diag(system, kk, false);
+        let eigenvalues = match &seud_enum {
+            SEudEnum::SEud2(seud) => [seud.u.eigenvalues.as_slice().to_vec(), seud.d.eigenvalues.as_slice().to_vec()],
+            SEudEnum::SEud6(seud) => [seud.u.eigenvalues.as_slice().to_vec(), seud.d.eigenvalues.as_slice().to_vec()],
+        };
+
+        for (spin, energies) in eigenvalues.iter().enumerate() {
+            for (band_num, &energy) in energies.iter().enumerate() {
+                out.push(BandCurvature {
+                    point,
+                    kk,
+                    spin,
+                    band_num,
+                    energy,
+                    inverse_mass : inverse_effective_mass(&seud_enum, system, kk, spin, band_num, setting),
+                });
+            }
+        }
+    }
+
+    out
+}
+
+/// 高対称点でのバンドの曲率を.datファイルに出力する
+pub fn write_band_curvature_to_dat(curvatures : &[BandCurvature], path : &str) -> std::io::Result<()> {
+    let mut file = std::fs::File::create(path)?;
+
+    writeln!(file, "# point,kx,ky,spin,band_index,energy,minv_xx,minv_xy,minv_yy,minv_min,minv_max")?;
+    for c in curvatures {
+        let (min, max) = c.principal_inverse_masses();
+        writeln!(
+            file,
+            "{},{},{},{},{},{},{},{},{},{},{}",
+            c.point, c.kk.x, c.kk.y, c.spin, c.band_num, c.energy,
+            c.inverse_mass[(0,0)], c.inverse_mass[(0,1)], c.inverse_mass[(1,1)],
+            min, max
+        )?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,312 @@
+use crate::consts::*;
+use crate::honeycomb::{
+    dv2::DV2,
+    honeycomb_grids::Grids,
+    setting::CalcSetting,
+    util::GridInfo,
+};
+use crate::system::{
+    diag::{diag, SEudEnum},
+    hamiltonian::{hamiltonian_dxi_from_system, HamiltonianEnum},
+    model::System,
+};
+
+use nalgebra::{Complex, DMatrix, DVector, Vector2, Vector3};
+use std::io::Write;
+
+//----------------------------------------------------------------
+// 探索の設定
+//----------------------------------------------------------------
+#[derive(Debug, Clone, Copy)]
+pub struct GapSearchSetting{
+    pub coarse_mesh : usize,    // 粗いスキャンのメッシュ数
+    pub n_candidates : usize,   // 局所最適化を行う候補点の数
+    pub tolerance : f64,        // 局所最適化の収束判定（ギャップの値）
+    pub max_iter : usize,       // 局所最適化の最大反復回数
+    pub loop_radius : f64,      // Berry位相を計算するループの半径（BZの辺の長さに対する比）
+    pub loop_div : usize,       // ループの分割数
+}
+
+impl GapSearchSetting{
+    pub fn standard() -> Self{
+        GapSearchSetting {
+            coarse_mesh : 60,
+            n_candidates : 12,
+            tolerance : 1e-12,
+            max_iter : 500,
+            loop_radius : 1e-3,
+            loop_div : 64,
+        }
+    }
+}
+
+//----------------------------------------------------------------
+// 直接ギャップの極小点（ノード）
+//----------------------------------------------------------------
+#[derive(Debug, Clone, Copy)]
+pub struct Node{
+    pub kk : Vector2<f64>,
+    pub spin : usize,
+    pub band_num : usize,   // 下側のバンド（band_num と band_num + 1 の間のギャップ）
+    pub gap : f64,
+    pub berry_phase : f64,  // 下側のバンドのノード周りのBerry位相 [-π, π]
+    pub chirality : i32,    // 2バンド有効模型のカイラリティ（chirality_at 参照）
+}
+
+/// spin のバンド band_num と band_num + 1 の直接ギャップの極小点を探す
+///
+/// 粗いメッシュでの Grids のスキャンで局所極小点を求め、
+/// それらを初期値として DV2 座標で Nelder-Mead 法による局所最適化を行う。
+///
+/// # Returns
+/// * `Vec<Node>` - ギャップの小さい順に並んだノード（BZ内で重複なし）
+pub fn find_nodes(system : &System, spin : usize, band_num : usize, setting : &GapSearchSetting) -> Vec<Node> {
+    let size = system.size();
+    assert!(band_num + 1 < size, "band_num + 1 should be smaller than the system size");
+
+    let mesh = setting.coarse_mesh;
+    let calc_setting = CalcSetting{
+        mesh_kx : mesh,
+        mesh_ky : mesh,
+        height_map_div : 1,
+        threshold_berry : 1e-12,
+        main_mesh : 1,
+    };
+    let grids = Grids::build(calc_setting, system.clone(), GridInfo::no_divide());
+
+    let lower = &grids.index(spin)[band_num].0;
+    let upper = &grids.index(spin)[band_num + 1].0;
+    let gap_at = |i : usize, j : usize| upper[i % mesh][j % mesh].eigen - lower[i % mesh][j % mesh].eigen;
+
+    // 粗いメッシュ上での局所極小点（周期境界）
+    let mut candidates = Vec::new();
+    for (i, row) in lower.iter().enumerate().take(mesh) {
+        for (j, band_info) in row.iter().enumerate().take(mesh) {
+            let gap = gap_at(i, j);
+            let is_minimum = [(1, 0), (mesh - 1, 0), (0, 1), (0, mesh - 1), (1, 1), (mesh - 1, mesh - 1), (1, mesh - 1), (mesh - 1, 1)]
+                .iter()
+                .all(|(di, dj)| gap <= gap_at(i + di, j + dj));
+            if is_minimum {
+                candidates.push((gap, band_info.kk));
+            }
+        }
+    }
+    candidates.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+    candidates.truncate(setting.n_candidates);
+
+    // DV2 座標で局所最適化
+    let (dv2_k1, dv2_k2) = bz_basis(size);
+    let step = (dv2_k1 + dv2_k2) / mesh as f64;
+
+    let mut nodes: Vec<Node> = Vec::new();
+    for (_, kk) in candidates {
+        let x0 = DV2::from_car(kk, size);
+        let (x_min, gap) = nelder_mead_2d(
+            |x| band_gap(system, x.to_car(size), spin, band_num),
+            x0, step, setting.tolerance, setting.max_iter,
+        );
+        let kk = reduce_to_bz(x_min.to_car(size), size);
+
+        let duplicated = nodes.iter().any(|node| bz_distance(node.kk, kk, size) < step.to_car(size).norm() * 0.5);
+        if duplicated {
+            continue;
+        }
+
+        let radius = setting.loop_radius * dv2_k1.to_car(size).norm();
+        let berry_phase = berry_phase_around(system, kk, spin, band_num, radius, setting.loop_div);
+        let chirality = chirality_at(system, kk, spin, band_num);
+
+        nodes.push(Node { kk, spin, band_num, gap, berry_phase, chirality });
+    }
+
+    nodes.sort_by(|a, b| a.gap.partial_cmp(&b.gap).unwrap());
+    nodes
+}
+
+/// spin のバンド band_num と band_num + 1 の BZ 全体での最小直接ギャップ
+pub fn global_min_gap(system : &System, spin : usize, band_num : usize, setting : &GapSearchSetting) -> Option<Node> {
+    find_nodes(system, spin, band_num, setting).into_iter().next()
+}
+
+/// ノードの一覧を.datファイルに出力する
+pub fn write_nodes_to_dat(nodes : &[Node], path : &str) -> std::io::Result<()> {
+    let mut file = std::fs::File::create(path)?;
+
+    writeln!(file, "# kx,ky,spin,band_index,gap,berry_phase,chirality")?;
+    for node in nodes {
+        writeln!(file, "{},{},{},{},{},{},{}", node.kk.x, node.kk.y, node.spin, node.band_num, node.gap, node.berry_phase, node.chirality)?;
+    }
+
+    Ok(())
+}
+
+//----------------------------------------------------------------
+// ギャップとトポロジカルな量
+//----------------------------------------------------------------
+
+fn eigenvalues_of(seud_enum : &SEudEnum, spin : usize) -> Vec<f64> {
+    match seud_enum {
+        SEudEnum::SEud2(seud) => seud.index(spin).eigenvalues.as_slice().to_vec(),
+        SEudEnum::SEud6(seud) => seud.index(spin).eigenvalues.as_slice().to_vec(),
+    }
+}
+
+fn eigenvector_of(seud_enum : &SEudEnum, spin : usize, band_num : usize) -> DVector<Complex<f64>> {
+    match seud_enum {
+        SEudEnum::SEud2(seud) => DVector::from_column_slice(seud.index(spin).eigenvectors.column(band_num).as_slice()),
+        SEudEnum::SEud6(seud) => DVector::from_column_slice(seud.index(spin).eigenvectors.column(band_num).as_slice()),
+    }
+}
+
+/// ある点での band_num と band_num + 1 の直接ギャップ
+pub fn band_gap(system : &System, kk : Vector2<f64>, spin : usize, band_num : usize) -> f64 {
+    let eigenvalues = eigenvalues_of(&diag(system, kk, false), spin);
+    eigenvalues[band_num + 1] - eigenvalues[band_num]
+}
+
+/// kk を中心とする半径 radius の円周に沿った band_num の Berry 位相（Wilsonループ）
+pub fn berry_phase_around(system : &System, kk : Vector2<f64>, spin : usize, band_num : usize, radius : f64, loop_div : usize) -> f64 {
+    let states: Vec<DVector<Complex<f64>>> = (0..loop_div)
+        .map(|n| {
+            let theta = 2.0 * PI * n as f64 / loop_div as f64;
+            let k_loop = kk + radius * Vector2::new(theta.cos(), theta.sin());
+            eigenvector_of(&diag(system, k_loop, false), spin, band_num)
+        })
+        .collect();
+
+    let product = (0..loop_div).fold(ONE, |acc, n| {
+        acc * states[n].dotc(&states[(n + 1) % loop_div])
+    });
+
+    -product.arg()
+}
+
+/// kk での2バンドを基底とした有効模型のカイラリティ
+///
+/// 2バンドに射影した ∂H/∂k_a = v_a^0 + v_a・σ と副格子演算子 Σ_z = s^0 + s・σ を用いて
+/// sign[(v_x × v_y)・s] で定義する。2バンドの基底の取り方（U(2)回転）によらない。
+/// グラフェンでは K と K' で逆符号になる。
+pub fn chirality_at(system : &System, kk : Vector2<f64>, spin : usize, band_num : usize) -> i32 {
+    let seud_center = diag(system, kk, false);
+    let u_a = eigenvector_of(&seud_center, spin, band_num);
+    let u_b = eigenvector_of(&seud_center, spin, band_num + 1);
+
+    //2x2 のエルミート行列を σ で展開したベクトル部分
+    let pauli_vector = |m : &DMatrix<Complex<f64>>| {
+        let m_ab = u_a.dotc(&(m * &u_b));
+        let m_aa = u_a.dotc(&(m * &u_a)).re;
+        let m_bb = u_b.dotc(&(m * &u_b)).re;
+        Vector3::new(m_ab.re, -m_ab.im, (m_aa - m_bb) * 0.5)
+    };
+
+    let v_x = pauli_vector(&to_dmatrix(&hamiltonian_dxi_from_system(system, kk, false, 0), spin));
+    let v_y = pauli_vector(&to_dmatrix(&hamiltonian_dxi_from_system(system, kk, false, 1), spin));
+
+    let n = u_a.len();
+    let sublattice = DMatrix::from_fn(n, n, |i, j| if i == j { if i % 2 == 0 { ONE } else { -ONE } } else { ZERO });
+    let s = pauli_vector(&sublattice);
+
+    let triple = v_x.cross(&v_y).dot(&s);
+    if triple.abs() < 1e-12 {
+        0
+    } else {
+        triple.signum() as i32
+    }
+}
+
+fn to_dmatrix(hamiltonian_enum : &HamiltonianEnum, spin : usize) -> DMatrix<Complex<f64>> {
+    match hamiltonian_enum {
+        HamiltonianEnum::H2(h) => DMatrix::from_column_slice(2, 2, h.index(spin).as_slice()),
+        HamiltonianEnum::H6(h) => DMatrix::from_column_slice(6, 6, h.index(spin).as_slice()),
+    }
+}
+
+//----------------------------------------------------------------
+// BZ の座標に関する補助関数
+//----------------------------------------------------------------
+
+//BZ（平行四辺形）を張るベクトル
+fn bz_basis(size : usize) -> (DV2, DV2) {
+    let dv2_k1 = DV2::from_car(kpp(size),size) - DV2::from_car(-k(size),size);
+    let dv2_k2 = DV2::from_car(kp(size),size) - DV2::from_car(-k(size),size);
+    (dv2_k1, dv2_k2)
+}
+
+//kk を BZ の平行四辺形の基底で表した分率座標
+fn to_fractional(kk : Vector2<f64>, size : usize) -> Vector2<f64> {
+    let (dv2_k1, dv2_k2) = bz_basis(size);
+    let b1 = dv2_k1.to_car(size);
+    let b2 = dv2_k2.to_car(size);
+    let rel = kk + k(size);
+    let det = b1.x * b2.y - b1.y * b2.x;
+
+    Vector2::new(
+        (rel.x * b2.y - rel.y * b2.x) / det,
+        (b1.x * rel.y - b1.y * rel.x) / det,
+    )
+}
+
+//逆格子ベクトルだけずらして BZ の平行四辺形の中に戻す
+fn reduce_to_bz(kk : Vector2<f64>, size : usize) -> Vector2<f64> {
+    let (dv2_k1, dv2_k2) = bz_basis(size);
+    let frac = to_fractional(kk, size);
+    kk - dv2_k1.to_car(size) * frac.x.floor() - dv2_k2.to_car(size) * frac.y.floor()
+}
+
+//逆格子の周期性を考慮した2点間の距離
+fn bz_distance(k1 : Vector2<f64>, k2 : Vector2<f64>, size : usize) -> f64 {
+    let (dv2_k1, dv2_k2) = bz_basis(size);
+    let diff = to_fractional(k1, size) - to_fractional(k2, size);
+    let wrapped = diff.map(|x| x - x.round());
+    (dv2_k1.to_car(size) * wrapped.x + dv2_k2.to_car(size) * wrapped.y).norm()
+}
+
+//----------------------------------------------------------------
+// 2次元の Nelder-Mead 法
+//----------------------------------------------------------------
+fn nelder_mead_2d<F: Fn(DV2) -> f64>(f : F, x0 : DV2, step : DV2, tolerance : f64, max_iter : usize) -> (DV2, f64) {
+    let mut simplex = [
+        (x0, f(x0)),
+        (x0 + DV2::new(step.x, 0.0), f(x0 + DV2::new(step.x, 0.0))),
+        (x0 + DV2::new(0.0, step.y), f(x0 + DV2::new(0.0, step.y))),
+    ];
+
+    for _ in 0..max_iter {
+        simplex.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+        let spread = |a : DV2, b : DV2| (a - b).x.hypot((a - b).y);
+        let diameter = spread(simplex[1].0, simplex[0].0).max(spread(simplex[2].0, simplex[0].0));
+        if (simplex[2].1 - simplex[0].1).abs() < tolerance || diameter < 1e-14 {
+            break;
+        }
+
+        let centroid = (simplex[0].0 + simplex[1].0) / 2.0;
+        let worst = simplex[2];
+
+        let reflected = centroid + (centroid - worst.0);
+        let f_reflected = f(reflected);
+
+        if f_reflected < simplex[0].1 {
+            let expanded = centroid + (centroid - worst.0) * 2.0;
+            let f_expanded = f(expanded);
+            simplex[2] = if f_expanded < f_reflected { (expanded, f_expanded) } else { (reflected, f_reflected) };
+        } else if f_reflected < simplex[1].1 {
+            simplex[2] = (reflected, f_reflected);
+        } else {
+            let contracted = centroid + (worst.0 - centroid) * 0.5;
+            let f_contracted = f(contracted);
+            if f_contracted < worst.1 {
+                simplex[2] = (contracted, f_contracted);
+            } else {
+                // 最良点に向かって縮小
+                let best = simplex[0].0;
+                for vertex in simplex.iter_mut().skip(1) {
+                    let shrunk = best + (vertex.0 - best) * 0.5;
+                    *vertex = (shrunk, f(shrunk));
+                }
+            }
+        }
+    }
+
+    simplex.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+    simplex[0]
+}
@@ -1,6 +1,7 @@
 use crate::honeycomb::{
     cal_berry::{cal_anomaly_velocity, calculate_berry_curvature_from_seud, calculate_quantum_metric_from_seud, Tensor}, 
-    honeycomb_grids::{BandInfo, Grid, Grids}, setting::CalcSetting, util::{cal_cell_area, move_bz, to_hex}
+    honeycomb_grids::{BandInfo, Grid, Grids}, setting::CalcSetting, util::{cal_cell_area, move_bz, to_hex},
+    symmetry::{triangle_corners, IrreducibleMesh, SymmetryGroup, SymmetryOp, TriangleKey},
 };
 
 use crate::system::{
@@ -8,7 +9,8 @@ use crate::system::{
     diag::{diag,}
 };
 
-use nalgebra::{Vector2};
+use nalgebra::{Matrix2, Vector2};
+use rayon::prelude::*;
 use std::{fs::File,};
 use std::io::{Write, Result as IoResult};
 
@@ -77,6 +79,76 @@ impl AllHeightMaps{
 
         out
     }
+    /// 既約領域の三角形でのみ等高線とBerry曲率・量子計量を計算し、対称操作で展開する
+    ///
+    /// 線分の端点は R k に、速度は R v に、量子計量は R g Rᵀ に、
+    /// Berry曲率は `berry_sign` 倍に変換する。像の線分は逆格子ベクトルだけ平行移動して
+    /// 像の三角形の位置に置くので、`build` と同じ平行四辺形に収まる。
+    pub fn build_in_wedge(
+        grids : &Grids,
+        group : &SymmetryGroup,
+        wedge : &IrreducibleMesh,
+    ) -> Self{
+        let size = grids.system.size();
+        let div = grids.calc_setting.height_map_div;
+
+        let (highest_energy, ground_energy) = grids.energy_range();
+        let template = HeightMaps::initialize(ground_energy, highest_energy, div);
+
+        let centroid = |key : TriangleKey| {
+            triangle_corners(key).iter().map(|&(i, j)| grids.u[0].0[i][j].kk).sum::<Vector2<f64>>() / 3.0
+        };
+
+        // (spin, band, energy index, line)
+        let lines: Vec<(usize, usize, usize, Line)> = wedge.triangles
+            .par_iter()
+            .flat_map_iter(|orbit| {
+                let mut out = Vec::new();
+                let rep_centroid = centroid(orbit.rep);
+
+                for ud in 0..2{
+                    for band_num in 0..size{
+                        let corners = triangle_corners(orbit.rep).map(|(ci, cj)| grids.index(ud)[band_num].0[ci][cj]);
+                        let indices = corners.map(|corner| template.energy_2_index(&corner.eigen));
+                        let (min_index, max_index) = (*indices.iter().min().unwrap(), *indices.iter().max().unwrap());
+
+                        for index in min_index..=max_index{
+                            let enerygy = template.index_2_energy(&index);
+
+                            let line = create_triangle_line(
+                                div_internal(corners[0], corners[1], enerygy),
+                                div_internal(corners[1], corners[2], enerygy),
+                                div_internal(corners[2], corners[0], enerygy),
+                            );
+
+                            if let Some(mut calced_line) = line {
+                                calced_line.set_berry_quantum_geometry(&grids.calc_setting, &grids.system, ud, band_num);
+
+                                for &(image, op_index) in &orbit.images {
+                                    let op = &group.ops[op_index];
+                                    let shift = centroid(image) - op.rotation * rep_centroid;
+                                    out.push((op.map_spin(ud), band_num, index, calced_line.transform(op, shift)));
+                                }
+                            }
+                        }
+                    }
+                }
+                out
+            })
+            .collect();
+
+        let mut out = Self::ini(grids.calc_setting);
+        for ud in 0..2{
+            for _ in 0..size{
+                out.index_mut(ud).push(template.clone());
+            }
+        }
+        for (ud, band_num, index, line) in lines{
+            out.index_mut(ud)[band_num].contents[index].0.push(line);
+        }
+
+        out
+    }
     pub fn ini(calc_setting: CalcSetting) -> Self{
         AllHeightMaps { u: Vec::new(), d: Vec::new(), calc_setting }
     }
@@ -239,6 +311,24 @@ impl Line{
     pub fn center(&self) -> Vector2<f64>{
         (self.ini + self.end) / 2.0
     }
+    /// 対称操作で移した線分（端点は R k + shift）
+    pub fn transform(&self, op : &SymmetryOp, shift : Vector2<f64>) -> Self{
+        let rotation = op.rotation;
+        let metric = match (self.gm_xx, self.gm_xy, self.gm_yy) {
+            (Some(xx), Some(xy), Some(yy)) => Some(rotation * Matrix2::new(xx, xy, xy, yy) * rotation.transpose()),
+            _ => None,
+        };
+
+        Line {
+            ini : rotation * self.ini + shift,
+            end : rotation * self.end + shift,
+            berry : self.berry.map(|berry| berry * op.berry_sign),
+            anomaly_velocity : self.anomaly_velocity.map(|v| rotation * v),
+            gm_xx : metric.map(|g| g[(0,0)]),
+            gm_xy : metric.map(|g| g[(0,1)]),
+            gm_yy : metric.map(|g| g[(1,1)]),
+        }
+    }
     pub fn set_berry_quantum_geometry(&mut self, calc_setting: &CalcSetting, system : &System, ud : usize, band_num : usize){
         let kk = self.center();
         let seud = diag(system,kk,false);
@@ -0,0 +1,142 @@
+use crate::system::model::System;
+use crate::system::peierls::{Flux, MagneticSupercell};
+
+use rayon::prelude::*;
+use std::io::Write;
+
+//----------------------------------------------------------------
+// Hofstadter のバタフライと Streda の公式によるギャップの Chern 数
+//
+// 磁束 φ = p/q ごとに磁気スーパーセルのバンドの幅をスピンごとに k_points² 点から求め、
+// min_gap より広いギャップについて、基本単位胞あたりのギャップより下の状態の数 N(μ, φ) を数える。
+// ギャップが開いている間は N = s + C φ なので、隣の磁束 φ' でも μ がギャップの中にあれば
+//   C_σ = (N(μ, φ') - N(μ, φ)) / (φ' - φ)
+// とする（Streda の公式 σ_xy = ∂N/∂φ、e²/h 単位）。符号は chern::chern_numbers と同じで、
+// 磁場の弱い極限ではバルクのギャップの C_σ に戻る。
+//----------------------------------------------------------------
+
+/// 一つの磁束のスピンごとのバンドの幅
+#[derive(Debug, Clone)]
+pub struct HofstadterSpectrum{
+    pub flux : Flux,
+    pub primitive_cells : usize,
+    pub bands : [Vec<(f64, f64)>; 2],   // [spin][band] (min, max)
+}
+
+impl HofstadterSpectrum{
+    pub fn build(system : &System, flux : Flux, k_points : usize) -> Result<Self, String>{
+        let supercell = MagneticSupercell::new(system, flux)?;
+        let size = supercell.size();
+        let mut bands = [vec![(f64::INFINITY, f64::NEG_INFINITY); size], vec![(f64::INFINITY, f64::NEG_INFINITY); size]];
+
+        for kk in supercell.k_mesh(k_points) {
+            let seud = supercell.diag(kk);
+            for (spin, ranges) in bands.iter_mut().enumerate() {
+                for (range, &energy) in ranges.iter_mut().zip(seud.spin(spin).eigenvalues.iter()) {
+                    *range = (range.0.min(energy), range.1.max(energy));
+                }
+            }
+        }
+
+        Ok(HofstadterSpectrum { flux, primitive_cells : supercell.primitive_cells(), bands })
+    }
+    /// min_gap より広いギャップ (下のバンドの上端, 上のバンドの下端, 下のバンドの数)
+    pub fn gaps(&self, spin : usize, min_gap : f64) -> Vec<(f64, f64, usize)>{
+        let bands = &self.bands[spin];
+        (1..bands.len())
+            .map(|band| (bands[..band].iter().map(|b| b.1).fold(f64::NEG_INFINITY, f64::max), bands[band].0, band))
+            .filter(|&(lower, upper, _)| upper - lower > min_gap)
+            .collect()
+    }
+    /// energy より下の基本単位胞あたりの状態の数（energy がバンドの中なら None）
+    pub fn states_below(&self, spin : usize, energy : f64) -> Option<f64>{
+        let bands = &self.bands[spin];
+        if bands.iter().any(|&(min, max)| min <= energy && energy <= max) {
+            return None;
+        }
+        Some(bands.iter().filter(|&&(_, max)| max < energy).count() as f64 / self.primitive_cells as f64)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct HofstadterGap{
+    pub flux : Flux,
+    pub spin : usize,
+    pub lower : f64,
+    pub upper : f64,
+    pub states : f64,           // 基本単位胞あたりのギャップより下の状態の数
+    pub chern : Option<f64>,    // Streda の公式（隣の磁束でもギャップの中にあるとき）
+}
+
+pub struct Hofstadter{
+    pub name : String,
+    pub k_points : usize,
+    pub spectra : Vec<HofstadterSpectrum>,     // 磁束の昇順
+    pub gaps : Vec<HofstadterGap>,
+}
+
+impl Hofstadter{
+    /// 磁束ごとに並列に計算する
+    pub fn build(system : &System, fluxes : &[Flux], k_points : usize, min_gap : f64) -> Result<Self, String>{
+        let mut spectra = fluxes.par_iter()
+            .map(|&flux| HofstadterSpectrum::build(system, flux, k_points))
+            .collect::<Result<Vec<_>, String>>()?;
+        spectra.sort_by(|a, b| a.flux.value().partial_cmp(&b.flux.value()).unwrap());
+
+        let mut gaps = Vec::new();
+        for (index, spectrum) in spectra.iter().enumerate() {
+            //近い方の隣の磁束から先に試す
+            let mut neighbors: Vec<&HofstadterSpectrum> = [index.checked_sub(1), Some(index + 1)].into_iter()
+                .flatten()
+                .filter_map(|neighbor| spectra.get(neighbor))
+                .collect();
+            let phi = spectrum.flux.value();
+            neighbors.sort_by(|a, b| (a.flux.value() - phi).abs().partial_cmp(&(b.flux.value() - phi).abs()).unwrap());
+
+            for spin in 0..2 {
+                for (lower, upper, _) in spectrum.gaps(spin, min_gap) {
+                    let mu = 0.5 * (lower + upper);
+                    let states = spectrum.states_below(spin, mu).unwrap();
+                    let chern = neighbors.iter().find_map(|neighbor| {
+                        neighbor.states_below(spin, mu).map(|other| (other - states) / (neighbor.flux.value() - phi))
+                    });
+                    gaps.push(HofstadterGap { flux : spectrum.flux, spin, lower, upper, states, chern });
+                }
+            }
+        }
+
+        Ok(Hofstadter { name : system.debug(), k_points, spectra, gaps })
+    }
+    /// バンドの幅を.datファイルに出力する（バタフライの図）
+    pub fn write_bands_to_dat(&self, file_path : &str) -> std::io::Result<()>{
+        let mut file = std::fs::File::create(file_path)?;
+
+        writeln!(file, "# k_points={}", self.k_points)?;
+        writeln!(file, "# flux,p,q,spin,band,min,max")?;
+        for spectrum in &self.spectra {
+            let flux = spectrum.flux;
+            for (spin, bands) in spectrum.bands.iter().enumerate() {
+                for (band, (min, max)) in bands.iter().enumerate() {
+                    writeln!(file, "{},{},{},{},{},{},{}", flux.value(), flux.p, flux.q, spin, band, min, max)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+    /// ギャップと Streda の公式の Chern 数を.datファイルに出力する（求まらなければ nan）
+    pub fn write_gaps_to_dat(&self, file_path : &str) -> std::io::Result<()>{
+        let mut file = std::fs::File::create(file_path)?;
+
+        writeln!(file, "# flux,p,q,spin,lower,upper,states,chern,chern_rounded")?;
+        for gap in &self.gaps {
+            let chern = gap.chern.unwrap_or(f64::NAN);
+            writeln!(
+                file, "{},{},{},{},{},{},{},{},{}",
+                gap.flux.value(), gap.flux.p, gap.flux.q, gap.spin, gap.lower, gap.upper, gap.states, chern, chern.round()
+            )?;
+        }
+
+        Ok(())
+    }
+}
@@ -7,7 +7,7 @@ use crate::{
 use crate::honeycomb::{
     util::{i_j_to_kk,cal_cell_area,GridInfo},
     setting::CalcSetting,
-    cal_berry::{calculate_quantum_metric_from_seud, Tensor}
+    cal_berry::{calculate_quantum_metric_from_seud, degenerate_multiplet_of, find_multiplets, Multiplet, Tensor}
 };
 
 use nalgebra::{Complex, Vector2, Vector6};
@@ -18,6 +18,7 @@ pub struct Grids{
     pub system : System,
     pub calc_setting : CalcSetting,
     pub energy_range : Option<(f64,f64)>,
+    pub grid_info : GridInfo,
 }
 impl Grids{
     pub fn to_iter(&self) -> [&Vec<Grid>;2]{
@@ -37,6 +38,10 @@ impl Grids{
             _ => panic!("index should be 0 or 1"),
         }
     }
+    /// このGridsが覆う領域のBZ全体に対する面積比
+    pub fn area_fraction(&self) -> f64{
+        self.grid_info.dxy.x * self.grid_info.dxy.y
+    }
     pub fn energy_range(&self) -> (f64,f64){
         match self.energy_range{
             Some(r) => r,
@@ -88,7 +93,8 @@ pub struct BandInfo{
     pub eigen : f64,
     pub eigen_vector : EigenVectorEnum,
     pub berry : Option<f64>,
-
+    //縮退した多重項に属する場合はその多重項（berryは多重項のトレースを等分した値）
+    pub multiplet : Option<Multiplet>,
 }
 
 impl BandInfo{
@@ -100,10 +106,14 @@ impl BandInfo{
             j: None,
             eigen_vector: EigenVectorEnum::None,
             berry: None,
+            multiplet: None,
         }
     }
     pub fn new(kk : Vector2<f64>, i : usize, j : usize, eigen: f64, eigen_vector: EigenVectorEnum)-> Self{
-        BandInfo { kk, i : Some(i), j : Some(j), eigen , eigen_vector, berry : None }
+        BandInfo { kk, i : Some(i), j : Some(j), eigen , eigen_vector, berry : None, multiplet : None }
+    }
+    pub fn is_degenerate(&self) -> bool{
+        self.multiplet.is_some()
     }
 }
 
@@ -127,6 +137,14 @@ impl EigenVectorEnum{
             _ => panic!("size should be 6"),
         }
     }
+    /// サイトの数によらず成分を並べたもの
+    pub fn to_vec(&self) -> Vec<Complex<f64>>{
+        match self{
+            EigenVectorEnum::EigenVector6(vec) => vec.as_slice().to_vec(),
+            EigenVectorEnum::EigenVector2(vec) => vec.as_slice().to_vec(),
+            EigenVectorEnum::None => Vec::new(),
+        }
+    }
 }
 
 impl Grids{
@@ -153,7 +171,7 @@ impl Grids{
         let grid_u: Vec<Grid> = vec![grid.clone();size];
         let grid_d: Vec<Grid> = vec![grid;size];
 
-        let mut grids = Grids { u: grid_u, d: grid_d, system, calc_setting, energy_range : grid_info.energy_range };
+        let mut grids = Grids { u: grid_u, d: grid_d, system : system.clone(), calc_setting, energy_range : grid_info.energy_range, grid_info };
 
         // セル面積を事前計算
         let cell_area = cal_cell_area(mesh_kx, mesh_ky, size);
@@ -170,6 +188,7 @@ impl Grids{
                 match seud_enum{
                     SEudEnum::SEud2(seud) => {
                         for index in 0..2{
+                            let multiplets = find_multiplets(seud.index(index).eigenvalues.as_slice(), calc_setting.threshold_berry);
                             for band_num in 0..size{                             
                                 grids.index_mut(index)[band_num].0[i][j] = {
                                     let eigen = seud.index(index).eigenvalues[band_num];
@@ -177,6 +196,8 @@ impl Grids{
                                     let mut band_info = BandInfo::new(kk, i, j, eigen, EigenVectorEnum::EigenVector2(eigen_vector));
                                     // Berry曲率を設定
                                     band_info.berry = Some(berry_curvatures[index][band_num]);
+                                    // 縮退点であれば多重項を記録
+                                    band_info.multiplet = degenerate_multiplet_of(&multiplets, band_num);
                                     band_info
                                 }
                             }
@@ -184,6 +205,7 @@ impl Grids{
                     }
                     SEudEnum::SEud6(seud) => {
                         for index in 0..2{
+                            let multiplets = find_multiplets(seud.index(index).eigenvalues.as_slice(), calc_setting.threshold_berry);
                             for band_num in 0..size{                             
                                 grids.index_mut(index)[band_num].0[i][j] = {
                                     let eigen = seud.index(index).eigenvalues[band_num];
@@ -191,6 +213,8 @@ impl Grids{
                                     let mut band_info = BandInfo::new(kk, i, j, eigen, EigenVectorEnum::EigenVector6(eigen_vector));
                                     // Berry曲率を設定
                                     band_info.berry = Some(berry_curvatures[index][band_num]);
+                                    // 縮退点であれば多重項を記録
+                                    band_info.multiplet = degenerate_multiplet_of(&multiplets, band_num);
                                     band_info
                                 }
                             }
@@ -0,0 +1,247 @@
+use crate::consts::*;
+use crate::honeycomb::{
+    honeycomb_grids::Grids,
+    qpi::{site_positions, Impurity, Spectrum},
+    spectral::Broadening,
+    util::{i_j_to_kk, GridInfo},
+};
+use crate::system::model::System;
+
+use nalgebra::{Complex, Vector2};
+use rayon::prelude::*;
+use std::io::Write;
+
+//----------------------------------------------------------------
+// 実空間の Green 関数と点不純物の T 行列
+//
+// Grids の mesh_kx × mesh_ky 点の固有値と固有ベクトルから
+//   G_σ(R + r_i, R' + r_j; z) = (1/N) Σ_k e^{i k・(R + r_i - R' - r_j)} Σ_n ψ_n,i ψ_n,j^* / (z - ε_n)
+// を求める（R は単位胞の格子ベクトル、r_i は qpi::site_positions）。
+// 原点の単位胞のサイト i0 にある点不純物では T_σ = V_σ / (1 - V_σ G_σ(i0, i0; z)) で、
+//   ρ_σ(x, ω) = -Im [G_σ(x, x) + G_σ(x, i0) T_σ G_σ(i0, x)] / π
+// ギャップの中では G_σ(i0, i0; ω) は実数で ω について単調に減るので、
+// 束縛状態 1 = V_σ G_σ(i0, i0; ω) はスピンごとに高々一つで、二分法で求める。
+// 有限のメッシュでは端の準位で G が発散するので、ギャップの端に張り付いた解はメッシュを細かくして確かめる。
+//----------------------------------------------------------------
+
+/// 単位胞の格子ベクトル（2サイトは A3, A1、6サイトは 3 D1, 3 D2）
+pub fn cell_lattice(size : usize) -> [Vector2<f64>; 2]{
+    match size {
+        2 => [A3, A1],
+        6 => [D1 * 3.0, D2 * 3.0],
+        _ => panic!("system size should be 2 or 6"),
+    }
+}
+
+pub struct RealSpaceGreen{
+    pub system : System,
+    spectrum : Spectrum,
+    kks : Vec<Vector2<f64>>,
+    positions : Vec<Vector2<f64>>,
+    lattice : [Vector2<f64>; 2],
+}
+
+impl RealSpaceGreen{
+    pub fn new(grids : &Grids) -> Self{
+        let system = grids.system.clone();
+        let (mesh_kx, mesh_ky) = grids.calc_setting.meshes();
+        let kks = (0..mesh_kx * mesh_ky)
+            .map(|index| i_j_to_kk(index / mesh_ky, index % mesh_ky, mesh_kx, mesh_ky, false, system.size(), GridInfo::no_divide()))
+            .collect();
+
+        RealSpaceGreen {
+            spectrum : Spectrum::from_grids(grids),
+            kks,
+            positions : site_positions(system.size()),
+            lattice : cell_lattice(system.size()),
+            system,
+        }
+    }
+    /// 温度 0 で電子数 n になる化学ポテンシャル
+    pub fn chemical_potential(&self, n : f64) -> f64{
+        self.spectrum.thermo().chemical_potential(n, 0.0)
+    }
+    /// 単位胞 cell = (m1, m2) のサイト site の位置
+    pub fn position(&self, cell : [i64; 2], site : usize) -> Vector2<f64>{
+        self.lattice[0] * cell[0] as f64 + self.lattice[1] * cell[1] as f64 + self.positions[site]
+    }
+    /// G_σ(R + r_i, r_j; z)（R は単位胞 cell の格子ベクトル）
+    pub fn element(&self, spin : usize, cell : [i64; 2], i : usize, j : usize, z : Complex<f64>) -> Complex<f64>{
+        let d = self.position(cell, i) - self.positions[j];
+        let sum: Complex<f64> = self.kks.iter().zip(self.spectrum.levels[spin].iter())
+            .map(|(kk, levels)| {
+                let g: Complex<f64> = levels.iter().map(|(energy, vector)| vector[i] * vector[j].conj() / (z - energy)).sum();
+                g * Complex::new(0.0, kk.dot(&d)).exp()
+            })
+            .sum();
+        sum / self.kks.len() as f64
+    }
+    /// 局所 Green 関数 G_σ(r_i, r_i; z)
+    pub fn local(&self, spin : usize, i : usize, z : Complex<f64>) -> Complex<f64>{
+        let sum: Complex<f64> = self.spectrum.levels[spin].iter()
+            .flat_map(|levels| levels.iter().map(|(energy, vector)| vector[i].norm_sqr() / (z - energy)))
+            .sum();
+        sum / self.kks.len() as f64
+    }
+    /// μ の下の最も高い準位と上の最も低い準位
+    pub fn gap(&self, spin : usize, mu : f64) -> (f64, f64){
+        let energies = || self.spectrum.levels[spin].iter().flat_map(|levels| levels.iter().map(|(energy, _)| *energy));
+        let top = energies().filter(|&energy| energy <= mu).fold(f64::NEG_INFINITY, f64::max);
+        let bottom = energies().filter(|&energy| energy > mu).fold(f64::INFINITY, f64::min);
+        (top, bottom)
+    }
+    /// 点不純物の T 行列 [spin]
+    pub fn t_matrix(&self, impurity : Impurity, z : Complex<f64>) -> [Complex<f64>; 2]{
+        [0, 1].map(|spin| {
+            let v = Complex::new(impurity.potential(spin), 0.0);
+            if impurity.born { v } else { v / (ONE - v * self.local(spin, impurity.site, z)) }
+        })
+    }
+}
+
+//----------------------------------------------------------------
+// 不純物のまわりの局所状態密度
+//----------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy)]
+pub struct LdosPoint{
+    pub cell : [i64; 2],
+    pub site : usize,
+    pub position : Vector2<f64>,    // 不純物から測った位置
+    pub ldos : [f64; 2],            // [spin] 不純物がある系の ρ_σ(x, ω)
+    pub delta : [f64; 2],           // [spin] 不純物による変化 δρ_σ(x, ω)
+}
+
+pub struct ImpurityLdos{
+    pub n : f64,
+    pub mu : f64,
+    pub omega : f64,                // μ から測ったエネルギー
+    pub t_matrix : [Complex<f64>; 2],
+    pub points : Vec<LdosPoint>,
+}
+
+impl ImpurityLdos{
+    /// 不純物から距離 radius までのサイトの局所状態密度
+    pub fn build(green : &RealSpaceGreen, n : f64, mu : f64, omega : f64, impurity : Impurity, broadening : Broadening, radius : f64) -> Self{
+        let size = green.system.size();
+        let i0 = impurity.site;
+        let energy = omega + mu;
+        let z = Complex::new(energy, broadening.eta(energy, mu));
+        let t_matrix = green.t_matrix(impurity, z);
+        let local: [Vec<Complex<f64>>; 2] = [0, 1].map(|spin| (0..size).map(|i| green.local(spin, i, z)).collect());
+
+        //radius の中のサイトを含む単位胞の範囲
+        let origin = green.positions[i0];
+        let reach = (radius / green.lattice[0].norm().min(green.lattice[1].norm())).ceil() as i64 + 2;
+        let sites: Vec<([i64; 2], usize)> = (-reach..=reach)
+            .flat_map(|m1| (-reach..=reach).flat_map(move |m2| (0..size).map(move |i| ([m1, m2], i))))
+            .filter(|&(cell, i)| (green.position(cell, i) - origin).norm() <= radius + 1e-9)
+            .collect();
+
+        let points = sites.par_iter()
+            .map(|&(cell, i)| {
+                let position = green.position(cell, i) - origin;
+                let delta = [0, 1].map(|spin| {
+                    let to = green.element(spin, cell, i, i0, z);
+                    let from = green.element(spin, [-cell[0], -cell[1]], i0, i, z);
+                    -(to * t_matrix[spin] * from).im / PI
+                });
+                let ldos = [0, 1].map(|spin| -local[spin][i].im / PI + delta[spin]);
+                LdosPoint { cell, site : i, position, ldos, delta }
+            })
+            .collect();
+
+        ImpurityLdos { n, mu, omega, t_matrix, points }
+    }
+    pub fn write_to_dat(&self, file_path : &str) -> std::io::Result<()>{
+        let mut file = std::fs::File::create(file_path)?;
+
+        writeln!(
+            file, "# n={},mu={},omega={},t_up={}{:+}i,t_down={}{:+}i",
+            self.n, self.mu, self.omega, self.t_matrix[0].re, self.t_matrix[0].im, self.t_matrix[1].re, self.t_matrix[1].im
+        )?;
+        writeln!(file, "# m1,m2,site,x,y,distance,ldos_up,ldos_down,ldos_sz,delta_up,delta_down")?;
+        for point in &self.points {
+            let [up, down] = point.ldos;
+            writeln!(
+                file, "{},{},{},{},{},{},{},{},{},{},{}",
+                point.cell[0], point.cell[1], point.site, point.position.x, point.position.y, point.position.norm(),
+                up, down, up - down, point.delta[0], point.delta[1]
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+//----------------------------------------------------------------
+// ギャップの中の束縛状態
+//----------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy)]
+pub struct BoundState{
+    pub potential : f64,
+    pub energy : [Option<f64>; 2],  // [spin] μ から測った束縛状態のエネルギー（なければ None）
+}
+
+pub struct BoundStateScan{
+    pub n : f64,
+    pub mu : f64,
+    pub gap : [(f64, f64); 2],      // [spin] μ から測ったギャップの端
+    pub states : Vec<BoundState>,
+}
+
+impl BoundStateScan{
+    /// サイト site の不純物の強さ potentials ごとの束縛状態（ギャップが min_gap より小さいスピンは探さない）
+    pub fn build(green : &RealSpaceGreen, n : f64, site : usize, magnetic : bool, potentials : &[f64], min_gap : f64) -> Self{
+        let mu = green.chemical_potential(n);
+        let gap = [0, 1].map(|spin| green.gap(spin, mu));
+        let local = |spin : usize, omega : f64| green.local(spin, site, Complex::new(omega, 0.0)).re;
+
+        let states = potentials.par_iter()
+            .map(|&potential| {
+                let impurity = Impurity { site, potential, magnetic, born : false };
+                let energy = [0, 1].map(|spin| {
+                    let (top, bottom) = gap[spin];
+                    let v = impurity.potential(spin);
+                    if bottom - top < min_gap || v == 0.0 {
+                        return None;
+                    }
+                    //f(ω) = 1 - V G(ω) はギャップの中で単調
+                    let f = |omega : f64| 1.0 - v * local(spin, omega);
+                    let margin = (bottom - top) * 1e-9;
+                    let (mut low, mut high) = (top + margin, bottom - margin);
+                    let (f_low, f_high) = (f(low), f(high));
+                    if f_low * f_high > 0.0 {
+                        return None;
+                    }
+                    for _ in 0..100 {
+                        let middle = 0.5 * (low + high);
+                        if f(middle) * f_low > 0.0 { low = middle } else { high = middle }
+                    }
+                    Some(0.5 * (low + high) - mu)
+                });
+                BoundState { potential, energy }
+            })
+            .collect();
+
+        let gap = gap.map(|(top, bottom)| (top - mu, bottom - mu));
+        BoundStateScan { n, mu, gap, states }
+    }
+    /// 束縛状態がないところは nan
+    pub fn write_to_dat(&self, file_path : &str) -> std::io::Result<()>{
+        let mut file = std::fs::File::create(file_path)?;
+
+        writeln!(
+            file, "# n={},mu={},gap_up={},{},gap_down={},{}",
+            self.n, self.mu, self.gap[0].0, self.gap[0].1, self.gap[1].0, self.gap[1].1
+        )?;
+        writeln!(file, "# potential,bound_up,bound_down")?;
+        for state in &self.states {
+            let [up, down] = state.energy.map(|energy| energy.unwrap_or(f64::NAN));
+            writeln!(file, "{},{},{}", state.potential, up, down)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,306 @@
+use crate::consts::*;
+use crate::honeycomb::tanzaku::Tanzakus;
+use crate::system::disorder::{DisorderedLattice, DisorderSetting};
+use crate::system::model::System;
+use crate::system::random::SplitMix64;
+use crate::system::sparse::SparseMatrix;
+
+use nalgebra::{Complex, DMatrix};
+use rayon::prelude::*;
+use std::io::Write;
+
+//----------------------------------------------------------------
+// カーネル多項式法（KPM）による状態密度と Kubo-Bastin の Hall 伝導度
+//
+// H を Gershgorin の範囲で H~ = (H - b) / a（|H~| < 1）に縮め、ランダムな位相のベクトル |r⟩ で
+//   μ_n = Tr T_n(H~) / N,   μ_nm = Tr[v_x T_m(H~) v_y T_n(H~)]
+// を見積もる（Jackson のカーネル g_n を掛ける）。温度 0 の Hall 伝導度は
+//   σ_xy(μ) = -(8 / (Ω a²)) ∫_{-1}^{μ~} dε Σ_nm Γ_nm(ε) g_n g_m μ_nm / ((1 + δ_n0)(1 + δ_m0)(1 - ε²)²)  [e²/h]
+//   Γ_nm(ε) = (ε - i n √(1-ε²)) e^{i n θ} T_m(ε) + (ε + i m √(1-ε²)) e^{-i m θ} T_n(ε),  ε = cos θ
+// （Garcia, Covaci, Rappoport, PRL 114, 116602 (2015)）。Ω は試料の面積。
+// μ_nm はベクトル M 本を block 本ずつ持って計算するので、メモリは block × N で済む。
+// 全体の符号は電子の電荷 -e の分で、chern::chern_numbers、Tanzaku の berry / 2π と同じ
+// （ギャップの中ではスピンごとの Chern 数になる）。
+//----------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy)]
+pub struct KpmSetting{
+    pub moments : usize,            // Chebyshev の次数 M
+    pub vectors : usize,            // ランダムなベクトルの数
+    pub block : usize,              // 同時に持つ T_n |r⟩ の数
+    pub energy_points : usize,      // Kubo-Bastin の積分の点の数
+}
+
+//H~ = (H - center) / half_width
+struct Rescaled<'a>{
+    matrix : &'a SparseMatrix,
+    center : f64,
+    half_width : f64,
+}
+
+impl<'a> Rescaled<'a>{
+    fn new(matrix : &'a SparseMatrix) -> Self{
+        let (min, max) = matrix.gershgorin_bounds();
+        Rescaled { matrix, center : 0.5 * (max + min), half_width : 0.5 * (max - min) * 1.01 + 1e-9 }
+    }
+    fn apply(&self, x : &[Complex<f64>]) -> Vec<Complex<f64>>{
+        self.matrix.mul_vec(x).iter().zip(x).map(|(hx, x)| (hx - x * self.center) / self.half_width).collect()
+    }
+}
+
+//T_n(H~) |x⟩ を n = 0, 1, 2, ... の順に作る
+struct Chebyshev<'a>{
+    rescaled : &'a Rescaled<'a>,
+    previous : Vec<Complex<f64>>,
+    current : Vec<Complex<f64>>,
+    order : usize,
+}
+
+impl<'a> Chebyshev<'a>{
+    fn new(rescaled : &'a Rescaled<'a>, start : Vec<Complex<f64>>) -> Self{
+        Chebyshev { rescaled, previous : Vec::new(), current : start, order : 0 }
+    }
+    fn advance(&mut self){
+        let applied = self.rescaled.apply(&self.current);
+        let next = if self.order == 0 {
+            applied
+        } else {
+            applied.iter().zip(&self.previous).map(|(hx, previous)| 2.0 * hx - previous).collect()
+        };
+        self.previous = std::mem::replace(&mut self.current, next);
+        self.order += 1;
+    }
+}
+
+fn dotc(a : &[Complex<f64>], b : &[Complex<f64>]) -> Complex<f64>{
+    a.iter().zip(b).map(|(a, b)| a.conj() * b).sum()
+}
+
+/// Jackson のカーネル g_n（次数 moments）
+pub fn jackson(n : usize, moments : usize) -> f64{
+    let m = moments as f64 + 1.0;
+    let n = n as f64;
+    ((m - n) * (PI * n / m).cos() + (PI * n / m).sin() / (PI / m).tan()) / m
+}
+
+/// 一つのスピンのハミルトニアンの Chebyshev モーメント
+pub struct KpmMoments{
+    pub dim : usize,
+    pub center : f64,
+    pub half_width : f64,
+    pub dos : Vec<f64>,                 // μ_n（1サイトあたり、カーネルなし）
+    pub hall : DMatrix<Complex<f64>>,   // (n, m) Tr[v_x T_m v_y T_n]（カーネルなし）
+}
+
+impl KpmMoments{
+    pub fn build(hamiltonian : &SparseMatrix, velocities : &[SparseMatrix; 2], setting : KpmSetting, seed : u64) -> Self{
+        let rescaled = Rescaled::new(hamiltonian);
+        let (dim, moments) = (hamiltonian.dim, setting.moments);
+        let block = setting.block.max(1);
+        let mut rng = SplitMix64::new(seed);
+        let mut dos = vec![0.0; moments];
+        let mut hall = DMatrix::from_element(moments, moments, ZERO);
+
+        for _ in 0..setting.vectors {
+            let r: Vec<Complex<f64>> = (0..dim).map(|_| Complex::new(0.0, 2. * PI * rng.uniform()).exp()).collect();
+            let vx_r = velocities[0].mul_vec(&r);
+            //T_n |r⟩ はブロックをまたいで続ける
+            let mut right_chain = Chebyshev::new(&rescaled, r.clone());
+
+            for start in (0..moments).step_by(block) {
+                let end = (start + block).min(moments);
+                //v_y T_n |r⟩（n = start..end）
+                let mut right = Vec::with_capacity(end - start);
+                for mu in dos[start..end].iter_mut() {
+                    *mu += dotc(&r, &right_chain.current).re;
+                    right.push(velocities[1].mul_vec(&right_chain.current));
+                    right_chain.advance();
+                }
+                //⟨r| v_x T_m = (T_m v_x |r⟩)†
+                let mut chain = Chebyshev::new(&rescaled, vx_r.clone());
+                for m in 0..moments {
+                    let values: Vec<Complex<f64>> = right.par_iter().map(|w| dotc(&chain.current, w)).collect();
+                    for (k, value) in values.into_iter().enumerate() {
+                        hall[(start + k, m)] += value;
+                    }
+                    chain.advance();
+                }
+            }
+        }
+
+        let vectors = setting.vectors.max(1) as f64;
+        KpmMoments {
+            dim,
+            center : rescaled.center,
+            half_width : rescaled.half_width,
+            dos : dos.into_iter().map(|mu| mu / (vectors * dim as f64)).collect(),
+            hall : hall / Complex::new(vectors, 0.0),
+        }
+    }
+    fn rescale(&self, energy : f64) -> f64{
+        (energy - self.center) / self.half_width
+    }
+    fn kernel(&self, n : usize) -> f64{
+        jackson(n, self.dos.len())
+    }
+    /// 1サイトあたりの状態密度
+    pub fn density_of_states(&self, energy : f64) -> f64{
+        let x = self.rescale(energy);
+        if x.abs() >= 1.0 {
+            return 0.0;
+        }
+        let theta = x.acos();
+        let sum: f64 = self.dos.iter().enumerate()
+            .map(|(n, mu)| if n == 0 { 1.0 } else { 2.0 } * self.kernel(n) * mu * (n as f64 * theta).cos())
+            .sum();
+        sum / (PI * self.half_width * (1. - x * x).sqrt())
+    }
+    /// energy より下の1サイトあたりの状態の数（状態密度を解析的に積分したもの）
+    pub fn filling(&self, energy : f64) -> f64{
+        let theta = self.rescale(energy).clamp(-1.0, 1.0).acos();
+        self.dos.iter().enumerate()
+            .map(|(n, mu)| match n {
+                0 => self.kernel(0) * mu * (1. - theta / PI),
+                _ => -2. * self.kernel(n) * mu * (n as f64 * theta).sin() / (n as f64 * PI),
+            })
+            .sum()
+    }
+    /// 面積 area の試料の温度 0 の σ_xy [e²/h]（energies は昇順でなくてよい）
+    pub fn hall_conductivity(&self, area : f64, energies : &[f64], energy_points : usize) -> Vec<f64>{
+        let moments = self.dos.len();
+        let weighted = DMatrix::from_fn(moments, moments, |n, m| {
+            let delta = |k : usize| if k == 0 { 2.0 } else { 1.0 };
+            self.hall[(n, m)] * (self.kernel(n) * self.kernel(m) / (delta(n) * delta(m)))
+        });
+
+        //ε_k = -cos(π (k + 1/2) / K) で積分する（両端に点が集まる）
+        let points = energy_points.max(2);
+        let grid: Vec<f64> = (0..points).map(|k| -(PI * (k as f64 + 0.5) / points as f64).cos()).collect();
+        let integrand: Vec<f64> = grid.par_iter()
+            .map(|&x| {
+                let theta = x.acos();
+                let s = (1. - x * x).sqrt();
+                let chebyshev: Vec<f64> = (0..moments).map(|n| (n as f64 * theta).cos()).collect();
+                let rows = &weighted * DMatrix::from_fn(moments, 1, |m, _| Complex::new(chebyshev[m], 0.0));
+                let columns = weighted.transpose() * DMatrix::from_fn(moments, 1, |n, _| Complex::new(chebyshev[n], 0.0));
+                let gamma: Complex<f64> = (0..moments)
+                    .map(|k| {
+                        let k_f = k as f64;
+                        let a = Complex::new(x, -k_f * s) * Complex::new(0.0, k_f * theta).exp();
+                        let b = Complex::new(x, k_f * s) * Complex::new(0.0, -k_f * theta).exp();
+                        a * rows[k] + b * columns[k]
+                    })
+                    .sum();
+                gamma.re / (1. - x * x).powi(2)
+            })
+            .collect();
+
+        let mut cumulative = vec![0.0; points];
+        for k in 1..points {
+            cumulative[k] = cumulative[k - 1] + 0.5 * (integrand[k] + integrand[k - 1]) * (grid[k] - grid[k - 1]);
+        }
+        let prefactor = -8. / (area * self.half_width * self.half_width);
+
+        energies.iter()
+            .map(|&energy| {
+                let x = self.rescale(energy);
+                let k = grid.partition_point(|&e| e <= x);
+                let value = match k {
+                    0 => 0.0,
+                    k if k == points => cumulative[points - 1],
+                    k => cumulative[k - 1] + (cumulative[k] - cumulative[k - 1]) * (x - grid[k - 1]) / (grid[k] - grid[k - 1]),
+                };
+                prefactor * value
+            })
+            .collect()
+    }
+}
+
+//----------------------------------------------------------------
+// 乱れの配置についての平均
+//----------------------------------------------------------------
+
+/// 乱れの配置ひとつの結果 [spin][energy]
+#[derive(Debug, Clone)]
+pub struct KpmRealization{
+    pub setting : DisorderSetting,
+    pub flipped : usize,
+    pub dos : [Vec<f64>; 2],
+    pub filling : [Vec<f64>; 2],
+    pub hall : [Vec<f64>; 2],
+}
+
+impl KpmRealization{
+    pub fn build(system : &System, cells : [usize; 2], setting : DisorderSetting, kpm : KpmSetting, energies : &[f64]) -> Result<Self, String>{
+        let lattice = DisorderedLattice::new(system, cells, setting)?;
+        let area = lattice.area();
+        let curves = [0, 1].map(|spin| {
+            let moments = KpmMoments::build(&lattice.hamiltonian(spin), &lattice.velocities(spin), kpm, setting.seed.wrapping_add(spin as u64 + 1));
+            let dos: Vec<f64> = energies.iter().map(|&energy| moments.density_of_states(energy)).collect();
+            let filling: Vec<f64> = energies.iter().map(|&energy| moments.filling(energy)).collect();
+            (dos, filling, moments.hall_conductivity(area, energies, kpm.energy_points))
+        });
+        let [(dos_u, filling_u, hall_u), (dos_d, filling_d, hall_d)] = curves;
+
+        Ok(KpmRealization { setting, flipped : lattice.flipped, dos : [dos_u, dos_d], filling : [filling_u, filling_d], hall : [hall_u, hall_d] })
+    }
+}
+
+pub struct DisorderAverage{
+    pub name : String,
+    pub cells : [usize; 2],
+    pub kpm : KpmSetting,
+    pub energies : Vec<f64>,
+    pub realizations : Vec<KpmRealization>,
+}
+
+impl DisorderAverage{
+    /// 乱れの配置ごとに並列に計算する
+    pub fn build(system : &System, cells : [usize; 2], settings : &[DisorderSetting], kpm : KpmSetting, energies : &[f64]) -> Result<Self, String>{
+        let realizations = settings.par_iter()
+            .map(|&setting| KpmRealization::build(system, cells, setting, kpm, energies))
+            .collect::<Result<Vec<_>, String>>()?;
+
+        Ok(DisorderAverage { name : system.debug(), cells, kpm, energies : energies.to_vec(), realizations })
+    }
+    //[spin] の値の配置についての平均と標準偏差
+    fn statistics(&self, value : impl Fn(&KpmRealization) -> f64) -> (f64, f64){
+        let count = self.realizations.len().max(1) as f64;
+        let mean = self.realizations.iter().map(&value).sum::<f64>() / count;
+        let variance = self.realizations.iter().map(|realization| (value(realization) - mean).powi(2)).sum::<f64>() / count;
+        (mean, variance.sqrt())
+    }
+    /// clean があればきれいな系の Tanzakus の n と berry / 2π を並べる
+    pub fn write_to_dat(&self, clean : Option<&Tanzakus>, file_path : &str) -> std::io::Result<()>{
+        let mut file = std::fs::File::create(file_path)?;
+
+        let setting = self.realizations.first().map(|realization| realization.setting).unwrap_or(DisorderSetting::clean());
+        writeln!(
+            file, "# cells={}x{},sites={},anderson={},flip={},realizations={},moments={},vectors={}",
+            self.cells[0], self.cells[1], 2 * self.cells[0] * self.cells[1], setting.anderson, setting.flip,
+            self.realizations.len(), self.kpm.moments, self.kpm.vectors
+        )?;
+        writeln!(file, "# energy,dos_up,dos_down,n,sigma_up,sigma_down,sigma_xy,sigma_xy_std,sigma_spin,clean_n,clean_sigma_xy")?;
+        for (index, &energy) in self.energies.iter().enumerate() {
+            let (dos_up, _) = self.statistics(|realization| realization.dos[0][index]);
+            let (dos_down, _) = self.statistics(|realization| realization.dos[1][index]);
+            let (n, _) = self.statistics(|realization| realization.filling[0][index] + realization.filling[1][index]);
+            let (up, _) = self.statistics(|realization| realization.hall[0][index]);
+            let (down, _) = self.statistics(|realization| realization.hall[1][index]);
+            let (total, std) = self.statistics(|realization| realization.hall[0][index] + realization.hall[1][index]);
+            let (clean_n, clean_sigma) = match clean {
+                Some(tanzakus) => {
+                    let tanzaku = tanzakus.linear_interpolate_at_energy(energy);
+                    (tanzaku.n, tanzaku.berry / (2. * PI))
+                }
+                None => (f64::NAN, f64::NAN),
+            };
+            writeln!(
+                file, "{},{},{},{},{},{},{},{},{},{},{}",
+                energy, dos_up, dos_down, n, up, down, total, std, 0.5 * (up - down), clean_n, clean_sigma
+            )?;
+        }
+
+        Ok(())
+    }
+}
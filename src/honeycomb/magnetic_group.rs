@@ -0,0 +1,202 @@
+use crate::honeycomb::{
+    setting::CalcSetting,
+    symmetry::{point_op_name, SymmetryGroup, SymmetryOp},
+    tanzaku::{Tanzaku, Tanzakus},
+};
+use crate::system::model::System;
+
+use nalgebra::{DMatrix, DVector, Matrix2};
+
+const TOLERANCE : f64 = 1e-9;
+
+//----------------------------------------------------------------
+// 磁気点群の元
+// k空間での操作 M と Berry曲率の符号 s から、
+// s = det M なら通常の操作 R = M、s = -det M なら時間反転と組み合わせた操作 R' (R = -M)
+//----------------------------------------------------------------
+#[derive(Debug, Clone, Copy)]
+pub struct MagneticOp{
+    pub op : SymmetryOp,
+    pub antiunitary : bool,
+    pub real_rotation : Matrix2<f64>,   // 実空間での点群操作
+}
+
+impl MagneticOp{
+    pub fn from_symmetry_op(op : SymmetryOp) -> Self{
+        let antiunitary = op.berry_sign * op.det() < 0.0;
+        let real_rotation = if antiunitary { -op.rotation } else { op.rotation };
+
+        MagneticOp { op, antiunitary, real_rotation }
+    }
+    //時間反転を含む操作にはプライムを付ける
+    pub fn label(&self) -> String{
+        let name = point_op_name(&self.real_rotation);
+        if self.antiunitary {
+            format!("{}'", name)
+        } else {
+            name.to_string()
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MagneticPointGroup{
+    pub ops : Vec<MagneticOp>,
+}
+
+impl MagneticPointGroup{
+    pub fn from_system(system : &System, calc_setting : &CalcSetting) -> Self{
+        Self::from_symmetry_group(&SymmetryGroup::detect(system, calc_setting))
+    }
+    pub fn from_symmetry_group(group : &SymmetryGroup) -> Self{
+        MagneticPointGroup { ops : group.ops.iter().map(|op| MagneticOp::from_symmetry_op(*op)).collect() }
+    }
+    pub fn order(&self) -> usize{
+        self.ops.len()
+    }
+    pub fn labels(&self) -> Vec<String>{
+        self.ops.iter().map(|op| op.label()).collect()
+    }
+    /// 時間反転を含まない元のなす部分群の位数
+    pub fn unitary_order(&self) -> usize{
+        self.ops.iter().filter(|op| !op.antiunitary).count()
+    }
+    /// 磁気点群の型
+    /// I: 時間反転を含む元がない, II: 時間反転そのもの(E')を含む（灰色群）, III: それ以外
+    pub fn group_type(&self) -> usize{
+        let has_pure_time_reversal = self.ops.iter()
+            .any(|op| op.antiunitary && (op.real_rotation - Matrix2::identity()).norm() < TOLERANCE);
+
+        if self.unitary_order() == self.order() {
+            1
+        } else if has_pure_time_reversal {
+            2
+        } else {
+            3
+        }
+    }
+
+    //----------------------------------------------------------------
+    // 対称性から許される応答テンソルの成分
+    //----------------------------------------------------------------
+
+    /// σ_xy（Berry曲率の積分）は擬スカラーで、Ω → s Ω と変換される
+    fn sigma_xy_projector(&self) -> f64{
+        self.ops.iter().map(|op| op.op.berry_sign).sum::<f64>() / self.order() as f64
+    }
+    /// BCD (D_a ∝ ∫ v_a Ω) は D → s M D と変換されるベクトル
+    fn bcd_projector(&self) -> Matrix2<f64>{
+        self.ops.iter().map(|op| op.op.rotation * op.op.berry_sign).sum::<Matrix2<f64>>() / self.order() as f64
+    }
+    /// QMD・BCP双極子は速度と対称テンソルの積 T_abc ∝ ∫ v_a G_bc で、
+    /// Berry曲率を含まないので T → M⊗M⊗M T と変換される（成分の番号は 4a + 2b + c）
+    fn rank3_projector(&self) -> DMatrix<f64>{
+        let mut projector = DMatrix::zeros(8, 8);
+        for op in &self.ops {
+            let m = DMatrix::from_column_slice(2, 2, op.op.rotation.as_slice());
+            projector += m.kronecker(&m).kronecker(&m);
+        }
+        projector / self.order() as f64
+    }
+
+    pub fn allowed_responses(&self) -> AllowedResponses{
+        let sigma_xy = self.sigma_xy_projector().abs() > TOLERANCE;
+
+        let bcd_projector = self.bcd_projector();
+        let bcd = [0, 1].map(|a| bcd_projector.column(a).norm() > TOLERANCE);
+
+        let rank3 = self.rank3_projector();
+        let allowed = |functional : DVector<f64>| (&rank3 * functional).norm() > TOLERANCE;
+        let e = |a : usize, b : usize, c : usize| {
+            let mut v = DVector::zeros(8);
+            v[4 * a + 2 * b + c] = 1.0;
+            v
+        };
+        //T_abc の bc についての対称成分
+        let sym = |a : usize, b : usize, c : usize| (e(a, b, c) + e(a, c, b)) * 0.5;
+
+        //Tanzakus と同じ定義 qmd_x = v_y g_xx - v_x g_xy, qmd_y = v_x g_yy - v_y g_xy
+        let qmd = [
+            allowed(sym(1, 0, 0) - sym(0, 0, 1)),
+            allowed(sym(0, 1, 1) - sym(1, 0, 1)),
+        ];
+
+        let bcp_dipole = BCP_COMPONENTS.map(|(name, (a, b, c))| (name, allowed(sym(a, b, c))));
+
+        AllowedResponses { sigma_xy, bcd, qmd, bcp_dipole }
+    }
+}
+
+//BCP双極子 ∫ v_a G_bc の独立な成分
+const BCP_COMPONENTS : [(&str, (usize, usize, usize)); 6] = [
+    ("xxx", (0, 0, 0)), ("xxy", (0, 0, 1)), ("xyy", (0, 1, 1)),
+    ("yxx", (1, 0, 0)), ("yxy", (1, 0, 1)), ("yyy", (1, 1, 1)),
+];
+
+#[derive(Debug, Clone, Copy)]
+pub struct AllowedResponses{
+    pub sigma_xy : bool,
+    pub bcd : [bool; 2],
+    pub qmd : [bool; 2],
+    pub bcp_dipole : [(&'static str, bool); 6],
+}
+
+impl AllowedResponses{
+    pub fn summary(&self) -> String{
+        let mark = |allowed : bool| if allowed { "allowed" } else { "forbidden" };
+        let bcp: Vec<String> = self.bcp_dipole.iter().map(|(name, allowed)| format!("{}:{}", name, mark(*allowed))).collect();
+
+        format!(
+            "sigma_xy:{} bcd_x:{} bcd_y:{} qmd_x:{} qmd_y:{} bcp_dipole[{}]",
+            mark(self.sigma_xy), mark(self.bcd[0]), mark(self.bcd[1]),
+            mark(self.qmd[0]), mark(self.qmd[1]), bcp.join(" ")
+        )
+    }
+}
+
+//----------------------------------------------------------------
+// 計算結果の検証
+//----------------------------------------------------------------
+
+/// 対称性で禁止された成分が有限の値を持っていた場合の記録
+#[derive(Debug, Clone, Copy)]
+pub struct SymmetryViolation{
+    pub quantity : &'static str,
+    pub n : f64,
+    pub energy : f64,
+    pub value : f64,
+}
+
+//Tanzaku から成分を取り出す関数
+type Extractor = fn(&Tanzaku) -> f64;
+
+/// Tanzakus の各成分が対称性の予測と矛盾しないかを確かめる
+///
+/// 禁止された成分の絶対値が `tolerance` を超えたものを、成分ごとに最大のもの一つずつ返す。
+/// 数値的な問題（メッシュが粗い、縮退の扱いなど）を示唆する。
+pub fn check_tanzakus(tanzakus : &Tanzakus, allowed : &AllowedResponses, tolerance : f64) -> Vec<SymmetryViolation>{
+    let components : [(&str, bool, Extractor); 5] = [
+        ("sigma_xy", allowed.sigma_xy, |tanzaku| tanzaku.berry),
+        ("bcd_x", allowed.bcd[0], |tanzaku| tanzaku.bcd.x),
+        ("bcd_y", allowed.bcd[1], |tanzaku| tanzaku.bcd.y),
+        ("qmd_x", allowed.qmd[0], |tanzaku| tanzaku.qmd.x),
+        ("qmd_y", allowed.qmd[1], |tanzaku| tanzaku.qmd.y),
+    ];
+
+    let mut violations = Vec::new();
+
+    for (quantity, is_allowed, extract) in components {
+        if is_allowed {
+            continue;
+        }
+
+        let worst = tanzakus.data.iter()
+            .map(|tanzaku| SymmetryViolation { quantity, n : tanzaku.n, energy : tanzaku.energy, value : extract(tanzaku) })
+            .filter(|violation| violation.value.abs() > tolerance)
+            .max_by(|a, b| a.value.abs().total_cmp(&b.value.abs()));
+
+        violations.extend(worst);
+    }
+
+    violations
+}
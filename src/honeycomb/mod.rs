@@ -6,4 +6,19 @@ pub mod height_map;
 pub mod cal_berry;
 pub mod tanzaku;
 pub mod compare;
-pub mod parallelization;
\ No newline at end of file
+pub mod parallelization;
+pub mod effective_mass;
+pub mod gap_finder;
+pub mod adaptive;
+pub mod symmetry;
+pub mod magnetic_group;
+pub mod band_path;
+pub mod phase_diagram;
+pub mod thermodynamics;
+pub mod unfolding;
+pub mod spectral;
+pub mod qpi;
+pub mod impurity;
+pub mod chern;
+pub mod kpm;
+pub mod hofstadter;
@@ -20,7 +20,7 @@ pub fn parallel_calculate_tanzaku(
     //エネルギーの範囲を取得するための事前処理
 
     let grid_info = GridInfo::no_divide();
-    let grids = Grids::build(calc_setting, system,grid_info);
+    let grids = Grids::build(calc_setting, system.clone(),grid_info);
     let energy_range = grids.energy_range();
 
     let final_tanzakus = (0..(main_grid * main_grid))
@@ -34,13 +34,13 @@ pub fn parallel_calculate_tanzaku(
             let grid_info = GridInfo::new_ijn(i, j, main_grid, main_grid, Some(energy_range));
 
             // ハニカム格子の構築
-            let grids = Grids::build(calc_setting, system, grid_info);
+            let grids = Grids::build(calc_setting, system.clone(), grid_info);
 
             // 全バンドの等高線データを作成
             let all_height_maps = AllHeightMaps::build(&grids);
 
             // このスレッド専用のローカルなTanzakusを作成
-            let mut partial_tanzakus = Tanzakus::new(calc_setting, system);
+            let mut partial_tanzakus = Tanzakus::new(calc_setting, system.clone());
             partial_tanzakus.write_energy_n_bc_sum_to_tanzaku(&grids);
             partial_tanzakus.write_bcd_sum_to_tanzakus(&all_height_maps);
 
@@ -51,7 +51,7 @@ pub fn parallel_calculate_tanzaku(
         // 2つのTanzakusを受け取り、1つにマージする
         .reduce(
             // 最初の要素がない場合の初期値を作成するクロージャ
-            || Tanzakus::new(calc_setting, system),
+            || Tanzakus::new(calc_setting, system.clone()),
             // 2つのTanzakus (t1, t2) をマージするクロージャ
             |mut t1, t2| {
                 t1.merge(&t2);
@@ -0,0 +1,224 @@
+use crate::honeycomb::{
+    compare::{candidate_energies, CompareOptions},
+    parallelization::parallel_calculate_tanzaku,
+    setting::CalcSetting,
+    tanzaku::{Tanzaku, Tanzakus},
+};
+use crate::system::model::{Param, System};
+
+use std::collections::HashMap;
+use std::io::Write;
+
+//全エネルギー差がこれ以下なら縮退しているとみなす（J = 0 など）
+const DEGENERACY_TOLERANCE : f64 = 1e-10;
+
+//----------------------------------------------------------------
+// 相図の縦軸（横軸は電子数 n）
+//----------------------------------------------------------------
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PhaseAxis{
+    Lambda,
+    Jj,
+}
+
+impl PhaseAxis{
+    pub fn name(&self) -> &'static str{
+        match self {
+            PhaseAxis::Lambda => "lambda",
+            PhaseAxis::Jj => "jj",
+        }
+    }
+    pub fn value(&self, param : &Param) -> f64{
+        match self {
+            PhaseAxis::Lambda => param.lambda,
+            PhaseAxis::Jj => param.jj,
+        }
+    }
+    /// param の縦軸の値だけを value に置き換える
+    pub fn with_value(&self, param : Param, value : f64) -> Param{
+        match self {
+            PhaseAxis::Lambda => Param::new(value, param.jj),
+            PhaseAxis::Jj => Param::new(param.lambda, value),
+        }
+    }
+}
+
+//----------------------------------------------------------------
+// 相図の各点
+//----------------------------------------------------------------
+#[derive(Clone, Copy)]
+pub struct PhasePoint{
+    pub value : f64,        // 縦軸の値
+    pub n : f64,
+    pub winner : usize,     // 最安定なスピン配置（candidates の添字）
+    pub runner_up : usize,  // 次に安定なスピン配置
+    pub margin : f64,       // 次に安定なスピン配置との全エネルギー差（候補が一つならinf）
+    pub total_energy : f64, // 最安定なスピン配置の全エネルギー
+    pub tanzaku : Tanzaku,  // 最安定なスピン配置でのBerry曲率, BCD, QMD
+}
+
+/// 縦軸の value と value の間で最安定なスピン配置が lower から upper に変わる
+#[derive(Debug, Clone, Copy)]
+pub struct PhaseBoundary{
+    pub n : f64,
+    pub value : f64,
+    pub lower : usize,
+    pub upper : usize,
+}
+
+//----------------------------------------------------------------
+// 相図
+//----------------------------------------------------------------
+pub struct PhaseDiagram{
+    pub candidates : Vec<System>,       // 比較するスピン配置（パラメーターは各点で置き換える）
+    pub base : Param,                   // 縦軸でない方のパラメーター
+    pub axis : PhaseAxis,
+    pub values : Vec<f64>,
+    pub options : CompareOptions,
+    pub calc_setting : CalcSetting,
+    pub points : Vec<Vec<PhasePoint>>,  // [value][n]
+}
+
+impl PhaseDiagram{
+    /// 縦軸の各値で候補のスピン配置の全エネルギーを比べ、電子数ごとに最安定な配置を求める
+    ///
+    /// 電子数は compare_candidates と同じく 0 から 2 を options.n_div 等分する。
+    /// Tanzakusはどこかの電子数で最安定となったスピン配置についてだけ計算する。候補が空ならエラー。
+    pub fn build(candidates : &[System], axis : PhaseAxis, values : &[f64], calc_setting : CalcSetting, options : CompareOptions) -> Result<Self, String>{
+        let base = *candidates.first()
+            .ok_or_else(|| "the phase diagram needs at least one candidate".to_string())?
+            .param();
+        let n_div = options.n_div;
+
+        let points = values.iter().map(|&value| {
+            let param = axis.with_value(base, value);
+            let energies = energies_at(candidates, param, options);
+            let ranks: Vec<(usize, usize, f64)> = (0..n_div).map(|i| rank(&energies, i)).collect();
+
+            let mut tanzakuss: Vec<Option<Tanzakus>> = vec![None; candidates.len()];
+            for &(winner, _, _) in &ranks {
+                if tanzakuss[winner].is_none() {
+                    let system = candidates[winner].with_param(param);
+                    tanzakuss[winner] = Some(parallel_calculate_tanzaku(calc_setting, system).interpolate_by_n(n_div));
+                }
+            }
+
+            ranks.iter().enumerate()
+                .map(|(i, &(winner, runner_up, margin))| {
+                    let tanzaku = tanzakuss[winner].as_ref().unwrap().data[i];
+                    PhasePoint {
+                        value,
+                        n : 2.0 * i as f64 / n_div as f64,
+                        winner,
+                        runner_up,
+                        margin,
+                        total_energy : energies[winner][i],
+                        tanzaku,
+                    }
+                })
+                .collect()
+        }).collect();
+
+        Ok(PhaseDiagram {
+            candidates : candidates.to_vec(),
+            base,
+            axis,
+            values : values.to_vec(),
+            options,
+            calc_setting,
+            points,
+        })
+    }
+
+    /// 隣り合う縦軸の値で最安定なスピン配置が変わる所を二分法で tolerance まで絞り込む
+    ///
+    /// 二分法では全エネルギーだけを計算する。途中の値での全エネルギーは電子数の間で使い回す。
+    /// どちらかの端で最安定な配置が縮退している場合は相境界とみなさない。
+    pub fn boundaries(&self, tolerance : f64) -> Vec<PhaseBoundary>{
+        let mut cache: HashMap<u64, Vec<Vec<f64>>> = HashMap::new();
+        let mut boundaries = Vec::new();
+
+        for (row, next_row) in self.points.iter().zip(self.points.iter().skip(1)) {
+            for i in 0..self.options.n_div {
+                let (lower, upper) = (row[i].winner, next_row[i].winner);
+                if lower == upper || row[i].margin < DEGENERACY_TOLERANCE || next_row[i].margin < DEGENERACY_TOLERANCE {
+                    continue;
+                }
+
+                let (mut lo, mut hi, mut upper) = (row[i].value, next_row[i].value, upper);
+                while (hi - lo).abs() > tolerance {
+                    let mid = 0.5 * (lo + hi);
+                    let energies = cache.entry(mid.to_bits())
+                        .or_insert_with(|| energies_at(&self.candidates, self.axis.with_value(self.base, mid), self.options));
+                    let (winner, _, _) = rank(energies, i);
+
+                    if winner == lower {
+                        lo = mid;
+                    } else {
+                        hi = mid;
+                        upper = winner;
+                    }
+                }
+
+                boundaries.push(PhaseBoundary { n : row[i].n, value : 0.5 * (lo + hi), lower, upper });
+            }
+        }
+
+        boundaries
+    }
+
+    /// 相図を一つの表として.datファイルに出力する
+    pub fn write_to_dat(&self, path : &str) -> std::io::Result<()>{
+        let mut file = std::fs::File::create(path)?;
+
+        writeln!(file, "# {},n,stable,runner_up,margin,total_energy,energy,berry,bcd_x,bcd_y,qmd_x,qmd_y", self.axis.name())?;
+        for point in self.points.iter().flatten() {
+            let tanzaku = &point.tanzaku;
+            writeln!(
+                file,
+                "{},{},{},{},{},{},{},{},{},{},{},{}",
+                point.value, point.n,
+                self.candidates[point.winner].debug_only_name(), self.candidates[point.runner_up].debug_only_name(),
+                point.margin, point.total_energy,
+                tanzaku.energy, tanzaku.berry, tanzaku.bcd.x, tanzaku.bcd.y, tanzaku.qmd.x, tanzaku.qmd.y
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// 相境界を.datファイルに出力する
+    pub fn write_boundaries_to_dat(&self, boundaries : &[PhaseBoundary], path : &str) -> std::io::Result<()>{
+        let mut file = std::fs::File::create(path)?;
+
+        writeln!(file, "# n,{},lower,upper", self.axis.name())?;
+        for boundary in boundaries {
+            writeln!(
+                file,
+                "{},{},{},{}",
+                boundary.n, boundary.value,
+                self.candidates[boundary.lower].debug_only_name(), self.candidates[boundary.upper].debug_only_name()
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+//各スピン配置の電子数ごとの全エネルギー [candidate][n]
+fn energies_at(candidates : &[System], param : Param, options : CompareOptions) -> Vec<Vec<f64>>{
+    let systems: Vec<System> = candidates.iter().map(|system| system.with_param(param)).collect();
+    candidate_energies(&systems, options)
+}
+
+//電子数 energies[_][i] で最安定な配置、次に安定な配置とその差
+fn rank(energies : &[Vec<f64>], i : usize) -> (usize, usize, f64){
+    let mut order: Vec<usize> = (0..energies.len()).collect();
+    order.sort_by(|&a, &b| energies[a][i].partial_cmp(&energies[b][i]).unwrap());
+
+    match order.as_slice() {
+        [winner, runner_up, ..] => (*winner, *runner_up, energies[*runner_up][i] - energies[*winner][i]),
+        [winner] => (*winner, *winner, f64::INFINITY),
+        [] => panic!("no candidate for the phase diagram"),
+    }
+}
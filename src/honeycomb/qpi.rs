@@ -0,0 +1,237 @@
+use crate::consts::*;
+use crate::honeycomb::{
+    honeycomb_grids::Grids,
+    setting::CalcSetting,
+    spectral::Broadening,
+    thermodynamics::ThermoSpectrum,
+    util::{i_j_to_kk, GridInfo},
+};
+use crate::system::{hamiltonian::six_site_positions, model::System};
+
+use nalgebra::{Complex, Vector2};
+use rayon::prelude::*;
+use std::io::Write;
+
+//----------------------------------------------------------------
+// 準粒子干渉（QPI）と結合状態密度（JDOS）
+//
+// Grids の mesh_kx × mesh_ky 点（境界の重複を除く）の固有値と固有ベクトルから、
+// 単位胞の一つのサイト i0 にある点不純物による局所状態密度の変調のフーリエ成分
+//   δρ_σ(q, ω) = -(F_σ(q) - F_σ(-q)^*) / (2πi)
+//   F_σ(q) = e^{-i q・r0} (1/N) Σ_k Σ_a G_σ,a i0(k) T_σ G_σ,i0 a(k - q)
+// を求める。T_σ = V_σ（Born 近似）または V_σ / (1 - V_σ G_σ,i0 i0(ω))（T 行列）で、
+// 磁性不純物では V_↑ = V, V_↓ = -V。JDOS は J_σ(q) = (1/N) Σ_k A_σ(k) A_σ(k + q)。
+//----------------------------------------------------------------
+
+/// 点不純物
+#[derive(Debug, Clone, Copy)]
+pub struct Impurity{
+    pub site : usize,           // 単位胞の中のサイト
+    pub potential : f64,        // V
+    pub magnetic : bool,        // true なら上向きに V、下向きに -V
+    pub born : bool,            // true なら T = V（Born 近似）
+}
+
+impl Impurity{
+    pub fn potential(&self, spin : usize) -> f64{
+        if self.magnetic && spin == 1 { -self.potential } else { self.potential }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct QpiSetting{
+    pub impurity : Impurity,
+    pub broadening : Broadening,
+}
+
+/// 単位胞の各サイトの位置（hamiltonian_2, hamiltonian_6 の位相と同じ）
+pub fn site_positions(size : usize) -> Vec<Vector2<f64>>{
+    match size {
+        2 => vec![Vector2::zeros(), D1],
+        6 => six_site_positions().to_vec(),
+        _ => panic!("system size should be 2 or 6"),
+    }
+}
+
+//k 点での準位 (ε_n, ψ_n)
+pub(crate) type Levels = Vec<(f64, Vec<Complex<f64>>)>;
+
+//Grids の k 点ごとの固有値と固有ベクトル [spin][k]（k 点の順番は index = i * mesh_ky + j）
+pub(crate) struct Spectrum{
+    pub(crate) levels : [Vec<Levels>; 2],
+}
+
+impl Spectrum{
+    //化学ポテンシャルを求めるための全固有値
+    pub(crate) fn thermo(&self) -> ThermoSpectrum{
+        let mut eigenvalues: Vec<f64> = self.levels.iter()
+            .flat_map(|levels| levels.iter().flat_map(|levels| levels.iter().map(|(energy, _)| *energy)))
+            .collect();
+        eigenvalues.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        ThermoSpectrum { eigenvalues, n_k : self.levels[0].len() }
+    }
+    pub(crate) fn from_grids(grids : &Grids) -> Self{
+        let (mesh_kx, mesh_ky) = grids.calc_setting.meshes();
+        let size = grids.system.size();
+        let levels = [0, 1].map(|spin| {
+            (0..mesh_kx * mesh_ky).map(|index| {
+                let (i, j) = (index / mesh_ky, index % mesh_ky);
+                (0..size).map(|band| {
+                    let band_info = &grids.index(spin)[band].0[i][j];
+                    (band_info.eigen, band_info.eigen_vector.to_vec())
+                }).collect()
+            }).collect()
+        });
+        Spectrum { levels }
+    }
+}
+
+/// ある ω での q 点ごとの QPI と JDOS
+#[derive(Debug, Clone, Copy)]
+pub struct QpiPoint{
+    pub q : Vector2<f64>,
+    pub di : i64,
+    pub dj : i64,
+    pub qpi : [Complex<f64>; 2],    // [spin] δρ_σ(q, ω)
+    pub jdos : [f64; 2],            // [spin] J_σ(q, ω)
+}
+
+pub struct QpiMap{
+    pub system : System,
+    pub n : f64,
+    pub mu : f64,
+    pub omega : f64,        // μ から測ったエネルギー
+    pub t_matrix : [Complex<f64>; 2],
+    pub points : Vec<QpiPoint>,
+}
+
+impl QpiMap{
+    /// Grids を作って電子数 n、μ から測ったエネルギー omegas での QPI を求める
+    pub fn build_all(system : System, calc_setting : CalcSetting, n : f64, omegas : &[f64], setting : QpiSetting) -> Vec<Self>{
+        let grids = Grids::build(calc_setting, system, GridInfo::no_divide());
+        let spectrum = Spectrum::from_grids(&grids);
+        let mu = spectrum.thermo().chemical_potential(n, 0.0);
+
+        omegas.iter()
+            .map(|&omega| QpiMap::build(&grids, &spectrum, n, mu, omega, setting))
+            .collect()
+    }
+    fn build(grids : &Grids, spectrum : &Spectrum, n : f64, mu : f64, omega : f64, setting : QpiSetting) -> Self{
+        let system = grids.system.clone();
+        let (mesh_kx, mesh_ky) = grids.calc_setting.meshes();
+        let n_k = (mesh_kx * mesh_ky) as f64;
+        let impurity = setting.impurity;
+        let i0 = impurity.site;
+        let energy = omega + mu;
+        let eta = setting.broadening.eta(energy, mu);
+        let z = Complex::new(energy, eta);
+
+        //k 点ごとの G_a i0(k), G_i0 a(k) と A(k)
+        let greens = [0, 1].map(|spin| {
+            spectrum.levels[spin].par_iter()
+                .map(|levels| {
+                    let size = levels.len();
+                    let mut column = vec![ZERO; size];
+                    let mut row = vec![ZERO; size];
+                    let mut spectral = 0.0;
+                    for (energy, vector) in levels {
+                        let propagator = (z - energy).inv();
+                        for a in 0..size {
+                            column[a] += vector[a] * vector[i0].conj() * propagator;
+                            row[a] += vector[i0] * vector[a].conj() * propagator;
+                        }
+                        spectral += setting.broadening.lorentzian(omega + mu, *energy, mu);
+                    }
+                    (column, row, spectral)
+                })
+                .collect::<Vec<_>>()
+        });
+
+        let t_matrix = [0, 1].map(|spin| {
+            let v = Complex::new(impurity.potential(spin), 0.0);
+            if impurity.born {
+                v
+            } else {
+                let local: Complex<f64> = greens[spin].iter().map(|(column, _, _)| column[i0]).sum::<Complex<f64>>() / n_k;
+                v / (ONE - v * local)
+            }
+        });
+
+        let index = |i : i64, j : i64| (i.rem_euclid(mesh_kx as i64) as usize) * mesh_ky + j.rem_euclid(mesh_ky as i64) as usize;
+        let origin = i_j_to_kk(0, 0, mesh_kx, mesh_ky, false, system.size(), GridInfo::no_divide());
+        let g1 = i_j_to_kk(mesh_kx, 0, mesh_kx, mesh_ky, false, system.size(), GridInfo::no_divide()) - origin;
+        let g2 = i_j_to_kk(0, mesh_ky, mesh_kx, mesh_ky, false, system.size(), GridInfo::no_divide()) - origin;
+        let positions = site_positions(system.size());
+        let r0 = positions[i0];
+
+        //F_σ(q) を (Δi, Δj) ごとに求める
+        //k - q がメッシュの外なら k - q = k' + G（k' はメッシュの点）で、
+        //H(k) の位相 exp(i k・d) から G_i0 a(k' + G) = e^{-i G・(r_i0 - r_a)} G_i0 a(k')
+        let f = |spin : usize, di : i64, dj : i64| -> Complex<f64> {
+            let greens = &greens[spin];
+            let mut sum = ZERO;
+            for i in 0..mesh_kx as i64 {
+                for j in 0..mesh_ky as i64 {
+                    let (column, _, _) = &greens[index(i, j)];
+                    let (_, row, _) = &greens[index(i - di, j - dj)];
+                    let g = g1 * (i - di).div_euclid(mesh_kx as i64) as f64 + g2 * (j - dj).div_euclid(mesh_ky as i64) as f64;
+                    sum += column.iter().zip(row.iter()).zip(positions.iter())
+                        .map(|((c, r), position)| c * r * Complex::new(0.0, -g.dot(&(r0 - position))).exp())
+                        .sum::<Complex<f64>>();
+                }
+            }
+            sum * t_matrix[spin] / n_k
+        };
+
+        let (half_x, half_y) = ((mesh_kx / 2) as i64, (mesh_ky / 2) as i64);
+        let shifts: Vec<(i64, i64)> = (-half_x..mesh_kx as i64 - half_x)
+            .flat_map(|di| (-half_y..mesh_ky as i64 - half_y).map(move |dj| (di, dj)))
+            .collect();
+
+        let points = shifts.par_iter()
+            .map(|&(di, dj)| {
+                let q = g1 * (di as f64 / mesh_kx as f64) + g2 * (dj as f64 / mesh_ky as f64);
+                let phase = |q : Vector2<f64>| Complex::new(0.0, -q.dot(&r0)).exp();
+                let qpi = [0, 1].map(|spin| {
+                    let plus = f(spin, di, dj) * phase(q);
+                    let minus = f(spin, -di, -dj) * phase(-q);
+                    (plus - minus.conj()) / (Complex::new(0.0, 2.0) * -PI)
+                });
+                let jdos = [0, 1].map(|spin| {
+                    let greens = &greens[spin];
+                    let mut sum = 0.0;
+                    for i in 0..mesh_kx as i64 {
+                        for j in 0..mesh_ky as i64 {
+                            sum += greens[index(i, j)].2 * greens[index(i + di, j + dj)].2;
+                        }
+                    }
+                    sum / n_k
+                });
+                QpiPoint { q, di, dj, qpi, jdos }
+            })
+            .collect();
+
+        QpiMap { system, n, mu, omega, t_matrix, points }
+    }
+    pub fn write_to_dat(&self, file_path : &str) -> std::io::Result<()>{
+        let mut file = std::fs::File::create(file_path)?;
+
+        writeln!(
+            file, "# n={},mu={},omega={},t_up={}{:+}i,t_down={}{:+}i",
+            self.n, self.mu, self.omega, self.t_matrix[0].re, self.t_matrix[0].im, self.t_matrix[1].re, self.t_matrix[1].im
+        )?;
+        //qpi は |δρ(q)|（サイトに反転対称性がないので δρ(q) は一般に複素数）、qpi_sz = |δρ_↑ - δρ_↓|
+        writeln!(file, "# di,dj,qx,qy,qpi_up,qpi_down,qpi,qpi_sz,jdos_up,jdos_down,jdos")?;
+        for point in &self.points {
+            let [up, down] = point.qpi;
+            let [jdos_up, jdos_down] = point.jdos;
+            writeln!(
+                file, "{},{},{},{},{},{},{},{},{},{},{}",
+                point.di, point.dj, point.q.x, point.q.y,
+                up.norm(), down.norm(), (up + down).norm(), (up - down).norm(), jdos_up, jdos_down, jdos_up + jdos_down
+            )?;
+        }
+
+        Ok(())
+    }
+}
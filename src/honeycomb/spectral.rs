@@ -0,0 +1,174 @@
+use crate::consts::PI;
+use crate::honeycomb::{
+    band_path::BandPath,
+    thermodynamics::ThermoSpectrum,
+    unfolding::unfolding_weights,
+    util::{i_j_to_kk, GridInfo},
+};
+use crate::system::{
+    diag::{diag, SEudEnum},
+    model::System,
+};
+
+use nalgebra::{Complex, Vector2};
+use rayon::prelude::*;
+use std::io::Write;
+
+//----------------------------------------------------------------
+// スペクトル関数 A_σ(k, ω) = -Im Tr G_σ(k, ω) / π
+//
+// diag の固有値 ε_n と固有ベクトル ψ_n から
+//   A_σ,s(k, ω) = Σ_n w_n,s (η/π) / ((ω - ε_n)² + η²)
+// を副格子 s ごとに求める。w_n,s は副格子 s のサイトでの重み Σ_{i ∈ s} |ψ_n,i|²、
+// unfold = true のときは基本単位胞への展開の重み（unfolding::unfolding_weights、行列要素は考えない）。
+//----------------------------------------------------------------
+
+/// 幅 η(ω) = constant + quadratic (ω - μ)²（quadratic = 0 なら一定）
+#[derive(Debug, Clone, Copy)]
+pub struct Broadening{
+    pub constant : f64,
+    pub quadratic : f64,
+}
+
+impl Broadening{
+    pub fn eta(&self, omega : f64, mu : f64) -> f64{
+        self.constant + self.quadratic * (omega - mu).powi(2)
+    }
+    /// エネルギー ε の準位の ω での Lorentz 関数
+    pub fn lorentzian(&self, omega : f64, energy : f64, mu : f64) -> f64{
+        let eta = self.eta(omega, mu);
+        eta / PI / ((omega - energy).powi(2) + eta * eta)
+    }
+}
+
+/// k点での準位 (ε_n, [w_n,A, w_n,B]) [spin]
+pub type SpectralLevels = [Vec<(f64, [f64; 2])>; 2];
+
+/// k点での各スピンの準位と副格子ごとの重み（unfold = true なら6サイトで対角化して展開する）
+pub fn spectral_levels(system : &System, kk : Vector2<f64>, unfold : bool) -> SpectralLevels{
+    let seud = diag(system, kk, unfold);
+    let levels = |eigenvalues : &[f64], vectors : Vec<Vec<Complex<f64>>>| -> Vec<(f64, [f64; 2])> {
+        eigenvalues.iter().zip(vectors.iter())
+            .map(|(&energy, vector)| {
+                let weights = if unfold {
+                    unfolding_weights(vector)
+                } else {
+                    let mut weights = [0.0; 2];
+                    for (site, value) in vector.iter().enumerate() {
+                        weights[site % 2] += value.norm_sqr();
+                    }
+                    weights
+                };
+                (energy, weights)
+            })
+            .collect()
+    };
+
+    [0, 1].map(|spin| match &seud {
+        SEudEnum::SEud2(seud) => {
+            let eigen = seud.index(spin);
+            levels(eigen.eigenvalues.as_slice(), eigen.eigenvectors.column_iter().map(|c| c.iter().copied().collect()).collect())
+        }
+        SEudEnum::SEud6(seud) => {
+            let eigen = seud.index(spin);
+            levels(eigen.eigenvalues.as_slice(), eigen.eigenvectors.column_iter().map(|c| c.iter().copied().collect()).collect())
+        }
+    })
+}
+
+/// ω での A_σ,s [spin][sublattice]
+pub fn spectral_at(levels : &SpectralLevels, omega : f64, mu : f64, broadening : Broadening) -> [[f64; 2]; 2]{
+    std::array::from_fn(|spin| {
+        let mut a = [0.0; 2];
+        for &(energy, weights) in &levels[spin] {
+            let lorentzian = broadening.lorentzian(omega, energy, mu);
+            a[0] += weights[0] * lorentzian;
+            a[1] += weights[1] * lorentzian;
+        }
+        a
+    })
+}
+
+//----------------------------------------------------------------
+// スピン分解 ARPES の図（E-k 断面と等エネルギー面）
+//----------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy)]
+pub struct SpectralSetting{
+    pub broadening : Broadening,
+    pub unfold : bool,          // 基本単位胞のブリルアンゾーンに展開する
+    pub energy_mesh : usize,    // 化学ポテンシャルを決めるk点のメッシュ
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SpectralPoint{
+    pub distance : f64,         // E-k 断面では経路の長さ、等エネルギー面では 0
+    pub kk : Vector2<f64>,
+    pub omega : f64,            // μ から測ったエネルギー
+    pub a : [[f64; 2]; 2],      // [spin][sublattice]
+}
+
+/// 電子数 n の系の A(k, ω) の図
+pub struct SpectralMap{
+    pub n : f64,
+    pub mu : f64,
+    pub points : Vec<SpectralPoint>,
+}
+
+impl SpectralMap{
+    /// 経路上の E-k 断面（omegas は μ から測ったエネルギー）
+    pub fn energy_momentum_cut(system : &System, n : f64, path : &BandPath, omegas : &[f64], setting : SpectralSetting) -> Self{
+        let mu = ThermoSpectrum::new(system, setting.energy_mesh).chemical_potential(n, 0.0);
+
+        let points = path.points.par_iter().zip(path.distance.par_iter())
+            .flat_map_iter(|(&kk, &distance)| {
+                let levels = spectral_levels(system, kk, setting.unfold);
+                omegas.iter()
+                    .map(|&omega| SpectralPoint { distance, kk, omega, a : spectral_at(&levels, omega + mu, mu, setting.broadening) })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+
+        SpectralMap { n, mu, points }
+    }
+    /// ブリルアンゾーン（unfold なら基本単位胞の六角形）の mesh × mesh 点での等エネルギー面
+    pub fn constant_energy_cut(system : &System, n : f64, omega : f64, mesh : usize, setting : SpectralSetting) -> Self{
+        let mu = ThermoSpectrum::new(system, setting.energy_mesh).chemical_potential(n, 0.0);
+        let size = if setting.unfold { 2 } else { system.size() };
+
+        let points = (0..mesh * mesh).into_par_iter()
+            .map(|index| {
+                let kk = i_j_to_kk(index / mesh, index % mesh, mesh, mesh, true, size, GridInfo::no_divide());
+                let levels = spectral_levels(system, kk, setting.unfold);
+                SpectralPoint { distance : 0.0, kk, omega, a : spectral_at(&levels, omega + mu, mu, setting.broadening) }
+            })
+            .collect();
+
+        SpectralMap { n, mu, points }
+    }
+    /// a_up, a_down は副格子の和、a_sz = a_up - a_down
+    pub fn write_to_dat(&self, file_path : &str, path : Option<&BandPath>) -> std::io::Result<()>{
+        let mut file = std::fs::File::create(file_path)?;
+
+        if let Some(path) = path {
+            let ticks: Vec<String> = path.labels.iter().zip(path.label_distance.iter())
+                .map(|(label, distance)| format!("{}={}", label, distance))
+                .collect();
+            writeln!(file, "# high_symmetry_points: {}", ticks.join(","))?;
+        }
+        writeln!(file, "# n={},mu={}", self.n, self.mu)?;
+        writeln!(file, "# distance,kx,ky,omega,a_up,a_down,a_sz,a_up_a,a_up_b,a_down_a,a_down_b")?;
+
+        for point in &self.points {
+            let [[up_a, up_b], [down_a, down_b]] = point.a;
+            writeln!(
+                file, "{},{},{},{},{},{},{},{},{},{},{}",
+                point.distance, point.kk.x, point.kk.y, point.omega,
+                up_a + up_b, down_a + down_b, up_a + up_b - down_a - down_b,
+                up_a, up_b, down_a, down_b
+            )?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,404 @@
+use crate::consts::*;
+use crate::honeycomb::{
+    cal_berry::{calculate_berry_curvature_from_seud, calculate_quantum_metric_from_seud, degenerate_multiplet_of, find_multiplets, Tensor},
+    height_map::AllHeightMaps,
+    honeycomb_grids::{BandInfo, EigenVectorEnum, Grid, Grids},
+    parallelization::parallel_calculate_tanzaku,
+    setting::CalcSetting,
+    tanzaku::Tanzakus,
+    util::{cal_cell_area, i_j_to_kk, GridInfo},
+};
+use crate::system::{
+    diag::{diag, SEudEnum},
+    model::System,
+};
+
+use nalgebra::{Matrix2, Vector2};
+use rayon::prelude::*;
+
+//対称性の判定に用いる一般の点（高対称点や高対称線の上に乗らないように選ぶ）
+const TEST_POINTS : [(f64, f64); 3] = [(0.1234, 0.3711), (-0.4172, 0.0935), (0.2671, -0.5318)];
+const TOLERANCE : f64 = 1e-8;
+
+//----------------------------------------------------------------
+// 対称操作
+// k → R k と、必要であればスピンの入れ替え（u ↔ d）を組み合わせたもの
+// Berry曲率は Ω_{s'}(Rk) = berry_sign * Ω_s(k) と変換される
+//----------------------------------------------------------------
+#[derive(Debug, Clone, Copy)]
+pub struct SymmetryOp{
+    pub rotation : Matrix2<f64>,
+    pub spin_swap : bool,
+    pub berry_sign : f64,
+    pub name : &'static str,    // k空間での点群操作の名前
+}
+
+impl SymmetryOp{
+    pub fn det(&self) -> f64{
+        self.rotation.determinant()
+    }
+    //スピン s の状態が移る先のスピン
+    pub fn map_spin(&self, spin : usize) -> usize{
+        if self.spin_swap { 1 - spin } else { spin }
+    }
+    pub fn label(&self) -> String{
+        if self.spin_swap {
+            format!("{}*S", self.name)
+        } else {
+            self.name.to_string()
+        }
+    }
+    //逆格子ベクトル b1, b2 を基底とした整数行列
+    fn integer_action(&self, size : usize) -> [[i64; 2]; 2]{
+        let basis = reciprocal_basis(size);
+        let action = basis.try_inverse().unwrap() * self.rotation * basis;
+
+        let mut out = [[0; 2]; 2];
+        for (a, row) in out.iter_mut().enumerate() {
+            for (b, elem) in row.iter_mut().enumerate() {
+                *elem = action[(a, b)].round() as i64;
+                assert!((action[(a, b)] - *elem as f64).abs() < 1e-9, "point group operation should map the reciprocal lattice onto itself");
+            }
+        }
+        out
+    }
+    fn act(&self, size : usize, pq : (i64, i64)) -> (i64, i64){
+        let m = self.integer_action(size);
+        (m[0][0] * pq.0 + m[0][1] * pq.1, m[1][0] * pq.0 + m[1][1] * pq.1)
+    }
+}
+
+//k空間でのC6vの12個の操作
+fn c6v() -> Vec<(Matrix2<f64>, &'static str)>{
+    let rotation_names = ["E", "C6", "C3", "C2", "C3^-1", "C6^-1"];
+    let mirror_names = ["m_0", "m_30", "m_60", "m_90", "m_120", "m_150"];
+
+    let mut out = Vec::new();
+    for (n, name) in rotation_names.iter().enumerate() {
+        let (s, c) = (n as f64 * PI / 3.0).sin_cos();
+        out.push((Matrix2::new(c, -s, s, c), *name));
+    }
+    //鏡映軸の角度はkx軸から30°刻み
+    for (n, name) in mirror_names.iter().enumerate() {
+        let (s, c) = (n as f64 * PI / 3.0).sin_cos();
+        out.push((Matrix2::new(c, s, s, -c), *name));
+    }
+    out
+}
+
+/// C6v の操作の名前（行列が C6v の元でなければ "?"）
+pub fn point_op_name(rotation : &Matrix2<f64>) -> &'static str{
+    c6v()
+        .into_iter()
+        .find(|(r, _)| (r - rotation).norm() < 1e-9)
+        .map_or("?", |(_, name)| name)
+}
+
+fn reciprocal_basis(size : usize) -> Matrix2<f64>{
+    let b1 = kpp(size) + k(size);
+    let b2 = kp(size) + k(size);
+    Matrix2::from_columns(&[b1, b2])
+}
+
+//----------------------------------------------------------------
+// 対称群
+//----------------------------------------------------------------
+#[derive(Debug, Clone)]
+pub struct SymmetryGroup{
+    pub ops : Vec<SymmetryOp>,
+    pub size : usize,
+}
+
+impl SymmetryGroup{
+    /// C6v の各操作とスピンの入れ替えの組み合わせのうち、固有値とBerry曲率を
+    /// 保つものを数値的に探して対称群とする
+    ///
+    /// 一般の点 k で ε_{s',n}(Rk) = ε_{s,n}(k) が全てのスピン・バンドで成り立ち、
+    /// さらに Ω_{s',n}(Rk) = ±Ω_{s,n}(k) の符号が一通りに定まるときに対称操作とみなす。
+    /// Berry曲率が恒等的に0の場合は符号を+1とする。
+    pub fn detect(system : &System, calc_setting : &CalcSetting) -> Self{
+        let size = system.size();
+        let samples: Vec<(Vector2<f64>, Spectrum)> = TEST_POINTS
+            .iter()
+            .map(|&(x, y)| {
+                let kk = Vector2::new(x, y) * k(size).norm();
+                (kk, spectrum(system, kk, calc_setting))
+            })
+            .collect();
+
+        let mut ops = Vec::new();
+
+        for (rotation, name) in c6v() {
+            for spin_swap in [false, true] {
+                let mut plus = true;
+                let mut minus = true;
+                let mut energy_match = true;
+
+                for (kk, (energies, berries)) in &samples {
+                    let (rot_energies, rot_berries) = spectrum(system, rotation * kk, calc_setting);
+
+                    for spin in 0..2 {
+                        let spin_to = if spin_swap { 1 - spin } else { spin };
+                        for band_num in 0..size {
+                            let (e, e_rot) = (energies[spin][band_num], rot_energies[spin_to][band_num]);
+                            let (b, b_rot) = (berries[spin][band_num], rot_berries[spin_to][band_num]);
+
+                            energy_match &= (e - e_rot).abs() < TOLERANCE * (1.0 + e.abs());
+                            plus &= (b - b_rot).abs() < TOLERANCE * (1.0 + b.abs());
+                            minus &= (b + b_rot).abs() < TOLERANCE * (1.0 + b.abs());
+                        }
+                    }
+                }
+
+                if energy_match && (plus || minus) {
+                    let berry_sign = if plus { 1.0 } else { -1.0 };
+                    ops.push(SymmetryOp { rotation, spin_swap, berry_sign, name });
+                }
+            }
+        }
+
+        SymmetryGroup { ops, size }
+    }
+    pub fn order(&self) -> usize{
+        self.ops.len()
+    }
+    pub fn labels(&self) -> Vec<String>{
+        self.ops.iter().map(|op| op.label()).collect()
+    }
+}
+
+//各スピン・バンドの固有値とBerry曲率 ([spin][band])
+type Spectrum = (Vec<Vec<f64>>, Vec<Vec<f64>>);
+
+fn spectrum(system : &System, kk : Vector2<f64>, calc_setting : &CalcSetting) -> Spectrum{
+    let seud_enum = diag(system, kk, false);
+    let energies = match &seud_enum {
+        SEudEnum::SEud2(seud) => vec![seud.u.eigenvalues.as_slice().to_vec(), seud.d.eigenvalues.as_slice().to_vec()],
+        SEudEnum::SEud6(seud) => vec![seud.u.eigenvalues.as_slice().to_vec(), seud.d.eigenvalues.as_slice().to_vec()],
+    };
+    let berries = calculate_berry_curvature_from_seud(&seud_enum, system, kk, 1.0, calc_setting);
+
+    (energies, berries)
+}
+
+//----------------------------------------------------------------
+// 既約領域
+// メッシュ点 (i,j) と三角形 (t,i,j) を対称操作の軌道に分類する
+// t = 0 は三角形ABD、t = 1 は三角形BCD（height_mapと同じ分割）
+//----------------------------------------------------------------
+pub type TriangleKey = (usize, usize, usize);
+
+#[derive(Debug, Clone)]
+pub struct TriangleOrbit{
+    pub rep : TriangleKey,
+    pub images : Vec<(TriangleKey, usize)>,   // 軌道上の三角形とそこへ移す操作の番号（repを含む）
+}
+
+#[derive(Debug, Clone)]
+pub struct IrreducibleMesh{
+    pub mesh : usize,
+    pub reps : Vec<(usize, usize)>,              // 代表点
+    pub point_rep : Vec<Vec<(usize, usize)>>,    // 各メッシュ点の (代表点の番号, 代表点から移す操作の番号)
+    pub triangles : Vec<TriangleOrbit>,
+}
+
+impl IrreducibleMesh{
+    /// メッシュを既約領域に分割する
+    ///
+    /// メッシュ点がΓ点を含み、かつ対称操作で閉じている必要があるので、
+    /// `mesh_kx == mesh_ky` で3の倍数のときのみ構築できる。
+    pub fn build(group : &SymmetryGroup, calc_setting : &CalcSetting) -> Option<Self>{
+        let (mesh_kx, mesh_ky) = calc_setting.meshes();
+        if mesh_kx != mesh_ky || mesh_kx % 3 != 0 {
+            return None;
+        }
+        let mesh = mesh_kx;
+        let size = group.size;
+
+        //メッシュの番号とΓ点を原点とする整数座標の変換
+        let shift = (mesh / 3) as i64;
+        let to_pq = |i : usize, j : usize| (i as i64 - shift, j as i64 - shift);
+        let to_ij = |pq : (i64, i64)| ((pq.0 + shift).rem_euclid(mesh as i64) as usize, (pq.1 + shift).rem_euclid(mesh as i64) as usize);
+
+        let mut reps = Vec::new();
+        let mut point_rep = vec![vec![None; mesh]; mesh];
+
+        for i in 0..mesh {
+            for j in 0..mesh {
+                if point_rep[i][j].is_some() {
+                    continue;
+                }
+                let rep_index = reps.len();
+                reps.push((i, j));
+
+                for (op_index, op) in group.ops.iter().enumerate() {
+                    let (i2, j2) = to_ij(op.act(size, to_pq(i, j)));
+                    if point_rep[i2][j2].is_none() {
+                        point_rep[i2][j2] = Some((rep_index, op_index));
+                    }
+                }
+            }
+        }
+
+        let mut triangles = Vec::new();
+        let mut assigned = vec![vec![[false; 2]; mesh]; mesh];
+
+        for i in 0..mesh {
+            for j in 0..mesh {
+                for t in 0..2 {
+                    if assigned[i][j][t] {
+                        continue;
+                    }
+                    let mut orbit = TriangleOrbit { rep : (t, i, j), images : Vec::new() };
+
+                    for (op_index, op) in group.ops.iter().enumerate() {
+                        let corners = triangle_corners((t, i, j)).map(|(ci, cj)| op.act(size, to_pq(ci, cj)));
+                        let (t2, pq) = identify_triangle(corners);
+                        let (i2, j2) = to_ij(pq);
+
+                        if !assigned[i2][j2][t2] {
+                            assigned[i2][j2][t2] = true;
+                            orbit.images.push(((t2, i2, j2), op_index));
+                        }
+                    }
+                    triangles.push(orbit);
+                }
+            }
+        }
+
+        let point_rep = point_rep
+            .into_iter()
+            .map(|row| row.into_iter().map(|x| x.unwrap()).collect())
+            .collect();
+
+        Some(IrreducibleMesh { mesh, reps, point_rep, triangles })
+    }
+    /// 既約な点の数に対する全メッシュ点の数の比
+    pub fn reduction(&self) -> f64{
+        (self.mesh * self.mesh) as f64 / self.reps.len() as f64
+    }
+}
+
+//三角形の頂点（メッシュの番号）
+pub fn triangle_corners(key : TriangleKey) -> [(usize, usize); 3]{
+    let (t, i, j) = key;
+    match t {
+        0 => [(i, j), (i + 1, j), (i, j + 1)],
+        1 => [(i + 1, j), (i + 1, j + 1), (i, j + 1)],
+        _ => panic!("triangle type should be 0 or 1"),
+    }
+}
+
+//頂点の整数座標から三角形の種類と左下のセルの座標を求める
+fn identify_triangle(corners : [(i64, i64); 3]) -> (usize, (i64, i64)){
+    for &c in &corners {
+        let has = |d : (i64, i64)| corners.contains(&(c.0 + d.0, c.1 + d.1));
+        if has((1, 0)) && has((0, 1)) {
+            return (0, c);
+        }
+        if has((-1, 0)) && has((0, -1)) {
+            return (1, (c.0 - 1, c.1 - 1));
+        }
+    }
+    panic!("image of a mesh triangle should be a mesh triangle")
+}
+
+//----------------------------------------------------------------
+// 既約領域での計算と展開
+//----------------------------------------------------------------
+
+/// 代表点だけを対角化し、対称操作で全メッシュ点に展開したGridsを構築する
+///
+/// 展開した点では固有値をそのまま、Berry曲率を `berry_sign` 倍して写す。
+/// 固有ベクトルは位相が定まらないので代表点以外では `EigenVectorEnum::None` とする。
+pub fn build_grids_in_wedge(
+    calc_setting : CalcSetting,
+    system : System,
+    group : &SymmetryGroup,
+    wedge : &IrreducibleMesh,
+) -> Grids{
+    let mesh = wedge.mesh;
+    let size = system.size();
+    let grid_info = GridInfo::no_divide();
+    let cell_area = cal_cell_area(mesh, mesh, size);
+
+    //代表点での [spin][band] の値
+    let rep_values: Vec<Vec<Vec<BandInfo>>> = wedge.reps
+        .par_iter()
+        .map(|&(i, j)| {
+            let kk = i_j_to_kk(i, j, mesh, mesh, false, size, grid_info);
+            let seud_enum = diag(&system, kk, false);
+            let berry_curvatures = calculate_quantum_metric_from_seud(&seud_enum, &system, kk, cell_area, true, Tensor::XY, &calc_setting);
+
+            (0..2).map(|spin| {
+                let eigenvalues = match &seud_enum {
+                    SEudEnum::SEud2(seud) => seud.index(spin).eigenvalues.as_slice().to_vec(),
+                    SEudEnum::SEud6(seud) => seud.index(spin).eigenvalues.as_slice().to_vec(),
+                };
+                let multiplets = find_multiplets(&eigenvalues, calc_setting.threshold_berry);
+
+                (0..size).map(|band_num| {
+                    let mut band_info = BandInfo::new(kk, i, j, eigenvalues[band_num], EigenVectorEnum::None);
+                    band_info.berry = Some(berry_curvatures[spin][band_num]);
+                    band_info.multiplet = degenerate_multiplet_of(&multiplets, band_num);
+                    band_info
+                }).collect()
+            }).collect()
+        })
+        .collect();
+
+    let grid = Grid(vec![vec![BandInfo::ini(); mesh + 1]; mesh + 1]);
+    let mut grids = Grids {
+        u : vec![grid.clone(); size],
+        d : vec![grid; size],
+        system,
+        calc_setting,
+        energy_range : None,
+        grid_info,
+    };
+
+    for i in 0..=mesh {
+        for j in 0..=mesh {
+            let kk = i_j_to_kk(i, j, mesh, mesh, false, size, grid_info);
+            let (rep_index, op_index) = wedge.point_rep[i % mesh][j % mesh];
+            let op = &group.ops[op_index];
+
+            for spin in 0..2 {
+                //スピン spin の状態は代表点のスピン op.map_spin(spin) から移される
+                for (band_num, source) in rep_values[rep_index][op.map_spin(spin)].iter().enumerate() {
+
+                    let mut band_info = BandInfo::new(kk, i, j, source.eigen, EigenVectorEnum::None);
+                    band_info.berry = source.berry.map(|berry| berry * op.berry_sign);
+                    band_info.multiplet = source.multiplet;
+                    grids.index_mut(spin)[band_num].0[i][j] = band_info;
+                }
+            }
+        }
+    }
+
+    grids
+}
+
+/// 既約領域のみを計算し、対称操作で展開してTanzakusを計算する
+///
+/// メッシュが既約領域に分割できない場合は `parallel_calculate_tanzaku` で全領域を計算する。
+pub fn calculate_tanzaku_in_wedge(
+    calc_setting : CalcSetting,
+    system : System,
+) -> Tanzakus{
+    let group = SymmetryGroup::detect(&system, &calc_setting);
+
+    let wedge = match IrreducibleMesh::build(&group, &calc_setting) {
+        Some(wedge) => wedge,
+        None => return parallel_calculate_tanzaku(calc_setting, system),
+    };
+
+    let grids = build_grids_in_wedge(calc_setting, system.clone(), &group, &wedge);
+    let all_height_maps = AllHeightMaps::build_in_wedge(&grids, &group, &wedge);
+
+    let mut tanzakus = Tanzakus::new(calc_setting, system);
+    tanzakus.write_energy_n_bc_sum_to_tanzaku(&grids);
+    tanzakus.write_bcd_sum_to_tanzakus(&all_height_maps);
+
+    tanzakus
+}
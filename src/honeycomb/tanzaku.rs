@@ -23,7 +23,11 @@ impl Tanzakus{
             system
         }
     }
-    pub fn write_to_dat(&self,dir :  Option<&String>, create_stable: bool) -> std::io::Result<()>{
+    /// write_to_dat が出力するファイルのパス
+    pub fn dat_path(dir : &str, system : &System, setting : &CalcSetting) -> String{
+        format!("{}/data_{}_{}.dat", dir, system.debug(), setting.debug())
+    }
+    pub fn write_to_dat(&self,dir :  Option<&String>) -> std::io::Result<()>{
 
         let dir = match dir{
             Some(d) => d,
@@ -32,28 +36,13 @@ impl Tanzakus{
         // 出力ディレクトリを作成（存在しない場合）
         std::fs::create_dir_all(&dir)?;
 
-        let system_str = self.system.debug();
-        let path = format!("{}/data_{}_{}.dat", dir, system_str, self.setting.debug());
+        let path = Self::dat_path(dir, &self.system, &self.setting);
 
         let mut file = std::fs::File::create(path)?;
 
-        if create_stable {
-            writeln!(file, "# n,energy,berry,bcd_x,bcd_y,qmd_x,qmd_y,stable")?;
-
-
-            for tanzaku in &self.data{
-                let stable_name = match tanzaku.stable {
-                    Some(system) => system.debug_only_name(),
-                    None => "None".to_string(),
-                };
-                writeln!(file, "{},{},{},{},{},{},{},{}",tanzaku.n,tanzaku.energy,tanzaku.berry,tanzaku.bcd.x,tanzaku.bcd.y,tanzaku.qmd.x,tanzaku.qmd.y,stable_name)?;
-            }
-        }
-        else{
-            writeln!(file, "# n,energy,berry,bcd_x,bcd_y,qmd_x,qmd_y")?;
-            for tanzaku in &self.data{
-                writeln!(file, "{},{},{},{},{},{},{}",tanzaku.n,tanzaku.energy,tanzaku.berry,tanzaku.bcd.x,tanzaku.bcd.y,tanzaku.qmd.x,tanzaku.qmd.y)?;
-            }
+        writeln!(file, "# n,energy,berry,bcd_x,bcd_y,qmd_x,qmd_y")?;
+        for tanzaku in &self.data{
+            writeln!(file, "{},{},{},{},{},{},{}",tanzaku.n,tanzaku.energy,tanzaku.berry,tanzaku.bcd.x,tanzaku.bcd.y,tanzaku.qmd.x,tanzaku.qmd.y)?;
         }
 
         Ok(())
@@ -151,7 +140,8 @@ impl Tanzakus{
             // 規約: 全充填時 n=2, 半充填時 n=1
             let n_electrons = (states_below_energy as f64) / (total_k_points as f64) / size as f64;
 
-            let weight = 1.0 / (self.setting.main_mesh as f64).powi(2);
+            // このGridsが覆う領域の面積比（main_mesh で等分した場合は 1 / main_mesh^2）
+            let weight = grids.area_fraction();
             
             self.data[div_index].n = n_electrons * weight;
             self.data[div_index].energy = energy;
@@ -185,7 +175,7 @@ impl Tanzakus{
     }
 
     /// 指定されたnの値で線形補間を行う
-    fn linear_interpolate_at_n(&self, target_n: f64) -> Tanzaku {
+    pub fn linear_interpolate_at_n(&self, target_n: f64) -> Tanzaku {
         // nでソートされたデータを取得
         let mut sorted_data = self.data.clone();
         sorted_data.sort_by(|a, b| a.n.partial_cmp(&b.n).unwrap());
@@ -226,6 +216,25 @@ impl Tanzakus{
         // ここには到達しないはずだが、念のため最初の要素を返す
         sorted_data[0]
     }
+    /// 指定されたエネルギーで線形補間を行う（n で補間し直す）
+    pub fn linear_interpolate_at_energy(&self, target_energy: f64) -> Tanzaku {
+        let mut sorted_data = self.data.clone();
+        sorted_data.sort_by(|a, b| a.energy.partial_cmp(&b.energy).unwrap());
+
+        if target_energy <= sorted_data[0].energy {
+            return sorted_data[0];
+        }
+        if target_energy >= sorted_data[sorted_data.len() - 1].energy {
+            return sorted_data[sorted_data.len() - 1];
+        }
+
+        let i = sorted_data.partition_point(|t| t.energy <= target_energy);
+        let (t1, t2) = (&sorted_data[i - 1], &sorted_data[i]);
+        let weight = (target_energy - t1.energy) / (t2.energy - t1.energy);
+        let mut tanzaku = self.linear_interpolate_at_n(t1.n + weight * (t2.n - t1.n));
+        tanzaku.energy = target_energy;
+        tanzaku
+    }
     pub fn merge(&mut self, other: &Tanzakus) {
         if self.data.len() != other.data.len() {
             panic!("Tanzakusのデータ長が一致しません");
@@ -233,6 +242,8 @@ impl Tanzakus{
 
 
         for i in 0..self.data.len() {
+            // エネルギーの分割は全ての領域で共通なので値をそのまま引き継ぐ
+            self.data[i].energy = other.data[i].energy;
             self.data[i].n += other.data[i].n;
             self.data[i].berry += other.data[i].berry;
             self.data[i].bcd += other.data[i].bcd;
@@ -249,7 +260,6 @@ pub struct Tanzaku {
     pub berry : f64,
     pub bcd : Vector2<f64>,
     pub qmd : Vector2<f64>,
-    pub stable : Option<System>,
 }
 
 impl Tanzaku{
@@ -260,7 +270,6 @@ impl Tanzaku{
             berry,
             bcd,
             qmd,
-            stable : None,
         }
     }
 }
@@ -0,0 +1,305 @@
+use crate::honeycomb::compare::all_eigenvalues;
+use crate::system::model::System;
+
+use rayon::prelude::*;
+use std::io::Write;
+
+//化学ポテンシャルを二分法で求める回数
+const BISECTION_STEPS : usize = 100;
+//自由エネルギーが凸包からこれ以上浮いていれば相分離しているとみなす
+const HULL_TOLERANCE : f64 = 1e-10;
+
+//----------------------------------------------------------------
+// Fermi分布（温度 0 では階段関数）
+//----------------------------------------------------------------
+
+/// エネルギー x = ε - μ の状態の占有率
+pub fn fermi(x : f64, temperature : f64) -> f64 {
+    if temperature <= 0.0 {
+        if x < 0.0 { 1.0 } else if x > 0.0 { 0.0 } else { 0.5 }
+    } else {
+        1.0 / ((x / temperature).exp() + 1.0)
+    }
+}
+
+/// エネルギー x = ε - μ の状態のグランドポテンシャルへの寄与 -T ln(1 + e^{-x/T})
+pub fn grand_potential_term(x : f64, temperature : f64) -> f64 {
+    if temperature <= 0.0 {
+        x.min(0.0)
+    } else if x > 0.0 {
+        -temperature * (-x / temperature).exp().ln_1p()
+    } else {
+        x - temperature * (x / temperature).exp().ln_1p()
+    }
+}
+
+//----------------------------------------------------------------
+// 一つのスピン配置の熱力学量（6サイトの単位胞あたり）
+//----------------------------------------------------------------
+pub struct ThermoSpectrum{
+    pub eigenvalues : Vec<f64>,     // 昇順
+    pub n_k : usize,                // k点の数
+}
+
+impl ThermoSpectrum{
+    pub fn new(system : &System, energy_mesh : usize) -> Self{
+        ThermoSpectrum {
+            eigenvalues : all_eigenvalues(system, energy_mesh),
+            n_k : energy_mesh * energy_mesh,
+        }
+    }
+    /// 電子数 n が 1 増えたときの単位胞あたりの電子数の増加（全充填で n = 2）
+    pub fn electrons_per_n(&self) -> f64{
+        self.eigenvalues.len() as f64 / self.n_k as f64 / 2.0
+    }
+    /// 化学ポテンシャル mu での電子数 n
+    pub fn filling(&self, mu : f64, temperature : f64) -> f64{
+        let occupied: f64 = self.eigenvalues.iter().map(|&e| fermi(e - mu, temperature)).sum();
+        2.0 * occupied / self.eigenvalues.len() as f64
+    }
+    /// グランドポテンシャル Ω(μ, T)
+    pub fn grand_potential(&self, mu : f64, temperature : f64) -> f64{
+        let omega: f64 = self.eigenvalues.iter().map(|&e| grand_potential_term(e - mu, temperature)).sum();
+        omega / self.n_k as f64
+    }
+    /// 電子数が n になる化学ポテンシャル（二分法）
+    pub fn chemical_potential(&self, n : f64, temperature : f64) -> f64{
+        let margin = 1.0 + 50.0 * temperature.max(0.0);
+        let mut lo = self.eigenvalues[0] - margin;
+        let mut hi = self.eigenvalues[self.eigenvalues.len() - 1] + margin;
+
+        for _ in 0..BISECTION_STEPS {
+            let mid = 0.5 * (lo + hi);
+            if self.filling(mid, temperature) < n {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+
+        0.5 * (lo + hi)
+    }
+    /// 自由エネルギー F(n, T) = Ω(μ(n), T) + μ N
+    ///
+    /// 温度 0 では最後の準位を部分的に占有させて、下から詰めた固有値の和をとる（状態数の丸めをしない）。
+    pub fn free_energy(&self, n : f64, temperature : f64) -> f64{
+        if temperature <= 0.0 {
+            let occupied = (n * 0.5 * self.eigenvalues.len() as f64).clamp(0.0, self.eigenvalues.len() as f64);
+            let full = occupied.floor() as usize;
+            let partial = match self.eigenvalues.get(full) {
+                Some(e) => (occupied - full as f64) * e,
+                None => 0.0,
+            };
+            let sum: f64 = self.eigenvalues[..full].iter().sum();
+            return (sum + partial) / self.n_k as f64;
+        }
+
+        let mu = self.chemical_potential(n, temperature);
+        self.grand_potential(mu, temperature) + mu * n * self.electrons_per_n()
+    }
+}
+
+//----------------------------------------------------------------
+// スピン配置の比較
+//----------------------------------------------------------------
+
+/// 化学ポテンシャルを固定した比較（ゲートで μ を制御する場合）
+#[derive(Debug, Clone)]
+pub struct GrandPotentialPoint{
+    pub mu : f64,
+    pub winner : usize,         // Ω が最も低い候補（candidates の添字）
+    pub omegas : Vec<f64>,      // 各候補の Ω
+    pub fillings : Vec<f64>,    // 各候補の電子数
+}
+
+/// 電子数を固定した比較
+#[derive(Debug, Clone)]
+pub struct FreeEnergyPoint{
+    pub n : f64,
+    pub winner : usize,             // F が最も低い候補
+    pub free_energies : Vec<f64>,   // 各候補の F
+    pub hull : f64,                 // min F の下側凸包（Maxwell構成）
+    pub phase_separated : bool,     // min F が凸包より高い（μ 固定では実現しない）
+}
+
+/// Maxwell構成で n_low と n_high の間が二相に分離する
+#[derive(Debug, Clone, Copy)]
+pub struct PhaseSeparation{
+    pub n_low : f64,
+    pub n_high : f64,
+    pub low : usize,    // n_low で安定な候補
+    pub high : usize,   // n_high で安定な候補
+    pub mu : f64,       // 二相が共存する化学ポテンシャル（共通接線の傾き）
+}
+
+pub struct ThermoComparison{
+    pub candidates : Vec<System>,
+    pub temperature : f64,
+    pub spectra : Vec<ThermoSpectrum>,
+}
+
+impl ThermoComparison{
+    pub fn new(candidates : &[System], temperature : f64, energy_mesh : usize) -> Self{
+        let spectra = candidates.par_iter()
+            .map(|system| ThermoSpectrum::new(system, energy_mesh))
+            .collect();
+
+        ThermoComparison { candidates : candidates.to_vec(), temperature, spectra }
+    }
+    /// 全ての候補の固有値を含むエネルギー範囲
+    pub fn energy_range(&self) -> (f64, f64){
+        let min = self.spectra.iter().map(|spectrum| spectrum.eigenvalues[0]).fold(f64::INFINITY, f64::min);
+        let max = self.spectra.iter().map(|spectrum| spectrum.eigenvalues[spectrum.eigenvalues.len() - 1]).fold(f64::NEG_INFINITY, f64::max);
+        (min, max)
+    }
+    /// 各化学ポテンシャルで Ω(μ, T) を比べる
+    pub fn at_chemical_potentials(&self, mus : &[f64]) -> Vec<GrandPotentialPoint>{
+        mus.par_iter()
+            .map(|&mu| {
+                let omegas: Vec<f64> = self.spectra.iter().map(|spectrum| spectrum.grand_potential(mu, self.temperature)).collect();
+                let fillings = self.spectra.iter().map(|spectrum| spectrum.filling(mu, self.temperature)).collect();
+                GrandPotentialPoint { mu, winner : argmin(&omegas), omegas, fillings }
+            })
+            .collect()
+    }
+    /// 0 から 2 を n_div 等分した各電子数で F(n, T) を比べ、min F の下側凸包をとる
+    pub fn at_fillings(&self, n_div : usize) -> Vec<FreeEnergyPoint>{
+        let ns: Vec<f64> = (0..=n_div).map(|i| 2.0 * i as f64 / n_div as f64).collect();
+
+        let free_energiess: Vec<Vec<f64>> = ns.par_iter()
+            .map(|&n| self.spectra.iter().map(|spectrum| spectrum.free_energy(n, self.temperature)).collect())
+            .collect();
+        let minimums: Vec<f64> = free_energiess.iter().map(|free_energies| free_energies[argmin(free_energies)]).collect();
+        let hull = lower_convex_hull(&ns, &minimums);
+
+        ns.iter().zip(free_energiess).zip(minimums.iter().zip(hull))
+            .map(|((&n, free_energies), (&minimum, hull))| FreeEnergyPoint {
+                n,
+                winner : argmin(&free_energies),
+                free_energies,
+                hull,
+                phase_separated : minimum - hull > HULL_TOLERANCE,
+            })
+            .collect()
+    }
+    /// at_fillings の結果から相分離する電子数の範囲を取り出す
+    pub fn phase_separations(&self, points : &[FreeEnergyPoint]) -> Vec<PhaseSeparation>{
+        let electrons_per_n = self.spectra[0].electrons_per_n();
+        let mut separations = Vec::new();
+
+        let mut i = 0;
+        while i < points.len() {
+            if !points[i].phase_separated {
+                i += 1;
+                continue;
+            }
+
+            //凸包に乗っている両端の点
+            let low = i - 1;
+            let mut high = i;
+            while points[high].phase_separated {
+                high += 1;
+            }
+
+            let (a, b) = (&points[low], &points[high]);
+            separations.push(PhaseSeparation {
+                n_low : a.n,
+                n_high : b.n,
+                low : a.winner,
+                high : b.winner,
+                mu : (b.hull - a.hull) / (b.n - a.n) / electrons_per_n,
+            });
+            i = high;
+        }
+
+        separations
+    }
+
+    pub fn write_grand_potential_to_dat(&self, points : &[GrandPotentialPoint], path : &str) -> std::io::Result<()>{
+        let mut file = std::fs::File::create(path)?;
+
+        writeln!(file, "# temperature = {}", self.temperature)?;
+        writeln!(file, "# mu,stable,n_stable,{}", self.column_names("omega").join(","))?;
+        for point in points {
+            writeln!(
+                file, "{},{},{},{}",
+                point.mu, self.candidates[point.winner].debug_only_name(), point.fillings[point.winner], join(&point.omegas)
+            )?;
+        }
+
+        Ok(())
+    }
+    pub fn write_free_energy_to_dat(&self, points : &[FreeEnergyPoint], path : &str) -> std::io::Result<()>{
+        let mut file = std::fs::File::create(path)?;
+
+        writeln!(file, "# temperature = {}", self.temperature)?;
+        writeln!(file, "# n,stable,hull,phase_separated,{}", self.column_names("f").join(","))?;
+        for point in points {
+            writeln!(
+                file, "{},{},{},{},{}",
+                point.n, self.candidates[point.winner].debug_only_name(), point.hull, point.phase_separated as u8, join(&point.free_energies)
+            )?;
+        }
+
+        Ok(())
+    }
+    pub fn write_phase_separations_to_dat(&self, separations : &[PhaseSeparation], path : &str) -> std::io::Result<()>{
+        let mut file = std::fs::File::create(path)?;
+
+        writeln!(file, "# temperature = {}", self.temperature)?;
+        writeln!(file, "# n_low,n_high,mu,low,high")?;
+        for separation in separations {
+            writeln!(
+                file, "{},{},{},{},{}",
+                separation.n_low, separation.n_high, separation.mu,
+                self.candidates[separation.low].debug_only_name(), self.candidates[separation.high].debug_only_name()
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn column_names(&self, prefix : &str) -> Vec<String>{
+        self.candidates.iter().map(|system| format!("{}_{}", prefix, system.debug_only_name())).collect()
+    }
+}
+
+fn argmin(values : &[f64]) -> usize{
+    let mut index = 0;
+    for (i, value) in values.iter().enumerate() {
+        if *value < values[index] {
+            index = i;
+        }
+    }
+    index
+}
+
+fn join(values : &[f64]) -> String{
+    values.iter().map(|value| value.to_string()).collect::<Vec<_>>().join(",")
+}
+
+//点列 (xs, ys) の下側凸包を xs で評価した値（xs は昇順）
+fn lower_convex_hull(xs : &[f64], ys : &[f64]) -> Vec<f64>{
+    let mut vertices: Vec<usize> = Vec::new();
+
+    for i in 0..xs.len() {
+        while let [.., a, b] = vertices[..] {
+            //a → b → i が左に曲がらなければ b は凸包に乗らない
+            let cross = (xs[b] - xs[a]) * (ys[i] - ys[a]) - (ys[b] - ys[a]) * (xs[i] - xs[a]);
+            if cross > 0.0 {
+                break;
+            }
+            vertices.pop();
+        }
+        vertices.push(i);
+    }
+
+    let mut hull = ys.to_vec();
+    for pair in vertices.windows(2) {
+        let (a, b) = (pair[0], pair[1]);
+        for i in a + 1..b {
+            hull[i] = ys[a] + (ys[b] - ys[a]) * (xs[i] - xs[a]) / (xs[b] - xs[a]);
+        }
+    }
+    hull
+}
@@ -0,0 +1,151 @@
+use crate::consts::PI;
+use crate::honeycomb::{
+    band_path::BandPath,
+    thermodynamics::ThermoSpectrum,
+    util::{i_j_to_kk, GridInfo},
+};
+use crate::system::{
+    diag::diag,
+    model::System,
+};
+
+use nalgebra::{Complex, Vector2};
+use rayon::prelude::*;
+use std::io::Write;
+
+//----------------------------------------------------------------
+// 6サイトの単位胞のバンドを基本単位胞（2サイト）のブリルアンゾーンに展開する
+//
+// hamiltonian_6 は結合ベクトルの位相 exp(i k・d) を使うので、基本単位胞の波数 k の
+// Bloch 状態 |k, s⟩ は k で対角化した固有ベクトルの副格子 s の成分を足したものと重なる：
+//   W_s(k) = |Σ_{i ∈ s} ψ_i(k)|² / 3（偶数番目のサイトが A 副格子）
+// W = W_A + W_B は 0 から 1 で、各k点でスピンごとに全バンドの和が 2 になる。
+//
+// hamiltonian_2 は B 副格子に、hamiltonian_6 は A 副格子に tmd を掛けるので、tmd ≠ 1 の
+// 反強磁性（SatoTmd, AfmKanemele）の展開は2サイトの jj を -jj にしたバンドと一致する。
+//----------------------------------------------------------------
+
+//6サイトの単位胞に含まれる基本単位胞の数
+const FOLD : f64 = 3.0;
+
+/// 固有ベクトルの副格子ごとの展開の重み [W_A, W_B]
+pub fn unfolding_weights(vector : &[Complex<f64>]) -> [f64; 2]{
+    let mut amplitude = [Complex::new(0.0, 0.0); 2];
+    for (site, value) in vector.iter().enumerate() {
+        amplitude[site % 2] += value;
+    }
+    amplitude.map(|a| a.norm_sqr() / FOLD)
+}
+
+/// 基本単位胞の波数 kk での6サイトのバンドの固有値と重み [spin][band]
+pub fn unfold_at(system : &System, kk : Vector2<f64>) -> ([Vec<f64>; 2], [Vec<f64>; 2]){
+    let seud = diag(system, kk, true);
+    let seud = seud.is_6();
+
+    let energies = [0, 1].map(|spin| seud.index(spin).eigenvalues.as_slice().to_vec());
+    let weights = [0, 1].map(|spin| {
+        let eigen = seud.index(spin);
+        (0..6).map(|band| {
+            let vector: Vec<Complex<f64>> = eigen.eigenvectors.column(band).iter().copied().collect();
+            unfolding_weights(&vector).iter().sum()
+        }).collect()
+    });
+
+    (energies, weights)
+}
+
+//----------------------------------------------------------------
+// 展開したバンド構造
+//----------------------------------------------------------------
+
+#[derive(Debug, Clone)]
+pub struct UnfoldedPoint{
+    pub distance : f64,
+    pub kk : Vector2<f64>,
+    pub energies : [Vec<f64>; 2],   // [spin][band]（6サイトのバンド）
+    pub weights : [Vec<f64>; 2],    // [spin][band] 基本単位胞の Bloch 状態への重み
+}
+
+/// 基本単位胞のブリルアンゾーンの経路（BandPath::from_labels(labels, 2, ..)）上で展開したバンド
+pub fn unfolded_band_structure(system : &System, path : &BandPath) -> Vec<UnfoldedPoint> {
+    path.points.par_iter().zip(path.distance.par_iter())
+        .map(|(&kk, &distance)| {
+            let (energies, weights) = unfold_at(system, kk);
+            UnfoldedPoint { distance, kk, energies, weights }
+        })
+        .collect()
+}
+
+/// 展開したバンド構造を.datファイルに出力する（高対称点の位置はヘッダーに記す）
+pub fn write_unfolded_bands_to_dat(bands : &[UnfoldedPoint], path : &BandPath, file_path : &str) -> std::io::Result<()> {
+    let mut file = std::fs::File::create(file_path)?;
+
+    let ticks: Vec<String> = path.labels.iter().zip(path.label_distance.iter())
+        .map(|(label, distance)| format!("{}={}", label, distance))
+        .collect();
+    writeln!(file, "# high_symmetry_points: {}", ticks.join(","))?;
+    writeln!(file, "# distance,kx,ky,spin,band_index,energy,weight")?;
+
+    for point in bands {
+        for spin in 0..2 {
+            for (band_index, (energy, weight)) in point.energies[spin].iter().zip(point.weights[spin].iter()).enumerate() {
+                writeln!(file, "{},{},{},{},{},{},{}", point.distance, point.kk.x, point.kk.y, spin, band_index, energy, weight)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+//----------------------------------------------------------------
+// 展開したフェルミ面（重み付きのスペクトル関数）
+//----------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy)]
+pub struct FermiSurfacePoint{
+    pub kk : Vector2<f64>,
+    pub spectral : [f64; 2],    // [spin] A(k, μ) = Σ_n W_n (η/π) / ((ε_n - μ)² + η²)
+}
+
+/// 電子数 n でのフェルミ面
+pub struct UnfoldedFermiSurface{
+    pub n : f64,
+    pub mu : f64,
+    pub broadening : f64,
+    pub points : Vec<FermiSurfacePoint>,
+}
+
+impl UnfoldedFermiSurface{
+    /// 基本単位胞の六角形のブリルアンゾーンの mesh × mesh 点で μ(n) でのスペクトル関数を求める
+    /// （μ は energy_mesh の6サイトのバンドから温度 0 で決める）
+    pub fn build(system : &System, n : f64, mesh : usize, energy_mesh : usize, broadening : f64) -> Self{
+        let mu = ThermoSpectrum::new(system, energy_mesh).chemical_potential(n, 0.0);
+        let lorentzian = |x : f64| broadening / PI / (x * x + broadening * broadening);
+
+        let points = (0..mesh * mesh).into_par_iter()
+            .map(|index| {
+                let kk = i_j_to_kk(index / mesh, index % mesh, mesh, mesh, true, 2, GridInfo::no_divide());
+                let (energies, weights) = unfold_at(system, kk);
+                let spectral = [0, 1].map(|spin| {
+                    energies[spin].iter().zip(weights[spin].iter())
+                        .map(|(energy, weight)| weight * lorentzian(energy - mu))
+                        .sum()
+                });
+                FermiSurfacePoint { kk, spectral }
+            })
+            .collect();
+
+        UnfoldedFermiSurface { n, mu, broadening, points }
+    }
+    pub fn write_to_dat(&self, file_path : &str) -> std::io::Result<()>{
+        let mut file = std::fs::File::create(file_path)?;
+
+        writeln!(file, "# n={},mu={},broadening={}", self.n, self.mu, self.broadening)?;
+        writeln!(file, "# kx,ky,a_up,a_down")?;
+        for point in &self.points {
+            writeln!(file, "{},{},{},{}", point.kk.x, point.kk.y, point.spectral[0], point.spectral[1])?;
+        }
+
+        Ok(())
+    }
+}
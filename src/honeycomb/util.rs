@@ -33,6 +33,13 @@ impl GridInfo{
     pub fn no_divide() -> Self{
         GridInfo::new_ijn(0,0,1,1,None)
     }
+    /// この平行四辺形を 2x2 に等分した小さな平行四辺形
+    pub fn subdivide(&self) -> [Self;4]{
+        let half = self.dxy / 2.0;
+        let sub = |i : f64, j : f64| GridInfo::new(self.ini.x + half.x * i, self.ini.y + half.y * j, half.x, half.y, self.energy_range);
+
+        [sub(0.,0.), sub(1.,0.), sub(0.,1.), sub(1.,1.)]
+    }
 }
 
 pub fn move_bz(kk : Vector2<f64>, i: i32, j : i32, size : usize) -> Vector2<f64>{
@@ -0,0 +1,309 @@
+use crate::honeycomb::{
+    thermodynamics::{fermi, ThermoSpectrum},
+    util::{i_j_to_kk, GridInfo},
+};
+use crate::consts::{A1, A2, A3, ZERO};
+use crate::system::{
+    diag::{diag, SEud, SEudEnum},
+    hamiltonian::{bonds_6, Bond, BOND_COUNT_6},
+    model::{Param, SiteFields, System},
+};
+
+use nalgebra::{Complex, Const, Dim, DimMin, Vector2};
+use rayon::prelude::*;
+use std::io::Write;
+use std::sync::Arc;
+
+//----------------------------------------------------------------
+// オンサイトHubbard U のHartree-Fock（平均場）近似
+//
+// U n_{i↑} n_{i↓} → U <n_{i↓}> n_{i↑} + U <n_{i↑}> n_{i↓} - U <n_{i↑}><n_{i↓}>
+// を交換場 -U m_i（m_i = (n_{i↑} - n_{i↓}) / 2）と電荷ポテンシャル U n_i / 2 に分けて
+// System::MeanField のサイトごとの場として与える。
+//
+// 拡張Hubbard模型では最近接 V（と次近接 V2）の相互作用
+// V n_i n_j → V <n_j> n_i + V <n_i> n_j（Hartree項）- V Σ_σ (<c†_{jσ} c_{iσ}> c†_{iσ} c_{jσ} + h.c.)（Fock項）
+// も取り入れる。Hartree項は電荷ポテンシャルに、Fock項は結合ごとの飛び移りの補正になる。
+// V, V2 が 0 でなければ電荷秩序と6サイトのスピン配置が競合できるよう常に6サイトの単位胞で解く。
+//----------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy)]
+pub struct HubbardSetting{
+    pub u : f64,
+    pub v : f64,                // 最近接の相互作用
+    pub v2 : f64,               // 次近接の相互作用
+    pub filling : f64,          // 電子数 n（全充填で n = 2）
+    pub mesh : usize,           // mesh × mesh のk点でサイトごとの密度を求める
+    pub temperature : f64,      // 収束を安定させるためのFermi分布の幅
+    pub mixing : f64,           // 新しい場を混ぜる割合（0 < mixing <= 1）
+    pub tolerance : f64,        // 場の変化の最大値がこれ以下になれば収束とする
+    pub max_iterations : usize,
+}
+
+impl HubbardSetting{
+    pub fn standard(u : f64, filling : f64) -> Self{
+        HubbardSetting {
+            u,
+            v : 0.0,
+            v2 : 0.0,
+            filling,
+            mesh : 30,
+            temperature : 1e-3,
+            mixing : 0.3,
+            tolerance : 1e-6,
+            max_iterations : 500,
+        }
+    }
+    pub fn is_extended(&self) -> bool{
+        self.v != 0.0 || self.v2 != 0.0
+    }
+    //結合 bond の相互作用
+    fn bond_interaction(&self, bond : &Bond) -> f64{
+        if bond.neighbor == 1 { self.v } else { self.v2 }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MeanFieldSolution{
+    pub system : System,                // 収束した場を持つ System::MeanField
+    pub seed : System,                  // 初期値に使ったスピン配置
+    pub charge_seeded : bool,           // 初期値に副格子の電荷秩序を加えたか
+    pub densities : [[f64; 6]; 2],      // [spin][site] の電子数
+    pub energy : f64,                   // 6サイトの単位胞あたりの平均場の全エネルギー（cal_e_vs_n と同じ規格化）
+    pub mu : f64,
+    pub iterations : usize,
+    pub converged : bool,
+}
+
+impl MeanFieldSolution{
+    /// サイトごとの磁気モーメント m_i = (n_{i↑} - n_{i↓}) / 2
+    pub fn moments(&self) -> [f64; 6]{
+        std::array::from_fn(|site| 0.5 * (self.densities[0][site] - self.densities[1][site]))
+    }
+    /// 副格子の電荷秩序 (n_A - n_B) / 2（n_A, n_B は副格子のサイトあたりの電子数、偶数番目のサイトが A）
+    pub fn charge_order(&self) -> f64{
+        let size = self.system.size();
+        let sublattice = |parity : usize| -> f64 {
+            (parity..size).step_by(2).map(|site| self.densities[0][site] + self.densities[1][site]).sum::<f64>() / (size / 2) as f64
+        };
+        0.5 * (sublattice(0) - sublattice(1))
+    }
+    /// Fock項が生んだ次近接の虚数の飛び移り（Kane-Mele項の λ と同じ向き、規格化）の (Haldane, Kane-Mele) 成分
+    ///
+    /// スピンごとの λ_σ を副格子と結合の向きで符号を揃えて平均し、(λ_↑ + λ_↓) / 2 と (λ_↑ - λ_↓) / 2 を返す。
+    pub fn chirality(&self) -> (f64, f64){
+        let bonds = match &self.system {
+            System::MeanField(_, fields) if fields.size == 6 => fields.bonds,
+            _ => return (0.0, 0.0),
+        };
+
+        let lambdas: Vec<f64> = bonds.iter().map(|corrections| {
+            let (sum, count) = bonds_6().iter().zip(corrections.iter())
+                .filter(|(bond, _)| bond.neighbor == 2)
+                .fold((0.0, 0), |(sum, count), (bond, delta)| {
+                    let sublattice = if bond.i % 2 == 0 { 1.0 } else { -1.0 };
+                    let direction = if [A1, A2, A3].iter().any(|a| (bond.r - a).norm() < 1e-12) { 1.0 } else { -1.0 };
+                    (sum + sublattice * direction * delta.im, count + 1)
+                });
+            sum / count as f64
+        }).collect();
+
+        (0.5 * (lambdas[0] + lambdas[1]), 0.5 * (lambdas[0] - lambdas[1]))
+    }
+}
+
+//固有状態のエネルギーとスピン、固有ベクトル
+struct EigenState{
+    energy : f64,
+    spin : usize,
+    kk : Vector2<f64>,
+    vector : Vec<Complex<f64>>,
+}
+
+/// seed のスピン配置を初期値として自己無撞着な平均場を求める
+///
+/// 初期値の交換場は seed の SpinSeq6 × U / 2（完全に偏極した場合の大きさ）とし、
+/// V, V2 が 0 なら seed の単位胞（2 または 6 サイト）のまま、そうでなければ6サイトで解く。
+/// charge_seeded なら初期値の電荷ポテンシャルに副格子で符号の変わる 3V min(n, 2 - n) を加える。
+pub fn solve(seed : &System, charge_seeded : bool, setting : HubbardSetting) -> MeanFieldSolution{
+    let size = if setting.is_extended() { 6 } else { seed.size() };
+    let u = setting.u;
+    let n = setting.filling;
+    let spin_seq = seed.spinseq();
+    let signs = [spin_seq.a, spin_seq.b, spin_seq.c, spin_seq.d, spin_seq.e, spin_seq.f];
+    let bonds = if size == 6 { bonds_6() } else { Vec::new() };
+
+    //一様な密度 n / 2 での Hartree項（各サイトは最近接3本、次近接6本の結合を持つ）
+    let uniform = 0.5 * u * n + (3.0 * setting.v + 6.0 * setting.v2) * n;
+    let staggered = if charge_seeded { 3.0 * setting.v * n.min(2.0 - n) } else { 0.0 };
+
+    let mut fields = SiteFields {
+        size,
+        tmd : seed.tmd(),
+        u,
+        v : setting.v,
+        v2 : setting.v2,
+        seed : seed.name(),
+        exchange : std::array::from_fn(|site| if site < size { 0.5 * u * signs[site] } else { 0.0 }),
+        charge : std::array::from_fn(|site| {
+            let sublattice = if site % 2 == 0 { -1.0 } else { 1.0 };
+            if site < size { uniform + sublattice * staggered } else { 0.0 }
+        }),
+        bonds : [[ZERO; BOND_COUNT_6]; 2],
+    };
+    let param = Param::new(seed.param().lambda, 0.0);
+
+    let mut iterations = 0;
+    loop {
+        iterations += 1;
+        let system = System::MeanField(param, Arc::new(fields));
+        let (states, mu) = occupied_states(&system, &setting);
+
+        //サイトごとの密度と、結合ごとの <c†_j c_i> = (1/N) Σ_k e^{-ik・r} Σ_n f ψ_n(i) ψ_n(j)*
+        let mut densities = [[0.0; 6]; 2];
+        let mut amplitudes = [[ZERO; BOND_COUNT_6]; 2];
+        let mut band_energy = 0.0;
+        for state in &states {
+            let occupation = fermi(state.energy - mu, setting.temperature);
+            band_energy += occupation * state.energy;
+            for (site, c) in state.vector.iter().enumerate() {
+                densities[state.spin][site] += occupation * c.norm_sqr();
+            }
+            for (b, bond) in bonds.iter().enumerate() {
+                let phase = Complex::new(0.0, -state.kk.dot(&bond.r)).exp();
+                amplitudes[state.spin][b] += phase * state.vector[bond.i] * state.vector[bond.j].conj() * occupation;
+            }
+        }
+        let n_k = (setting.mesh * setting.mesh) as f64;
+        densities = densities.map(|spin| spin.map(|density| density / n_k));
+        amplitudes = amplitudes.map(|spin| spin.map(|amplitude| amplitude / n_k));
+
+        //平均場から求めた新しい場（size を超えるサイトの密度は 0 なので場も 0 のまま）
+        let [up, down] = densities;
+        let total: [f64; 6] = std::array::from_fn(|site| up[site] + down[site]);
+        let hartree = bonds.iter().fold([0.0; 6], |mut hartree, bond| {
+            let v = setting.bond_interaction(bond);
+            hartree[bond.i] += v * total[bond.j];
+            hartree[bond.j] += v * total[bond.i];
+            hartree
+        });
+        let new_exchange: [f64; 6] = std::array::from_fn(|site| -0.5 * u * (up[site] - down[site]));
+        let new_charge: [f64; 6] = std::array::from_fn(|site| 0.5 * u * total[site] + hartree[site]);
+        let new_bonds: [[Complex<f64>; BOND_COUNT_6]; 2] = amplitudes.map(|spin| {
+            std::array::from_fn(|b| match bonds.get(b) {
+                Some(bond) => -setting.bond_interaction(bond) * spin[b],
+                None => ZERO,
+            })
+        });
+
+        let change = fields.exchange.iter().zip(new_exchange.iter())
+            .chain(fields.charge.iter().zip(new_charge.iter()))
+            .fold(0.0_f64, |change, (old, new)| change.max((new - old).abs()));
+        let change = fields.bonds.iter().flatten().zip(new_bonds.iter().flatten())
+            .fold(change, |change, (old, new)| change.max((new - old).norm()));
+
+        let converged = change < setting.tolerance;
+        if converged || iterations >= setting.max_iterations {
+            //二重に数えた相互作用エネルギーを引く（Fock項は -V |<c†_j c_i>|² を二重に数えている）
+            let on_site: f64 = (0..size).map(|site| u * densities[0][site] * densities[1][site]).sum();
+            let hartree: f64 = bonds.iter().map(|bond| setting.bond_interaction(bond) * total[bond.i] * total[bond.j]).sum();
+            let fock: f64 = bonds.iter().enumerate()
+                .map(|(b, bond)| setting.bond_interaction(bond) * (amplitudes[0][b].norm_sqr() + amplitudes[1][b].norm_sqr()))
+                .sum();
+            let double_counting = on_site + hartree - fock;
+
+            return MeanFieldSolution {
+                system,
+                seed : seed.clone(),
+                charge_seeded,
+                densities,
+                energy : (band_energy / n_k - double_counting) * 6.0 / size as f64,
+                mu,
+                iterations,
+                converged,
+            };
+        }
+
+        let mix = |old : f64, new : f64| old + setting.mixing * (new - old);
+        fields.exchange = std::array::from_fn(|site| mix(fields.exchange[site], new_exchange[site]));
+        fields.charge = std::array::from_fn(|site| mix(fields.charge[site], new_charge[site]));
+        fields.bonds = std::array::from_fn(|spin| {
+            std::array::from_fn(|b| fields.bonds[spin][b] + (new_bonds[spin][b] - fields.bonds[spin][b]) * setting.mixing)
+        });
+    }
+}
+
+/// 複数の初期値から解き、全エネルギーの低い順に並べる
+///
+/// V が 0 でなければ各スピン配置について電荷秩序を加えた初期値からも解く。
+pub fn solve_presets(seeds : &[System], setting : HubbardSetting) -> Vec<MeanFieldSolution>{
+    let charge_seeds: &[bool] = if setting.v != 0.0 { &[false, true] } else { &[false] };
+    let starts: Vec<(System, bool)> = seeds.iter()
+        .flat_map(|seed| charge_seeds.iter().map(move |&charge_seeded| (seed.clone(), charge_seeded)))
+        .collect();
+
+    let mut solutions: Vec<MeanFieldSolution> = starts.par_iter()
+        .map(|(seed, charge_seeded)| solve(seed, *charge_seeded, setting))
+        .collect();
+    solutions.sort_by(|a, b| a.energy.partial_cmp(&b.energy).unwrap());
+    solutions
+}
+
+//全てのk点の固有状態と、電子数が setting.filling になる化学ポテンシャル
+fn occupied_states(system : &System, setting : &HubbardSetting) -> (Vec<EigenState>, f64){
+    let mesh = setting.mesh;
+
+    let states: Vec<EigenState> = (0..mesh * mesh).into_par_iter()
+        .flat_map_iter(|index| {
+            let kk = i_j_to_kk(index / mesh, index % mesh, mesh, mesh, false, system.size(), GridInfo::no_divide());
+            match diag(system, kk, false) {
+                SEudEnum::SEud2(seud) => eigen_states(&seud, kk),
+                SEudEnum::SEud6(seud) => eigen_states(&seud, kk),
+            }
+        })
+        .collect();
+
+    let mut eigenvalues: Vec<f64> = states.iter().map(|state| state.energy).collect();
+    eigenvalues.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let spectrum = ThermoSpectrum { eigenvalues, n_k : mesh * mesh };
+    let mu = spectrum.chemical_potential(setting.filling, setting.temperature);
+
+    (states, mu)
+}
+
+fn eigen_states<const N: usize>(seud : &SEud<N>, kk : Vector2<f64>) -> Vec<EigenState>
+where
+    Const<N>: Dim + DimMin<Const<N>, Output = Const<N>>,
+{
+    (0..2)
+        .flat_map(|spin| (0..N).map(move |band| (spin, band)))
+        .map(|(spin, band)| EigenState {
+            energy : seud.index(spin).eigenvalues[band],
+            spin,
+            kk,
+            vector : seud.index(spin).eigenvectors.column(band).iter().copied().collect(),
+        })
+        .collect()
+}
+
+/// 平均場の解を.datファイルに出力する
+pub fn write_solutions_to_dat(solutions : &[MeanFieldSolution], file_path : &str) -> std::io::Result<()>{
+    let mut file = std::fs::File::create(file_path)?;
+
+    writeln!(file, "# seed,charge_seeded,name,energy,mu,converged,iterations,charge_order,haldane,kane_mele,m_0,m_1,m_2,m_3,m_4,m_5,n_0,n_1,n_2,n_3,n_4,n_5")?;
+    for solution in solutions {
+        let moments = solution.moments();
+        let densities: [f64; 6] = std::array::from_fn(|site| solution.densities[0][site] + solution.densities[1][site]);
+        let (haldane, kane_mele) = solution.chirality();
+        let join = |values : [f64; 6]| values.map(|value| value.to_string()).join(",");
+        writeln!(
+            file, "{},{},{},{},{},{},{},{},{},{},{},{}",
+            solution.seed.debug_only_name(), solution.charge_seeded as u8, solution.system.debug_only_name(),
+            solution.energy, solution.mu, solution.converged, solution.iterations,
+            solution.charge_order(), haldane, kane_mele,
+            join(moments), join(densities)
+        )?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,4 @@
+pub mod hubbard;
+pub mod phase_diagram;
+pub mod rkky;
+pub mod susceptibility;
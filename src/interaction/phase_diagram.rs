@@ -0,0 +1,83 @@
+use crate::honeycomb::{
+    parallelization::parallel_calculate_tanzaku,
+    setting::CalcSetting,
+    tanzaku::Tanzaku,
+};
+use crate::interaction::hubbard::{solve_presets, HubbardSetting, MeanFieldSolution};
+use crate::system::model::System;
+
+use std::io::Write;
+
+//----------------------------------------------------------------
+// (U, V, n) の平均場の相図
+//
+// 各点で全ての初期値から平均場を解き、全エネルギーが最も低い解の秩序変数と
+// Berry曲率, BCD, QMD（tanzakuと同じ計算）を記録する。
+//----------------------------------------------------------------
+#[derive(Clone)]
+pub struct MeanFieldPhasePoint{
+    pub u : f64,
+    pub v : f64,
+    pub n : f64,
+    pub solution : MeanFieldSolution,   // 全エネルギーが最も低い解
+    pub margin : f64,                   // 次に低い解とのエネルギー差（初期値が一つならinf）
+    pub tanzaku : Tanzaku,              // 最も低い解での電子数 n の Berry曲率, BCD, QMD
+}
+
+pub struct MeanFieldPhaseDiagram{
+    pub seeds : Vec<System>,            // 初期値のスピン配置
+    pub base : HubbardSetting,          // U, V, n 以外の設定
+    pub calc_setting : CalcSetting,
+    pub points : Vec<MeanFieldPhasePoint>,  // U, V, n の順に並ぶ
+}
+
+impl MeanFieldPhaseDiagram{
+    pub fn build(seeds : &[System], base : HubbardSetting, us : &[f64], vs : &[f64], ns : &[f64], calc_setting : CalcSetting) -> Self{
+        let mut points = Vec::with_capacity(us.len() * vs.len() * ns.len());
+
+        for &u in us {
+            for &v in vs {
+                for &n in ns {
+                    let setting = HubbardSetting { u, v, filling : n, ..base };
+                    let solutions = solve_presets(seeds, setting);
+                    let solution = solutions[0].clone();
+                    let margin = solutions.get(1).map_or(f64::INFINITY, |runner_up| runner_up.energy - solution.energy);
+
+                    let tanzaku = parallel_calculate_tanzaku(calc_setting, solution.system.clone()).linear_interpolate_at_n(n);
+                    points.push(MeanFieldPhasePoint { u, v, n, solution, margin, tanzaku });
+                }
+            }
+        }
+
+        MeanFieldPhaseDiagram { seeds : seeds.to_vec(), base, calc_setting, points }
+    }
+
+    /// 相図を一つの表として.datファイルに出力する
+    pub fn write_to_dat(&self, path : &str) -> std::io::Result<()>{
+        let mut file = std::fs::File::create(path)?;
+
+        writeln!(file, "# u,v,n,stable,charge_seeded,converged,total_energy,margin,charge_order,magnetization,staggered,haldane,kane_mele,berry,bcd_x,bcd_y,qmd_x,qmd_y")?;
+        for point in &self.points {
+            let solution = &point.solution;
+            let moments = solution.moments();
+            let size = solution.system.size();
+            let magnetization = moments[..size].iter().sum::<f64>() / size as f64;
+            let staggered = moments[..size].iter().enumerate()
+                .map(|(site, m)| if site % 2 == 0 { *m } else { -m })
+                .sum::<f64>() / size as f64;
+            let (haldane, kane_mele) = solution.chirality();
+            let tanzaku = &point.tanzaku;
+            writeln!(
+                file,
+                "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+                point.u, point.v, point.n,
+                solution.system.debug_only_name(), solution.charge_seeded as u8, solution.converged,
+                solution.energy, point.margin,
+                solution.charge_order(), magnetization, staggered, haldane, kane_mele,
+                tanzaku.berry, tanzaku.bcd.x, tanzaku.bcd.y, tanzaku.qmd.x, tanzaku.qmd.y
+            )?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,278 @@
+use crate::consts::{A1, A3, D1};
+use crate::honeycomb::{
+    thermodynamics::{fermi, ThermoSpectrum},
+    util::{i_j_to_kk, GridInfo},
+};
+use crate::system::{
+    diag::diag,
+    hamiltonian::{six_site_index, six_site_positions},
+    model::{Param, System},
+};
+
+use nalgebra::{Complex, Vector2};
+use rayon::prelude::*;
+use std::io::Write;
+
+//固有値がこれより近ければ縮退とみなし、(f_α - f_β) / (ε_α - ε_β) を f の微分で置き換える
+const DEGENERACY_TOLERANCE : f64 = 1e-9;
+//これより小さい重みの組は χ に寄与しないとみなす
+const WEIGHT_CUTOFF : f64 = 1e-14;
+
+//----------------------------------------------------------------
+// 常磁性の母体の静的スピン感受率から求めるRKKY相互作用
+//
+// 局在スピン S_i が J Σ_i S_i (n_{i↑} - n_{i↓}) で遍歴電子と結合するとき、J の2次で
+// E = (1/2) Σ_{i≠j} J_ij S_i S_j + 定数、J_ij = -J² (χ^↑_ij + χ^↓_ij)
+// χ^σ_ij = -Σ_{αβ} (f_α - f_β) / (ε_α - ε_β) ψ_α(i)* ψ_β(i) ψ_β(j)* ψ_α(j)
+// となる（J_ij > 0 が反強磁性）。比べるスピン配置と同じハミルトニアンになるよう、
+// 母体には jj = 0 とした uuuddd（6サイト）を使う。
+//----------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy)]
+pub struct RkkySetting{
+    pub mesh : usize,           // mesh × mesh のk点で感受率を求める
+    pub temperature : f64,      // Fermi分布の幅（0 より大きくする）
+    pub cutoff : f64,           // この距離までのサイトの組を求める
+}
+
+impl RkkySetting{
+    pub fn standard() -> Self{
+        RkkySetting {
+            mesh : 18,
+            temperature : 0.05,
+            cutoff : 6.0,
+        }
+    }
+}
+
+/// 副格子 origin（0 = A, 1 = B）のサイトから r だけ離れた副格子 target のサイト
+#[derive(Debug, Clone, Copy)]
+pub struct Separation{
+    pub origin : usize,
+    pub target : usize,
+    pub r : Vector2<f64>,
+}
+
+impl Separation{
+    pub fn distance(&self) -> f64{
+        self.r.norm()
+    }
+}
+
+/// 副格子の位置（A を6サイトの単位胞のサイト 0、B をサイト 1 に置く）
+fn sublattice_position(sublattice : usize) -> Vector2<f64>{
+    if sublattice == 0 { Vector2::zeros() } else { D1 }
+}
+
+/// 両方の副格子から cutoff 以内にある全てのサイト（自分自身を除く、副格子と距離の順）
+pub fn separations(cutoff : f64) -> Vec<Separation>{
+    let range = (cutoff / A3.norm()).ceil() as i64 + 1;
+    let mut separations = Vec::new();
+
+    for origin in 0..2 {
+        for target in 0..2 {
+            for n1 in -range..=range {
+                for n2 in -range..=range {
+                    let r = A3 * n1 as f64 + A1 * n2 as f64 + sublattice_position(target) - sublattice_position(origin);
+                    if r.norm() > 1e-9 && r.norm() <= cutoff + 1e-9 {
+                        separations.push(Separation { origin, target, r });
+                    }
+                }
+            }
+        }
+    }
+
+    separations.sort_by(|a, b| {
+        (a.origin, a.distance()).partial_cmp(&(b.origin, b.distance())).unwrap()
+    });
+    separations
+}
+
+//----------------------------------------------------------------
+// 常磁性の母体の固有状態
+//----------------------------------------------------------------
+struct EigenState{
+    energy : f64,
+    kk : Vector2<f64>,
+    vector : [Complex<f64>; 6],
+}
+
+pub struct RkkyHost{
+    pub system : System,        // 常磁性の母体
+    pub jj : f64,               // 局在スピンとの結合 J
+    pub setting : RkkySetting,
+    states : [Vec<EigenState>; 2],  // [spin]
+    spectrum : ThermoSpectrum,
+}
+
+/// 電子数 n での各サイトの組の感受率と交換相互作用
+pub struct RkkyCouplings{
+    pub n : f64,
+    pub mu : f64,
+    pub separations : Vec<Separation>,
+    pub chi : Vec<f64>,         // χ^↑ + χ^↓
+    pub exchange : Vec<f64>,    // J_ij = -J² χ
+}
+
+impl RkkyHost{
+    /// family の jj = 0 とした uuuddd を母体として対角化する（param.jj は局在スピンとの結合に使う）
+    /// 縮退した準位の寄与 -f(1-f)/T が発散するので、temperature ≤ 0 ならErrを返す
+    pub fn new(family : &str, param : Param, setting : RkkySetting) -> Result<Self, String>{
+        if setting.temperature <= 0.0 {
+            return Err(format!("rkky temperature should be positive: {}", setting.temperature));
+        }
+        let system = System::from_family(family, "uuuddd", Param::new(param.lambda, 0.0))
+            .ok_or_else(|| format!("unknown family for rkky: {}", family))?;
+
+        let mesh = setting.mesh;
+        let states: Vec<(usize, EigenState)> = (0..mesh * mesh).into_par_iter()
+            .flat_map_iter(|index| {
+                let kk = i_j_to_kk(index / mesh, index % mesh, mesh, mesh, false, 6, GridInfo::no_divide());
+                let seud = diag(&system, kk, true).is_6().clone();
+                (0..2).flat_map(move |spin| (0..6).map(move |band| (spin, band)))
+                    .map(move |(spin, band)| {
+                        let eigen = seud.index(spin);
+                        let vector = std::array::from_fn(|site| eigen.eigenvectors[(site, band)]);
+                        (spin, EigenState { energy : eigen.eigenvalues[band], kk, vector })
+                    })
+            })
+            .collect();
+
+        let mut eigenvalues: Vec<f64> = states.iter().map(|(_, state)| state.energy).collect();
+        eigenvalues.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let mut by_spin: [Vec<EigenState>; 2] = [Vec::new(), Vec::new()];
+        for (spin, state) in states {
+            by_spin[spin].push(state);
+        }
+
+        Ok(RkkyHost {
+            system,
+            jj : param.jj,
+            setting,
+            states : by_spin,
+            spectrum : ThermoSpectrum { eigenvalues, n_k : mesh * mesh },
+        })
+    }
+
+    /// 電子数 n（全充填で n = 2）での cutoff 以内の全てのサイトの組の J_ij
+    pub fn couplings(&self, n : f64) -> RkkyCouplings{
+        let temperature = self.setting.temperature;
+        let mu = self.spectrum.chemical_potential(n, temperature);
+        let separations = separations(self.setting.cutoff);
+        let n_k = (self.setting.mesh * self.setting.mesh) as f64;
+
+        let mut chi = vec![0.0; separations.len()];
+        for states in &self.states {
+            for (total, value) in chi.iter_mut().zip(spin_susceptibility(states, &separations, mu, temperature)) {
+                *total += value / (n_k * n_k);
+            }
+        }
+        let exchange = chi.iter().map(|chi| -self.jj * self.jj * chi).collect();
+
+        RkkyCouplings { n, mu, separations, chi, exchange }
+    }
+}
+
+//一つのスピンの χ^σ × N²（N はk点の数）
+fn spin_susceptibility(states : &[EigenState], separations : &[Separation], mu : f64, temperature : f64) -> Vec<f64>{
+    //X_α(r) = ψ_α(origin)* ψ_α(target) e^{ik・r} とすると χ = -Σ_{αβ} w_αβ X_α X_β*
+    //（origin は6サイトの単位胞のサイト 0 または 1、target はその位置から r だけ離れたサイト）
+    let sites: Vec<usize> = separations.iter()
+        .map(|s| six_site_index(&(sublattice_position(s.origin) + s.r)).expect("site outside of the honeycomb lattice"))
+        .collect();
+    let amplitudes: Vec<Vec<Complex<f64>>> = states.iter()
+        .map(|state| {
+            separations.iter().zip(sites.iter())
+                .map(|(s, &site)| state.vector[s.origin].conj() * state.vector[site] * Complex::new(0.0, state.kk.dot(&s.r)).exp())
+                .collect()
+        })
+        .collect();
+    let occupations: Vec<f64> = states.iter().map(|state| fermi(state.energy - mu, temperature)).collect();
+
+    (0..states.len()).into_par_iter()
+        .fold(|| vec![0.0; separations.len()], |mut chi, a| {
+            for b in a..states.len() {
+                let de = states[a].energy - states[b].energy;
+                let weight = if de.abs() < DEGENERACY_TOLERANCE {
+                    -occupations[a] * (1.0 - occupations[a]) / temperature
+                } else {
+                    (occupations[a] - occupations[b]) / de
+                };
+                if weight.abs() < WEIGHT_CUTOFF {
+                    continue;
+                }
+                //α ≠ β の組は β, α の組と複素共役なので実部を2倍する
+                let factor = if a == b { 1.0 } else { 2.0 };
+                for (value, (xa, xb)) in chi.iter_mut().zip(amplitudes[a].iter().zip(amplitudes[b].iter())) {
+                    *value -= factor * weight * (xa * xb.conj()).re;
+                }
+            }
+            chi
+        })
+        .reduce(|| vec![0.0; separations.len()], |a, b| a.iter().zip(b.iter()).map(|(a, b)| a + b).collect())
+}
+
+impl RkkyCouplings{
+    /// J_ij から求めた system のスピン配置の古典的なエネルギー（6サイトの単位胞あたり）
+    pub fn classical_energy(&self, system : &System) -> f64{
+        let spin = system.spinseq();
+        let spins = [spin.a, spin.b, spin.c, spin.d, spin.e, spin.f];
+        let positions = six_site_positions();
+
+        let mut energy = 0.0;
+        for (i, position) in positions.iter().enumerate() {
+            for (separation, exchange) in self.separations.iter().zip(self.exchange.iter()) {
+                if separation.origin != i % 2 {
+                    continue;
+                }
+                let j = six_site_index(&(position + separation.r)).expect("site outside of the honeycomb lattice");
+                energy += 0.5 * exchange * spins[i] * spins[j];
+            }
+        }
+        energy
+    }
+}
+
+//----------------------------------------------------------------
+// .datファイルへの出力
+//----------------------------------------------------------------
+
+/// 電子数ごとの J_ij を距離の順に出力する
+pub fn write_couplings_to_dat(couplingss : &[RkkyCouplings], path : &str) -> std::io::Result<()>{
+    let mut file = std::fs::File::create(path)?;
+
+    writeln!(file, "# n,mu,origin,target,rx,ry,distance,chi,j")?;
+    for couplings in couplingss {
+        for ((separation, chi), exchange) in couplings.separations.iter().zip(couplings.chi.iter()).zip(couplings.exchange.iter()) {
+            writeln!(
+                file, "{},{},{},{},{},{},{},{},{}",
+                couplings.n, couplings.mu,
+                ["A", "B"][separation.origin], ["A", "B"][separation.target],
+                separation.r.x, separation.r.y, separation.distance(), chi, exchange
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// 電子数ごとに各スピン配置の古典的なエネルギーを出力する
+pub fn write_classical_energies_to_dat(couplingss : &[RkkyCouplings], candidates : &[System], path : &str) -> std::io::Result<()>{
+    let mut file = std::fs::File::create(path)?;
+
+    let names: Vec<String> = candidates.iter().map(|system| format!("e_{}", system.debug_only_name())).collect();
+    writeln!(file, "# n,mu,stable,{}", names.join(","))?;
+    for couplings in couplingss {
+        let energies: Vec<f64> = candidates.iter().map(|system| couplings.classical_energy(system)).collect();
+        let stable = energies.iter().enumerate()
+            .fold(0, |best, (i, energy)| if *energy < energies[best] { i } else { best });
+        writeln!(
+            file, "{},{},{},{}",
+            couplings.n, couplings.mu, candidates[stable].debug_only_name(),
+            energies.iter().map(|energy| energy.to_string()).collect::<Vec<_>>().join(",")
+        )?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,304 @@
+use crate::consts::{A1, A3, PI};
+use crate::honeycomb::thermodynamics::{fermi, ThermoSpectrum};
+use crate::system::{
+    diag::{diag, SEud},
+    model::{Param, System},
+};
+
+use nalgebra::{Complex, Matrix2, Matrix4, Vector2};
+use rayon::prelude::*;
+use std::io::Write;
+
+//固有値がこれより近ければ縮退とみなし、(f_n - f_m) / (ε_n - ε_m) を f の微分で置き換える
+const DEGENERACY_TOLERANCE : f64 = 1e-9;
+//分数座標がこれより近ければ高対称点とみなす
+const POINT_TOLERANCE : f64 = 1e-9;
+
+//----------------------------------------------------------------
+// 常磁性の母体の静的スピン感受率 χ_ab(q)（a, b は副格子）とRPA
+//
+// χ^σ_ab(q) = -(1/N) Σ_k Σ_nm (f_n(k) - f_m(k-q)) / (ε_n(k) - ε_m(k-q))
+//             ψ_na(k)* ψ_ma(k-q) ψ_mb(k-q)* ψ_nb(k)
+// オンサイトの U n↑ n↓ は ↑ と ↓ の泡を結ぶので、縦（z）成分のRPAは
+// χ = χ0 (1 + Û χ0)^{-1}、Û = U [[0, 1], [1, 0]]（スピンの2×2、副格子は対角）となり、
+// U² χ0↑ χ0↓ の最大固有値が 1 になる U で磁気秩序が不安定になる。
+// 母体は jj = 0 とした afm（2サイトの単位胞）を使う。
+//----------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy)]
+pub struct SusceptibilitySetting{
+    pub mesh : usize,           // mesh × mesh のk点で和をとる
+    pub q_mesh : usize,         // q_mesh × q_mesh のq点で χ を求める（Γ, K, M を含むよう 6 の倍数にする）
+    pub temperature : f64,      // Fermi分布の幅（0 より大きくする）
+}
+
+impl SusceptibilitySetting{
+    pub fn standard() -> Self{
+        SusceptibilitySetting {
+            mesh : 36,
+            q_mesh : 12,
+            temperature : 0.05,
+        }
+    }
+}
+
+//----------------------------------------------------------------
+// 2サイトの単位胞の逆格子と高対称点
+//----------------------------------------------------------------
+
+/// 2サイトの単位胞の逆格子ベクトル b1, b2（実空間の格子ベクトル A3, A1 に対応）
+pub fn reciprocal_vectors() -> (Vector2<f64>, Vector2<f64>){
+    let det = A3.x * A1.y - A3.y * A1.x;
+    let b1 = Vector2::new(A1.y, -A1.x) * (2.0 * PI / det);
+    let b2 = Vector2::new(-A3.y, A3.x) * (2.0 * PI / det);
+    (b1, b2)
+}
+
+/// 分数座標 (f1, f2) の点 f1 b1 + f2 b2
+pub fn fractional_to_kk(f1 : f64, f2 : f64) -> Vector2<f64>{
+    let (b1, b2) = reciprocal_vectors();
+    b1 * f1 + b2 * f2
+}
+
+/// 秩序の波数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderingPoint{
+    Gamma,          // 単位胞を変えない秩序（強磁性、Néel）
+    K,              // √3×√3（6サイトの単位胞）
+    M,              // ストライプ
+    Other,          // 非整合
+}
+
+impl OrderingPoint{
+    /// 分数座標 (f1, f2) の点がどの高対称点か（b1, b2 は60度をなすので K は (1/3, 1/3), (2/3, 2/3)）
+    pub fn classify(f1 : f64, f2 : f64) -> Self{
+        let near = |a : f64, b : f64| {
+            let d = (a - b).rem_euclid(1.0);
+            d < POINT_TOLERANCE || 1.0 - d < POINT_TOLERANCE
+        };
+        let at = |g1 : f64, g2 : f64| near(f1, g1) && near(f2, g2);
+
+        if at(0.0, 0.0) {
+            OrderingPoint::Gamma
+        } else if at(1.0 / 3.0, 1.0 / 3.0) || at(2.0 / 3.0, 2.0 / 3.0) {
+            OrderingPoint::K
+        } else if at(0.5, 0.0) || at(0.0, 0.5) || at(0.5, 0.5) {
+            OrderingPoint::M
+        } else {
+            OrderingPoint::Other
+        }
+    }
+    pub fn name(&self) -> &'static str{
+        match self {
+            OrderingPoint::Gamma => "Gamma",
+            OrderingPoint::K => "K",
+            OrderingPoint::M => "M",
+            OrderingPoint::Other => "other",
+        }
+    }
+    /// 6サイトの単位胞（hamiltonian_6）で表せる秩序か（Γ と K は6サイトの単位胞の Γ に折り畳まれる）
+    pub fn fits_six_site_cell(&self) -> bool{
+        matches!(self, OrderingPoint::Gamma | OrderingPoint::K)
+    }
+}
+
+//----------------------------------------------------------------
+// 各q点の感受率
+//----------------------------------------------------------------
+#[derive(Debug, Clone, Copy)]
+pub struct QPoint{
+    pub index : (usize, usize),         // q = (i b1 + j b2) / q_mesh
+    pub q : Vector2<f64>,
+    pub point : OrderingPoint,
+    pub chi0 : [Matrix2<Complex<f64>>; 2],  // [spin] の裸の泡 χ0^σ_ab
+}
+
+impl QPoint{
+    /// 縦成分のRPAが発散する U（U² χ0↑ χ0↓ の最大固有値が 1）
+    pub fn critical_u(&self) -> f64{
+        let product = self.chi0[1] * self.chi0[0];
+        let trace = product.trace().re;
+        let det = product.determinant().re;
+        let largest = 0.5 * (trace + (trace * trace - 4.0 * det).max(0.0).sqrt());
+        if largest > 0.0 { 1.0 / largest.sqrt() } else { f64::INFINITY }
+    }
+    /// 裸の縦成分 χ0^zz = (χ0↑ + χ0↓) / 4
+    pub fn chi0_zz(&self) -> Matrix2<Complex<f64>>{
+        (self.chi0[0] + self.chi0[1]) * Complex::new(0.25, 0.0)
+    }
+    /// U でのRPAの縦成分 χ^zz_ab（U が critical_u を超えると意味を持たない）
+    pub fn rpa_zz(&self, u : f64) -> Option<Matrix2<Complex<f64>>>{
+        let mut chi0 = Matrix4::zeros();
+        let mut interaction = Matrix4::zeros();
+        for spin in 0..2 {
+            chi0.fixed_view_mut::<2, 2>(2 * spin, 2 * spin).copy_from(&self.chi0[spin]);
+            interaction.fixed_view_mut::<2, 2>(2 * spin, 2 * (1 - spin)).copy_from(&(Matrix2::identity() * Complex::new(u, 0.0)));
+        }
+        let chi = chi0 * (Matrix4::identity() + interaction * chi0).try_inverse()?;
+
+        //χ^zz = (1/4) Σ_{σσ'} σσ' χ_σσ'
+        let block = |s : usize, t : usize| chi.fixed_view::<2, 2>(2 * s, 2 * t).into_owned();
+        Some((block(0, 0) + block(1, 1) - block(0, 1) - block(1, 0)) * Complex::new(0.25, 0.0))
+    }
+    /// 行列 matrix の最大固有値
+    pub fn largest_eigenvalue(matrix : &Matrix2<Complex<f64>>) -> f64{
+        let trace = matrix.trace().re;
+        let det = matrix.determinant().re;
+        0.5 * (trace + (trace * trace - 4.0 * det).max(0.0).sqrt())
+    }
+    /// χ0^zz の最大固有値の固有ベクトルが副格子で同符号である割合（1 なら強磁性的、0 ならNéel的）
+    pub fn in_phase(&self) -> f64{
+        let chi = self.chi0_zz();
+        let largest = QPoint::largest_eigenvalue(&chi);
+        //(chi - λ) v = 0 の解 v = (χ_AB, λ - χ_AA)
+        let (a, b) = (chi[(0, 1)], Complex::new(largest, 0.0) - chi[(0, 0)]);
+        let norm = a.norm_sqr() + b.norm_sqr();
+        if norm < 1e-30 {
+            return 0.5;
+        }
+        0.5 * (a + b).norm_sqr() / norm
+    }
+}
+
+//----------------------------------------------------------------
+// 電子数 n での全てのq点の感受率
+//----------------------------------------------------------------
+pub struct Susceptibility{
+    pub host : System,
+    pub setting : SusceptibilitySetting,
+    pub n : f64,
+    pub mu : f64,
+    pub qpoints : Vec<QPoint>,
+}
+
+impl Susceptibility{
+    /// family の jj = 0 とした afm を母体として、電子数 n での χ0(q) を求める
+    /// 母体が2サイトでないとき、temperature ≤ 0（縮退した準位の寄与が発散する）ときはErrを返す
+    pub fn new(family : &str, lambda : f64, n : f64, setting : SusceptibilitySetting) -> Result<Self, String>{
+        if setting.temperature <= 0.0 {
+            return Err(format!("susceptibility temperature should be positive: {}", setting.temperature));
+        }
+        let host = System::from_family(family, "afm", Param::new(lambda, 0.0))
+            .ok_or_else(|| format!("unknown family for susceptibility: {}", family))?;
+        if host.size() != 2 {
+            return Err(format!("susceptibility needs a two-site afm host, but {} has {} sites", host.debug(), host.size()));
+        }
+
+        let mesh = setting.mesh;
+        let kks: Vec<Vector2<f64>> = (0..mesh * mesh)
+            .map(|index| fractional_to_kk((index / mesh) as f64 / mesh as f64, (index % mesh) as f64 / mesh as f64))
+            .collect();
+
+        let seuds: Vec<SEud<2>> = kks.par_iter().map(|kk| diag(&host, *kk, false).is_2().clone()).collect();
+
+        let mut eigenvalues: Vec<f64> = seuds.iter().flat_map(|seud| seud.eigenvalues()).collect();
+        eigenvalues.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let spectrum = ThermoSpectrum { eigenvalues, n_k : mesh * mesh };
+        let mu = spectrum.chemical_potential(n, setting.temperature);
+
+        let q_mesh = setting.q_mesh;
+        let qpoints = (0..q_mesh * q_mesh).into_par_iter()
+            .map(|index| {
+                let (i, j) = (index / q_mesh, index % q_mesh);
+                let (f1, f2) = (i as f64 / q_mesh as f64, j as f64 / q_mesh as f64);
+                let q = fractional_to_kk(f1, f2);
+                QPoint {
+                    index : (i, j),
+                    q,
+                    point : OrderingPoint::classify(f1, f2),
+                    chi0 : bare_bubble(&host, &kks, &seuds, q, mu, setting.temperature),
+                }
+            })
+            .collect();
+
+        Ok(Susceptibility { host, setting, n, mu, qpoints })
+    }
+    /// critical_u が最も小さい（最初に不安定になる）q点
+    pub fn leading(&self) -> &QPoint{
+        self.qpoints.iter()
+            .min_by(|a, b| a.critical_u().partial_cmp(&b.critical_u()).unwrap())
+            .unwrap()
+    }
+}
+
+//χ0^σ_ab(q) を [spin] ごとに求める（seuds は kks の各点の固有状態）
+fn bare_bubble(host : &System, kks : &[Vector2<f64>], seuds : &[SEud<2>], q : Vector2<f64>, mu : f64, temperature : f64) -> [Matrix2<Complex<f64>>; 2]{
+    let mut chi = [Matrix2::<Complex<f64>>::zeros(); 2];
+
+    for (kk, seud_k) in kks.iter().zip(seuds) {
+        let seud_kq = diag(host, kk - q, false);
+        let seud_kq = seud_kq.is_2();
+
+        for (spin, chi) in chi.iter_mut().enumerate() {
+            let (eigen_k, eigen_kq) = (seud_k.index(spin), seud_kq.index(spin));
+            for n in 0..2 {
+                for m in 0..2 {
+                    let (e_n, e_m) = (eigen_k.eigenvalues[n], eigen_kq.eigenvalues[m]);
+                    let (f_n, f_m) = (fermi(e_n - mu, temperature), fermi(e_m - mu, temperature));
+                    let weight = if (e_n - e_m).abs() < DEGENERACY_TOLERANCE {
+                        -f_n * (1.0 - f_n) / temperature
+                    } else {
+                        (f_n - f_m) / (e_n - e_m)
+                    };
+
+                    for a in 0..2 {
+                        for b in 0..2 {
+                            let overlap = eigen_k.eigenvectors[(a, n)].conj() * eigen_kq.eigenvectors[(a, m)]
+                                * eigen_kq.eigenvectors[(b, m)].conj() * eigen_k.eigenvectors[(b, n)];
+                            chi[(a, b)] -= overlap * weight;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    chi.map(|chi| chi / Complex::new(kks.len() as f64, 0.0))
+}
+
+//----------------------------------------------------------------
+// .datファイルへの出力
+//----------------------------------------------------------------
+
+/// 各q点の裸の χ0^zz とRPAの χ^zz（最大固有値）、不安定になる U
+pub fn write_qmap_to_dat(susceptibilities : &[Susceptibility], u : f64, path : &str) -> std::io::Result<()>{
+    let mut file = std::fs::File::create(path)?;
+
+    writeln!(file, "# u = {}", u)?;
+    writeln!(file, "# n,mu,i,j,qx,qy,point,chi0_aa,chi0_bb,chi0_ab_re,chi0_ab_im,chi0_max,chi_rpa_max,u_c")?;
+    for susceptibility in susceptibilities {
+        for qpoint in &susceptibility.qpoints {
+            let chi0 = qpoint.chi0_zz();
+            //U が critical_u 以上なら RPA は発散している
+            let rpa = match qpoint.rpa_zz(u) {
+                Some(chi) if u < qpoint.critical_u() => QPoint::largest_eigenvalue(&chi),
+                _ => f64::INFINITY,
+            };
+            writeln!(
+                file, "{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+                susceptibility.n, susceptibility.mu, qpoint.index.0, qpoint.index.1, qpoint.q.x, qpoint.q.y, qpoint.point.name(),
+                chi0[(0, 0)].re, chi0[(1, 1)].re, chi0[(0, 1)].re, chi0[(0, 1)].im,
+                QPoint::largest_eigenvalue(&chi0), rpa, qpoint.critical_u()
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// 電子数ごとに最初に不安定になるq点と、それが6サイトの単位胞で表せるか
+pub fn write_instabilities_to_dat(susceptibilities : &[Susceptibility], path : &str) -> std::io::Result<()>{
+    let mut file = std::fs::File::create(path)?;
+
+    writeln!(file, "# n,mu,point,i,j,qx,qy,u_c,in_phase,fits_six_site_cell")?;
+    for susceptibility in susceptibilities {
+        let leading = susceptibility.leading();
+        writeln!(
+            file, "{},{},{},{},{},{},{},{},{},{}",
+            susceptibility.n, susceptibility.mu, leading.point.name(), leading.index.0, leading.index.1,
+            leading.q.x, leading.q.y, leading.critical_u(), leading.in_phase(), leading.point.fits_six_site_cell() as u8
+        )?;
+    }
+
+    Ok(())
+}
@@ -1,8 +1,10 @@
+pub mod run; //実行設定ファイルの読み込みとサブコマンドの実行
+pub mod interaction; //相互作用の平均場近似など、ハミルトニアンを自己無撞着に決めるところ
 pub mod honeycomb; //BZ内でのメッシュの取り方
 pub mod system; //ハミルトニアンの定義、対角化を行うところ
 pub mod consts;  //計算に必要な定数を定義するところ
 
 //-------------------------------------------------
-// metric -> honeycomb -> system -> consts
+// run -> metric -> interaction -> honeycomb -> system -> consts
 // 下層が上層を参照しないように設計している
 //-------------------------------------------------
@@ -0,0 +1,865 @@
+use crate::honeycomb::{
+    adaptive::{adaptive_calculate_tanzaku, AdaptiveSetting},
+    chern::{bulk_gap, chern_numbers, write_edge_modes_to_dat},
+    hofstadter::Hofstadter,
+    kpm::DisorderAverage,
+    band_path::{band_structure, supercell_band_structure, write_bands_to_dat, BandPath},
+    compare::{compare_candidates, CompareResult},
+    effective_mass::{band_curvature_at_extrema, write_band_curvature_to_dat},
+    gap_finder::{find_nodes, write_nodes_to_dat, GapSearchSetting},
+    height_map::AllHeightMaps,
+    honeycomb_grids::Grids,
+    impurity::{BoundStateScan, ImpurityLdos, RealSpaceGreen},
+    magnetic_group::{check_tanzakus, MagneticPointGroup},
+    parallelization::parallel_calculate_tanzaku,
+    phase_diagram::{PhaseAxis, PhaseDiagram},
+    qpi::QpiMap,
+    spectral::SpectralMap,
+    symmetry::calculate_tanzaku_in_wedge,
+    tanzaku::Tanzakus,
+    thermodynamics::ThermoComparison,
+    unfolding::{unfolded_band_structure, write_unfolded_bands_to_dat, UnfoldedFermiSurface},
+    util::GridInfo,
+};
+use crate::interaction::{
+    hubbard::{solve_presets, write_solutions_to_dat, HubbardSetting},
+    phase_diagram::MeanFieldPhaseDiagram,
+    rkky::{write_classical_energies_to_dat, write_couplings_to_dat, RkkyCouplings, RkkyHost},
+    susceptibility::{write_instabilities_to_dat, write_qmap_to_dat, Susceptibility},
+};
+use crate::run::{
+    config::{invalid, Command, Observable, RunConfig, Sampling},
+    sweep::{Axis, JobStatus, Sweep, SweepJob, SweepPoint},
+};
+use crate::system::{
+    model::{Param, System},
+    disorder::DisorderSetting,
+    flake::{Flake, FlakeShape, LocalChernMarker},
+    ribbon::{write_ribbon_bands_to_dat, Ribbon},
+    supercell::write_supercell_energies_to_dat,
+};
+
+use rayon::prelude::*;
+use std::io::{Result as IoResult, Write};
+
+//対称性で禁止された成分を非零とみなす閾値
+const SYMMETRY_TOLERANCE : f64 = 1e-8;
+
+/// サブコマンドを実行する
+pub fn run(command : Command, config : &RunConfig) -> IoResult<()>{
+    std::fs::create_dir_all(&config.output.dir)?;
+
+    match command {
+        Command::Bands => run_bands(config),
+        Command::Contours => run_contours(config),
+        Command::Tanzaku => run_tanzaku(config),
+        Command::Compare => run_compare(config),
+        Command::Sweep => run_sweep(config),
+        Command::PhaseDiagram => run_phase_diagram(config),
+        Command::Thermodynamics => run_thermodynamics(config),
+        Command::Hubbard => run_hubbard(config),
+        Command::HubbardPhaseDiagram => run_hubbard_phase_diagram(config),
+        Command::Rkky => run_rkky(config),
+        Command::Susceptibility => run_susceptibility(config),
+        Command::Supercell => run_supercell(config),
+        Command::Unfold => run_unfold(config),
+        Command::Spectral => run_spectral(config),
+        Command::Qpi => run_qpi(config),
+        Command::Impurity => run_impurity(config),
+        Command::Ribbon => run_ribbon(config),
+        Command::Flake => run_flake(config),
+        Command::Kpm => run_kpm(config),
+        Command::Hofstadter => run_hofstadter(config),
+    }
+}
+
+//----------------------------------------------------------------
+// bands: 高対称点を結ぶ経路でのバンド構造
+//----------------------------------------------------------------
+fn run_bands(config : &RunConfig) -> IoResult<()>{
+    let system = config.system()?;
+
+    let path = BandPath::from_labels(&config.bands.path, system.size(), config.bands.points_per_segment)
+        .ok_or_else(|| invalid(format!("unknown high symmetry point in {:?}", config.bands.path)))?;
+    let bands = band_structure(&system, &path);
+
+    let file_path = format!("{}/bands_{}.dat", config.output.dir, system.debug());
+    write_bands_to_dat(&bands, &path, &file_path)?;
+    println!("Band structure written to {}", file_path);
+
+    Ok(())
+}
+
+//----------------------------------------------------------------
+// contours: フェルミ面の等高線
+//----------------------------------------------------------------
+fn run_contours(config : &RunConfig) -> IoResult<()>{
+    let system = config.system()?;
+    let calc_setting = config.calc_setting();
+
+    let grids = Grids::build(calc_setting, system.clone(), GridInfo::no_divide());
+    let height_map = AllHeightMaps::build(&grids);
+
+    let file_path = format!("{}/contour_lines_{}_{}.dat", config.output.dir, system.debug(), calc_setting.debug());
+    height_map.write_to_dat(&file_path, system.size())?;
+    println!("Contour data written to {}", file_path);
+
+    Ok(())
+}
+
+//----------------------------------------------------------------
+// tanzaku: Tanzakus と observables に指定された物理量
+//----------------------------------------------------------------
+fn run_tanzaku(config : &RunConfig) -> IoResult<()>{
+    let system = config.system()?;
+    let calc_setting = config.calc_setting();
+    let dir = &config.output.dir;
+
+    let wants = |observable : Observable| config.observables.contains(&observable);
+
+    if wants(Observable::Symmetry) {
+        let group = MagneticPointGroup::from_system(&system, &calc_setting);
+        let file_path = format!("{}/symmetry_{}.dat", dir, system.debug());
+        let mut file = std::fs::File::create(&file_path)?;
+        writeln!(file, "# group_type,order,unitary_order,operations")?;
+        writeln!(file, "{},{},{},{}", group.group_type(), group.order(), group.unitary_order(), group.labels().join(" "))?;
+        writeln!(file, "# {}", group.allowed_responses().summary())?;
+        println!("Magnetic point group written to {}", file_path);
+    }
+
+    if wants(Observable::Tanzaku) {
+        let tanzakus = calculate_tanzakus(config, &system)?;
+
+        let tanzakus: Tanzakus = match config.tanzaku.interpolate_n {
+            Some(n_div) => tanzakus.interpolate_by_n(n_div),
+            None => tanzakus,
+        };
+        tanzakus.write_to_dat(Some(dir))?;
+        println!("Tanzakus written to {}", dir);
+    }
+
+    if wants(Observable::Nodes) {
+        let setting = GapSearchSetting::standard();
+        let nodes: Vec<_> = (0..2)
+            .flat_map(|spin| (0..system.size() - 1).map(move |band_num| (spin, band_num)))
+            .flat_map(|(spin, band_num)| find_nodes(&system, spin, band_num, &setting))
+            .collect();
+
+        let file_path = format!("{}/nodes_{}.dat", dir, system.debug());
+        write_nodes_to_dat(&nodes, &file_path)?;
+        println!("Nodes written to {}", file_path);
+    }
+
+    if wants(Observable::BandCurvature) {
+        let curvatures = band_curvature_at_extrema(&system, &calc_setting);
+
+        let file_path = format!("{}/band_curvature_{}.dat", dir, system.debug());
+        write_band_curvature_to_dat(&curvatures, &file_path)?;
+        println!("Band curvature written to {}", file_path);
+    }
+
+    Ok(())
+}
+
+//tanzaku.sampling に従ってTanzakusを計算する
+//observables に symmetry があれば対称性で禁止された成分を検査する
+fn calculate_tanzakus(config : &RunConfig, system : &System) -> IoResult<Tanzakus>{
+    let calc_setting = config.calc_setting();
+
+    let tanzakus = match config.tanzaku.sampling {
+        Sampling::Full => parallel_calculate_tanzaku(calc_setting, system.clone()),
+        Sampling::Wedge => calculate_tanzaku_in_wedge(calc_setting, system.clone()),
+        Sampling::Adaptive => {
+            let adaptive = adaptive_calculate_tanzaku(calc_setting, system.clone(), AdaptiveSetting::standard());
+            adaptive.write_tiles_to_dat(&format!("{}/tiles_{}_{}.dat", config.output.dir, system.debug(), calc_setting.debug()))?;
+            adaptive.tanzakus
+        }
+    };
+
+    if config.observables.contains(&Observable::Symmetry) {
+        let group = MagneticPointGroup::from_system(system, &calc_setting);
+        for violation in check_tanzakus(&tanzakus, &group.allowed_responses(), SYMMETRY_TOLERANCE) {
+            eprintln!(
+                "warning: {} of {} is forbidden by symmetry but is {:e} at n = {}, energy = {}",
+                violation.quantity, system.debug(), violation.value, violation.n, violation.energy
+            );
+        }
+    }
+
+    Ok(tanzakus)
+}
+
+//----------------------------------------------------------------
+// compare: スピン配置の比較
+//----------------------------------------------------------------
+fn run_compare(config : &RunConfig) -> IoResult<()>{
+    let systems = compare_systems(config)?;
+
+    let result = compare_candidates(&systems, config.calc_setting(), config.compare.options()).map_err(invalid)?;
+    result.write_to_dat(&config.output.dir)?;
+    println!("Comparison written to {}", config.output.dir);
+
+    Ok(())
+}
+
+//compare.spins のスピン配置（[hubbard] があればそれぞれを初期値とした平均場の解）
+fn compare_systems(config : &RunConfig) -> IoResult<Vec<System>>{
+    let systems = config.compare.spins.iter()
+        .map(|spin| config.system_with_spin(spin))
+        .collect::<IoResult<Vec<System>>>()?;
+
+    if systems.is_empty() {
+        return Err(invalid("compare.spins should not be empty".to_string()));
+    }
+
+    Ok(systems)
+}
+
+//----------------------------------------------------------------
+// phase_diagram: (lambda または jj, n) の相図と相境界
+//----------------------------------------------------------------
+fn run_phase_diagram(config : &RunConfig) -> IoResult<()>{
+    let phase_config = config.phase_diagram.as_ref()
+        .ok_or_else(|| invalid("[phase_diagram] section is required for the phase_diagram command".to_string()))?;
+    //平均場はパラメーターごとに解き直す必要があるので、相図の縦軸に沿って場を使い回せない
+    if config.hubbard.is_some() {
+        return Err(invalid("phase_diagram does not support [hubbard]; use sweep instead".to_string()));
+    }
+
+    let axis = phase_config.axis.to_axis()?;
+    let phase_axis = match axis.axis {
+        Axis::Lambda => PhaseAxis::Lambda,
+        Axis::Jj => PhaseAxis::Jj,
+        Axis::Filling => return Err(invalid("phase_diagram.axis should be lambda or jj".to_string())),
+    };
+
+    let systems = compare_systems(config)?;
+    let diagram = PhaseDiagram::build(&systems, phase_axis, &axis.values, config.calc_setting(), config.compare.options()).map_err(invalid)?;
+    let boundaries = diagram.boundaries(phase_config.tolerance);
+
+    let file_path = output_path(Command::PhaseDiagram, config);
+    diagram.write_to_dat(&file_path)?;
+    println!("Phase diagram written to {}", file_path);
+
+    let file_path = file_path.replacen("phase_diagram_", "phase_boundaries_", 1);
+    diagram.write_boundaries_to_dat(&boundaries, &file_path)?;
+    println!("{} phase boundaries written to {}", boundaries.len(), file_path);
+
+    Ok(())
+}
+
+//----------------------------------------------------------------
+// thermodynamics: μ 固定の Ω(μ, T) と n 固定の F(n, T) による比較、Maxwell構成
+//----------------------------------------------------------------
+fn run_thermodynamics(config : &RunConfig) -> IoResult<()>{
+    let thermo_config = config.thermodynamics;
+    let systems = compare_systems(config)?;
+    let comparison = ThermoComparison::new(&systems, thermo_config.temperature, config.compare.energy_mesh);
+
+    let (e_min, e_max) = comparison.energy_range();
+    let (mu_start, mu_stop) = (thermo_config.mu_start.unwrap_or(e_min), thermo_config.mu_stop.unwrap_or(e_max));
+    let mus: Vec<f64> = (0..thermo_config.mu_steps)
+        .map(|i| mu_start + (mu_stop - mu_start) * i as f64 / (thermo_config.mu_steps.max(2) - 1) as f64)
+        .collect();
+
+    let grand_potentials = comparison.at_chemical_potentials(&mus);
+    let free_energies = comparison.at_fillings(config.compare.n_div);
+    let separations = comparison.phase_separations(&free_energies);
+
+    let file_path = output_path(Command::Thermodynamics, config);
+    comparison.write_free_energy_to_dat(&free_energies, &file_path)?;
+    println!("Free energies written to {}", file_path);
+
+    let file_path = file_path.replacen("free_energy_", "grand_potential_", 1);
+    comparison.write_grand_potential_to_dat(&grand_potentials, &file_path)?;
+    println!("Grand potentials written to {}", file_path);
+
+    let file_path = file_path.replacen("grand_potential_", "phase_separation_", 1);
+    comparison.write_phase_separations_to_dat(&separations, &file_path)?;
+    println!("{} phase separated regions written to {}", separations.len(), file_path);
+
+    Ok(())
+}
+
+//----------------------------------------------------------------
+// hubbard: compare.spins を初期値としたHubbard平均場の解
+//----------------------------------------------------------------
+fn run_hubbard(config : &RunConfig) -> IoResult<()>{
+    let hubbard = config.hubbard
+        .ok_or_else(|| invalid("[hubbard] section is required for the hubbard command".to_string()))?;
+
+    let seeds = config.compare.spins.iter()
+        .map(|spin| config.system.build_with_spin(spin))
+        .collect::<IoResult<Vec<System>>>()?;
+    let solutions = solve_presets(&seeds, hubbard.to_setting());
+
+    let file_path = output_path(Command::Hubbard, config);
+    write_solutions_to_dat(&solutions, &file_path)?;
+    println!("Mean field solutions written to {} (lowest: {})", file_path, solutions[0].system.debug_only_name());
+
+    Ok(())
+}
+
+//----------------------------------------------------------------
+// hubbard_phase_diagram: (U, V, n) の各点で最も低い平均場の解とその観測量
+//----------------------------------------------------------------
+fn run_hubbard_phase_diagram(config : &RunConfig) -> IoResult<()>{
+    let phase_config = config.hubbard_phase_diagram.as_ref()
+        .ok_or_else(|| invalid("[hubbard_phase_diagram] section is required for the hubbard_phase_diagram command".to_string()))?;
+    let base = config.hubbard.map_or(HubbardSetting::standard(0.0, 0.0), |hubbard| hubbard.to_setting());
+
+    let seeds = config.compare.spins.iter()
+        .map(|spin| config.system.build_with_spin(spin))
+        .collect::<IoResult<Vec<System>>>()?;
+    let diagram = MeanFieldPhaseDiagram::build(&seeds, base, &phase_config.u, &phase_config.v, &phase_config.filling, config.calc_setting());
+
+    let file_path = output_path(Command::HubbardPhaseDiagram, config);
+    diagram.write_to_dat(&file_path)?;
+    println!("Mean field phase diagram written to {}", file_path);
+
+    Ok(())
+}
+
+//----------------------------------------------------------------
+// rkky: 常磁性の母体から求めた J_ij と、それによる各スピン配置の古典的なエネルギー
+//----------------------------------------------------------------
+fn run_rkky(config : &RunConfig) -> IoResult<()>{
+    let family = &config.system.family;
+    let host = RkkyHost::new(family, config.system.param(), config.rkky.to_setting()).map_err(invalid)?;
+
+    let candidates = config.compare.spins.iter()
+        .map(|spin| config.system.build_with_spin(spin))
+        .collect::<IoResult<Vec<System>>>()?;
+    let couplingss: Vec<RkkyCouplings> = config.rkky.fillings.iter().map(|&n| host.couplings(n)).collect();
+
+    let file_path = output_path(Command::Rkky, config);
+    write_couplings_to_dat(&couplingss, &file_path)?;
+    println!("RKKY couplings written to {}", file_path);
+
+    let file_path = file_path.replacen("rkky_", "rkky_energy_", 1);
+    write_classical_energies_to_dat(&couplingss, &candidates, &file_path)?;
+    println!("Classical energies written to {}", file_path);
+
+    Ok(())
+}
+
+//----------------------------------------------------------------
+// susceptibility: 電子数ごとの χ(q) と最初に不安定になる秩序の波数
+//----------------------------------------------------------------
+fn run_susceptibility(config : &RunConfig) -> IoResult<()>{
+    let susceptibility_config = &config.susceptibility;
+    let setting = susceptibility_config.to_setting();
+    if !setting.q_mesh.is_multiple_of(6) {
+        eprintln!("warning: susceptibility.q_mesh = {} does not contain both K and M", setting.q_mesh);
+    }
+
+    let family = &config.system.family;
+    let susceptibilities = susceptibility_config.fillings.iter()
+        .map(|&n| Susceptibility::new(family, config.system.lambda, n, setting))
+        .collect::<Result<Vec<Susceptibility>, String>>()
+        .map_err(invalid)?;
+
+    let file_path = output_path(Command::Susceptibility, config);
+    write_qmap_to_dat(&susceptibilities, susceptibility_config.u, &file_path)?;
+    println!("Susceptibilities written to {}", file_path);
+
+    let file_path = file_path.replacen("susceptibility_", "instability_", 1);
+    write_instabilities_to_dat(&susceptibilities, &file_path)?;
+    println!("Leading instabilities written to {}", file_path);
+
+    Ok(())
+}
+
+//----------------------------------------------------------------
+// supercell: 任意の超格子でのスピン配置のバンドと全エネルギー
+//----------------------------------------------------------------
+fn run_supercell(config : &RunConfig) -> IoResult<()>{
+    let supercell_config = config.supercell.as_ref()
+        .ok_or_else(|| invalid("[supercell] section is required for the supercell command".to_string()))?;
+
+    let systems = supercell_config.systems(&config.system)?;
+    let supercell = supercell_config.supercell()?;
+    println!("Supercell {:?}: {} sites", supercell.matrix, supercell.size());
+
+    let path = BandPath::from_supercell_labels(&supercell_config.path, &supercell, supercell_config.points_per_segment)
+        .ok_or_else(|| invalid(format!("unknown high symmetry point in {:?}", supercell_config.path)))?;
+    for system in &systems {
+        let bands = supercell_band_structure(system, &path);
+        let file_path = format!(
+            "{}/supercell_bands_{}_{}_{}_{}.dat",
+            config.output.dir, config.system.family, supercell.debug(), system.name, system.param.debug()
+        );
+        write_bands_to_dat(&bands, &path, &file_path)?;
+        println!("Band structure written to {}", file_path);
+    }
+
+    let energies: Vec<Vec<f64>> = systems.par_iter()
+        .map(|system| system.e_vs_n(supercell_config.energy_mesh, supercell_config.n_div))
+        .collect();
+
+    let file_path = output_path(Command::Supercell, config);
+    write_supercell_energies_to_dat(&systems, &energies, &file_path)?;
+    println!("Total energies written to {}", file_path);
+
+    Ok(())
+}
+
+//----------------------------------------------------------------
+// unfold: 6サイトのバンドとフェルミ面を基本単位胞のブリルアンゾーンに展開する
+//----------------------------------------------------------------
+fn run_unfold(config : &RunConfig) -> IoResult<()>{
+    let system = config.system()?;
+    let unfolding = &config.unfolding;
+
+    let path = BandPath::from_labels(&unfolding.path, 2, unfolding.points_per_segment)
+        .ok_or_else(|| invalid(format!("unknown high symmetry point in {:?}", unfolding.path)))?;
+    let bands = unfolded_band_structure(&system, &path);
+
+    let file_path = output_path(Command::Unfold, config);
+    write_unfolded_bands_to_dat(&bands, &path, &file_path)?;
+    println!("Unfolded band structure written to {}", file_path);
+
+    for &n in &unfolding.fillings {
+        let surface = UnfoldedFermiSurface::build(&system, n, unfolding.mesh, unfolding.energy_mesh, unfolding.broadening);
+        let file_path = format!(
+            "{}/unfolded_fermi_surface_{}_n{}.dat",
+            config.output.dir, system.debug(), format!("{:.4}", n).replace('.', "p")
+        );
+        surface.write_to_dat(&file_path)?;
+        println!("Unfolded Fermi surface at n = {} (mu = {}) written to {}", n, surface.mu, file_path);
+    }
+
+    Ok(())
+}
+
+//----------------------------------------------------------------
+// spectral: スペクトル関数の E-k 断面と等エネルギー面（スピン分解 ARPES）
+//----------------------------------------------------------------
+fn run_spectral(config : &RunConfig) -> IoResult<()>{
+    let system = config.system()?;
+    let spectral = &config.spectral;
+    let setting = spectral.to_setting();
+
+    let size = if spectral.unfold { 2 } else { system.size() };
+    let path = BandPath::from_labels(&spectral.path, size, spectral.points_per_segment)
+        .ok_or_else(|| invalid(format!("unknown high symmetry point in {:?}", spectral.path)))?;
+    let map = SpectralMap::energy_momentum_cut(&system, spectral.filling, &path, &spectral.omegas(), setting);
+
+    let file_path = output_path(Command::Spectral, config);
+    map.write_to_dat(&file_path, Some(&path))?;
+    println!("E-k cut (mu = {}) written to {}", map.mu, file_path);
+
+    for &omega in &spectral.cut_energies {
+        let map = SpectralMap::constant_energy_cut(&system, spectral.filling, omega, spectral.mesh, setting);
+        let stem = file_path.trim_end_matches(".dat").replacen("spectral_ek_", "spectral_cut_", 1);
+        let cut_path = format!("{}_w{}.dat", stem, format!("{:.4}", omega).replace('.', "p").replace('-', "m"));
+        map.write_to_dat(&cut_path, None)?;
+        println!("Constant energy cut at omega = {} written to {}", omega, cut_path);
+    }
+
+    Ok(())
+}
+
+//----------------------------------------------------------------
+// qpi: 点不純物による準粒子干渉と JDOS（スピン配置ごと）
+//----------------------------------------------------------------
+fn run_qpi(config : &RunConfig) -> IoResult<()>{
+    let qpi = &config.qpi;
+    let spins = if qpi.spins.is_empty() { vec![config.system.spin.clone()] } else { qpi.spins.clone() };
+
+    for spin in &spins {
+        let system = config.system_with_spin(spin)?;
+        if qpi.site >= system.size() {
+            return Err(invalid(format!("qpi.site = {} is out of the {}-site cell of {}", qpi.site, system.size(), system.debug())));
+        }
+
+        let maps = QpiMap::build_all(system.clone(), config.calc_setting(), qpi.filling, &qpi.energies, qpi.to_setting());
+        for map in &maps {
+            let file_path = qpi_path(config, &system, map.omega);
+            map.write_to_dat(&file_path)?;
+            println!("QPI at omega = {} (mu = {}) written to {}", map.omega, map.mu, file_path);
+        }
+    }
+
+    Ok(())
+}
+
+//----------------------------------------------------------------
+// impurity: 点不純物のまわりの局所状態密度とギャップの中の束縛状態（スピン配置ごと）
+//----------------------------------------------------------------
+fn run_impurity(config : &RunConfig) -> IoResult<()>{
+    let impurity = &config.impurity;
+    let spins = if impurity.spins.is_empty() { vec![config.system.spin.clone()] } else { impurity.spins.clone() };
+
+    for spin in &spins {
+        let system = config.system_with_spin(spin)?;
+        if impurity.site >= system.size() {
+            return Err(invalid(format!("impurity.site = {} is out of the {}-site cell of {}", impurity.site, system.size(), system.debug())));
+        }
+
+        let grids = Grids::build(config.calc_setting(), system.clone(), GridInfo::no_divide());
+        let green = RealSpaceGreen::new(&grids);
+        let mu = green.chemical_potential(impurity.filling);
+
+        for &omega in &impurity.energies {
+            let ldos = ImpurityLdos::build(&green, impurity.filling, mu, omega, impurity.impurity(), impurity.broadening(), impurity.radius);
+            let file_path = impurity_ldos_path(config, &system, omega);
+            ldos.write_to_dat(&file_path)?;
+            println!("LDOS at omega = {} (mu = {}) written to {}", omega, mu, file_path);
+        }
+
+        let scan = BoundStateScan::build(&green, impurity.filling, impurity.site, impurity.magnetic, &impurity.potentials(), impurity.min_gap);
+        let file_path = impurity_bound_path(config, &system);
+        scan.write_to_dat(&file_path)?;
+        println!("bound states (gap up = {:?}, gap down = {:?}) written to {}", scan.gap[0], scan.gap[1], file_path);
+    }
+
+    Ok(())
+}
+
+//----------------------------------------------------------------
+// ribbon: ナノリボンのバンドと端の状態をバルクの Chern 数と比べる（スピン配置ごと）
+//----------------------------------------------------------------
+fn run_ribbon(config : &RunConfig) -> IoResult<()>{
+    let ribbon_config = &config.ribbon;
+    let edges = ribbon_config.edges()?;
+    let spins = if ribbon_config.spins.is_empty() { vec![config.system.spin.clone()] } else { ribbon_config.spins.clone() };
+
+    for spin in &spins {
+        let system = config.system_with_spin(spin)?;
+        let (top, bottom) = bulk_gap(&system, ribbon_config.filling, ribbon_config.energy_mesh);
+        let e_fermi = 0.5 * (top + bottom);
+        let grids = Grids::build(config.calc_setting(), system.clone(), GridInfo::no_divide());
+        let chern = chern_numbers(&grids, e_fermi);
+
+        let mut counts = Vec::new();
+        for &edge in &edges {
+            let ribbon = Ribbon::new(&system, edge, ribbon_config.width).map_err(invalid)?;
+            let bands = ribbon.band_structure(ribbon_config.k_points, ribbon_config.edge_depth);
+            let file_path = format!("{}/ribbon_bands_{}.dat", config.output.dir, ribbon.debug());
+            write_ribbon_bands_to_dat(&ribbon, &bands, &file_path)?;
+            println!("{} ribbon ({} sites, period {}) written to {}", edge.name(), ribbon.size(), ribbon.period, file_path);
+
+            let count = ribbon.edge_modes(ribbon_config.k_points, ribbon_config.edge_depth, e_fermi);
+            println!(
+                "  C = ({:.3}, {:.3}), edge modes at {}: up (lower {}, upper {}), down (lower {}, upper {})",
+                chern[0].chern, chern[1].chern, e_fermi, count[0].lower, count[0].upper, count[1].lower, count[1].upper
+            );
+            counts.push((edge, count));
+        }
+
+        let file_path = ribbon_edge_modes_path(config, &system);
+        write_edge_modes_to_dat(&counts, &chern, e_fermi, &file_path)?;
+        println!("edge modes and Chern numbers written to {}", file_path);
+    }
+
+    Ok(())
+}
+
+//----------------------------------------------------------------
+// flake: 有限のフレークの局所 Chern マーカーをバルクの Chern 数と比べる（スピン配置、乱れの配置ごと）
+//----------------------------------------------------------------
+fn run_flake(config : &RunConfig) -> IoResult<()>{
+    let flake_config = &config.flake;
+    let shape = flake_config.shape()?;
+    let spins = if flake_config.spins.is_empty() { vec![config.system.spin.clone()] } else { flake_config.spins.clone() };
+
+    for spin in &spins {
+        let system = config.system_with_spin(spin)?;
+        let (top, bottom) = bulk_gap(&system, flake_config.filling, flake_config.energy_mesh);
+        let e_fermi = 0.5 * (top + bottom);
+        let grids = Grids::build(config.calc_setting(), system.clone(), GridInfo::no_divide());
+        let chern = chern_numbers(&grids, e_fermi);
+        let clean = Flake::new(&system, shape).map_err(invalid)?;
+        println!("{} flake of {} with {} sites, C = ({:.3}, {:.3}) at e_fermi = {}", shape.name(), system.debug(), clean.size(), chern[0].chern, chern[1].chern, e_fermi);
+
+        for seed in flake_config.seeds() {
+            let flake = clean.clone().with_anderson(flake_config.anderson, seed);
+            let marker = LocalChernMarker::build(&flake, e_fermi);
+            marker.write_levels_to_dat(&format!("{}/flake_levels_{}.dat", config.output.dir, flake.debug()))?;
+            let file_path = flake_marker_path(config, &system, shape, seed);
+            marker.write_to_dat(&flake, &file_path)?;
+
+            let [up, down] = marker.bulk_average(&flake, flake_config.bulk_radius);
+            println!("  W = {}, seed = {}: marker within {} = ({:.4}, {:.4}) written to {}", flake.anderson, seed, flake_config.bulk_radius, up, down, file_path);
+        }
+    }
+
+    Ok(())
+}
+
+//----------------------------------------------------------------
+// kpm: 乱れのあるスーパーセルの状態密度と Hall 伝導度をきれいな系の Tanzakus と比べる（スピン配置、乱れの強さごと）
+//----------------------------------------------------------------
+fn run_kpm(config : &RunConfig) -> IoResult<()>{
+    let kpm_config = &config.kpm;
+    let energies = kpm_config.energies();
+    let spins = if kpm_config.spins.is_empty() { vec![config.system.spin.clone()] } else { kpm_config.spins.clone() };
+
+    for spin in &spins {
+        let system = config.system_with_spin(spin)?;
+        let clean = if kpm_config.clean { Some(calculate_tanzakus(config, &system)?) } else { None };
+
+        for settings in kpm_config.disorders() {
+            let average = DisorderAverage::build(&system, kpm_config.cells, &settings, kpm_config.kpm_setting(), &energies).map_err(invalid)?;
+            let file_path = kpm_path(config, &system, &settings[0]);
+            average.write_to_dat(clean.as_ref(), &file_path)?;
+            println!(
+                "KPM of {} ({} x {} cells, {}, {} realizations) written to {}",
+                system.debug(), kpm_config.cells[0], kpm_config.cells[1], settings[0].debug(), settings.len(), file_path
+            );
+        }
+    }
+
+    Ok(())
+}
+
+//----------------------------------------------------------------
+// hofstadter: 磁束ごとのバンド（バタフライ）とギャップの Chern 数（スピン配置ごと）
+//----------------------------------------------------------------
+fn run_hofstadter(config : &RunConfig) -> IoResult<()>{
+    let hofstadter_config = &config.hofstadter;
+    let fluxes = hofstadter_config.fluxes();
+    let spins = if hofstadter_config.spins.is_empty() { vec![config.system.spin.clone()] } else { hofstadter_config.spins.clone() };
+
+    for spin in &spins {
+        let system = config.system_with_spin(spin)?;
+        let hofstadter = Hofstadter::build(&system, &fluxes, hofstadter_config.k_points, hofstadter_config.min_gap).map_err(invalid)?;
+
+        let file_path = format!("{}/hofstadter_bands_{}_q{}.dat", config.output.dir, system.debug(), hofstadter_config.q_max);
+        hofstadter.write_bands_to_dat(&file_path)?;
+        println!("Hofstadter spectrum of {} ({} fluxes) written to {}", system.debug(), fluxes.len(), file_path);
+
+        let file_path = hofstadter_gaps_path(config, &system);
+        hofstadter.write_gaps_to_dat(&file_path)?;
+        let resolved = hofstadter.gaps.iter().filter(|gap| gap.chern.is_some()).count();
+        println!("{} gaps ({} with Streda Chern numbers) written to {}", hofstadter.gaps.len(), resolved, file_path);
+    }
+
+    Ok(())
+}
+
+//----------------------------------------------------------------
+// sweep: パラメーターを変えながらサブコマンドを繰り返す
+//----------------------------------------------------------------
+fn run_sweep(config : &RunConfig) -> IoResult<()>{
+    let sweep_config = config.sweep.as_ref()
+        .ok_or_else(|| invalid("[sweep] section is required for the sweep command".to_string()))?;
+
+    let axes = sweep_config.axes.iter().map(|axis| axis.to_axis()).collect::<IoResult<Vec<_>>>()?;
+    let sweep = Sweep::new(config.system.param(), axes)?;
+
+    match sweep_config.command {
+        Command::Sweep | Command::PhaseDiagram | Command::Thermodynamics | Command::Hubbard | Command::HubbardPhaseDiagram => return Err(invalid(format!("sweep.command should not be {:?}", sweep_config.command))),
+        Command::Tanzaku => {}
+        _ if sweep.has_filling() => return Err(invalid("filling axis is only supported for the tanzaku command".to_string())),
+        _ => {}
+    }
+
+    let manifest = sweep_config.manifest.clone()
+        .unwrap_or_else(|| format!("{}/sweep_manifest.dat", config.output.dir));
+
+    let job = CommandJob { config : config.clone(), command : sweep_config.command };
+    let entries = sweep.run(&job, &manifest)?;
+
+    let count = |status : JobStatus| entries.iter().filter(|entry| entry.status == status).count();
+    println!(
+        "Sweep finished: {} computed, {} skipped, {} failed (manifest: {})",
+        count(JobStatus::Computed), count(JobStatus::Skipped), count(JobStatus::Failed), manifest
+    );
+
+    Ok(())
+}
+
+/// 掃引の各点でサブコマンドを実行するジョブ
+pub struct CommandJob{
+    pub config : RunConfig,
+    pub command : Command,
+}
+
+impl CommandJob{
+    fn config_at(&self, param : Param) -> RunConfig{
+        let mut config = self.config.clone();
+        config.system.lambda = param.lambda;
+        config.system.jj = param.jj;
+        config
+    }
+}
+
+impl SweepJob for CommandJob{
+    fn output_path(&self, point : &SweepPoint) -> String{
+        let config = self.config_at(point.param);
+        match point.filling {
+            Some(n) => filling_path(&config, n),
+            None => output_path(self.command, &config),
+        }
+    }
+    fn run(&self, param : Param, points : &[SweepPoint]) -> IoResult<()>{
+        let config = self.config_at(param);
+
+        if points.iter().all(|point| point.filling.is_none()) {
+            return run(self.command, &config);
+        }
+
+        //電子数を掃引する場合は一度だけTanzakusを計算し、各電子数での値を出力する
+        std::fs::create_dir_all(&config.output.dir)?;
+        let tanzakus = calculate_tanzakus(&config, &config.system()?)?;
+
+        for point in points {
+            let n = point.filling.unwrap();
+            let tanzaku = tanzakus.linear_interpolate_at_n(n);
+
+            let mut file = std::fs::File::create(filling_path(&config, n))?;
+            writeln!(file, "# n,energy,berry,bcd_x,bcd_y,qmd_x,qmd_y")?;
+            writeln!(file, "{},{},{},{},{},{},{}", tanzaku.n, tanzaku.energy, tanzaku.berry, tanzaku.bcd.x, tanzaku.bcd.y, tanzaku.qmd.x, tanzaku.qmd.y)?;
+        }
+
+        Ok(())
+    }
+}
+
+//サブコマンドの主な出力ファイルのパス（掃引で計算済みかの判定に使う）
+fn output_path(command : Command, config : &RunConfig) -> String{
+    let dir = &config.output.dir;
+    let calc_setting = config.calc_setting();
+    let system = match config.system() {
+        Ok(system) => system,
+        Err(_) => return String::new(),
+    };
+
+    //スピン配置ごとに出力するコマンドは、最後のスピン配置で最後に書くファイルを完了の目印にする
+    let last_spin = |spins : &[String]| match spins.last() {
+        Some(spin) => config.system_with_spin(spin).ok(),
+        None => Some(system.clone()),
+    };
+
+    match command {
+        Command::Bands => format!("{}/bands_{}.dat", dir, system.debug()),
+        Command::Ribbon => last_spin(&config.ribbon.spins)
+            .map(|system| ribbon_edge_modes_path(config, &system))
+            .unwrap_or_default(),
+        Command::Hofstadter => last_spin(&config.hofstadter.spins)
+            .map(|system| hofstadter_gaps_path(config, &system))
+            .unwrap_or_default(),
+        Command::Kpm => last_spin(&config.kpm.spins)
+            .zip(config.kpm.disorders().last().cloned())
+            .map(|(system, settings)| kpm_path(config, &system, &settings[0]))
+            .unwrap_or_default(),
+        Command::Flake => last_spin(&config.flake.spins)
+            .zip(config.flake.shape().ok())
+            .map(|(system, shape)| flake_marker_path(config, &system, shape, *config.flake.seeds().last().unwrap()))
+            .unwrap_or_default(),
+        Command::Impurity => last_spin(&config.impurity.spins)
+            .map(|system| impurity_bound_path(config, &system))
+            .unwrap_or_default(),
+        Command::Qpi => last_spin(&config.qpi.spins)
+            .map(|system| qpi_path(config, &system, config.qpi.energies.last().copied().unwrap_or_default()))
+            .unwrap_or_default(),
+        Command::Spectral => {
+            let unfolded = if config.spectral.unfold { "_unfolded" } else { "" };
+            let filling = format!("{:.4}", config.spectral.filling).replace('.', "p");
+            format!("{}/spectral_ek_{}{}_n{}.dat", dir, system.debug(), unfolded, filling)
+        }
+        Command::Unfold => format!("{}/unfolded_bands_{}.dat", dir, system.debug()),
+        Command::Contours => format!("{}/contour_lines_{}_{}.dat", dir, system.debug(), calc_setting.debug()),
+        Command::Compare => CompareResult::stable_path(dir, system.param(), &calc_setting),
+        Command::PhaseDiagram => {
+            let axis = config.phase_diagram.as_ref().map(|phase| format!("{:?}", phase.axis.parameter).to_lowercase()).unwrap_or_default();
+            format!("{}/phase_diagram_{}_{}_{}.dat", dir, config.system.family, axis, calc_setting.debug())
+        }
+        Command::Hubbard => {
+            let setting = config.hubbard.map(|hubbard| hubbard.to_setting()).unwrap_or(HubbardSetting::standard(0.0, 0.0));
+            let label = |value : f64| format!("{:.2}", value).replace('.', "p");
+            let v = if setting.is_extended() { format!("_v{}_v2{}", label(setting.v), label(setting.v2)) } else { String::new() };
+            format!(
+                "{}/hubbard_{}_lambda{}_u{}{}_n{}.dat",
+                dir, config.system.family, label(config.system.lambda), label(setting.u), v, label(setting.filling)
+            )
+        }
+        Command::Susceptibility => {
+            let label = |value : f64| format!("{:.2}", value).replace('.', "p");
+            let temperature = format!("{:.4}", config.susceptibility.temperature).replace('.', "p");
+            format!("{}/susceptibility_{}_lambda{}_t{}.dat", dir, config.system.family, label(config.system.lambda), temperature)
+        }
+        Command::Supercell => {
+            let supercell = config.supercell.as_ref().and_then(|supercell| supercell.supercell().ok()).map(|supercell| supercell.debug()).unwrap_or_default();
+            format!("{}/supercell_{}_{}_{}.dat", dir, config.system.family, supercell, config.system.param().debug())
+        }
+        Command::Rkky => format!("{}/rkky_{}_{}.dat", dir, config.system.family, config.system.param().debug()),
+        Command::HubbardPhaseDiagram => {
+            let label = format!("{:.2}", config.system.lambda).replace('.', "p");
+            format!("{}/hubbard_phase_diagram_{}_lambda{}_{}.dat", dir, config.system.family, label, calc_setting.debug())
+        }
+        Command::Thermodynamics => {
+            let temperature = format!("{:.4}", config.thermodynamics.temperature).replace('.', "p");
+            format!("{}/free_energy_{}_{}_t{}.dat", dir, config.system.family, system.param().debug(), temperature)
+        }
+        Command::Tanzaku | Command::Sweep => {
+            let wants = |observable : Observable| config.observables.contains(&observable);
+            if wants(Observable::Tanzaku) {
+                Tanzakus::dat_path(dir, &system, &calc_setting)
+            } else if wants(Observable::Nodes) {
+                format!("{}/nodes_{}.dat", dir, system.debug())
+            } else if wants(Observable::BandCurvature) {
+                format!("{}/band_curvature_{}.dat", dir, system.debug())
+            } else {
+                format!("{}/symmetry_{}.dat", dir, system.debug())
+            }
+        }
+    }
+}
+
+fn qpi_path(config : &RunConfig, system : &System, omega : f64) -> String{
+    format!(
+        "{}/qpi_{}_{}_n{}_w{}.dat",
+        config.output.dir, system.debug(), config.qpi.debug(),
+        format!("{:.4}", config.qpi.filling).replace('.', "p"),
+        format!("{:.4}", omega).replace('.', "p").replace('-', "m")
+    )
+}
+
+fn impurity_ldos_path(config : &RunConfig, system : &System, omega : f64) -> String{
+    format!(
+        "{}/impurity_ldos_{}_{}_n{}_w{}.dat",
+        config.output.dir, system.debug(), config.impurity.debug(),
+        format!("{:.4}", config.impurity.filling).replace('.', "p"),
+        format!("{:.4}", omega).replace('.', "p").replace('-', "m")
+    )
+}
+
+fn impurity_bound_path(config : &RunConfig, system : &System) -> String{
+    let impurity = &config.impurity;
+    let label = if impurity.magnetic { "_mag" } else { "" };
+    format!(
+        "{}/impurity_bound_{}_site{}{}_n{}.dat",
+        config.output.dir, system.debug(), impurity.site, label, format!("{:.4}", impurity.filling).replace('.', "p")
+    )
+}
+
+fn ribbon_edge_modes_path(config : &RunConfig, system : &System) -> String{
+    format!(
+        "{}/ribbon_edge_modes_{}_w{}_n{}.dat",
+        config.output.dir, system.debug(), config.ribbon.width, format!("{:.4}", config.ribbon.filling).replace('.', "p")
+    )
+}
+
+fn flake_marker_path(config : &RunConfig, system : &System, shape : FlakeShape, seed : u64) -> String{
+    let flake = Flake::debug_of(&system.debug(), shape, config.flake.anderson, seed);
+    format!("{}/flake_marker_{}_n{}.dat", config.output.dir, flake, format!("{:.4}", config.flake.filling).replace('.', "p"))
+}
+
+fn kpm_path(config : &RunConfig, system : &System, setting : &DisorderSetting) -> String{
+    format!("{}/kpm_{}_{}x{}_{}.dat", config.output.dir, system.debug(), config.kpm.cells[0], config.kpm.cells[1], setting.debug())
+}
+
+fn hofstadter_gaps_path(config : &RunConfig, system : &System) -> String{
+    format!("{}/hofstadter_gaps_{}_q{}.dat", config.output.dir, system.debug(), config.hofstadter.q_max)
+}
+
+fn filling_path(config : &RunConfig, n : f64) -> String{
+    let system = config.system().map(|system| system.debug()).unwrap_or_default();
+    format!(
+        "{}/tanzaku_at_n_{}_{}_n{}.dat",
+        config.output.dir, system, config.calc_setting().debug(), format!("{:.4}", n).replace('.', "p")
+    )
+}
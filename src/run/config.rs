@@ -0,0 +1,968 @@
+use crate::honeycomb::{
+    compare::CompareOptions,
+    kpm::KpmSetting,
+    setting::CalcSetting,
+    qpi::{Impurity, QpiSetting},
+    spectral::{Broadening, SpectralSetting},
+};
+use crate::interaction::{
+    hubbard::{solve, HubbardSetting},
+    rkky::RkkySetting,
+    susceptibility::SusceptibilitySetting,
+};
+use crate::run::sweep::{Axis, SweepAxis};
+use crate::system::{
+    model::{Param, System},
+    disorder::DisorderSetting,
+    flake::FlakeShape,
+    peierls::Flux,
+    ribbon::Edge,
+    supercell::{SpinPattern, Supercell, SupercellSystem},
+};
+
+use nalgebra::Vector2;
+use serde::Deserialize;
+use std::io::{Error, ErrorKind, Result as IoResult};
+
+//----------------------------------------------------------------
+// 実行設定ファイル（TOMLまたはJSON）
+//----------------------------------------------------------------
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RunConfig{
+    pub system : SystemConfig,
+    #[serde(default)]
+    pub calc : CalcConfig,
+    #[serde(default)]
+    pub output : OutputConfig,
+    #[serde(default = "default_observables")]
+    pub observables : Vec<Observable>,
+    #[serde(default)]
+    pub tanzaku : TanzakuConfig,
+    #[serde(default)]
+    pub bands : BandsConfig,
+    #[serde(default)]
+    pub compare : CompareConfig,
+    pub sweep : Option<SweepConfig>,
+    pub phase_diagram : Option<PhaseDiagramConfig>,
+    #[serde(default)]
+    pub thermodynamics : ThermodynamicsConfig,
+    pub hubbard : Option<HubbardConfig>,
+    pub hubbard_phase_diagram : Option<HubbardPhaseDiagramConfig>,
+    #[serde(default)]
+    pub rkky : RkkyConfig,
+    #[serde(default)]
+    pub susceptibility : SusceptibilityConfig,
+    pub supercell : Option<SupercellConfig>,
+    #[serde(default)]
+    pub unfolding : UnfoldingConfig,
+    #[serde(default)]
+    pub spectral : SpectralConfig,
+    #[serde(default)]
+    pub qpi : QpiConfig,
+    #[serde(default)]
+    pub impurity : ImpurityConfig,
+    #[serde(default)]
+    pub ribbon : RibbonConfig,
+    #[serde(default)]
+    pub flake : FlakeConfig,
+    #[serde(default)]
+    pub kpm : KpmConfig,
+    #[serde(default)]
+    pub hofstadter : HofstadterConfig,
+}
+
+impl RunConfig{
+    /// 拡張子が .json ならJSON、それ以外はTOMLとして読み込む
+    pub fn load(path : &str) -> IoResult<Self>{
+        let text = std::fs::read_to_string(path)?;
+
+        let parsed = if path.ends_with(".json") {
+            serde_json::from_str(&text).map_err(|e| e.to_string())
+        } else {
+            toml::from_str(&text).map_err(|e| e.to_string())
+        };
+
+        parsed.map_err(|e| invalid(format!("{}: {}", path, e)))
+    }
+    pub fn system(&self) -> IoResult<System>{
+        self.system_with_spin(&self.system.spin)
+    }
+    /// [hubbard] があれば spin を初期値としたHubbard平均場の解（System::MeanField）を返す
+    pub fn system_with_spin(&self, spin : &str) -> IoResult<System>{
+        let system = self.system.build_with_spin(spin)?;
+
+        match &self.hubbard {
+            Some(hubbard) => {
+                let solution = solve(&system, false, hubbard.to_setting());
+                if !solution.converged {
+                    eprintln!("warning: Hubbard mean field from {} did not converge in {} iterations", spin, solution.iterations);
+                }
+                Ok(solution.system)
+            }
+            None => Ok(system),
+        }
+    }
+    pub fn calc_setting(&self) -> CalcSetting{
+        self.calc.to_calc_setting()
+    }
+}
+
+pub(crate) fn invalid(message : String) -> Error{
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+//----------------------------------------------------------------
+// 模型
+//----------------------------------------------------------------
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SystemConfig{
+    pub family : String,    // "original", "tmd", "kanemele"
+    pub spin : String,      // "fm", "afm", "uuuddd" など（System::from_family 参照）
+    pub lambda : f64,
+    pub jj : f64,
+}
+
+impl SystemConfig{
+    pub fn param(&self) -> Param{
+        Param::new(self.lambda, self.jj)
+    }
+    pub fn build(&self) -> IoResult<System>{
+        self.build_with_spin(&self.spin)
+    }
+    pub fn build_with_spin(&self, spin : &str) -> IoResult<System>{
+        System::from_family(&self.family, spin, self.param())
+            .ok_or_else(|| invalid(format!("unknown system: family = {}, spin = {}", self.family, spin)))
+    }
+}
+
+//----------------------------------------------------------------
+// 計算設定（CalcSettingと同じ項目）
+//----------------------------------------------------------------
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CalcConfig{
+    pub mesh_kx : usize,
+    pub mesh_ky : usize,
+    pub height_map_div : usize,
+    pub threshold_berry : f64,
+    pub main_mesh : usize,
+}
+
+impl Default for CalcConfig{
+    fn default() -> Self{
+        CalcConfig {
+            mesh_kx : 100,
+            mesh_ky : 100,
+            height_map_div : 39,
+            threshold_berry : 1e-12,
+            main_mesh : 1,
+        }
+    }
+}
+
+impl CalcConfig{
+    pub fn to_calc_setting(&self) -> CalcSetting{
+        CalcSetting {
+            mesh_kx : self.mesh_kx,
+            mesh_ky : self.mesh_ky,
+            height_map_div : self.height_map_div,
+            threshold_berry : self.threshold_berry,
+            main_mesh : self.main_mesh,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputConfig{
+    pub dir : String,
+}
+
+impl Default for OutputConfig{
+    fn default() -> Self{
+        OutputConfig { dir : "./output".to_string() }
+    }
+}
+
+//----------------------------------------------------------------
+// 計算する物理量
+//----------------------------------------------------------------
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Observable{
+    Tanzaku,        // n, energy, berry, bcd, qmd
+    Nodes,          // 直接ギャップの極小点（gap_finder）
+    BandCurvature,  // 高対称点での逆有効質量テンソル（effective_mass）
+    Symmetry,       // 磁気点群と許される応答の成分（magnetic_group）
+}
+
+fn default_observables() -> Vec<Observable>{
+    vec![Observable::Tanzaku]
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Sampling{
+    #[default]
+    Full,       // parallel_calculate_tanzaku
+    Wedge,      // calculate_tanzaku_in_wedge
+    Adaptive,   // adaptive_calculate_tanzaku
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TanzakuConfig{
+    pub sampling : Sampling,
+    pub interpolate_n : Option<usize>,  // 指定すれば電子数について等間隔に補間して出力する
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BandsConfig{
+    pub path : Vec<String>,
+    pub points_per_segment : usize,
+}
+
+impl Default for BandsConfig{
+    fn default() -> Self{
+        BandsConfig {
+            path : ["Gamma", "K", "M", "Gamma"].map(String::from).to_vec(),
+            points_per_segment : 100,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CompareConfig{
+    pub spins : Vec<String>,
+    pub n_div : usize,
+    pub energy_mesh : usize,
+}
+
+impl Default for CompareConfig{
+    fn default() -> Self{
+        CompareConfig {
+            spins : ["fm", "one1", "one2", "twin", "tri1", "uuuddd", "tri2", "afm"].map(String::from).to_vec(),
+            n_div : 300,
+            energy_mesh : 100,
+        }
+    }
+}
+
+impl CompareConfig{
+    pub fn options(&self) -> CompareOptions{
+        CompareOptions { n_div : self.n_div, energy_mesh : self.energy_mesh }
+    }
+}
+
+//----------------------------------------------------------------
+// パラメーターの掃引
+//----------------------------------------------------------------
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SweepConfig{
+    pub axes : Vec<AxisConfig>,
+    pub command : Command,              // 各点で実行するサブコマンド
+    pub manifest : Option<String>,      // 省略時は output.dir/sweep_manifest.dat
+}
+
+/// 値を `values` で直接与えるか、`start`, `stop`, `steps` で等間隔に与える
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AxisConfig{
+    pub parameter : Axis,
+    #[serde(default)]
+    pub values : Vec<f64>,
+    pub start : Option<f64>,
+    pub stop : Option<f64>,
+    pub steps : Option<usize>,
+}
+
+impl AxisConfig{
+    pub fn to_axis(&self) -> IoResult<SweepAxis>{
+        match (self.values.is_empty(), self.start, self.stop, self.steps) {
+            (false, None, None, None) => Ok(SweepAxis::new(self.parameter, self.values.clone())),
+            (true, Some(start), Some(stop), Some(steps)) => Ok(SweepAxis::linspace(self.parameter, start, stop, steps)),
+            _ => Err(invalid(format!("axis {:?} should have either values or start/stop/steps", self.parameter))),
+        }
+    }
+}
+
+//----------------------------------------------------------------
+// 相図（候補のスピン配置と電子数の分割は [compare] の設定を使う）
+//----------------------------------------------------------------
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PhaseDiagramConfig{
+    pub axis : AxisConfig,              // parameter は lambda または jj
+    #[serde(default = "default_tolerance")]
+    pub tolerance : f64,                // 相境界を二分法で絞り込む幅
+}
+
+fn default_tolerance() -> f64{
+    1e-3
+}
+
+//----------------------------------------------------------------
+// 有限温度での比較（候補のスピン配置と電子数の分割は [compare] の設定を使う）
+//----------------------------------------------------------------
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThermodynamicsConfig{
+    pub temperature : f64,
+    pub mu_start : Option<f64>,     // 省略時は全ての候補の固有値を含む範囲
+    pub mu_stop : Option<f64>,
+    pub mu_steps : usize,
+}
+
+impl Default for ThermodynamicsConfig{
+    fn default() -> Self{
+        ThermodynamicsConfig {
+            temperature : 0.0,
+            mu_start : None,
+            mu_stop : None,
+            mu_steps : 301,
+        }
+    }
+}
+
+//----------------------------------------------------------------
+// Hubbard平均場（省略した項目は HubbardSetting::standard の値）
+//----------------------------------------------------------------
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HubbardConfig{
+    pub u : f64,
+    pub v : Option<f64>,
+    pub v2 : Option<f64>,
+    pub filling : f64,
+    pub mesh : Option<usize>,
+    pub temperature : Option<f64>,
+    pub mixing : Option<f64>,
+    pub tolerance : Option<f64>,
+    pub max_iterations : Option<usize>,
+}
+
+impl HubbardConfig{
+    pub fn to_setting(&self) -> HubbardSetting{
+        let standard = HubbardSetting::standard(self.u, self.filling);
+        HubbardSetting {
+            v : self.v.unwrap_or(standard.v),
+            v2 : self.v2.unwrap_or(standard.v2),
+            mesh : self.mesh.unwrap_or(standard.mesh),
+            temperature : self.temperature.unwrap_or(standard.temperature),
+            mixing : self.mixing.unwrap_or(standard.mixing),
+            tolerance : self.tolerance.unwrap_or(standard.tolerance),
+            max_iterations : self.max_iterations.unwrap_or(standard.max_iterations),
+            ..standard
+        }
+    }
+}
+
+//----------------------------------------------------------------
+// (U, V, n) の平均場の相図（初期値は [compare] の spins、それ以外の設定は [hubbard] の値）
+//----------------------------------------------------------------
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HubbardPhaseDiagramConfig{
+    pub u : Vec<f64>,
+    pub v : Vec<f64>,
+    pub filling : Vec<f64>,
+}
+
+//----------------------------------------------------------------
+// RKKY相互作用（古典的なエネルギーを比べるスピン配置は [compare] の spins）
+//----------------------------------------------------------------
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RkkyConfig{
+    pub mesh : usize,
+    pub temperature : f64,
+    pub cutoff : f64,
+    pub fillings : Vec<f64>,
+}
+
+impl Default for RkkyConfig{
+    fn default() -> Self{
+        let standard = RkkySetting::standard();
+        RkkyConfig {
+            mesh : standard.mesh,
+            temperature : standard.temperature,
+            cutoff : standard.cutoff,
+            fillings : (1..20).map(|i| 0.1 * i as f64).collect(),
+        }
+    }
+}
+
+impl RkkyConfig{
+    pub fn to_setting(&self) -> RkkySetting{
+        RkkySetting { mesh : self.mesh, temperature : self.temperature, cutoff : self.cutoff }
+    }
+}
+
+//----------------------------------------------------------------
+// 静的スピン感受率 χ(q) と磁気不安定性（u はRPAの χ(q) の出力に使う）
+//----------------------------------------------------------------
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SusceptibilityConfig{
+    pub mesh : usize,
+    pub q_mesh : usize,
+    pub temperature : f64,
+    pub u : f64,
+    pub fillings : Vec<f64>,
+}
+
+impl Default for SusceptibilityConfig{
+    fn default() -> Self{
+        let standard = SusceptibilitySetting::standard();
+        SusceptibilityConfig {
+            mesh : standard.mesh,
+            q_mesh : standard.q_mesh,
+            temperature : standard.temperature,
+            u : 1.0,
+            fillings : (1..20).map(|i| 0.1 * i as f64).collect(),
+        }
+    }
+}
+
+impl SusceptibilityConfig{
+    pub fn to_setting(&self) -> SusceptibilitySetting{
+        SusceptibilitySetting { mesh : self.mesh, q_mesh : self.q_mesh, temperature : self.temperature }
+    }
+}
+
+//----------------------------------------------------------------
+// 任意の超格子（matrix の行が a1 = A3, a2 = A1 を単位とした L1, L2）
+//----------------------------------------------------------------
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SupercellConfig{
+    pub matrix : [[i64; 2]; 2],
+    #[serde(default = "default_supercell_spins")]
+    pub spins : Vec<String>,                // SpinPattern::from_name の名前
+    #[serde(default)]
+    pub wave : Vec<WaveConfig>,
+    #[serde(default)]
+    pub custom : Vec<CustomSpinConfig>,
+    #[serde(default = "default_supercell_path")]
+    pub path : Vec<String>,                 // Supercell::high_symmetry_point の名前
+    #[serde(default = "default_points_per_segment")]
+    pub points_per_segment : usize,
+    #[serde(default = "default_supercell_n_div")]
+    pub n_div : usize,
+    #[serde(default = "default_supercell_mesh")]
+    pub energy_mesh : usize,                // 畳み込まれたブリルアンゾーンのメッシュ
+}
+
+/// 波数 q（b1, b2 の分数座標。susceptibility の出力の (i, j) / q_mesh）の変調
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WaveConfig{
+    pub name : String,
+    pub q : [f64; 2],
+    #[serde(default)]
+    pub staggered : bool,
+}
+
+/// サイトごとのスピンの向きを直接与える（Supercell::sites の順）
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CustomSpinConfig{
+    pub name : String,
+    pub spins : Vec<f64>,
+}
+
+fn default_supercell_spins() -> Vec<String>{
+    ["para", "fm", "neel"].map(String::from).to_vec()
+}
+
+fn default_supercell_path() -> Vec<String>{
+    ["Gamma", "M1", "K", "M2", "Gamma"].map(String::from).to_vec()
+}
+
+fn default_points_per_segment() -> usize{
+    100
+}
+
+fn default_supercell_n_div() -> usize{
+    200
+}
+
+fn default_supercell_mesh() -> usize{
+    48
+}
+
+impl SupercellConfig{
+    pub fn supercell(&self) -> IoResult<Supercell>{
+        Supercell::new(self.matrix).ok_or_else(|| invalid(format!("supercell.matrix {:?} is singular", self.matrix)))
+    }
+    /// 名前付き、波数、直接指定の順に並べたスピン配置
+    pub fn patterns(&self) -> IoResult<Vec<(String, SpinPattern)>>{
+        let mut patterns = Vec::new();
+        for name in &self.spins {
+            let pattern = SpinPattern::from_name(name).ok_or_else(|| invalid(format!("unknown supercell spin pattern: {}", name)))?;
+            patterns.push((name.clone(), pattern));
+        }
+        for wave in &self.wave {
+            let q = Vector2::new(wave.q[0], wave.q[1]);
+            patterns.push((wave.name.clone(), SpinPattern::Wave { q, staggered : wave.staggered }));
+        }
+        for custom in &self.custom {
+            patterns.push((custom.name.clone(), SpinPattern::Custom(custom.spins.clone())));
+        }
+        Ok(patterns)
+    }
+    /// system の family と lambda, jj で各スピン配置の SupercellSystem を作る
+    pub fn systems(&self, system : &SystemConfig) -> IoResult<Vec<SupercellSystem>>{
+        let supercell = self.supercell()?;
+        let param = system.param();
+        //tmd の値は系列で決まる（uuuddd はどの系列にもある）
+        let tmd = System::from_family(&system.family, "uuuddd", param)
+            .ok_or_else(|| invalid(format!("unknown family: {}", system.family)))?
+            .tmd();
+
+        self.patterns()?.into_iter()
+            .map(|(name, pattern)| {
+                let spins = pattern.spins(&supercell).map_err(|message| invalid(format!("{}: {}", name, message)))?;
+                Ok(SupercellSystem::new(&name, supercell.clone(), param, tmd, spins))
+            })
+            .collect()
+    }
+}
+
+//----------------------------------------------------------------
+// 6サイトのバンドの基本単位胞への展開（path は2サイトの高対称点）
+//----------------------------------------------------------------
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UnfoldingConfig{
+    pub path : Vec<String>,
+    pub points_per_segment : usize,
+    pub mesh : usize,               // フェルミ面のk点のメッシュ
+    pub energy_mesh : usize,        // 化学ポテンシャルを決めるk点のメッシュ
+    pub broadening : f64,
+    pub fillings : Vec<f64>,        // フェルミ面を出力する電子数
+}
+
+impl Default for UnfoldingConfig{
+    fn default() -> Self{
+        UnfoldingConfig {
+            path : ["Gamma", "K", "M", "Gamma"].map(String::from).to_vec(),
+            points_per_segment : 100,
+            mesh : 120,
+            energy_mesh : 60,
+            broadening : 0.05,
+            fillings : vec![0.5, 1.0, 1.5],
+        }
+    }
+}
+
+//----------------------------------------------------------------
+// スペクトル関数 A(k, ω)（エネルギーは μ(filling) から測る）
+//----------------------------------------------------------------
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SpectralConfig{
+    pub filling : f64,
+    pub broadening : f64,
+    pub broadening_quadratic : f64,     // η(ω) = broadening + broadening_quadratic (ω - μ)²
+    pub unfold : bool,                  // true なら path は2サイトの高対称点
+    pub path : Vec<String>,
+    pub points_per_segment : usize,
+    pub omega_min : f64,
+    pub omega_max : f64,
+    pub omega_div : usize,
+    pub cut_energies : Vec<f64>,        // 等エネルギー面を出力するエネルギー
+    pub mesh : usize,                   // 等エネルギー面のk点のメッシュ
+    pub energy_mesh : usize,            // 化学ポテンシャルを決めるk点のメッシュ
+}
+
+impl Default for SpectralConfig{
+    fn default() -> Self{
+        SpectralConfig {
+            filling : 1.0,
+            broadening : 0.05,
+            broadening_quadratic : 0.0,
+            unfold : false,
+            path : ["Gamma", "K", "M", "Gamma"].map(String::from).to_vec(),
+            points_per_segment : 100,
+            omega_min : -3.0,
+            omega_max : 1.0,
+            omega_div : 200,
+            cut_energies : vec![0.0],
+            mesh : 120,
+            energy_mesh : 60,
+        }
+    }
+}
+
+impl SpectralConfig{
+    pub fn to_setting(&self) -> SpectralSetting{
+        SpectralSetting {
+            broadening : Broadening { constant : self.broadening, quadratic : self.broadening_quadratic },
+            unfold : self.unfold,
+            energy_mesh : self.energy_mesh,
+        }
+    }
+    /// omega_min から omega_max を omega_div 等分したエネルギー
+    pub fn omegas(&self) -> Vec<f64>{
+        (0..=self.omega_div)
+            .map(|i| self.omega_min + (self.omega_max - self.omega_min) * i as f64 / self.omega_div as f64)
+            .collect()
+    }
+}
+
+//----------------------------------------------------------------
+// 点不純物による準粒子干渉（k点のメッシュは [calc] の mesh_kx, mesh_ky）
+//----------------------------------------------------------------
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QpiConfig{
+    pub filling : f64,
+    pub energies : Vec<f64>,            // μ から測ったエネルギー
+    pub broadening : f64,
+    pub broadening_quadratic : f64,
+    pub site : usize,                   // 不純物を置く単位胞の中のサイト
+    pub potential : f64,
+    pub magnetic : bool,
+    pub born : bool,                    // true なら T = V
+    pub spins : Vec<String>,            // 比べるスピン配置（空なら system.spin）
+}
+
+impl Default for QpiConfig{
+    fn default() -> Self{
+        QpiConfig {
+            filling : 1.0,
+            energies : vec![0.0],
+            broadening : 0.05,
+            broadening_quadratic : 0.0,
+            site : 0,
+            potential : 1.0,
+            magnetic : false,
+            born : false,
+            spins : Vec::new(),
+        }
+    }
+}
+
+impl QpiConfig{
+    pub fn to_setting(&self) -> QpiSetting{
+        QpiSetting {
+            impurity : Impurity { site : self.site, potential : self.potential, magnetic : self.magnetic, born : self.born },
+            broadening : Broadening { constant : self.broadening, quadratic : self.broadening_quadratic },
+        }
+    }
+    /// ファイル名用（例: site0_v1p00_mag_born）
+    pub fn debug(&self) -> String{
+        let label = format!("{:.2}", self.potential).replace('.', "p").replace('-', "m");
+        let magnetic = if self.magnetic { "_mag" } else { "" };
+        let born = if self.born { "_born" } else { "" };
+        format!("site{}_v{}{}{}", self.site, label, magnetic, born)
+    }
+}
+
+//----------------------------------------------------------------
+// 点不純物のまわりの局所状態密度と束縛状態（k点のメッシュは [calc] の mesh_kx, mesh_ky）
+//----------------------------------------------------------------
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ImpurityConfig{
+    pub filling : f64,
+    pub energies : Vec<f64>,            // 局所状態密度を出力する μ から測ったエネルギー
+    pub broadening : f64,
+    pub broadening_quadratic : f64,
+    pub site : usize,                   // 不純物を置く単位胞の中のサイト
+    pub potential : f64,
+    pub magnetic : bool,
+    pub born : bool,                    // true なら T = V
+    pub radius : f64,                   // 局所状態密度を出力する不純物からの距離
+    pub potential_min : f64,            // 束縛状態を探す不純物の強さの範囲
+    pub potential_max : f64,
+    pub potential_div : usize,
+    pub min_gap : f64,                  // これより小さいギャップでは束縛状態を探さない
+    pub spins : Vec<String>,            // 比べるスピン配置（空なら system.spin）
+}
+
+impl Default for ImpurityConfig{
+    fn default() -> Self{
+        ImpurityConfig {
+            filling : 1.0,
+            energies : vec![0.0],
+            broadening : 0.02,
+            broadening_quadratic : 0.0,
+            site : 0,
+            potential : 1.0,
+            magnetic : false,
+            born : false,
+            radius : 6.0,
+            potential_min : -5.0,
+            potential_max : 5.0,
+            potential_div : 100,
+            min_gap : 1e-3,
+            spins : Vec::new(),
+        }
+    }
+}
+
+impl ImpurityConfig{
+    pub fn impurity(&self) -> Impurity{
+        Impurity { site : self.site, potential : self.potential, magnetic : self.magnetic, born : self.born }
+    }
+    pub fn broadening(&self) -> Broadening{
+        Broadening { constant : self.broadening, quadratic : self.broadening_quadratic }
+    }
+    /// potential_min から potential_max を potential_div 等分した不純物の強さ
+    pub fn potentials(&self) -> Vec<f64>{
+        (0..=self.potential_div)
+            .map(|i| self.potential_min + (self.potential_max - self.potential_min) * i as f64 / self.potential_div as f64)
+            .collect()
+    }
+    /// ファイル名用（例: site0_v1p00_mag）
+    pub fn debug(&self) -> String{
+        let label = format!("{:.2}", self.potential).replace('.', "p").replace('-', "m");
+        let magnetic = if self.magnetic { "_mag" } else { "" };
+        let born = if self.born { "_born" } else { "" };
+        format!("site{}_v{}{}{}", self.site, label, magnetic, born)
+    }
+}
+
+//----------------------------------------------------------------
+// ナノリボンの端の状態（Chern 数のk点のメッシュは [calc] の mesh_kx, mesh_ky）
+//----------------------------------------------------------------
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RibbonConfig{
+    pub edges : Vec<String>,            // "zigzag", "armchair"
+    pub width : usize,
+    pub k_points : usize,
+    pub edge_depth : f64,               // 端の重みを数えるサイトの端からの距離
+    pub filling : f64,                  // 端の状態を数えるエネルギー（バルクのギャップの中央）を決める電子数
+    pub energy_mesh : usize,            // バルクのギャップを決めるk点のメッシュ
+    pub spins : Vec<String>,            // 比べるスピン配置（空なら system.spin）
+}
+
+impl Default for RibbonConfig{
+    fn default() -> Self{
+        RibbonConfig {
+            edges : ["zigzag", "armchair"].map(String::from).to_vec(),
+            width : 16,
+            k_points : 200,
+            edge_depth : 2.0,
+            filling : 1.0,
+            energy_mesh : 60,
+            spins : Vec::new(),
+        }
+    }
+}
+
+impl RibbonConfig{
+    pub fn edges(&self) -> IoResult<Vec<Edge>>{
+        self.edges.iter()
+            .map(|name| Edge::from_name(name).ok_or_else(|| invalid(format!("unknown edge {:?} (zigzag or armchair)", name))))
+            .collect()
+    }
+}
+
+//----------------------------------------------------------------
+// 有限のフレークの局所 Chern マーカー（比べる k 空間の Chern 数のメッシュは [calc] の mesh_kx, mesh_ky）
+//----------------------------------------------------------------
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FlakeConfig{
+    pub shape : String,                 // "hexagonal" または "rectangular"
+    pub rings : usize,                  // 六角形の六員環の重なりの数
+    pub columns : usize,                // 長方形の x 方向の周期の数
+    pub rows : usize,                   // 長方形のジグザグ鎖の数
+    pub filling : f64,                  // フェルミエネルギー（バルクのギャップの中央）を決める電子数
+    pub energy_mesh : usize,            // バルクのギャップを決めるk点のメッシュ
+    pub anderson : f64,                 // オンサイトの乱れの幅 W
+    pub seeds : Vec<u64>,               // 乱れの配置ごとの seed
+    pub bulk_radius : f64,              // マーカーを平均する中心からの距離
+    pub spins : Vec<String>,            // 比べるスピン配置（空なら system.spin）
+}
+
+impl Default for FlakeConfig{
+    fn default() -> Self{
+        FlakeConfig {
+            shape : "hexagonal".to_string(),
+            rings : 8,
+            columns : 12,
+            rows : 10,
+            filling : 1.0,
+            energy_mesh : 60,
+            anderson : 0.0,
+            seeds : vec![0],
+            bulk_radius : 3.0,
+            spins : Vec::new(),
+        }
+    }
+}
+
+impl FlakeConfig{
+    pub fn shape(&self) -> IoResult<FlakeShape>{
+        match self.shape.as_str() {
+            "hexagonal" => Ok(FlakeShape::Hexagonal { rings : self.rings }),
+            "rectangular" => Ok(FlakeShape::Rectangular { columns : self.columns, rows : self.rows }),
+            name => Err(invalid(format!("unknown flake shape {:?} (hexagonal or rectangular)", name))),
+        }
+    }
+    /// 乱れの配置の seed（乱れがなければ最初の一つだけ）
+    pub fn seeds(&self) -> Vec<u64>{
+        let seeds = if self.seeds.is_empty() { vec![0] } else { self.seeds.clone() };
+        if self.anderson == 0.0 { seeds[..1].to_vec() } else { seeds }
+    }
+}
+
+//----------------------------------------------------------------
+// 乱れのあるスーパーセルの KPM の状態密度と Hall 伝導度（きれいな系の Tanzakus は [calc] の設定）
+//----------------------------------------------------------------
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KpmConfig{
+    pub cells : [usize; 2],             // 基本単位胞の数（6サイトの系は 3 の倍数）
+    pub moments : usize,
+    pub vectors : usize,
+    pub block : usize,
+    pub energy_points : usize,
+    pub anderson : Vec<f64>,            // 乱れの幅 W
+    pub flip : Vec<f64>,                // 磁気モーメントを反転する確率
+    pub realizations : usize,           // 乱れの配置の数（seed, seed + 1, ...）
+    pub seed : u64,
+    pub energy_min : f64,
+    pub energy_max : f64,
+    pub energy_div : usize,
+    pub clean : bool,                   // きれいな系の Tanzakus の n と berry / 2π を並べる
+    pub spins : Vec<String>,            // 比べるスピン配置（空なら system.spin）
+}
+
+impl Default for KpmConfig{
+    fn default() -> Self{
+        KpmConfig {
+            cells : [60, 60],
+            moments : 256,
+            vectors : 4,
+            block : 64,
+            energy_points : 1024,
+            anderson : vec![0.0, 1.0, 2.0],
+            flip : vec![0.0],
+            realizations : 4,
+            seed : 1,
+            energy_min : -3.0,
+            energy_max : 3.0,
+            energy_div : 120,
+            clean : true,
+            spins : Vec::new(),
+        }
+    }
+}
+
+impl KpmConfig{
+    pub fn kpm_setting(&self) -> KpmSetting{
+        KpmSetting { moments : self.moments, vectors : self.vectors, block : self.block, energy_points : self.energy_points }
+    }
+    pub fn energies(&self) -> Vec<f64>{
+        (0..=self.energy_div)
+            .map(|i| self.energy_min + (self.energy_max - self.energy_min) * i as f64 / self.energy_div.max(1) as f64)
+            .collect()
+    }
+    /// 乱れの強さごとの配置（anderson × flip）
+    pub fn disorders(&self) -> Vec<Vec<DisorderSetting>>{
+        self.anderson.iter()
+            .flat_map(|&anderson| self.flip.iter().map(move |&flip| (anderson, flip)))
+            .map(|(anderson, flip)| {
+                (0..self.realizations.max(1) as u64).map(|k| DisorderSetting { anderson, flip, seed : self.seed + k }).collect()
+            })
+            .collect()
+    }
+}
+
+//----------------------------------------------------------------
+// 垂直な磁場の Hofstadter のスペクトルと Streda の公式の Chern 数
+//----------------------------------------------------------------
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HofstadterConfig{
+    pub q_max : i64,                    // 六員環あたりの磁束 p/q の分母の最大値
+    pub flux_max : f64,                 // 磁束の範囲 [0, flux_max]（λ ≠ 0 なら周期は 6）
+    pub k_points : usize,               // バンドの幅を求める磁気ブリルアンゾーンのk点（k_points²）
+    pub min_gap : f64,                  // これより狭いギャップは数えない
+    pub spins : Vec<String>,            // 比べるスピン配置（空なら system.spin）
+}
+
+impl Default for HofstadterConfig{
+    fn default() -> Self{
+        HofstadterConfig {
+            q_max : 24,
+            flux_max : 1.0,
+            k_points : 2,
+            min_gap : 0.05,
+            spins : Vec::new(),
+        }
+    }
+}
+
+impl HofstadterConfig{
+    pub fn fluxes(&self) -> Vec<Flux>{
+        Flux::list(self.q_max, self.flux_max)
+    }
+}
+
+//----------------------------------------------------------------
+// サブコマンド
+//----------------------------------------------------------------
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Command{
+    Bands,
+    Contours,
+    Tanzaku,
+    Compare,
+    Sweep,
+    PhaseDiagram,
+    Thermodynamics,
+    Hubbard,
+    HubbardPhaseDiagram,
+    Rkky,
+    Susceptibility,
+    Supercell,
+    Unfold,
+    Spectral,
+    Qpi,
+    Impurity,
+    Ribbon,
+    Flake,
+    Kpm,
+    Hofstadter,
+}
+
+impl Command{
+    pub fn from_name(name : &str) -> Option<Self>{
+        match name {
+            "bands" => Some(Command::Bands),
+            "contours" => Some(Command::Contours),
+            "tanzaku" => Some(Command::Tanzaku),
+            "compare" => Some(Command::Compare),
+            "sweep" => Some(Command::Sweep),
+            "phase_diagram" => Some(Command::PhaseDiagram),
+            "thermodynamics" => Some(Command::Thermodynamics),
+            "hubbard" => Some(Command::Hubbard),
+            "hubbard_phase_diagram" => Some(Command::HubbardPhaseDiagram),
+            "rkky" => Some(Command::Rkky),
+            "susceptibility" => Some(Command::Susceptibility),
+            "supercell" => Some(Command::Supercell),
+            "unfold" => Some(Command::Unfold),
+            "spectral" => Some(Command::Spectral),
+            "qpi" => Some(Command::Qpi),
+            "impurity" => Some(Command::Impurity),
+            "ribbon" => Some(Command::Ribbon),
+            "flake" => Some(Command::Flake),
+            "kpm" => Some(Command::Kpm),
+            "hofstadter" => Some(Command::Hofstadter),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,197 @@
+use crate::system::model::Param;
+
+use rayon::prelude::*;
+use serde::Deserialize;
+use std::io::{Error, ErrorKind, Result as IoResult, Write};
+use std::path::Path;
+
+//----------------------------------------------------------------
+// 掃引する軸
+//----------------------------------------------------------------
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Axis{
+    Lambda,
+    Jj,
+    Filling,    // 電子数 n
+}
+
+#[derive(Debug, Clone)]
+pub struct SweepAxis{
+    pub axis : Axis,
+    pub values : Vec<f64>,
+}
+
+impl SweepAxis{
+    pub fn new(axis : Axis, values : Vec<f64>) -> Self{
+        SweepAxis { axis, values }
+    }
+    /// start から stop まで（両端を含む）steps 点
+    pub fn linspace(axis : Axis, start : f64, stop : f64, steps : usize) -> Self{
+        let values = if steps <= 1 {
+            vec![start]
+        } else {
+            (0..steps).map(|i| start + (stop - start) * i as f64 / (steps - 1) as f64).collect()
+        };
+        SweepAxis { axis, values }
+    }
+}
+
+//----------------------------------------------------------------
+// 掃引の各点
+//----------------------------------------------------------------
+#[derive(Debug, Clone, Copy)]
+pub struct SweepPoint{
+    pub index : usize,
+    pub param : Param,
+    pub filling : Option<f64>,
+}
+
+/// 掃引の各点で行う計算
+///
+/// Param が同じで filling だけが異なる点は一度の `run` にまとめて渡されるので、
+/// 電子数によらない計算（Tanzakusなど）は一度だけ行えばよい。
+pub trait SweepJob: Sync{
+    /// この点の出力ファイルのパス（既に存在すれば計算を省略する）
+    fn output_path(&self, point : &SweepPoint) -> String;
+    /// 出力がまだ存在しない点だけが渡される
+    fn run(&self, param : Param, points : &[SweepPoint]) -> IoResult<()>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobStatus{
+    Computed,
+    Skipped,    // 出力が既に存在した
+    Failed,
+}
+
+impl JobStatus{
+    pub fn name(&self) -> &'static str{
+        match self {
+            JobStatus::Computed => "computed",
+            JobStatus::Skipped => "skipped",
+            JobStatus::Failed => "failed",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ManifestEntry{
+    pub point : SweepPoint,
+    pub path : String,
+    pub status : JobStatus,
+}
+
+//----------------------------------------------------------------
+// 掃引
+//----------------------------------------------------------------
+#[derive(Debug, Clone)]
+pub struct Sweep{
+    pub base : Param,           // 掃引しない軸の値
+    pub axes : Vec<SweepAxis>,
+}
+
+impl Sweep{
+    pub fn new(base : Param, axes : Vec<SweepAxis>) -> IoResult<Self>{
+        for (i, axis) in axes.iter().enumerate() {
+            if axes[..i].iter().any(|other| other.axis == axis.axis) {
+                return Err(Error::new(ErrorKind::InvalidInput, format!("axis {:?} appears more than once", axis.axis)));
+            }
+        }
+        Ok(Sweep { base, axes })
+    }
+    pub fn has_filling(&self) -> bool{
+        self.axes.iter().any(|axis| axis.axis == Axis::Filling)
+    }
+    /// 全ての軸の直積（最後の軸が最も速く変わる）
+    pub fn points(&self) -> Vec<SweepPoint>{
+        let mut points = vec![(self.base, None)];
+
+        for axis in &self.axes {
+            points = points.into_iter()
+                .flat_map(|(param, filling)| {
+                    axis.values.iter().map(move |&value| match axis.axis {
+                        Axis::Lambda => (Param::new(value, param.jj), filling),
+                        Axis::Jj => (Param::new(param.lambda, value), filling),
+                        Axis::Filling => (param, Some(value)),
+                    })
+                })
+                .collect();
+        }
+
+        points.into_iter()
+            .enumerate()
+            .map(|(index, (param, filling))| SweepPoint { index, param, filling })
+            .collect()
+    }
+
+    /// 全ての点で job を実行し、マニフェストを `manifest_path` に出力する
+    ///
+    /// 出力が既に存在する点は計算せず、Param ごとに並列に計算する。
+    /// 失敗した点は警告を表示してマニフェストに記録し、残りの点の計算を続ける。
+    pub fn run<J: SweepJob>(&self, job : &J, manifest_path : &str) -> IoResult<Vec<ManifestEntry>>{
+        let points = self.points();
+
+        //Param が同じ点をまとめる（順序は保つ）
+        let mut groups: Vec<Vec<SweepPoint>> = Vec::new();
+        for point in points {
+            let same_param = |group : &&mut Vec<SweepPoint>| {
+                let param = group[0].param;
+                param.lambda == point.param.lambda && param.jj == point.param.jj
+            };
+            match groups.iter_mut().find(same_param) {
+                Some(group) => group.push(point),
+                None => groups.push(vec![point]),
+            }
+        }
+
+        let mut entries: Vec<ManifestEntry> = groups
+            .par_iter()
+            .flat_map_iter(|group| {
+                let (done, pending): (Vec<SweepPoint>, Vec<SweepPoint>) = group.iter()
+                    .partition(|point| Path::new(&job.output_path(point)).exists());
+
+                let status = if pending.is_empty() {
+                    JobStatus::Computed
+                } else {
+                    match job.run(group[0].param, &pending) {
+                        Ok(()) => JobStatus::Computed,
+                        Err(e) => {
+                            eprintln!("warning: sweep point {:?} failed: {}", group[0].param, e);
+                            JobStatus::Failed
+                        }
+                    }
+                };
+
+                let skipped = done.into_iter().map(|point| (point, JobStatus::Skipped));
+                let computed = pending.into_iter().map(move |point| (point, status));
+
+                skipped.chain(computed)
+                    .map(|(point, status)| ManifestEntry { point, path : job.output_path(&point), status })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+
+        entries.sort_by_key(|entry| entry.point.index);
+        write_manifest(&entries, manifest_path)?;
+
+        Ok(entries)
+    }
+}
+
+/// マニフェストを.datファイルに出力する（filling を掃引しない場合は NaN）
+pub fn write_manifest(entries : &[ManifestEntry], path : &str) -> IoResult<()>{
+    let mut file = std::fs::File::create(path)?;
+
+    writeln!(file, "# index,lambda,jj,filling,status,path")?;
+    for entry in entries {
+        writeln!(
+            file,
+            "{},{},{},{},{},{}",
+            entry.point.index, entry.point.param.lambda, entry.point.param.jj,
+            entry.point.filling.unwrap_or(f64::NAN), entry.status.name(), entry.path
+        )?;
+    }
+
+    Ok(())
+}
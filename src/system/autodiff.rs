@@ -0,0 +1,159 @@
+//ハミルトニアンのk微分を自動微分で求めるためのスカラー型
+//ハミルトニアンを組み立てるコードをスカラー型についてジェネリックにしておき、
+//Complex<f64>を入れればH(k)そのもの、HyperDualを入れればHとその1階・2階微分が同時に得られる
+
+use std::ops::{Add, Mul, Neg, Sub};
+use nalgebra::{Complex, Vector2};
+
+use crate::consts::*;
+
+//----------------------------------------------------------------
+// 微分をとるk点と方向
+//----------------------------------------------------------------
+#[derive(Debug, Clone, Copy)]
+pub struct KSeed{
+    pub kk : Vector2<f64>,
+    pub xindex : usize,
+    pub yindex : usize,
+}
+
+impl KSeed{
+    pub fn new(kk : Vector2<f64>, xindex : usize, yindex : usize) -> Self{
+        KSeed { kk, xindex, yindex }
+    }
+    //微分を必要としない場合
+    pub fn value(kk : Vector2<f64>) -> Self{
+        KSeed { kk, xindex : 0, yindex : 0 }
+    }
+}
+
+//----------------------------------------------------------------
+// ハミルトニアンの行列要素として使えるスカラー型
+//----------------------------------------------------------------
+pub trait KScalar:
+    nalgebra::Scalar
+    + Copy
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Neg<Output = Self>
+    + Mul<f64, Output = Self>
+    + Mul<Complex<f64>, Output = Self>
+{
+    //kに依存しない定数
+    fn constant(c : Complex<f64>) -> Self;
+    fn zero() -> Self{
+        Self::constant(ZERO)
+    }
+    //複素共役（kは実数なので成分ごとに共役をとればよい）
+    fn conj(self) -> Self;
+    //exp(i k・d)
+    fn exp_ik(seed : &KSeed, d : &Vector2<f64>) -> Self;
+    //sin(k・d)
+    fn sin_k(seed : &KSeed, d : &Vector2<f64>) -> Self;
+}
+
+impl KScalar for Complex<f64>{
+    fn constant(c : Complex<f64>) -> Self{
+        c
+    }
+    fn conj(self) -> Self{
+        Complex::conj(&self)
+    }
+    fn exp_ik(seed : &KSeed, d : &Vector2<f64>) -> Self{
+        Complex::exp(I * seed.kk.dot(d))
+    }
+    fn sin_k(seed : &KSeed, d : &Vector2<f64>) -> Self{
+        seed.kk.dot(d).sin() * ONE
+    }
+}
+
+//----------------------------------------------------------------
+// 超双対数
+// f(k + ε1 e_x + ε2 e_y) = f + ε1 ∂_x f + ε2 ∂_y f + ε1ε2 ∂_x∂_y f  (ε1² = ε2² = 0)
+//----------------------------------------------------------------
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HyperDual{
+    pub re : Complex<f64>,
+    pub dx : Complex<f64>,
+    pub dy : Complex<f64>,
+    pub dxdy : Complex<f64>,
+}
+
+impl HyperDual{
+    pub fn new(re : Complex<f64>, dx : Complex<f64>, dy : Complex<f64>, dxdy : Complex<f64>) -> Self{
+        HyperDual { re, dx, dy, dxdy }
+    }
+}
+
+impl KScalar for HyperDual{
+    fn constant(c : Complex<f64>) -> Self{
+        HyperDual::new(c, ZERO, ZERO, ZERO)
+    }
+    fn conj(self) -> Self{
+        HyperDual::new(self.re.conj(), self.dx.conj(), self.dy.conj(), self.dxdy.conj())
+    }
+    fn exp_ik(seed : &KSeed, d : &Vector2<f64>) -> Self{
+        let f = Complex::exp(I * seed.kk.dot(d));
+        let (dx, dy) = (d[seed.xindex], d[seed.yindex]);
+
+        HyperDual::new(f, f * I * dx, f * I * dy, f * -dx * dy)
+    }
+    fn sin_k(seed : &KSeed, d : &Vector2<f64>) -> Self{
+        let (s, c) = seed.kk.dot(d).sin_cos();
+        let (dx, dy) = (d[seed.xindex], d[seed.yindex]);
+
+        HyperDual::new(s * ONE, c * dx * ONE, c * dy * ONE, -s * dx * dy * ONE)
+    }
+}
+
+//----------------------------------------------------------------
+// 以下、演算を簡略化するための部分
+//----------------------------------------------------------------
+
+impl Add for HyperDual {
+    type Output = Self;
+    fn add(self, other: Self) -> Self::Output {
+        HyperDual::new(self.re + other.re, self.dx + other.dx, self.dy + other.dy, self.dxdy + other.dxdy)
+    }
+}
+
+impl Sub for HyperDual {
+    type Output = Self;
+    fn sub(self, other: Self) -> Self::Output {
+        HyperDual::new(self.re - other.re, self.dx - other.dx, self.dy - other.dy, self.dxdy - other.dxdy)
+    }
+}
+
+impl Mul for HyperDual {
+    type Output = Self;
+    fn mul(self, other: Self) -> Self::Output {
+        HyperDual::new(
+            self.re * other.re,
+            self.re * other.dx + self.dx * other.re,
+            self.re * other.dy + self.dy * other.re,
+            self.re * other.dxdy + self.dx * other.dy + self.dy * other.dx + self.dxdy * other.re,
+        )
+    }
+}
+
+impl Neg for HyperDual {
+    type Output = Self;
+    fn neg(self) -> Self::Output {
+        HyperDual::new(-self.re, -self.dx, -self.dy, -self.dxdy)
+    }
+}
+
+impl Mul<f64> for HyperDual {
+    type Output = Self;
+    fn mul(self, rhs: f64) -> Self::Output {
+        HyperDual::new(self.re * rhs, self.dx * rhs, self.dy * rhs, self.dxdy * rhs)
+    }
+}
+
+impl Mul<Complex<f64>> for HyperDual {
+    type Output = Self;
+    fn mul(self, rhs: Complex<f64>) -> Self::Output {
+        HyperDual::new(self.re * rhs, self.dx * rhs, self.dy * rhs, self.dxdy * rhs)
+    }
+}
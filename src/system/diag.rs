@@ -1,5 +1,5 @@
 use crate::system::model::{System,};
-use nalgebra::{Complex, Const, SymmetricEigen, Vector2, DimMin, Dim, OMatrix, OVector};
+use nalgebra::{allocator::Allocator, Complex, Const, DefaultAllocator, SymmetricEigen, Vector2, DimMin, Dim};
 use crate::system::hamiltonian::{self, HamiltonianEnum};
 
 //----------------------------------------------------------------
@@ -35,6 +35,10 @@ where
         eigens.extend_from_slice(self.d.eigenvalues.as_slice());
         eigens
     }
+    /// スピン spin、バンド band の固有ベクトルの各サイトでの重み |ψ(i)|²
+    pub fn site_weights(&self, spin : usize, band : usize) -> Vec<f64> {
+        self.index(spin).eigenvectors.column(band).iter().map(|c| c.norm_sqr()).collect()
+    }
     pub fn index(&self, index : usize) -> &SymmetricEigen<Complex<f64>, Const<N>>{
         match index {
             0 => &self.u,
@@ -85,11 +89,12 @@ impl SEudEnum{
 // 固有値の昇順に並び替える関数
 //----------------------------------------------------------------
 
-pub fn sort_symmetric_eigen_ascending<const N: usize>(
-    eigen: SymmetricEigen<Complex<f64>, Const<N>>,
-) -> SymmetricEigen<Complex<f64>, Const<N>>
+//（Const<N> でも supercell の Dyn でも使える）
+pub fn sort_symmetric_eigen_ascending<D: Dim>(
+    eigen: SymmetricEigen<Complex<f64>, D>,
+) -> SymmetricEigen<Complex<f64>, D>
 where
-    Const<N>: Dim + DimMin<Const<N>, Output = Const<N>>,
+    DefaultAllocator: Allocator<D, D> + Allocator<D>,
 {
     let mut indexed_eigenvalues: Vec<(usize, f64)> = eigen
         .eigenvalues
@@ -100,9 +105,9 @@ where
 
     indexed_eigenvalues.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
 
-    // サイズ N のベクトルと行列を作成
-    let mut sorted_eigenvalues = OVector::<f64, Const<N>>::zeros();
-    let mut sorted_eigenvectors = OMatrix::<Complex<f64>, Const<N>, Const<N>>::zeros();
+    // 元と同じ大きさのベクトルと行列を作成
+    let mut sorted_eigenvalues = eigen.eigenvalues.clone();
+    let mut sorted_eigenvectors = eigen.eigenvectors.clone();
 
     for (new_index, (old_index, _)) in indexed_eigenvalues.iter().enumerate() {
         sorted_eigenvalues[new_index] = eigen.eigenvalues[*old_index];
@@ -0,0 +1,123 @@
+use crate::consts::*;
+use crate::system::hamiltonian::{check_real_space, locate, real_space_bonds, SiteBond};
+use crate::system::model::System;
+use crate::system::random::SplitMix64;
+use crate::system::sparse::SparseMatrix;
+use crate::system::supercell::Supercell;
+
+use nalgebra::{Complex, Vector2};
+
+//----------------------------------------------------------------
+// 乱れのある大きな周期的スーパーセル（実空間、疎行列）
+//
+// 基本格子 a1 = A3, a2 = A1 の cells[0] × cells[1] 個の単位胞に周期境界条件を課し、
+// サイト (n1, n2, 副格子) を 2 (n1 cells[1] + n2) + 副格子 番目に並べる。
+// 交換場と電荷ポテンシャルはバルクの単位胞の値（6サイトなら Supercell::six_site の並び）で、
+// それぞれのサイトに
+//   Anderson の乱れ: [-W/2, W/2) の一様分布のオンサイトのポテンシャル（スピンによらない）
+//   磁気モーメントの反転: 確率 flip で交換場の符号を反転する
+// を seed から加える。結合は hamiltonian::real_space_bonds（ribbon, flake と同じ）。
+// 速度演算子は v = i[H, r] で、結合 i → j の要素は i H_ij d_ij（d_ij は結合ベクトル）。
+//----------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy)]
+pub struct DisorderSetting{
+    pub anderson : f64,     // 乱れの幅 W
+    pub flip : f64,         // 磁気モーメントを反転する確率
+    pub seed : u64,
+}
+
+impl DisorderSetting{
+    pub fn clean() -> Self{
+        DisorderSetting { anderson : 0.0, flip : 0.0, seed : 0 }
+    }
+    /// ファイル名用（例: w1p00_f0p10）
+    pub fn debug(&self) -> String{
+        let label = |value : f64| format!("{:.2}", value).replace('.', "p");
+        format!("w{}_f{}", label(self.anderson), label(self.flip))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DisorderedLattice{
+    pub name : String,
+    pub cells : [usize; 2],
+    pub setting : DisorderSetting,
+    pub flipped : usize,                // 反転した磁気モーメントの数
+    onsite : [Vec<f64>; 2],             // [spin][site]
+    hopping : [Vec<SiteBond>; 2],       // [副格子] (d, [spin] 振幅)
+}
+
+impl DisorderedLattice{
+    /// 6サイトの磁気単位胞と合わない大きさと、拡張した平均場の結合の補正はErrを返す
+    pub fn new(system : &System, cells : [usize; 2], setting : DisorderSetting) -> Result<Self, String>{
+        check_real_space(system)?;
+        let size = system.size();
+        let six_site = Supercell::six_site();
+        if cells[0] == 0 || cells[1] == 0 {
+            return Err("the disordered lattice should have at least one cell".to_string());
+        }
+        if size == 6 && !(six_site.is_lattice_vector([cells[0] as i64, 0]) && six_site.is_lattice_vector([0, cells[1] as i64])) {
+            return Err(format!("{} x {} cells do not fit the six-site magnetic cell (use multiples of 3)", cells[0], cells[1]));
+        }
+
+        let exchange = system.exchange();
+        let charge = system.charge();
+        let mut rng = SplitMix64::new(setting.seed);
+        let mut onsite = [Vec::with_capacity(2 * cells[0] * cells[1]), Vec::with_capacity(2 * cells[0] * cells[1])];
+        let mut flipped = 0;
+        for n1 in 0..cells[0] as i64 {
+            for n2 in 0..cells[1] as i64 {
+                for sublattice in 0..2 {
+                    let index = if size == 2 { sublattice } else { six_site.site_index([n1, n2], sublattice) };
+                    let disorder = rng.symmetric(setting.anderson);
+                    let sign = if rng.uniform() < setting.flip { flipped += 1; -1.0 } else { 1.0 };
+                    onsite[0].push(charge[index] + disorder + sign * exchange[index]);
+                    onsite[1].push(charge[index] + disorder - sign * exchange[index]);
+                }
+            }
+        }
+
+        let hopping = [0, 1].map(|sublattice| real_space_bonds(system, sublattice));
+
+        Ok(DisorderedLattice { name : system.debug(), cells, setting, flipped, onsite, hopping })
+    }
+    pub fn size(&self) -> usize{
+        2 * self.cells[0] * self.cells[1]
+    }
+    /// 試料の面積
+    pub fn area(&self) -> f64{
+        (self.cells[0] * self.cells[1]) as f64 * A3.x * A1.y
+    }
+    fn index(&self, cell : [i64; 2], sublattice : usize) -> usize{
+        let n1 = cell[0].rem_euclid(self.cells[0] as i64) as usize;
+        let n2 = cell[1].rem_euclid(self.cells[1] as i64) as usize;
+        2 * (n1 * self.cells[1] + n2) + sublattice
+    }
+    //(i, j, d_ij, H_ij)
+    fn bonds(&self, spin : usize) -> impl Iterator<Item = (usize, usize, Vector2<f64>, Complex<f64>)> + '_{
+        (0..self.size()).flat_map(move |i| {
+            let (cell, sublattice) = (i / 2, i % 2);
+            let cell = [(cell / self.cells[1]) as i64, (cell % self.cells[1]) as i64];
+            let position = A3 * cell[0] as f64 + A1 * cell[1] as f64 + if sublattice == 0 { Vector2::zeros() } else { D1 };
+            self.hopping[sublattice].iter()
+                .map(move |&(d, amplitude)| {
+                    let (cell, s) = locate(position + d).expect("bond vectors end on lattice sites");
+                    (i, self.index(cell, s), d, amplitude[spin])
+                })
+        })
+    }
+    /// スピン spin のハミルトニアン
+    pub fn hamiltonian(&self, spin : usize) -> SparseMatrix{
+        let onsite = self.onsite[spin].iter().enumerate().map(|(i, &energy)| (i, i, energy * ONE));
+        let bonds = self.bonds(spin).map(|(i, j, _, value)| (i, j, value));
+        SparseMatrix::from_triplets(self.size(), onsite.chain(bonds).collect())
+    }
+    /// スピン spin の速度演算子 [v_x, v_y]
+    pub fn velocities(&self, spin : usize) -> [SparseMatrix; 2]{
+        [0, 1].map(|axis| {
+            let triplets = self.bonds(spin).map(|(i, j, d, value)| (i, j, I * value * d[axis])).collect();
+            SparseMatrix::from_triplets(self.size(), triplets)
+        })
+    }
+}
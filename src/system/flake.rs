@@ -0,0 +1,291 @@
+use crate::consts::*;
+use crate::system::diag::sort_symmetric_eigen_ascending;
+use crate::system::hamiltonian::{check_real_space, locate, real_space_bonds};
+use crate::system::model::System;
+use crate::system::random::SplitMix64;
+use crate::system::sparse::SparseMatrix;
+use crate::system::supercell::{Supercell, SupercellEigen};
+
+use nalgebra::{Complex, DMatrix, SymmetricEigen, Vector2};
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::io::Write;
+
+//----------------------------------------------------------------
+// 有限の大きさの蜂の巣格子の断片（フレーク）
+//
+// 六角形: 中心の六員環から rings 重までの六員環のサイト（端はすべてジグザグ端、6 rings² サイト）
+// 長方形: ジグザグ鎖を rows 本、x 方向に columns 周期（上下がジグザグ端、左右がアームチェア端）
+// で切り出し、最近接が一つしかないサイトは取り除く。
+// 交換場と電荷ポテンシャルはバルクの単位胞のサイトの値をそのまま使い、
+// 結合は hamiltonian::real_space_bonds（ribbon と同じ）。
+// ハミルトニアンはスピンごとの疎行列で、厳密対角化は密行列に直して行う。
+//----------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlakeShape{
+    Hexagonal{ rings : usize },
+    Rectangular{ columns : usize, rows : usize },
+}
+
+impl FlakeShape{
+    /// ファイル名用（例: hexagon_r8, rectangle_12x10）
+    pub fn name(&self) -> String{
+        match self {
+            FlakeShape::Hexagonal { rings } => format!("hexagon_r{}", rings),
+            FlakeShape::Rectangular { columns, rows } => format!("rectangle_{}x{}", columns, rows),
+        }
+    }
+    //切り出す前のサイトの位置
+    fn positions(&self) -> Vec<Vector2<f64>>{
+        match *self {
+            FlakeShape::Hexagonal { rings } => {
+                //六員環の中心 -D3 + n1 a1 + n2 a2 の六角形の距離が rings - 1 以内
+                let reach = rings as i64 - 1;
+                let mut positions = Vec::new();
+                for n1 in -reach..=reach {
+                    for n2 in -reach..=reach {
+                        if n1.abs().max(n2.abs()).max((n1 - n2).abs()) > reach {
+                            continue;
+                        }
+                        let center = -D3 + A3 * n1 as f64 + A1 * n2 as f64;
+                        for d in [D1, D2, D3] {
+                            positions.push(center + d);
+                            positions.push(center - d);
+                        }
+                    }
+                }
+                positions
+            }
+            FlakeShape::Rectangular { columns, rows } => {
+                //ジグザグ鎖 m は y = 3m/2 の A と y = 3m/2 + 1/2 の B
+                let length = columns as f64 * SQRT_3;
+                let mut positions = Vec::new();
+                for m in 0..rows as i64 {
+                    for n in 0..=(columns as i64 + m) {
+                        let position = A3 * n as f64 + A1 * m as f64;
+                        for position in [position, position + D1] {
+                            if position.x > -1e-9 && position.x < length - 1e-9 {
+                                positions.push(position);
+                            }
+                        }
+                    }
+                }
+                positions
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FlakeSite{
+    pub cell : [i64; 2],
+    pub sublattice : usize,
+    pub position : Vector2<f64>,
+    pub exchange : f64,     // 上向きスピンに +、下向きに - で加わる
+    pub charge : f64,
+    pub disorder : f64,     // Anderson の乱れ（スピンによらない）
+}
+
+//サイト i から j への結合
+#[derive(Debug, Clone, Copy)]
+struct FlakeBond{
+    i : usize,
+    j : usize,
+    hopping : [Complex<f64>; 2],
+}
+
+#[derive(Debug, Clone)]
+pub struct Flake{
+    pub name : String,
+    pub shape : FlakeShape,
+    pub center : Vector2<f64>,      // サイトの位置の平均
+    pub sites : Vec<FlakeSite>,
+    pub anderson : f64,             // 乱れの幅 W（[-W/2, W/2) の一様分布）
+    pub seed : u64,
+    bonds : Vec<FlakeBond>,
+}
+
+impl Flake{
+    /// 拡張した平均場の結合の補正（V, V2 の Fock項）は扱えないのでErrを返す
+    pub fn new(system : &System, shape : FlakeShape) -> Result<Self, String>{
+        check_real_space(system)?;
+
+        //最近接が一つ以下のサイトがなくなるまで取り除く
+        let mut keys: Vec<([i64; 2], usize)> = shape.positions().into_iter().filter_map(locate).collect();
+        keys.sort();
+        keys.dedup();
+        let neighbors = |cell : [i64; 2], sublattice : usize| {
+            let position = A3 * cell[0] as f64 + A1 * cell[1] as f64 + if sublattice == 0 { Vector2::zeros() } else { D1 };
+            let sign = if sublattice == 0 { 1.0 } else { -1.0 };
+            [D1, D2, D3].map(|d| locate(position + d * sign).unwrap())
+        };
+        loop {
+            let set: std::collections::HashSet<_> = keys.iter().copied().collect();
+            let before = keys.len();
+            keys.retain(|&(cell, sublattice)| neighbors(cell, sublattice).iter().filter(|key| set.contains(key)).count() >= 2);
+            if keys.len() == before {
+                break;
+            }
+        }
+        if keys.is_empty() {
+            return Err(format!("{} has no sites", shape.name()));
+        }
+
+        let size = system.size();
+        let six_site = Supercell::six_site();
+        let exchange = system.exchange();
+        let charge = system.charge();
+        let bulk_index = |cell : [i64; 2], sublattice : usize| if size == 2 { sublattice } else { six_site.site_index(cell, sublattice) };
+
+        let mut sites: Vec<FlakeSite> = keys.iter()
+            .map(|&(cell, sublattice)| {
+                let position = A3 * cell[0] as f64 + A1 * cell[1] as f64 + if sublattice == 0 { Vector2::zeros() } else { D1 };
+                let index = bulk_index(cell, sublattice);
+                FlakeSite { cell, sublattice, position, exchange : exchange[index], charge : charge[index], disorder : 0.0 }
+            })
+            .collect();
+        sites.sort_by(|a, b| a.position.y.partial_cmp(&b.position.y).unwrap().then(a.position.x.partial_cmp(&b.position.x).unwrap()));
+        let center = sites.iter().map(|site| site.position).sum::<Vector2<f64>>() / sites.len() as f64;
+        let index: HashMap<([i64; 2], usize), usize> = sites.iter().enumerate().map(|(i, site)| ((site.cell, site.sublattice), i)).collect();
+        let find = |position : Vector2<f64>| locate(position).and_then(|key| index.get(&key).copied());
+
+        let site_bonds = [0, 1].map(|sublattice| real_space_bonds(system, sublattice));
+        let mut bonds = Vec::new();
+        for (i, site) in sites.iter().enumerate() {
+            for &(d, hopping) in &site_bonds[site.sublattice] {
+                if let Some(j) = find(site.position + d) {
+                    bonds.push(FlakeBond { i, j, hopping });
+                }
+            }
+        }
+
+        Ok(Flake { name : system.debug(), shape, center, sites, anderson : 0.0, seed : 0, bonds })
+    }
+    /// 幅 anderson の一様分布のオンサイトの乱れを seed から加える
+    pub fn with_anderson(mut self, anderson : f64, seed : u64) -> Self{
+        let mut rng = SplitMix64::new(seed);
+        for site in self.sites.iter_mut() {
+            site.disorder = rng.symmetric(anderson);
+        }
+        self.anderson = anderson;
+        self.seed = seed;
+        self
+    }
+    pub fn size(&self) -> usize{
+        self.sites.len()
+    }
+    /// ファイル名用（例: UuudddKanemele_lambda0p30_j0p50_hexagon_r8、乱れがあれば _w1p00_s7 を付ける）
+    pub fn debug(&self) -> String{
+        Flake::debug_of(&self.name, self.shape, self.anderson, self.seed)
+    }
+    /// フレークを作らずに debug と同じ名前を求める（name は System::debug）
+    pub fn debug_of(name : &str, shape : FlakeShape, anderson : f64, seed : u64) -> String{
+        let base = format!("{}_{}", name, shape.name());
+        if anderson == 0.0 {
+            base
+        } else {
+            format!("{}_w{}_s{}", base, format!("{:.2}", anderson).replace('.', "p"), seed)
+        }
+    }
+    /// スピンごとのハミルトニアン [H↑, H↓]
+    pub fn hamiltonian(&self) -> [SparseMatrix; 2]{
+        [0, 1].map(|spin| {
+            let sign = if spin == 0 { 1.0 } else { -1.0 };
+            let onsite = self.sites.iter().enumerate()
+                .map(|(i, site)| (i, i, (site.charge + site.disorder + sign * site.exchange) * ONE));
+            let hopping = self.bonds.iter().map(|bond| (bond.i, bond.j, bond.hopping[spin]));
+            SparseMatrix::from_triplets(self.size(), onsite.chain(hopping).collect())
+        })
+    }
+    /// 厳密対角化（固有値の昇順）
+    pub fn diag(&self) -> SupercellEigen{
+        let [hamiltonian_u, hamiltonian_d] = self.hamiltonian().map(|matrix| matrix.to_dense());
+        SupercellEigen {
+            u : sort_symmetric_eigen_ascending(SymmetricEigen::new(hamiltonian_u)),
+            d : sort_symmetric_eigen_ascending(SymmetricEigen::new(hamiltonian_d)),
+        }
+    }
+}
+
+//----------------------------------------------------------------
+// Bianco-Resta の局所 Chern マーカー
+//
+// e_fermi より下の固有状態への射影 P と Q = 1 - P から、サイト r ごとに
+//   C_σ(r) = -(4π / A_site) Im ⟨r| P x Q y P |r⟩
+// （A_site = 3√3/4 は1サイトあたりの面積）。バルクでは副格子で平均すると
+// chern::chern_numbers の C_σ になり、端の近くでは打ち消す向きに大きくなって全体の和は 0 になる。
+// 乱れがあっても使える。
+//----------------------------------------------------------------
+
+pub struct LocalChernMarker{
+    pub e_fermi : f64,
+    pub occupied : [usize; 2],
+    pub energies : [Vec<f64>; 2],   // [spin] フレークの準位（昇順）
+    pub markers : [Vec<f64>; 2],    // [spin][site]
+}
+
+impl LocalChernMarker{
+    pub fn build(flake : &Flake, e_fermi : f64) -> Self{
+        let seud = flake.diag();
+        let area = 0.75 * SQRT_3;
+        let x: Vec<f64> = flake.sites.iter().map(|site| site.position.x - flake.center.x).collect();
+        let y: Vec<f64> = flake.sites.iter().map(|site| site.position.y - flake.center.y).collect();
+
+        let occupied = [0, 1].map(|spin| seud.spin(spin).eigenvalues.iter().filter(|&&e| e < e_fermi).count());
+        let markers = [0, 1].map(|spin| {
+            let eigen = seud.spin(spin);
+            let vectors = eigen.eigenvectors.columns(0, occupied[spin]);
+            let p: DMatrix<Complex<f64>> = vectors * vectors.adjoint();
+            let mut py = p.clone();
+            for (j, mut column) in py.column_iter_mut().enumerate() {
+                column *= Complex::new(y[j], 0.0);
+            }
+            let pyp = &py * &p;
+
+            //⟨i| P x Q y P |i⟩ = Σ_j P_ij x_j (y_j P_ji - (P y P)_ji)
+            (0..flake.size()).into_par_iter()
+                .map(|i| {
+                    let value: Complex<f64> = (0..flake.size()).map(|j| p[(i, j)] * x[j] * (p[(j, i)] * y[j] - pyp[(j, i)])).sum();
+                    -4. * PI / area * value.im
+                })
+                .collect()
+        });
+        let energies = [0, 1].map(|spin| seud.spin(spin).eigenvalues.iter().copied().collect());
+
+        LocalChernMarker { e_fermi, occupied, energies, markers }
+    }
+    /// 中心から radius 以内のサイトの平均 [spin]
+    pub fn bulk_average(&self, flake : &Flake, radius : f64) -> [f64; 2]{
+        let inside: Vec<usize> = (0..flake.size()).filter(|&i| (flake.sites[i].position - flake.center).norm() <= radius + 1e-9).collect();
+        [0, 1].map(|spin| inside.iter().map(|&i| self.markers[spin][i]).sum::<f64>() / inside.len().max(1) as f64)
+    }
+    pub fn write_to_dat(&self, flake : &Flake, file_path : &str) -> std::io::Result<()>{
+        let mut file = std::fs::File::create(file_path)?;
+
+        writeln!(
+            file, "# shape={},sites={},anderson={},seed={},e_fermi={},occupied_up={},occupied_down={}",
+            flake.shape.name(), flake.size(), flake.anderson, flake.seed, self.e_fermi, self.occupied[0], self.occupied[1]
+        )?;
+        writeln!(file, "# index,sublattice,x,y,distance,marker_up,marker_down,marker_charge,marker_spin")?;
+        for (i, site) in flake.sites.iter().enumerate() {
+            let (up, down) = (self.markers[0][i], self.markers[1][i]);
+            let r = site.position - flake.center;
+            writeln!(file, "{},{},{},{},{},{},{},{},{}", i, site.sublattice, r.x, r.y, r.norm(), up, down, up + down, 0.5 * (up - down))?;
+        }
+
+        Ok(())
+    }
+    /// フレークの準位を.datファイルに出力する
+    pub fn write_levels_to_dat(&self, file_path : &str) -> std::io::Result<()>{
+        let mut file = std::fs::File::create(file_path)?;
+
+        writeln!(file, "# e_fermi={}", self.e_fermi)?;
+        writeln!(file, "# index,energy_up,energy_down")?;
+        for (index, (up, down)) in self.energies[0].iter().zip(&self.energies[1]).enumerate() {
+            writeln!(file, "{},{},{}", index, up, down)?;
+        }
+
+        Ok(())
+    }
+}
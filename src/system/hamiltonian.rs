@@ -1,5 +1,6 @@
 use crate::{consts::*,};
 use crate::system::model::{System,};
+use crate::system::autodiff::{HyperDual, KScalar, KSeed};
 use nalgebra::{Complex, Const, Matrix2, Matrix6, Vector2, DimMin, Dim};
 
 //----------------------------------------------------------------
@@ -57,35 +58,7 @@ pub fn hamiltonian_from_system(system: &System, kk: Vector2<f64>,force_6 : bool)
 
 //2x2 のハミルトニアン
 pub fn hamiltonian_2(system : &System, kk : Vector2<f64>) -> Hamiltonian<2>{
-    let param = system.param();
-    let spin_seq = system.spinseq();
-    let tmd = system.tmd();
-
-    let lambda = param.lambda;
-    let jj = param.jj;
-
-    let diag = {
-        2. * lambda * (
-            kk.dot(&A1).sin() +
-            kk.dot(&A2).sin() +
-            kk.dot(&A3).sin() 
-        )
-    } * ONE;
-
-    let off_diag = {
-        Complex::exp( I * kk.dot(&D1)) +
-        Complex::exp( I * kk.dot(&D2)) +
-        Complex::exp( I * kk.dot(&D3))
-    } * -T;
-
-    let hamiltonian_u: nalgebra::Matrix<Complex<f64>, Const<2>, Const<2>, nalgebra::ArrayStorage<Complex<f64>, 2, 2>> = Matrix2::new(
-        diag,off_diag,
-        off_diag.conj(),diag * tmd
-    ) + spin_seq.diag_matrix2(jj);
-    let hamiltonian_d = Matrix2::new(
-        -diag,off_diag,
-        off_diag.conj(),-diag * tmd
-    ) - spin_seq.diag_matrix2(jj);
+    let (hamiltonian_u, hamiltonian_d) = hamiltonian_2_generic::<Complex<f64>>(system, &KSeed::value(kk));
 
     Hamiltonian{
         u: hamiltonian_u,
@@ -95,161 +68,324 @@ pub fn hamiltonian_2(system : &System, kk : Vector2<f64>) -> Hamiltonian<2>{
 
 //6x6 のハミルトニアン
 pub fn hamiltonian_6(system : &System, kk : Vector2<f64>) -> Hamiltonian<6>{
-    let param = system.param();
-    let spin_seq = system.spinseq();
-    let tmd = system.tmd();
-
-    let ed1p = Complex::exp( I * kk.dot(&D1)) * -T;
-    let ed1m = Complex::exp(-I * kk.dot(&D1)) * -T;
-    let ed2p = Complex::exp( I * kk.dot(&D2)) * -T;
-    let ed2m = Complex::exp(-I * kk.dot(&D2)) * -T;
-    let ed3p = Complex::exp( I * kk.dot(&D3)) * -T;
-    let ed3m = Complex::exp(-I * kk.dot(&D3)) * -T;
-
-    let lambda = param.lambda;
-    let jj = param.jj;
-
-    let plu = {
-        Complex::exp( I * kk.dot(&A1)) +
-        Complex::exp( I * kk.dot(&A2)) +
-        Complex::exp( I * kk.dot(&A3))
-    } * I * lambda;
-    let mnu = {
-        Complex::exp(-I * kk.dot(&A1)) +
-        Complex::exp(-I * kk.dot(&A2)) +
-        Complex::exp(-I * kk.dot(&A3))
-    } * I * lambda;
-
-    let (hamiltonian_u, hamiltonian_d) = {
-        let (u_pre,d_pre) = hamiltonian_6_box(
-            ed1p, ed1m,
-            ed2p, ed2m,
-            ed3p, ed3m,
-            plu, mnu,
-            tmd
-        );
-
-        let diag = spin_seq.diag_matrix6(jj);
-
-        (
-            u_pre + diag,
-            d_pre - diag
-        )
-    };
+    let (hamiltonian_u, hamiltonian_d) = hamiltonian_6_generic::<Complex<f64>>(system, &KSeed::value(kk));
 
     Hamiltonian{
         u: hamiltonian_u,
         d: hamiltonian_d,
     }
-
-    
 }
 
-//2x2 pdv(H,k_x_i)
-pub fn hamiltonian_2_dxi(system : &System, kk : Vector2<f64>, xindex : usize) -> Hamiltonian<2>{
+//----------------------------------------------------------------
+// スカラー型についてジェネリックなハミルトニアン
+// Complex<f64> なら H(k)、HyperDual なら H とその k 微分を同時に与える
+//----------------------------------------------------------------
+
+//2x2 のハミルトニアン
+pub fn hamiltonian_2_generic<S: KScalar>(system : &System, seed : &KSeed) -> (Matrix2<S>, Matrix2<S>){
     let param = system.param();
+    let exchange = system.exchange();
+    let charge = system.charge();
     let tmd = system.tmd();
 
     let lambda = param.lambda;
 
     let diag = {
-        2. * lambda * (
-            kk.dot(&A1).cos() * A1[xindex] +
-            kk.dot(&A2).cos() * A2[xindex] +
-            kk.dot(&A3).cos() * A3[xindex] 
-        )
-    } * ONE;
+        S::sin_k(seed, &A1) +
+        S::sin_k(seed, &A2) +
+        S::sin_k(seed, &A3)
+    } * (2. * lambda);
 
     let off_diag = {
-        Complex::exp( I * kk.dot(&D1)) * I * D1[xindex] +
-        Complex::exp( I * kk.dot(&D2)) * I * D2[xindex] +
-        Complex::exp( I * kk.dot(&D3)) * I * D3[xindex]
+        S::exp_ik(seed, &D1) +
+        S::exp_ik(seed, &D2) +
+        S::exp_ik(seed, &D3)
     } * -T;
 
-    let hamiltonian_u: nalgebra::Matrix<Complex<f64>, Const<2>, Const<2>, nalgebra::ArrayStorage<Complex<f64>, 2, 2>> = Matrix2::new(
-        diag,off_diag,
-        off_diag.conj(),diag * tmd
-    ) ;
+    let ja = S::constant(exchange[0] * ONE);
+    let jb = S::constant(exchange[1] * ONE);
+    let ca = S::constant(charge[0] * ONE);
+    let cb = S::constant(charge[1] * ONE);
+
+    let hamiltonian_u = Matrix2::new(
+        diag + ja + ca,off_diag,
+        off_diag.conj(),diag * tmd + jb + cb
+    );
     let hamiltonian_d = Matrix2::new(
-        -diag,off_diag,
-        off_diag.conj(),-diag * tmd
+        -diag - ja + ca,off_diag,
+        off_diag.conj(),-diag * tmd - jb + cb
     );
 
-    Hamiltonian{
-        u: hamiltonian_u,
-        d: hamiltonian_d,
-    }
+    (hamiltonian_u, hamiltonian_d)
 }
 
-//6x6 pdv(H,k_x_i)
-pub fn hamiltonian_6_dxi(system : &System, kk : Vector2<f64>, xindex : usize) -> Hamiltonian<6>{
+//6x6 のハミルトニアン
+pub fn hamiltonian_6_generic<S: KScalar>(system : &System, seed : &KSeed) -> (Matrix6<S>, Matrix6<S>){
     let param = system.param();
+    let exchange = system.exchange();
+    let charge = system.charge();
     let tmd = system.tmd();
 
-    let ed1p = Complex::exp( I * kk.dot(&D1)) * -T;
-    let ed1m = Complex::exp(-I * kk.dot(&D1)) * -T;
-    let ed2p = Complex::exp( I * kk.dot(&D2)) * -T;
-    let ed2m = Complex::exp(-I * kk.dot(&D2)) * -T;
-    let ed3p = Complex::exp( I * kk.dot(&D3)) * -T;
-    let ed3m = Complex::exp(-I * kk.dot(&D3)) * -T;
+    let ed1p = S::exp_ik(seed, &D1) * -T;
+    let ed1m = S::exp_ik(seed, &-D1) * -T;
+    let ed2p = S::exp_ik(seed, &D2) * -T;
+    let ed2m = S::exp_ik(seed, &-D2) * -T;
+    let ed3p = S::exp_ik(seed, &D3) * -T;
+    let ed3m = S::exp_ik(seed, &-D3) * -T;
 
     let lambda = param.lambda;
 
-    let pludx = {
-        Complex::exp( I * kk.dot(&A1)) * I * A1[xindex] +
-        Complex::exp( I * kk.dot(&A2)) * I * A2[xindex] +
-        Complex::exp( I * kk.dot(&A3)) * I * A3[xindex]
-    } * I * lambda;
-    let mnudx = {
-        Complex::exp(-I * kk.dot(&A1)) * -I * A1[xindex] +
-        Complex::exp(-I * kk.dot(&A2)) * -I * A2[xindex] +
-        Complex::exp(-I * kk.dot(&A3)) * -I * A3[xindex] 
-    } * I * lambda;
-
-    let (hamiltonian_u, hamiltonian_d) = 
-    hamiltonian_6_box(
-        ed1p * I * D1[xindex], ed1m * -I * D1[xindex],
-        ed2p * I * D2[xindex], ed2m * -I * D2[xindex],
-        ed3p * I * D3[xindex], ed3m * -I * D3[xindex],
-        pludx, mnudx,
+    let plu = {
+        S::exp_ik(seed, &A1) +
+        S::exp_ik(seed, &A2) +
+        S::exp_ik(seed, &A3)
+    } * (I * lambda);
+    let mnu = {
+        S::exp_ik(seed, &-A1) +
+        S::exp_ik(seed, &-A2) +
+        S::exp_ik(seed, &-A3)
+    } * (I * lambda);
+
+    let (mut hamiltonian_u, mut hamiltonian_d) = hamiltonian_6_box(
+        ed1p, ed1m,
+        ed2p, ed2m,
+        ed3p, ed3m,
+        plu, mnu,
         tmd
     );
 
-    Hamiltonian{
-        u: hamiltonian_u,
-        d: hamiltonian_d,
+    for site in 0..6 {
+        let j_site = S::constant(exchange[site] * ONE);
+        let c_site = S::constant(charge[site] * ONE);
+        hamiltonian_u[(site, site)] = hamiltonian_u[(site, site)] + j_site + c_site;
+        hamiltonian_d[(site, site)] = hamiltonian_d[(site, site)] - j_site + c_site;
     }
 
-    
+    add_bond_corrections(system, &mut hamiltonian_u, &mut hamiltonian_d, |r, delta| S::exp_ik(seed, r) * delta);
+
+    (hamiltonian_u, hamiltonian_d)
+}
+
+//----------------------------------------------------------------
+// 自動微分による H, pdv(H,k_x_i), pdv(H,k_x_j), pdv(H,k_x_i,k_x_j)
+//----------------------------------------------------------------
+#[derive(Clone,Debug)]
+pub struct HamiltonianJet<const N: usize>{
+    pub h: Hamiltonian<N>,
+    pub dxi: Hamiltonian<N>,
+    pub dxj: Hamiltonian<N>,
+    pub dxidxj: Hamiltonian<N>,
+}
+
+pub fn hamiltonian_2_jet(system : &System, kk : Vector2<f64>, xindex : usize, yindex : usize) -> HamiltonianJet<2>{
+    let (u, d) = hamiltonian_2_generic::<HyperDual>(system, &KSeed::new(kk, xindex, yindex));
+
+    HamiltonianJet{
+        h: Hamiltonian{ u: u.map(|x| x.re), d: d.map(|x| x.re) },
+        dxi: Hamiltonian{ u: u.map(|x| x.dx), d: d.map(|x| x.dx) },
+        dxj: Hamiltonian{ u: u.map(|x| x.dy), d: d.map(|x| x.dy) },
+        dxidxj: Hamiltonian{ u: u.map(|x| x.dxdy), d: d.map(|x| x.dxdy) },
+    }
+}
+
+pub fn hamiltonian_6_jet(system : &System, kk : Vector2<f64>, xindex : usize, yindex : usize) -> HamiltonianJet<6>{
+    let (u, d) = hamiltonian_6_generic::<HyperDual>(system, &KSeed::new(kk, xindex, yindex));
+
+    HamiltonianJet{
+        h: Hamiltonian{ u: u.map(|x| x.re), d: d.map(|x| x.re) },
+        dxi: Hamiltonian{ u: u.map(|x| x.dx), d: d.map(|x| x.dx) },
+        dxj: Hamiltonian{ u: u.map(|x| x.dy), d: d.map(|x| x.dy) },
+        dxidxj: Hamiltonian{ u: u.map(|x| x.dxdy), d: d.map(|x| x.dxdy) },
+    }
+}
+
+//----------------------------------------------------------------
+// システムからハミルトニアンの微分を生成する関数
+//----------------------------------------------------------------
+pub fn hamiltonian_dxi_from_system(system: &System, kk: Vector2<f64>, force_6 : bool, xindex : usize) -> HamiltonianEnum{
+    match system.size(){
+        2 if !force_6 => HamiltonianEnum::H2(hamiltonian_2_jet(system, kk, xindex, xindex).dxi),
+        2 | 6 => HamiltonianEnum::H6(hamiltonian_6_jet(system, kk, xindex, xindex).dxi),
+        _ => panic!("system size should be 2 or 6"),
+    }
+}
+
+pub fn hamiltonian_dxidxj_from_system(system: &System, kk: Vector2<f64>, force_6 : bool, xindex : usize, yindex : usize) -> HamiltonianEnum{
+    match system.size(){
+        2 if !force_6 => HamiltonianEnum::H2(hamiltonian_2_jet(system, kk, xindex, yindex).dxidxj),
+        2 | 6 => HamiltonianEnum::H6(hamiltonian_6_jet(system, kk, xindex, yindex).dxidxj),
+        _ => panic!("system size should be 2 or 6"),
+    }
 }
 
 //----------------------------------------------------------------
 // 6x6 の重複部分を関数にしたもの
 //----------------------------------------------------------------
-fn hamiltonian_6_box(
-    ed1p : Complex<f64>, ed1m : Complex<f64>,
-    ed2p : Complex<f64>, ed2m : Complex<f64>, 
-    ed3p : Complex<f64>, ed3m : Complex<f64>,
-    plu  : Complex<f64>, mnu  : Complex<f64>,
+fn hamiltonian_6_box<S: KScalar>(
+    ed1p : S, ed1m : S,
+    ed2p : S, ed2m : S, 
+    ed3p : S, ed3m : S,
+    plu  : S, mnu  : S,
     tmd  : f64
-) -> (Matrix6<Complex<f64>>, Matrix6<Complex<f64>>){
+) -> (Matrix6<S>, Matrix6<S>){
+    let zero = S::zero();
     let hamiltonian_u = Matrix6::new(
-        ZERO  ,ed1p,-plu*tmd,ed3p, mnu*tmd,ed2p,
-        ed1m,ZERO  ,ed2m,-plu,ed3m, mnu,
-        mnu*tmd,ed2p,ZERO ,ed1p,-plu*tmd, ed3p,
-        ed3m, mnu,ed1m,ZERO  ,ed2m,-plu,
-        -plu*tmd,ed3p, mnu*tmd,ed2p,ZERO  ,ed1p,
-        ed2m,-plu,ed3m, mnu,ed1m,ZERO
+        zero  ,ed1p,-plu*tmd,ed3p, mnu*tmd,ed2p,
+        ed1m,zero  ,ed2m,-plu,ed3m, mnu,
+        mnu*tmd,ed2p,zero ,ed1p,-plu*tmd, ed3p,
+        ed3m, mnu,ed1m,zero  ,ed2m,-plu,
+        -plu*tmd,ed3p, mnu*tmd,ed2p,zero  ,ed1p,
+        ed2m,-plu,ed3m, mnu,ed1m,zero
     );
     let hamiltonian_d = Matrix6::new(
-        ZERO  ,ed1p, plu*tmd,ed3p,-mnu*tmd,ed2p,
-        ed1m,ZERO  ,ed2m, plu,ed3m,-mnu,
-        -mnu*tmd,ed2p,ZERO  ,ed1p, plu*tmd, ed3p,
-        ed3m,-mnu,ed1m,ZERO  ,ed2m, plu,
-        plu*tmd,ed3p,-mnu*tmd,ed2p,ZERO  ,ed1p,
-        ed2m, plu,ed3m,-mnu,ed1m,ZERO
+        zero  ,ed1p, plu*tmd,ed3p,-mnu*tmd,ed2p,
+        ed1m,zero  ,ed2m, plu,ed3m,-mnu,
+        -mnu*tmd,ed2p,zero  ,ed1p, plu*tmd, ed3p,
+        ed3m,-mnu,ed1m,zero  ,ed2m, plu,
+        plu*tmd,ed3p,-mnu*tmd,ed2p,zero  ,ed1p,
+        ed2m, plu,ed3m,-mnu,ed1m,zero
     );
     (hamiltonian_u, hamiltonian_d)
 }
 
+//----------------------------------------------------------------
+// 6サイトの単位胞内の結合（hamiltonian_6_box の行列要素と同じ並び）
+//----------------------------------------------------------------
+pub const BOND_COUNT_6 : usize = 27;
+
+/// サイト i から j（i < j）への結合。r は i から j へのベクトル、neighbor は 1（最近接）または 2（次近接）
+#[derive(Debug, Clone, Copy)]
+pub struct Bond{
+    pub i : usize,
+    pub j : usize,
+    pub r : Vector2<f64>,
+    pub neighbor : usize,
+}
+
+/// 最近接の9本と次近接の18本の結合
+pub fn bonds_6() -> Vec<Bond>{
+    let nearest = [
+        (0, 1, D1), (0, 3, D3), (0, 5, D2),
+        (1, 2, -D2), (1, 4, -D3),
+        (2, 3, D1), (2, 5, D3),
+        (3, 4, -D2),
+        (4, 5, D1),
+    ];
+    //A副格子 0 -> 2 -> 4 -> 0, B副格子 1 -> 3 -> 5 -> 1 がそれぞれ A1, A2, A3 のどれかで結ばれる
+    let next_nearest = [(0, 2, 1.0), (0, 4, -1.0), (1, 3, 1.0), (1, 5, -1.0), (2, 4, 1.0), (3, 5, 1.0)];
+
+    let mut bonds: Vec<Bond> = nearest.iter()
+        .map(|&(i, j, r)| Bond { i, j, r, neighbor : 1 })
+        .collect();
+    for &(i, j, sign) in &next_nearest {
+        for a in [A1, A2, A3] {
+            bonds.push(Bond { i, j, r : a * sign, neighbor : 2 });
+        }
+    }
+    bonds
+}
+
+//----------------------------------------------------------------
+// 6サイトの単位胞のサイトの位置（bonds_6 の結合と同じ並び）
+//----------------------------------------------------------------
+
+/// サイト 0 から見た6サイトの単位胞の各サイトの位置（偶数番目が A 副格子）
+pub fn six_site_positions() -> [Vector2<f64>; 6]{
+    [Vector2::zeros(), D1, D1 - D2, D1 * 2.0 - D2, D1 - D3, D2]
+}
+
+/// 位置 x にあるサイトの6サイトの単位胞での番号（単位胞の格子ベクトルは 3 D1, 3 D2）
+pub fn six_site_index(x : &Vector2<f64>) -> Option<usize>{
+    let (l1, l2) = (D1 * 3.0, D2 * 3.0);
+    let det = l1.x * l2.y - l1.y * l2.x;
+
+    six_site_positions().iter().position(|p| {
+        let d = x - p;
+        let a = (d.x * l2.y - d.y * l2.x) / det;
+        let b = (l1.x * d.y - l1.y * d.x) / det;
+        (a - a.round()).abs() < 1e-6 && (b - b.round()).abs() < 1e-6
+    })
+}
+
+//----------------------------------------------------------------
+// 実空間の格子の結合（supercell, ribbon, flake, disorder, peierls で共通）
+//
+// 基本格子 a1 = A3, a2 = A1 の基本単位胞 R ごとに A サイト（R）と B サイト（R + D1）を置く。
+//----------------------------------------------------------------
+
+/// 実空間のサイトから出る結合 (結合ベクトル d, [上向き, 下向き] の振幅)
+pub type SiteBond = (Vector2<f64>, [Complex<f64>; 2]);
+
+/// 位置から (基本単位胞, 副格子) を求める（格子点でなければNone）
+pub fn locate(position : Vector2<f64>) -> Option<([i64; 2], usize)>{
+    (0..2).find_map(|sublattice| {
+        let p = if sublattice == 0 { position } else { position - D1 };
+        let n2 = p.y / A1.y;
+        let n1 = (p.x - n2 * A1.x) / A3.x;
+        let cell = [n1.round() as i64, n2.round() as i64];
+        ((n1 - cell[0] as f64).abs() < 1e-6 && (n2 - cell[1] as f64).abs() < 1e-6).then_some((cell, sublattice))
+    })
+}
+
+/// 副格子 sublattice（0 = A, 1 = B）のサイトから出る最近接の3本と次近接の6本の結合
+/// （次近接の振幅には factor を掛ける）
+pub fn site_bonds(lambda : f64, sublattice : usize, factor : f64) -> Vec<SiteBond>{
+    let mut bonds = Vec::with_capacity(9);
+    //最近接：A から B へは +D1, +D2, +D3、B から A へはその逆向き
+    let sign = if sublattice == 0 { 1.0 } else { -1.0 };
+    for d in [D1, D2, D3] {
+        bonds.push((d * sign, [-T * ONE; 2]));
+    }
+    //次近接：+A 方向に -iλ、-A 方向に +iλ（下向きスピンは符号が逆）
+    for a in [A1, A2, A3] {
+        for direction in [1.0, -1.0] {
+            let hopping = -I * lambda * direction * factor;
+            bonds.push((a * direction, [hopping, -hopping]));
+        }
+    }
+    bonds
+}
 
+/// System の副格子 sublattice のサイトから出る結合
+/// （tmd の因子は hamiltonian_2 なら B 副格子、hamiltonian_6 なら A 副格子に掛ける）
+pub fn real_space_bonds(system : &System, sublattice : usize) -> Vec<SiteBond>{
+    let factor = match (system.size(), sublattice) {
+        (2, 1) | (6, 0) => system.tmd(),
+        _ => 1.0,
+    };
+    site_bonds(system.param().lambda, sublattice, factor)
+}
+
+/// 拡張した平均場の結合の補正（V, V2 の Fock項）は実空間の格子では扱えないのでErrを返す
+pub fn check_real_space(system : &System) -> Result<(), String>{
+    if let System::MeanField(_, fields) = system && fields.has_bond_corrections() {
+        return Err(format!("{} has bond corrections, which are not supported in real space", system.debug()));
+    }
+    Ok(())
+}
+
+//MeanField の結合ごとの補正（Fock項）を加える
+//term(r, delta) は補正 delta exp(i k・r)（またはその k 微分）
+fn add_bond_corrections<S: KScalar>(
+    system : &System,
+    hamiltonian_u : &mut Matrix6<S>,
+    hamiltonian_d : &mut Matrix6<S>,
+    term : impl Fn(&Vector2<f64>, Complex<f64>) -> S,
+){
+    let corrections = match system {
+        System::MeanField(_, fields) => fields.bonds,
+        _ => return,
+    };
+
+    for (b, bond) in bonds_6().iter().enumerate() {
+        for (spin, hamiltonian) in [&mut *hamiltonian_u, &mut *hamiltonian_d].into_iter().enumerate() {
+            let delta = corrections[spin][b];
+            if delta == ZERO {
+                continue;
+            }
+            let value = term(&bond.r, delta);
+            hamiltonian[(bond.i, bond.j)] = hamiltonian[(bond.i, bond.j)] + value;
+            hamiltonian[(bond.j, bond.i)] = hamiltonian[(bond.j, bond.i)] + value.conj();
+        }
+    }
+}
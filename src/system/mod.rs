@@ -1,4 +1,12 @@
 pub mod model;
 pub mod diag;
 mod spinseq;
-pub mod hamiltonian;
\ No newline at end of file
+pub mod hamiltonian;
+pub mod autodiff;
+pub mod supercell;
+pub mod ribbon;
+pub mod sparse;
+pub mod random;
+pub mod flake;
+pub mod disorder;
+pub mod peierls;
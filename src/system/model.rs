@@ -1,7 +1,10 @@
-use crate::consts::T;
+use crate::consts::{T, ZERO};
+use crate::system::hamiltonian::BOND_COUNT_6;
+use nalgebra::Complex;
+use std::sync::Arc;
 use crate::system::spinseq::SpinSeq6;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub enum System{
     Uuuddd(Param),
     Sato(Param),
@@ -16,8 +19,6 @@ pub enum System{
     Tri2Tmd(Param),         //DUDUDD+TMD
     SatoTmd(Param),         //UDUDUD+TMD
     //--------------------------------------------------------------------
-    Stable(Param),
-    //--------------------------------------------------------------------
     FmKanemele(Param),
     One1Kanemele(Param),
     One2Kanemele(Param),
@@ -26,6 +27,8 @@ pub enum System{
     Tri2Kanemele(Param),
     UuudddKanemele(Param),
     AfmKanemele(Param),
+    //--------------------------------------------------------------------
+    MeanField(Param, Arc<SiteFields>),  //Hubbard平均場で求めたスピン配置（interaction::hubbard）、結合ごとの補正で大きいので共有する
 }
 
 impl System{
@@ -44,8 +47,6 @@ impl System{
             Self::UuudddTmd(_) => {6}
             Self::SatoTmd(_) => {2}
             //--------------------------------------------------------------------
-            Self::Stable(_) => {panic!("Stable does not have a size");}
-            //--------------------------------------------------------------------
             Self::FmKanemele(_) => {2},
             Self::One1Kanemele(_) => {6},
             Self::One2Kanemele(_) => {6},
@@ -54,6 +55,8 @@ impl System{
             Self::Tri2Kanemele(_) => {6},
             Self::UuudddKanemele(_) => {6},
             Self::AfmKanemele(_) => {2},
+            //--------------------------------------------------------------------
+            Self::MeanField(_, fields) => fields.size,
         }
     }
     pub fn param(&self) -> &Param{
@@ -71,7 +74,6 @@ impl System{
             Self::UuudddTmd(param) => param,
             Self::SatoTmd(param) => param,
             //--------------------------------------------------------------------
-            Self::Stable(param) => param,
             Self::FmKanemele(param) => param,
             Self::One1Kanemele(param) => param,
             Self::One2Kanemele(param) => param,
@@ -80,6 +82,37 @@ impl System{
             Self::Tri2Kanemele(param) => param,
             Self::UuudddKanemele(param) => param,
             Self::AfmKanemele(param) => param,
+            //--------------------------------------------------------------------
+            Self::MeanField(param, _) => param,
+        }
+    }
+    /// スピン配置はそのままでパラメーターだけを置き換える
+    /// （MeanField のサイトごとの場は置き換えないので、自己無撞着ではなくなる）
+    pub fn with_param(&self, param : Param) -> Self{
+        match self{
+            Self::Uuuddd(_) => Self::Uuuddd(param),
+            Self::Tmd(_) => Self::Tmd(param),
+            Self::Sato(_) => Self::Sato(param),
+            //--------------------------------------------------------------------
+            Self::FmTmd(_) => Self::FmTmd(param),
+            Self::One1Tmd(_) => Self::One1Tmd(param),
+            Self::One2Tmd(_) => Self::One2Tmd(param),
+            Self::TwinTmd(_) => Self::TwinTmd(param),
+            Self::Tri1Tmd(_) => Self::Tri1Tmd(param),
+            Self::Tri2Tmd(_) => Self::Tri2Tmd(param),
+            Self::UuudddTmd(_) => Self::UuudddTmd(param),
+            Self::SatoTmd(_) => Self::SatoTmd(param),
+            //--------------------------------------------------------------------
+            Self::FmKanemele(_) => Self::FmKanemele(param),
+            Self::One1Kanemele(_) => Self::One1Kanemele(param),
+            Self::One2Kanemele(_) => Self::One2Kanemele(param),
+            Self::TwinKanemele(_) => Self::TwinKanemele(param),
+            Self::Tri1Kanemele(_) => Self::Tri1Kanemele(param),
+            Self::Tri2Kanemele(_) => Self::Tri2Kanemele(param),
+            Self::UuudddKanemele(_) => Self::UuudddKanemele(param),
+            Self::AfmKanemele(_) => Self::AfmKanemele(param),
+            //--------------------------------------------------------------------
+            Self::MeanField(_, fields) => Self::MeanField(param, fields.clone()),
         }
     }
     pub fn tmd(&self) -> f64{
@@ -97,8 +130,6 @@ impl System{
             Self::UuudddTmd(_) => 0.0,
             Self::SatoTmd(_) => 0.0,
             //--------------------------------------------------------------------
-            Self::Stable(_) => 1.0,
-            //--------------------------------------------------------------------
             Self::FmKanemele(_) => -1.0,
             Self::One1Kanemele(_) => -1.0,
             Self::One2Kanemele(_) => -1.0,
@@ -107,39 +138,32 @@ impl System{
             Self::Tri2Kanemele(_) => -1.0,
             Self::UuudddKanemele(_) => -1.0, 
             Self::AfmKanemele(_) => -1.0,
+            //--------------------------------------------------------------------
+            Self::MeanField(_, fields) => fields.tmd,
         }
     }
     pub fn debug(&self) -> String{
-        let system_name = match self {
-            Self::Uuuddd(_) => "Uuuddd",
-            Self::Tmd(_) => "Tmd",
-            Self::Sato(_) => "Sato",
-            //--------------------------------------------------------------------
-            Self::FmTmd(_) => "FmTmd",
-            Self::One1Tmd(_) => "One1Tmd",
-            Self::One2Tmd(_) => "One2Tmd",
-            Self::TwinTmd(_) => "TwinTmd",
-            Self::Tri1Tmd(_) => "Tri1Tmd",
-            Self::Tri2Tmd(_) => "Tri2Tmd",
-            Self::UuudddTmd(_) => "UuudddTmd",
-            Self::SatoTmd(_) => "SatoTmd",
-            //--------------------------------------------------------------------
-            Self::Stable(_) => "Stable",
-            //--------------------------------------------------------------------
-            Self::FmKanemele(_) => "FmKanemele",
-            Self::One1Kanemele(_) => "One1Kanemele",
-            Self::One2Kanemele(_) => "One2Kanemele",
-            Self::TwinKanemele(_) => "TwinKanemele",
-            Self::Tri1Kanemele(_) => "Tri1Kanemele",
-            Self::Tri2Kanemele(_) => "Tri2Kanemele",
-            Self::UuudddKanemele(_) => "UuudddKanemele",
-            Self::AfmKanemele(_) => "AfmKanemele",
-        };
-
-        format!("{}_lambda{}_j{}", system_name, format!("{:.2}", self.param().lambda).replace('.', "p"), format!("{:.2}", self.param().jj).replace('.', "p"))
+        format!("{}_{}", self.debug_only_name(), self.param().debug())
     }
     pub fn debug_only_name(&self) -> String{
-        let system_name = match self {
+        match self {
+            Self::MeanField(_, fields) => {
+                let label = |value : f64| format!("{:.2}", value).replace('.', "p");
+                let mut name = format!("MeanField{}U{}", fields.seed, label(fields.u));
+                if fields.v != 0.0 {
+                    name += &format!("V{}", label(fields.v));
+                }
+                if fields.v2 != 0.0 {
+                    name += &format!("V2{}", label(fields.v2));
+                }
+                name
+            }
+            _ => self.name().to_string(),
+        }
+    }
+    /// スピン配置の名前（MeanField は初期値によらず "MeanField"）
+    pub fn name(&self) -> &'static str{
+        match self {
             Self::Uuuddd(_) => "Uuuddd",
             Self::Tmd(_) => "Tmd",
             Self::Sato(_) => "Sato",
@@ -153,8 +177,6 @@ impl System{
             Self::UuudddTmd(_) => "UuudddTmd",
             Self::SatoTmd(_) => "SatoTmd",
             //--------------------------------------------------------------------
-            Self::Stable(_) => "Stable",
-            //--------------------------------------------------------------------
             Self::FmKanemele(_) => "FmKanemele",
             Self::One1Kanemele(_) => "One1Kanemele",
             Self::One2Kanemele(_) => "One2Kanemele",
@@ -163,9 +185,9 @@ impl System{
             Self::Tri2Kanemele(_) => "Tri2Kanemele",
             Self::UuudddKanemele(_) => "UuudddKanemele",
             Self::AfmKanemele(_) => "AfmKanemele",
-        };
-
-        system_name.to_string()
+            //--------------------------------------------------------------------
+            Self::MeanField(_, _) => "MeanField",
+        }
     }
     pub fn spinseq(&self) -> SpinSeq6{
         match self {
@@ -196,11 +218,87 @@ impl System{
             Self::Tmd(_) => {
                 SpinSeq6::para()
             }
-            Self::Stable(_) => {
-                panic!("Stable does not have a spin sequence");
+            Self::MeanField(_, fields) => {
+                //交換場の向きと大きさの比（最大の大きさを 1 とする）
+                let max = fields.exchange.iter().fold(0.0_f64, |max, h| max.max(h.abs()));
+                let scale = if max > 0.0 { 1.0 / max } else { 0.0 };
+                let [a, b, c, d, e, f] = fields.exchange.map(|h| h * scale);
+                SpinSeq6::new(a, b, c, d, e, f)
             }
         }
     }
+    /// サイトごとの交換場（スピン上向きに +、下向きに - で加わる）
+    pub fn exchange(&self) -> [f64; 6]{
+        match self {
+            Self::MeanField(_, fields) => fields.exchange,
+            _ => {
+                let spin_seq = self.spinseq();
+                let jj = self.param().jj;
+                [spin_seq.a, spin_seq.b, spin_seq.c, spin_seq.d, spin_seq.e, spin_seq.f].map(|s| s * jj)
+            }
+        }
+    }
+    /// サイトごとの電荷ポテンシャル（両スピンに加わる。MeanField 以外は 0）
+    pub fn charge(&self) -> [f64; 6]{
+        match self {
+            Self::MeanField(_, fields) => fields.charge,
+            _ => [0.0; 6],
+        }
+    }
+    /// 模型の系列とスピン配置の名前からSystemを作る（実行設定ファイル用）
+    ///
+    /// 系列は "original"（tmd = 1）, "tmd"（tmd = 0）, "kanemele"（tmd = -1）、
+    /// スピン配置は "para", "fm", "afm", "one1", "one2", "twin", "tri1", "tri2", "uuuddd"。
+    /// 存在しない組み合わせの場合はNoneを返す。
+    pub fn from_family(family : &str, spin : &str, param : Param) -> Option<Self>{
+        let system = match (family, spin) {
+            ("original", "uuuddd") => Self::Uuuddd(param),
+            ("original", "afm") => Self::Sato(param),
+            //--------------------------------------------------------------------
+            ("tmd", "para") => Self::Tmd(param),
+            ("tmd", "fm") => Self::FmTmd(param),
+            ("tmd", "one1") => Self::One1Tmd(param),
+            ("tmd", "one2") => Self::One2Tmd(param),
+            ("tmd", "twin") => Self::TwinTmd(param),
+            ("tmd", "tri1") => Self::Tri1Tmd(param),
+            ("tmd", "uuuddd") => Self::UuudddTmd(param),
+            ("tmd", "tri2") => Self::Tri2Tmd(param),
+            ("tmd", "afm") => Self::SatoTmd(param),
+            //--------------------------------------------------------------------
+            ("kanemele", "fm") => Self::FmKanemele(param),
+            ("kanemele", "one1") => Self::One1Kanemele(param),
+            ("kanemele", "one2") => Self::One2Kanemele(param),
+            ("kanemele", "twin") => Self::TwinKanemele(param),
+            ("kanemele", "tri1") => Self::Tri1Kanemele(param),
+            ("kanemele", "tri2") => Self::Tri2Kanemele(param),
+            ("kanemele", "uuuddd") => Self::UuudddKanemele(param),
+            ("kanemele", "afm") => Self::AfmKanemele(param),
+            _ => return None,
+        };
+
+        Some(system)
+    }
+}
+
+/// 平均場で求めたサイトごとの場（size = 2 なら先頭の2サイトだけを使う）
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SiteFields{
+    pub size : usize,
+    pub tmd : f64,              // 模型の系列（System::tmd と同じ）
+    pub u : f64,                // Hubbard U
+    pub v : f64,                // 最近接の相互作用 V
+    pub v2 : f64,               // 次近接の相互作用 V2
+    pub seed : &'static str,    // 初期値に使ったスピン配置の名前
+    pub exchange : [f64; 6],
+    pub charge : [f64; 6],
+    pub bonds : [[Complex<f64>; BOND_COUNT_6]; 2],  // [spin][bond] 結合ごとの飛び移りの補正（hamiltonian::bonds_6 の順、size = 6 のときだけ使う）
+}
+
+impl SiteFields{
+    /// 結合ごとの補正（V, V2 の Fock項）を持つか
+    pub fn has_bond_corrections(&self) -> bool{
+        self.bonds.iter().flatten().any(|delta| *delta != ZERO)
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -213,6 +311,10 @@ impl Param{
     pub fn new(lambda: f64, jj: f64) -> Self{
         Param { lambda, jj }
     }
+    /// ファイル名用（例: lambda0p30_j0p25）
+    pub fn debug(&self) -> String{
+        format!("lambda{}_j{}", format!("{:.2}", self.lambda).replace('.', "p"), format!("{:.2}", self.jj).replace('.', "p"))
+    }
     pub fn interesting() -> Self{
         let lambda = 0.3 * T;
         let jj = 0.25;
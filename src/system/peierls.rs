@@ -0,0 +1,185 @@
+use crate::consts::*;
+use crate::system::diag::sort_symmetric_eigen_ascending;
+use crate::system::hamiltonian::{check_real_space, locate, real_space_bonds};
+use crate::system::model::System;
+use crate::system::supercell::{Supercell, SupercellEigen};
+
+use nalgebra::{Complex, DMatrix, Matrix2, SymmetricEigen, Vector2};
+
+//----------------------------------------------------------------
+// 垂直な磁場の Peierls 置換と磁気スーパーセル
+//
+// 六員環（基本単位胞）あたりの磁束を φ = p/q（磁束量子の単位）とし、
+// r = ξ1 a1 + ξ2 a2（a1 = A3, a2 = A1）の分数座標でのゲージ A・dr ∝ ξ1 dξ2 をとると、
+// 結合 i → j（最近接も次近接の λ も）の位相は直線に沿った積分で
+//   θ_ij = 2π φ (ξ1_i + ξ1_j) / 2 (ξ2_j - ξ2_i)
+// になる。a2 方向の並進では変わらず、a1 方向に n1 だけずらすと 2π φ n1 Δξ2 だけ変わるので、
+// Δξ2 ∈ Z/3 から φ n1 ∈ 3Z なら周期的になる。n1 a1 × n2 a2 のスーパーセルは
+// さらに System の磁気単位胞の格子ベクトルになる最小のものをとる。
+// 交換場、電荷ポテンシャルと結合（hamiltonian::real_space_bonds）は ribbon, flake と同じで、
+// 位相は結合ベクトルの exp(i k・d)。
+//----------------------------------------------------------------
+
+/// 六員環あたりの磁束 p/q
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Flux{
+    pub p : i64,
+    pub q : i64,
+}
+
+fn gcd(a : i64, b : i64) -> i64{
+    if b == 0 { a.abs() } else { gcd(b, a % b) }
+}
+
+impl Flux{
+    pub fn new(p : i64, q : i64) -> Self{
+        let g = gcd(p, q).max(1) * q.signum();
+        Flux { p : p / g, q : q / g }
+    }
+    pub fn value(&self) -> f64{
+        self.p as f64 / self.q as f64
+    }
+    /// 0 ≤ p/q ≤ flux_max、q ≤ q_max の既約分数（昇順）
+    pub fn list(q_max : i64, flux_max : f64) -> Vec<Self>{
+        let mut fluxes: Vec<Flux> = (1..=q_max)
+            .flat_map(|q| (0..=(flux_max * q as f64).floor() as i64).map(move |p| (p, q)))
+            .filter(|&(p, q)| gcd(p, q) == 1)
+            .map(|(p, q)| Flux { p, q })
+            .collect();
+        fluxes.sort_by(|a, b| a.value().partial_cmp(&b.value()).unwrap());
+        fluxes
+    }
+}
+
+//分数座標 (ξ1, ξ2)
+fn fractional(position : Vector2<f64>) -> Vector2<f64>{
+    let xi2 = position.y / A1.y;
+    Vector2::new((position.x - xi2 * A1.x) / A3.x, xi2)
+}
+
+//スーパーセルの中のサイト i から j への結合（Peierls の位相を含む振幅）
+#[derive(Debug, Clone, Copy)]
+struct PeierlsBond{
+    i : usize,
+    j : usize,
+    d : Vector2<f64>,
+    hopping : [Complex<f64>; 2],
+}
+
+#[derive(Debug, Clone)]
+pub struct MagneticSupercell{
+    pub name : String,
+    pub flux : Flux,
+    pub cells : [usize; 2],             // n1, n2
+    pub lattice : [Vector2<f64>; 2],    // n1 a1, n2 a2
+    onsite : [Vec<f64>; 2],             // [spin][site]
+    bonds : Vec<PeierlsBond>,
+}
+
+impl MagneticSupercell{
+    /// 拡張した平均場の結合の補正（hamiltonian::check_real_space）はErrを返す
+    pub fn new(system : &System, flux : Flux) -> Result<Self, String>{
+        check_real_space(system)?;
+        if flux.q <= 0 {
+            return Err(format!("flux {}/{} should have a positive denominator", flux.p, flux.q));
+        }
+
+        let size = system.size();
+        let six_site = Supercell::six_site();
+        let fits = |cell : [i64; 2]| size == 2 || six_site.is_lattice_vector(cell);
+        let n1 = (1..=3 * flux.q)
+            .find(|&n1| (flux.p * n1) % (3 * flux.q) == 0 && fits([n1, 0]))
+            .ok_or_else(|| format!("no magnetic supercell for flux {}/{}", flux.p, flux.q))?;
+        let n2 = (1..=3).find(|&n2| fits([0, n2])).unwrap();
+        let cells = [n1 as usize, n2 as usize];
+        let phi = flux.value();
+
+        let index = |cell : [i64; 2], sublattice : usize| {
+            2 * (cell[0].rem_euclid(n1) * n2 + cell[1].rem_euclid(n2)) as usize + sublattice
+        };
+        let position = |cell : [i64; 2], sublattice : usize| {
+            A3 * cell[0] as f64 + A1 * cell[1] as f64 + if sublattice == 0 { Vector2::zeros() } else { D1 }
+        };
+
+        let exchange = system.exchange();
+        let charge = system.charge();
+        let site_bonds = [0, 1].map(|sublattice| real_space_bonds(system, sublattice));
+        let mut onsite = [Vec::new(), Vec::new()];
+        let mut bonds = Vec::new();
+        for m1 in 0..n1 {
+            for m2 in 0..n2 {
+                for (sublattice, site_bonds) in site_bonds.iter().enumerate() {
+                    let cell = [m1, m2];
+                    let bulk = if size == 2 { sublattice } else { six_site.site_index(cell, sublattice) };
+                    onsite[0].push(charge[bulk] + exchange[bulk]);
+                    onsite[1].push(charge[bulk] - exchange[bulk]);
+
+                    let i = index(cell, sublattice);
+                    let r_i = position(cell, sublattice);
+                    for &(d, amplitude) in site_bonds {
+                        let (cell_j, sublattice_j) = locate(r_i + d).expect("bond vectors end on lattice sites");
+                        let (xi_i, xi_j) = (fractional(r_i), fractional(r_i + d));
+                        let theta = 2. * PI * phi * 0.5 * (xi_i.x + xi_j.x) * (xi_j.y - xi_i.y);
+                        let phase = Complex::new(0., theta).exp();
+                        bonds.push(PeierlsBond { i, j : index(cell_j, sublattice_j), d, hopping : amplitude.map(|a| a * phase) });
+                    }
+                }
+            }
+        }
+
+        Ok(MagneticSupercell {
+            name : system.debug(),
+            flux,
+            cells,
+            lattice : [A3 * n1 as f64, A1 * n2 as f64],
+            onsite,
+            bonds,
+        })
+    }
+    pub fn size(&self) -> usize{
+        self.onsite[0].len()
+    }
+    /// スーパーセルに含まれる基本単位胞の数
+    pub fn primitive_cells(&self) -> usize{
+        self.cells[0] * self.cells[1]
+    }
+    /// スーパーセルの Brillouin ゾーンの k_points × k_points 点
+    pub fn k_mesh(&self, k_points : usize) -> Vec<Vector2<f64>>{
+        let lattice = Matrix2::from_columns(&self.lattice);
+        let reciprocal = lattice.try_inverse().unwrap().transpose() * (2. * PI);
+        let k_points = k_points.max(1);
+        (0..k_points * k_points)
+            .map(|index| {
+                let (i, j) = (index / k_points, index % k_points);
+                reciprocal * Vector2::new(i as f64, j as f64) / k_points as f64
+            })
+            .collect()
+    }
+    /// スピンごとのハミルトニアン (H↑, H↓)
+    pub fn hamiltonian(&self, kk : Vector2<f64>) -> (DMatrix<Complex<f64>>, DMatrix<Complex<f64>>){
+        let size = self.size();
+        let mut hamiltonian = [DMatrix::<Complex<f64>>::zeros(size, size), DMatrix::<Complex<f64>>::zeros(size, size)];
+
+        for (matrix, onsite) in hamiltonian.iter_mut().zip(&self.onsite) {
+            for (i, energy) in onsite.iter().enumerate() {
+                matrix[(i, i)] += energy * ONE;
+            }
+        }
+        for bond in &self.bonds {
+            let phase = Complex::new(0., kk.dot(&bond.d)).exp();
+            for (matrix, hopping) in hamiltonian.iter_mut().zip(bond.hopping) {
+                matrix[(bond.i, bond.j)] += hopping * phase;
+            }
+        }
+
+        let [hamiltonian_u, hamiltonian_d] = hamiltonian;
+        (hamiltonian_u, hamiltonian_d)
+    }
+    pub fn diag(&self, kk : Vector2<f64>) -> SupercellEigen{
+        let (hamiltonian_u, hamiltonian_d) = self.hamiltonian(kk);
+        SupercellEigen {
+            u : sort_symmetric_eigen_ascending(SymmetricEigen::new(hamiltonian_u)),
+            d : sort_symmetric_eigen_ascending(SymmetricEigen::new(hamiltonian_d)),
+        }
+    }
+}
@@ -0,0 +1,31 @@
+//----------------------------------------------------------------
+// 乱れのための擬似乱数（SplitMix64）
+//
+// 同じ seed からは同じ列を返すので、乱れの配置を再現できる。
+//----------------------------------------------------------------
+
+#[derive(Debug, Clone)]
+pub struct SplitMix64{
+    state : u64,
+}
+
+impl SplitMix64{
+    pub fn new(seed : u64) -> Self{
+        SplitMix64 { state : seed }
+    }
+    pub fn next_u64(&mut self) -> u64{
+        self.state = self.state.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+    /// [0, 1) の一様乱数
+    pub fn uniform(&mut self) -> f64{
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+    /// [-width / 2, width / 2) の一様乱数（Anderson の乱れ）
+    pub fn symmetric(&mut self, width : f64) -> f64{
+        width * (self.uniform() - 0.5)
+    }
+}
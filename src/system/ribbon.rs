@@ -0,0 +1,333 @@
+use crate::consts::*;
+use crate::system::diag::sort_symmetric_eigen_ascending;
+use crate::system::hamiltonian::{check_real_space, real_space_bonds};
+use crate::system::model::System;
+use crate::system::supercell::{Supercell, SupercellEigen};
+
+use nalgebra::{Complex, DMatrix, Dyn, SymmetricEigen, Vector2};
+use std::io::Write;
+
+//----------------------------------------------------------------
+// ジグザグ端またはアームチェア端のナノリボン
+//
+// 基本格子 a1 = A3, a2 = A1 の整数係数で、リボンの並進 T0 と幅方向のベクトル S0 を
+//   ジグザグ    : T0 = a1,        S0 = a2（幅 W で 2W サイト）
+//   アームチェア: T0 = a1 + 2 a2, S0 = a1（幅 W で 4W サイト）
+// とし、T = m T0 が System の磁気単位胞の格子ベクトルになる最小の m を周期にとる。
+// 法線 N = z × T / |T| への射影が [-offset, W |S0・N| - offset) のサイトを残すと、
+// 両側がそれぞれの端になる（offset は格子の列の間で切るためのずらし）。
+// 交換場と電荷ポテンシャルはバルクの単位胞のサイトの値をそのまま端まで延ばし、
+// 結合は hamiltonian::real_space_bonds（tmd の因子は hamiltonian_2 なら B 副格子、hamiltonian_6 なら A 副格子）。
+// 位相は結合ベクトル d の exp(i k・d) で、k = κ T / |T|²（κ ∈ [-π, π]）。
+//
+// 法線の負の側を lower、正の側を upper の端とすると、スピンごとに
+// ギャップを横切る端の状態の符号つきの数は N_upper = -N_lower = C_σ（chern::chern_numbers）になる。
+//----------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edge{
+    Zigzag,
+    Armchair,
+}
+
+impl Edge{
+    pub fn from_name(name : &str) -> Option<Self>{
+        match name {
+            "zigzag" => Some(Edge::Zigzag),
+            "armchair" => Some(Edge::Armchair),
+            _ => None,
+        }
+    }
+    pub fn name(&self) -> &'static str{
+        match self {
+            Edge::Zigzag => "zigzag",
+            Edge::Armchair => "armchair",
+        }
+    }
+    //(T0, S0, offset)
+    fn vectors(&self) -> ([i64; 2], [i64; 2], f64){
+        match self {
+            Edge::Zigzag => ([1, 0], [0, 1], 0.5),
+            Edge::Armchair => ([1, 2], [1, 0], SQRT_3 / 4.),
+        }
+    }
+}
+
+fn primitive(cell : [i64; 2]) -> Vector2<f64>{
+    A3 * cell[0] as f64 + A1 * cell[1] as f64
+}
+
+/// リボンのサイト。depth は片方の端（法線の負の側）からの距離
+#[derive(Debug, Clone, Copy)]
+pub struct RibbonSite{
+    pub cell : [i64; 2],
+    pub sublattice : usize,
+    pub position : Vector2<f64>,
+    pub depth : f64,
+    pub exchange : f64,     // 上向きスピンに +、下向きに - で加わる
+    pub charge : f64,
+}
+
+//サイト i から j への結合（k に依らない振幅と結合ベクトル）
+#[derive(Debug, Clone, Copy)]
+struct RibbonBond{
+    i : usize,
+    j : usize,
+    d : Vector2<f64>,
+    hopping : [Complex<f64>; 2],
+}
+
+#[derive(Debug, Clone)]
+pub struct Ribbon{
+    pub name : String,
+    pub edge : Edge,
+    pub width : usize,
+    pub period : usize,                 // T = period × T0
+    pub translation : Vector2<f64>,     // T
+    pub breadth : f64,                  // 両端のサイトの間の距離
+    pub sites : Vec<RibbonSite>,
+    bonds : Vec<RibbonBond>,
+}
+
+impl Ribbon{
+    /// 拡張した平均場の結合の補正（V, V2 の Fock項）は扱えないのでErrを返す
+    pub fn new(system : &System, edge : Edge, width : usize) -> Result<Self, String>{
+        check_real_space(system)?;
+        if width == 0 {
+            return Err("ribbon width should be positive".to_string());
+        }
+
+        let size = system.size();
+        let six_site = Supercell::six_site();
+        let (t0, s0, offset) = edge.vectors();
+        let period = (1..=3)
+            .find(|&m| size == 2 || six_site.is_lattice_vector([t0[0] * m, t0[1] * m]))
+            .ok_or_else(|| format!("no period of the {} ribbon matches the magnetic cell", edge.name()))?;
+        let translation = primitive(t0) * period as f64;
+        let normal = Vector2::new(-translation.y, translation.x) / translation.norm();
+        let spacing = primitive(s0).dot(&normal).abs();
+        let (lower, upper) = (-offset, width as f64 * spacing - offset);
+
+        //周期の中の位置（T 方向の分数座標）
+        let along = |position : Vector2<f64>| position.dot(&translation) / translation.norm_squared();
+
+        let exchange = system.exchange();
+        let charge = system.charge();
+        let bulk_index = |cell : [i64; 2], sublattice : usize| if size == 2 { sublattice } else { six_site.site_index(cell, sublattice) };
+
+        //平行四辺形を囲む範囲の基本単位胞（|n1 a1 + n2 a2| >= 1.5 max(|n1|, |n2|)）
+        let reach = ((translation.norm() + upper.abs() + lower.abs()) / 1.5).ceil() as i64 + 2;
+        let mut sites = Vec::new();
+        for n1 in -reach..=reach {
+            for n2 in -reach..=reach {
+                for sublattice in 0..2 {
+                    let cell = [n1, n2];
+                    let position = primitive(cell) + if sublattice == 0 { Vector2::zeros() } else { D1 };
+                    let v = position.dot(&normal);
+                    if (along(position) + 1e-9).floor() != 0.0 || v < lower - 1e-9 || v >= upper - 1e-9 {
+                        continue;
+                    }
+                    let index = bulk_index(cell, sublattice);
+                    sites.push(RibbonSite { cell, sublattice, position, depth : v - lower, exchange : exchange[index], charge : charge[index] });
+                }
+            }
+        }
+        let min_depth = sites.iter().map(|site| site.depth).fold(f64::INFINITY, f64::min);
+        let max_depth = sites.iter().map(|site| site.depth).fold(f64::NEG_INFINITY, f64::max);
+        for site in sites.iter_mut() {
+            site.depth -= min_depth;
+        }
+        sites.sort_by(|a, b| a.depth.partial_cmp(&b.depth).unwrap().then(along(a.position).partial_cmp(&along(b.position)).unwrap()));
+
+        //位置 T の整数倍を除いて一致するサイト（なければリボンの外）
+        let find = |position : Vector2<f64>| {
+            let folded = position - translation * (along(position) + 1e-9).floor();
+            sites.iter().position(|site| (site.position - folded).norm() < 1e-6)
+        };
+
+        let site_bonds = [0, 1].map(|sublattice| real_space_bonds(system, sublattice));
+        let mut bonds = Vec::new();
+        for (i, site) in sites.iter().enumerate() {
+            for &(d, hopping) in &site_bonds[site.sublattice] {
+                if let Some(j) = find(site.position + d) {
+                    bonds.push(RibbonBond { i, j, d, hopping });
+                }
+            }
+        }
+
+        Ok(Ribbon {
+            name : system.debug(),
+            edge,
+            width,
+            period : period as usize,
+            translation,
+            breadth : max_depth - min_depth,
+            sites,
+            bonds,
+        })
+    }
+    pub fn size(&self) -> usize{
+        self.sites.len()
+    }
+    /// ファイル名用（例: UuudddKanemele_lambda0p30_j0p50_zigzag_w12）
+    pub fn debug(&self) -> String{
+        format!("{}_{}_w{}", self.name, self.edge.name(), self.width)
+    }
+    /// スピンごとのハミルトニアン (H↑, H↓)（κ = k・T）
+    pub fn hamiltonian(&self, kappa : f64) -> (DMatrix<Complex<f64>>, DMatrix<Complex<f64>>){
+        let size = self.size();
+        let kk = self.translation * kappa / self.translation.norm_squared();
+        let mut hamiltonian = [DMatrix::<Complex<f64>>::zeros(size, size), DMatrix::<Complex<f64>>::zeros(size, size)];
+
+        for (i, site) in self.sites.iter().enumerate() {
+            hamiltonian[0][(i, i)] += (site.charge + site.exchange) * ONE;
+            hamiltonian[1][(i, i)] += (site.charge - site.exchange) * ONE;
+        }
+        for bond in &self.bonds {
+            let phase = Complex::new(0., kk.dot(&bond.d)).exp();
+            for (matrix, hopping) in hamiltonian.iter_mut().zip(bond.hopping) {
+                matrix[(bond.i, bond.j)] += hopping * phase;
+            }
+        }
+
+        let [hamiltonian_u, hamiltonian_d] = hamiltonian;
+        (hamiltonian_u, hamiltonian_d)
+    }
+    pub fn diag(&self, kappa : f64) -> SupercellEigen{
+        let (hamiltonian_u, hamiltonian_d) = self.hamiltonian(kappa);
+        SupercellEigen {
+            u : sort_symmetric_eigen_ascending(SymmetricEigen::new(hamiltonian_u)),
+            d : sort_symmetric_eigen_ascending(SymmetricEigen::new(hamiltonian_d)),
+        }
+    }
+    //両端から edge_depth 以内のサイト
+    fn edge_masks(&self, edge_depth : f64) -> (Vec<bool>, Vec<bool>){
+        let lower = self.sites.iter().map(|site| site.depth < edge_depth).collect();
+        let upper = self.sites.iter().map(|site| site.depth > self.breadth - edge_depth).collect();
+        (lower, upper)
+    }
+    /// κ ∈ [-π, π] を k_points 点に分けたバンドと、端から edge_depth 以内のサイトにある重み
+    pub fn band_structure(&self, k_points : usize, edge_depth : f64) -> Vec<RibbonBandPoint>{
+        let (lower, upper) = self.edge_masks(edge_depth);
+
+        kappas(k_points).into_iter()
+            .map(|kappa| {
+                let seud = self.diag(kappa);
+                let levels = [0, 1].map(|spin| {
+                    let eigen = seud.spin(spin);
+                    eigen.eigenvalues.iter().zip(eigen.eigenvectors.column_iter())
+                        .map(|(&energy, vector)| RibbonLevel { energy, lower : masked_weight(vector.iter(), &lower), upper : masked_weight(vector.iter(), &upper) })
+                        .collect()
+                });
+                RibbonBandPoint { kappa, levels }
+            })
+            .collect()
+    }
+    /// エネルギー energy を横切る状態をスピンごとに数える（重みが半分以上ある側の端の状態とする）
+    ///
+    /// 隣り合う κ で固有ベクトルの重なりが最大の準位をつないで追いかけるので、
+    /// （k は結合ベクトルの位相なので κ + 2π の固有ベクトルもそのままつながる）
+    /// 両端の状態がちょうど energy で交わっても（粒子正孔対称な系のギャップの中央など）取り違えない。
+    pub fn edge_modes(&self, k_points : usize, edge_depth : f64, energy : f64) -> [EdgeModeCount; 2]{
+        let (lower, upper) = self.edge_masks(edge_depth);
+        //κ = 0, π の縮退点を避けて 1/4 だけずらした点をとり、最後に最初の点 + 2π に戻る
+        let mut kappas: Vec<f64> = (0..k_points).map(|step| -PI + 2. * PI * (step as f64 + 0.25) / k_points as f64).collect();
+        kappas.push(kappas[0] + 2. * PI);
+        let mut counts = [EdgeModeCount::default(); 2];
+
+        let mut previous = self.diag(kappas[0]);
+        for &kappa in &kappas[1..] {
+            let current = self.diag(kappa);
+            for (spin, count) in counts.iter_mut().enumerate() {
+                let (before, after) = (previous.spin(spin), current.spin(spin));
+                let below = |eigen : &SymmetricEigen<Complex<f64>, Dyn>| eigen.eigenvalues.iter().filter(|&&e| e < energy).count();
+                let (n_before, n_after) = (below(before), below(after));
+                //energy の近くの準位だけを調べる
+                let window = n_before.min(n_after).saturating_sub(2)..(n_before.max(n_after) + 2).min(self.size());
+
+                for a in window.clone() {
+                    let vector = before.eigenvectors.column(a);
+                    let overlap = |b : usize| vector.dotc(&after.eigenvectors.column(b)).norm_sqr();
+                    let b = window.clone().max_by(|&x, &y| overlap(x).partial_cmp(&overlap(y)).unwrap()).unwrap();
+                    let (e_a, e_b) = (before.eigenvalues[a], after.eigenvalues[b]);
+                    if (e_a < energy) == (e_b < energy) {
+                        continue;
+                    }
+
+                    let velocity = if e_b > e_a { 1 } else { -1 };
+                    let next = after.eigenvectors.column(b);
+                    let weight_lower = 0.5 * (masked_weight(vector.iter(), &lower) + masked_weight(next.iter(), &lower));
+                    let weight_upper = 0.5 * (masked_weight(vector.iter(), &upper) + masked_weight(next.iter(), &upper));
+                    if weight_lower >= 0.5 {
+                        count.lower += velocity;
+                        count.lower_modes += 1;
+                    } else if weight_upper >= 0.5 {
+                        count.upper += velocity;
+                        count.upper_modes += 1;
+                    } else {
+                        count.bulk += 1;
+                    }
+                }
+            }
+            previous = current;
+        }
+
+        counts
+    }
+}
+
+//κ ∈ [-π, π] を k_points 点に分ける
+fn kappas(k_points : usize) -> Vec<f64>{
+    (0..k_points).map(|step| -PI + 2. * PI * step as f64 / (k_points - 1).max(1) as f64).collect()
+}
+
+fn masked_weight<'a>(vector : impl Iterator<Item = &'a Complex<f64>>, mask : &[bool]) -> f64{
+    vector.zip(mask).filter(|(_, m)| **m).map(|(c, _)| c.norm_sqr()).sum()
+}
+
+//----------------------------------------------------------------
+// リボンのバンドと端の状態の数え上げ
+//----------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy)]
+pub struct RibbonLevel{
+    pub energy : f64,
+    pub lower : f64,        // depth < edge_depth のサイトの重み
+    pub upper : f64,        // 反対側の端の重み
+}
+
+#[derive(Debug, Clone)]
+pub struct RibbonBandPoint{
+    pub kappa : f64,
+    pub levels : [Vec<RibbonLevel>; 2],     // [spin][band]（エネルギーの昇順）
+}
+
+/// あるエネルギーを横切る状態の数（群速度の符号つき、Ribbon::edge_modes）
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EdgeModeCount{
+    pub lower : i64,            // 片方の端の Σ sign(dE/dκ)
+    pub upper : i64,
+    pub lower_modes : usize,    // 向きによらない数
+    pub upper_modes : usize,
+    pub bulk : usize,           // どちらの端にも重みが半分以上ない状態
+}
+
+/// リボンのバンドを.datファイルに出力する
+pub fn write_ribbon_bands_to_dat(ribbon : &Ribbon, bands : &[RibbonBandPoint], file_path : &str) -> std::io::Result<()>{
+    let mut file = std::fs::File::create(file_path)?;
+
+    writeln!(
+        file, "# edge={},width={},period={},sites={},breadth={}",
+        ribbon.edge.name(), ribbon.width, ribbon.period, ribbon.size(), ribbon.breadth
+    )?;
+    writeln!(file, "# kappa,spin,band_index,energy,lower,upper")?;
+    for point in bands {
+        for (spin, levels) in point.levels.iter().enumerate() {
+            for (band_index, level) in levels.iter().enumerate() {
+                writeln!(file, "{},{},{},{},{},{}", point.kappa, spin, band_index, level.energy, level.lower, level.upper)?;
+            }
+        }
+    }
+
+    Ok(())
+}
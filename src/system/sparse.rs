@@ -0,0 +1,75 @@
+use crate::consts::ZERO;
+
+use nalgebra::{Complex, DMatrix};
+
+//----------------------------------------------------------------
+// 実空間の大きな系のための複素の疎行列（CSR 形式）
+//
+// (行, 列, 値) の組から作り、同じ位置の値は足し合わせる。
+// 行列とベクトルの積と、厳密対角化のための密行列への変換だけを持つ。
+//----------------------------------------------------------------
+
+#[derive(Debug, Clone)]
+pub struct SparseMatrix{
+    pub dim : usize,
+    row_start : Vec<usize>,         // 行 i の要素は row_start[i]..row_start[i + 1]
+    columns : Vec<usize>,
+    values : Vec<Complex<f64>>,
+}
+
+impl SparseMatrix{
+    pub fn from_triplets(dim : usize, mut triplets : Vec<(usize, usize, Complex<f64>)>) -> Self{
+        triplets.sort_by_key(|&(i, j, _)| (i, j));
+
+        let mut row_start = vec![0; dim + 1];
+        let mut columns: Vec<usize> = Vec::with_capacity(triplets.len());
+        let mut values: Vec<Complex<f64>> = Vec::with_capacity(triplets.len());
+        let mut last = None;
+        for (i, j, value) in triplets {
+            if last == Some((i, j)) {
+                *values.last_mut().unwrap() += value;
+                continue;
+            }
+            row_start[i + 1] += 1;
+            columns.push(j);
+            values.push(value);
+            last = Some((i, j));
+        }
+        for i in 0..dim {
+            row_start[i + 1] += row_start[i];
+        }
+
+        SparseMatrix { dim, row_start, columns, values }
+    }
+    /// 非零要素の数
+    pub fn nnz(&self) -> usize{
+        self.values.len()
+    }
+    /// 行 i の (列, 値)
+    pub fn row(&self, i : usize) -> impl Iterator<Item = (usize, Complex<f64>)> + '_{
+        let range = self.row_start[i]..self.row_start[i + 1];
+        self.columns[range.clone()].iter().copied().zip(self.values[range].iter().copied())
+    }
+    /// y = M x
+    pub fn mul_vec(&self, x : &[Complex<f64>]) -> Vec<Complex<f64>>{
+        (0..self.dim).map(|i| self.row(i).map(|(j, value)| value * x[j]).sum()).collect()
+    }
+    /// Gershgorin の円板から求めた固有値の範囲 (min, max)（エルミート行列）
+    pub fn gershgorin_bounds(&self) -> (f64, f64){
+        (0..self.dim).fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), i| {
+            let (diagonal, radius) = self.row(i).fold((0.0, 0.0), |(diagonal, radius), (j, value)| {
+                if j == i { (diagonal + value.re, radius) } else { (diagonal, radius + value.norm()) }
+            });
+            (min.min(diagonal - radius), max.max(diagonal + radius))
+        })
+    }
+    pub fn to_dense(&self) -> DMatrix<Complex<f64>>{
+        let mut dense = DMatrix::from_element(self.dim, self.dim, ZERO);
+        for i in 0..self.dim {
+            for (j, value) in self.row(i) {
+                dense[(i, j)] += value;
+            }
+        }
+        dense
+    }
+}
@@ -0,0 +1,366 @@
+use crate::consts::*;
+use crate::system::diag::sort_symmetric_eigen_ascending;
+use crate::system::hamiltonian::{locate, site_bonds};
+use crate::system::model::Param;
+
+use nalgebra::{Complex, DMatrix, Dyn, Matrix2, SymmetricEigen, Vector2};
+use std::io::Write;
+
+//----------------------------------------------------------------
+// 整数の超格子行列で与える任意の磁気単位胞（行列の大きさはサイト数で決まる）
+//
+// 基本格子 a1 = A3, a2 = A1（逆格子 b1, b2 は susceptibility と同じ）に対して
+// 超格子ベクトルは L1 = m00 a1 + m01 a2, L2 = m10 a1 + m11 a2。
+// 基本単位胞ごとに A サイト（R）と B サイト（R + D1）を置き、hamiltonian_6 と同じく
+// A 副格子が tmd の因子を持つ。[[2, 1], [-1, 1]] は hamiltonian_6 の6サイトの単位胞と同じ並びになる。
+//----------------------------------------------------------------
+
+/// 超格子のサイト。cell は基本単位胞の位置（a1, a2 の整数係数）、sublattice は 0（A）か 1（B）
+#[derive(Debug, Clone, Copy)]
+pub struct SupercellSite{
+    pub cell : [i64; 2],
+    pub sublattice : usize,
+    pub position : Vector2<f64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Supercell{
+    pub matrix : [[i64; 2]; 2],
+    pub lattice : [Vector2<f64>; 2],        // 超格子ベクトル L1, L2
+    pub reciprocal : [Vector2<f64>; 2],     // 畳み込まれた逆格子ベクトル G1, G2（Li・Gj = 2π δij）
+    pub sites : Vec<SupercellSite>,
+}
+
+impl Supercell{
+    /// 行列式が 0 の場合はNoneを返す
+    pub fn new(matrix : [[i64; 2]; 2]) -> Option<Self>{
+        let det = matrix[0][0] * matrix[1][1] - matrix[0][1] * matrix[1][0];
+        if det == 0 {
+            return None;
+        }
+
+        let primitive = |c : [i64; 2]| A3 * c[0] as f64 + A1 * c[1] as f64;
+        let lattice = [primitive(matrix[0]), primitive(matrix[1])];
+
+        let inverse = Matrix2::new(lattice[0].x, lattice[0].y, lattice[1].x, lattice[1].y).try_inverse()?;
+        let reciprocal = [
+            Vector2::new(inverse[(0, 0)], inverse[(1, 0)]) * (2. * PI),
+            Vector2::new(inverse[(0, 1)], inverse[(1, 1)]) * (2. * PI),
+        ];
+
+        let mut supercell = Supercell { matrix, lattice, reciprocal, sites : Vec::new() };
+
+        //超格子の平行四辺形を囲む範囲の基本単位胞から、分数座標が [0, 1) のものを選ぶ
+        let corners = [[0, 0], matrix[0], matrix[1], [matrix[0][0] + matrix[1][0], matrix[0][1] + matrix[1][1]]];
+        let range = |axis : usize| {
+            let values = corners.iter().map(|c| c[axis]);
+            values.clone().min().unwrap()..=values.max().unwrap()
+        };
+        let mut cells = Vec::new();
+        for n1 in range(0) {
+            for n2 in range(1) {
+                let (f1, f2) = supercell.fraction_numerators([n1, n2]);
+                if (f1, f2) == supercell.reduced_numerators([n1, n2]) {
+                    cells.push(((f1, f2), [n1, n2]));
+                }
+            }
+        }
+        cells.sort();
+
+        supercell.sites = cells.iter()
+            .flat_map(|&(_, cell)| {
+                let origin = primitive(cell);
+                [
+                    SupercellSite { cell, sublattice : 0, position : origin },
+                    SupercellSite { cell, sublattice : 1, position : origin + D1 },
+                ]
+            })
+            .collect();
+
+        Some(supercell)
+    }
+    /// 6サイトの単位胞（√3×√3）と同じ超格子
+    pub fn six_site() -> Self{
+        Supercell::new([[2, 1], [-1, 1]]).unwrap()
+    }
+    pub fn size(&self) -> usize{
+        self.sites.len()
+    }
+    /// 超格子に含まれる基本単位胞の数 |det M|
+    pub fn cells(&self) -> usize{
+        self.size() / 2
+    }
+    /// ファイル名用（例: m2_0_0_2）
+    pub fn debug(&self) -> String{
+        let [[a, b], [c, d]] = self.matrix;
+        format!("m{}_{}_{}_{}", a, b, c, d).replace('-', "n")
+    }
+    fn det(&self) -> i64{
+        self.matrix[0][0] * self.matrix[1][1] - self.matrix[0][1] * self.matrix[1][0]
+    }
+    //基本単位胞 cell の超格子での分数座標を |det| 倍した整数
+    fn fraction_numerators(&self, cell : [i64; 2]) -> (i64, i64){
+        let [[m00, m01], [m10, m11]] = self.matrix;
+        let [n1, n2] = cell;
+        let sign = self.det().signum();
+        ((n1 * m11 - n2 * m10) * sign, (n2 * m00 - n1 * m01) * sign)
+    }
+    //超格子ベクトルの分だけずらして [0, |det|) に収めたもの
+    fn reduced_numerators(&self, cell : [i64; 2]) -> (i64, i64){
+        let det = self.det().abs();
+        let (f1, f2) = self.fraction_numerators(cell);
+        (f1.rem_euclid(det), f2.rem_euclid(det))
+    }
+    /// 基本格子の整数係数 cell のベクトルが超格子ベクトルか
+    pub fn is_lattice_vector(&self, cell : [i64; 2]) -> bool{
+        self.reduced_numerators(cell) == (0, 0)
+    }
+    /// 基本単位胞 cell の副格子 sublattice のサイトの番号（超格子ベクトルの分の違いは同じサイト）
+    pub fn site_index(&self, cell : [i64; 2], sublattice : usize) -> usize{
+        let key = self.reduced_numerators(cell);
+        self.sites.iter()
+            .position(|site| site.sublattice == sublattice && self.fraction_numerators(site.cell) == key)
+            .expect("every cell should be folded into the supercell")
+    }
+
+    //------------------------------------------------------------
+    // 畳み込まれた逆格子の座標（DV2 の from_car, to_car に相当）
+    //------------------------------------------------------------
+
+    /// デカルト座標のkを G1, G2 を基底とする分数座標に変換する
+    pub fn from_car(&self, kk : Vector2<f64>) -> Vector2<f64>{
+        Vector2::new(kk.dot(&self.lattice[0]), kk.dot(&self.lattice[1])) / (2. * PI)
+    }
+    /// G1, G2 を基底とする分数座標をデカルト座標に変換する
+    pub fn to_car(&self, frac : Vector2<f64>) -> Vector2<f64>{
+        self.reciprocal[0] * frac.x + self.reciprocal[1] * frac.y
+    }
+    /// 基本格子の逆格子の分数座標 (q1, q2)（q = q1 b1 + q2 b2）の波数が超格子と整合するか
+    /// （整合すれば q は超格子の Γ に畳み込まれる）
+    pub fn is_commensurate(&self, q_frac : Vector2<f64>) -> bool{
+        self.matrix.iter().all(|row| {
+            let phase = q_frac.x * row[0] as f64 + q_frac.y * row[1] as f64;
+            (phase - phase.round()).abs() < 1e-9
+        })
+    }
+
+    //------------------------------------------------------------
+    // 畳み込まれたブリルアンゾーンの高対称点
+    //------------------------------------------------------------
+
+    /// 最も短い2本の逆格子ベクトル（Lagrange の簡約、なす角は 90° 以下）
+    pub fn reduced_reciprocal(&self) -> [Vector2<f64>; 2]{
+        let [mut g1, mut g2] = self.reciprocal;
+        loop {
+            if g2.norm_squared() < g1.norm_squared() {
+                std::mem::swap(&mut g1, &mut g2);
+            }
+            //|g1・g2| <= |g1|² / 2 なら簡約済み（六方格子の 1/2 ちょうどで往復しないように）
+            let ratio = g1.dot(&g2) / g1.norm_squared();
+            if ratio.abs() <= 0.5 + 1e-12 {
+                break;
+            }
+            g2 -= g1 * ratio.round();
+        }
+        if g1.dot(&g2) < 0.0 {
+            g2 = -g2;
+        }
+        [g1, g2]
+    }
+    /// Wigner-Seitz 胞の高対称点："Gamma"(または"G"), "M1", "M2"（辺の中点）, "K"（頂点）
+    pub fn high_symmetry_point(&self, label : &str) -> Option<Vector2<f64>>{
+        let [g1, g2] = self.reduced_reciprocal();
+        let kk = match label {
+            "Gamma" | "G" => Vector2::zeros(),
+            "M1" => g1 / 2.,
+            "M2" => g2 / 2.,
+            "K" => {
+                //0, g1, g2 から等距離の点
+                let matrix = Matrix2::new(g1.x, g1.y, g2.x, g2.y);
+                matrix.try_inverse()? * Vector2::new(g1.norm_squared(), g2.norm_squared()) / 2.
+            }
+            _ => return None,
+        };
+        Some(kk)
+    }
+}
+
+//----------------------------------------------------------------
+// 超格子上のスピン配置
+//----------------------------------------------------------------
+#[derive(Debug, Clone)]
+pub enum SpinPattern{
+    Para,
+    Fm,
+    Neel,                                       // A 副格子が上向き、B 副格子が下向き
+    Wave{ q : Vector2<f64>, staggered : bool },  // cos(q・R)（staggered なら B 副格子で符号を反転）、q は b1, b2 の分数座標
+    Custom(Vec<f64>),                           // サイトごとの向き（Supercell::sites の順）
+}
+
+impl SpinPattern{
+    /// "para", "fm", "neel", "stripe"（q = M）, "stripe_neel"（q = M で副格子を反転）
+    pub fn from_name(name : &str) -> Option<Self>{
+        let m = Vector2::new(0.5, 0.0);
+        let pattern = match name {
+            "para" => SpinPattern::Para,
+            "fm" => SpinPattern::Fm,
+            "neel" => SpinPattern::Neel,
+            "stripe" => SpinPattern::Wave { q : m, staggered : false },
+            "stripe_neel" => SpinPattern::Wave { q : m, staggered : true },
+            _ => return None,
+        };
+        Some(pattern)
+    }
+    /// サイトごとのスピンの向き。超格子と合わない場合はErrで理由を返す
+    pub fn spins(&self, supercell : &Supercell) -> Result<Vec<f64>, String>{
+        let sign = |site : &SupercellSite| if site.sublattice == 0 { 1.0 } else { -1.0 };
+        let spins = match self {
+            SpinPattern::Para => vec![0.0; supercell.size()],
+            SpinPattern::Fm => vec![1.0; supercell.size()],
+            SpinPattern::Neel => supercell.sites.iter().map(sign).collect(),
+            SpinPattern::Wave { q, staggered } => {
+                if !supercell.is_commensurate(*q) {
+                    return Err(format!("q = ({}, {}) is not commensurate with the supercell {:?}", q.x, q.y, supercell.matrix));
+                }
+                supercell.sites.iter()
+                    .map(|site| {
+                        let phase = 2. * PI * (q.x * site.cell[0] as f64 + q.y * site.cell[1] as f64);
+                        let stagger = if *staggered { sign(site) } else { 1.0 };
+                        phase.cos() * stagger
+                    })
+                    .collect()
+            }
+            SpinPattern::Custom(spins) => {
+                if spins.len() != supercell.size() {
+                    return Err(format!("{} spins are given for a supercell with {} sites", spins.len(), supercell.size()));
+                }
+                spins.clone()
+            }
+        };
+        Ok(spins)
+    }
+}
+
+//----------------------------------------------------------------
+// 超格子の模型（System の固定サイズの代わりに DMatrix を使う）
+//----------------------------------------------------------------
+
+#[derive(Debug, Clone)]
+pub struct SupercellSystem{
+    pub name : String,
+    pub supercell : Supercell,
+    pub param : Param,
+    pub tmd : f64,              // System::tmd と同じ（A 副格子に掛かる）
+    pub spins : Vec<f64>,       // サイトごとのスピンの向き（交換場は jj 倍）
+}
+
+/// 対角化後の固有値、固有ベクトル（スピンごと、固有値の昇順）
+#[derive(Debug, Clone)]
+pub struct SupercellEigen{
+    pub u : SymmetricEigen<Complex<f64>, Dyn>,
+    pub d : SymmetricEigen<Complex<f64>, Dyn>,
+}
+
+impl SupercellEigen{
+    pub fn spin(&self, index : usize) -> &SymmetricEigen<Complex<f64>, Dyn>{
+        match index {
+            0 => &self.u,
+            1 => &self.d,
+            _ => panic!("index should be 0 or 1"),
+        }
+    }
+    pub fn eigenvalues(&self) -> Vec<f64>{
+        let mut eigens = self.u.eigenvalues.as_slice().to_vec();
+        eigens.extend_from_slice(self.d.eigenvalues.as_slice());
+        eigens
+    }
+}
+
+impl SupercellSystem{
+    pub fn new(name : &str, supercell : Supercell, param : Param, tmd : f64, spins : Vec<f64>) -> Self{
+        SupercellSystem { name : name.to_string(), supercell, param, tmd, spins }
+    }
+    pub fn size(&self) -> usize{
+        self.supercell.size()
+    }
+    /// スピンごとのハミルトニアン (H↑, H↓)
+    ///
+    /// 位相は hamiltonian_6 と同じく結合ベクトル d を使った exp(i k・d)。
+    pub fn hamiltonian(&self, kk : Vector2<f64>) -> (DMatrix<Complex<f64>>, DMatrix<Complex<f64>>){
+        let size = self.size();
+        let mut hamiltonian_u = DMatrix::<Complex<f64>>::zeros(size, size);
+        let mut hamiltonian_d = DMatrix::<Complex<f64>>::zeros(size, size);
+
+        let jj = self.param.jj;
+        //A 副格子の次近接は tmd 倍
+        let bonds = [site_bonds(self.param.lambda, 0, self.tmd), site_bonds(self.param.lambda, 1, 1.0)];
+
+        for (i, site) in self.supercell.sites.iter().enumerate() {
+            let j_site = self.spins[i] * jj * ONE;
+            hamiltonian_u[(i, i)] += j_site;
+            hamiltonian_d[(i, i)] -= j_site;
+
+            for &(d, [hopping_u, hopping_d]) in &bonds[site.sublattice] {
+                let (cell, sublattice) = locate(site.position + d).expect("bond vectors end on lattice sites");
+                let j = self.supercell.site_index(cell, sublattice);
+                let phase = Complex::new(0., kk.dot(&d)).exp();
+                hamiltonian_u[(i, j)] += hopping_u * phase;
+                hamiltonian_d[(i, j)] += hopping_d * phase;
+            }
+        }
+
+        (hamiltonian_u, hamiltonian_d)
+    }
+    pub fn diag(&self, kk : Vector2<f64>) -> SupercellEigen{
+        let (hamiltonian_u, hamiltonian_d) = self.hamiltonian(kk);
+        SupercellEigen {
+            u : sort_symmetric_eigen_ascending(SymmetricEigen::new(hamiltonian_u)),
+            d : sort_symmetric_eigen_ascending(SymmetricEigen::new(hamiltonian_d)),
+        }
+    }
+    /// 畳み込まれたブリルアンゾーンの mesh × mesh 点での全固有値（昇順）
+    pub fn all_eigenvalues(&self, mesh : usize) -> Vec<f64>{
+        let mut all_eigens = Vec::with_capacity(mesh * mesh * 2 * self.size());
+        for i in 0..mesh {
+            for j in 0..mesh {
+                let frac = Vector2::new(i as f64, j as f64) / mesh as f64;
+                all_eigens.extend(self.diag(self.supercell.to_car(frac)).eigenvalues());
+            }
+        }
+        all_eigens.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        all_eigens
+    }
+    /// 1サイトあたりの電子数 n = 2 i / n_div（i = 0..=n_div）での1サイトあたりの全エネルギー（絶対零度）
+    pub fn e_vs_n(&self, mesh : usize, n_div : usize) -> Vec<f64>{
+        let all_eigens = self.all_eigenvalues(mesh);
+        let sites = (mesh * mesh * self.size()) as f64;
+
+        (0..=n_div).map(|step| {
+            let occupied = (step as f64 / n_div as f64 * all_eigens.len() as f64).round() as usize;
+            all_eigens.iter().take(occupied).sum::<f64>() / sites
+        }).collect()
+    }
+}
+
+/// 同じ超格子の各スピン配置の全エネルギーを比べて.datファイルに出力する
+/// （energies[pattern][i] は SupercellSystem::e_vs_n の結果）
+pub fn write_supercell_energies_to_dat(systems : &[SupercellSystem], energies : &[Vec<f64>], file_path : &str) -> std::io::Result<()>{
+    let mut file = std::fs::File::create(file_path)?;
+
+    let names: Vec<String> = systems.iter().map(|system| format!("e_{}", system.name)).collect();
+    writeln!(file, "# n,stable,{}", names.join(","))?;
+
+    let n_div = energies[0].len() - 1;
+    for i in 0..=n_div {
+        let mut winner = 0;
+        for j in 1..systems.len() {
+            if energies[j][i] < energies[winner][i] {
+                winner = j;
+            }
+        }
+        let values: Vec<String> = energies.iter().map(|energy| energy[i].to_string()).collect();
+        writeln!(file, "{},{},{}", 2.0 * i as f64 / n_div as f64, systems[winner].name, values.join(","))?;
+    }
+
+    Ok(())
+}
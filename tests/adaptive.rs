@@ -0,0 +1,65 @@
+//適応的な細分化が BZ をちょうど一度ずつ覆い、Berry曲率の集中する K 点の周りだけを細かくして、
+//一様な粗いメッシュより細かいメッシュの値にずっと近づくことを確かめる
+
+use uuuddd4::{
+    honeycomb::{
+        adaptive::{adaptive_calculate_tanzaku, AdaptiveSetting},
+        parallelization::parallel_calculate_tanzaku,
+        setting::CalcSetting,
+        tanzaku::Tanzakus,
+    },
+    system::model::{Param, System},
+};
+
+const TOLERANCE : f64 = 1e-10;
+const MAIN_MESH : usize = 2;
+
+fn setting(mesh : usize) -> CalcSetting{
+    CalcSetting { mesh_kx : mesh, mesh_ky : mesh, height_map_div : 40, threshold_berry : 1e-12, main_mesh : MAIN_MESH }
+}
+
+fn max_berry_difference(a : &Tanzakus, b : &Tanzakus) -> f64{
+    a.data.iter().zip(&b.data).map(|(a, b)| (a.berry - b.berry).abs()).fold(0.0, f64::max)
+}
+
+#[test]
+fn refinement_concentrates_on_berry_hot_spots(){
+    //λ が小さいと Berry曲率は K 点の周りに鋭く集中する
+    let system = System::FmKanemele(Param::new(0.02, 0.3));
+    let max_depth = 3;
+    let adaptive_setting = AdaptiveSetting { max_depth, tol_berry : 0.2, tol_energy : f64::INFINITY };
+
+    let coarse = parallel_calculate_tanzaku(setting(6), system.clone());
+    //最も深いタイルと同じ間隔の一様なメッシュ
+    let fine = parallel_calculate_tanzaku(setting(6 << max_depth), system.clone());
+    let adaptive = adaptive_calculate_tanzaku(setting(6), system.clone(), adaptive_setting);
+
+    //タイルは重複なく BZ を覆う
+    let area: f64 = adaptive.tiles.iter()
+        .map(|tile| 0.25_f64.powi(tile.depth as i32) / (MAIN_MESH * MAIN_MESH) as f64)
+        .sum();
+    assert!((area - 1.0).abs() < TOLERANCE, "tiles cover {} of the BZ", area);
+
+    //一部のタイルだけが最も深くまで細分化される
+    let finest = (MAIN_MESH * MAIN_MESH) << (2 * max_depth);
+    assert!(adaptive.tiles.iter().any(|tile| tile.depth == max_depth));
+    assert!(adaptive.tiles.len() < finest / 2, "{} of {} tiles", adaptive.tiles.len(), finest);
+
+    let coarse_error = max_berry_difference(&coarse, &fine);
+    let adaptive_error = max_berry_difference(&adaptive.tanzakus, &fine);
+    assert!(adaptive_error < 0.01 * coarse_error, "adaptive {} vs coarse {}", adaptive_error, coarse_error);
+}
+
+#[test]
+fn no_refinement_reproduces_the_uniform_mesh(){
+    let system = System::FmKanemele(Param::new(0.05, 0.3));
+    let adaptive_setting = AdaptiveSetting { max_depth : 0, ..AdaptiveSetting::standard() };
+
+    let uniform = parallel_calculate_tanzaku(setting(6), system.clone());
+    let adaptive = adaptive_calculate_tanzaku(setting(6), system, adaptive_setting);
+
+    assert_eq!(adaptive.tiles.len(), MAIN_MESH * MAIN_MESH);
+    for (a, b) in adaptive.tanzakus.data.iter().zip(&uniform.data) {
+        assert!((a.n - b.n).abs() < TOLERANCE && (a.berry - b.berry).abs() < TOLERANCE, "n = {}: berry {} vs {}", b.n, a.berry, b.berry);
+    }
+}
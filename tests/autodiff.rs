@@ -0,0 +1,317 @@
+//自動微分によるハミルトニアンの微分が手書きの微分と一致することを確かめる
+//（手書きの微分はこのファイルにだけ置く確認用のもので、結合ごとの補正を含まない。
+//  平均場は H(k) の中心差分と比べる）
+
+mod common;
+
+use common::{systems, Lcg};
+use uuuddd4::{
+    consts::{A1, A2, A3, D1, D2, D3, I, ONE, T, ZERO},
+    system::{
+        hamiltonian::{hamiltonian_2, hamiltonian_2_jet, hamiltonian_6, hamiltonian_6_jet, Hamiltonian, BOND_COUNT_6},
+        model::{Param, SiteFields, System},
+    },
+};
+
+use nalgebra::{Complex, Matrix2, Matrix6, Vector2};
+use std::sync::Arc;
+
+const TOLERANCE : f64 = 1e-12;
+//中心差分の誤差は刻みの2乗程度
+const FINITE_DIFFERENCE_TOLERANCE : f64 = 1e-5;
+
+//結合ごとの補正（Fock項）を持つ6サイトの平均場
+fn mean_field_with_bonds(rng : &mut Lcg) -> System{
+    let fields = SiteFields {
+        size : 6,
+        tmd : -1.0,
+        u : 2.0,
+        v : 1.0,
+        v2 : 0.0,
+        seed : "Uuuddd",
+        exchange : std::array::from_fn(|_| rng.next_f64() - 0.5),
+        charge : std::array::from_fn(|_| rng.next_f64()),
+        bonds : std::array::from_fn(|_| std::array::from_fn::<_, BOND_COUNT_6, _>(|_| Complex::new(rng.next_f64() - 0.5, rng.next_f64() - 0.5))),
+    };
+    System::MeanField(Param::new(0.3, 0.0), Arc::new(fields))
+}
+
+//----------------------------------------------------------------
+// 手書きの微分（自動微分を確かめるための参照）
+//----------------------------------------------------------------
+
+//2x2 pdv(H,k_x_i)
+fn hamiltonian_2_dxi(system : &System, kk : Vector2<f64>, xindex : usize) -> Hamiltonian<2>{
+    let param = system.param();
+    let tmd = system.tmd();
+
+    let lambda = param.lambda;
+
+    let diag = {
+        2. * lambda * (
+            kk.dot(&A1).cos() * A1[xindex] +
+            kk.dot(&A2).cos() * A2[xindex] +
+            kk.dot(&A3).cos() * A3[xindex] 
+        )
+    } * ONE;
+
+    let off_diag = {
+        Complex::exp( I * kk.dot(&D1)) * I * D1[xindex] +
+        Complex::exp( I * kk.dot(&D2)) * I * D2[xindex] +
+        Complex::exp( I * kk.dot(&D3)) * I * D3[xindex]
+    } * -T;
+
+    let hamiltonian_u = Matrix2::new(
+        diag,off_diag,
+        off_diag.conj(),diag * tmd
+    ) ;
+    let hamiltonian_d = Matrix2::new(
+        -diag,off_diag,
+        off_diag.conj(),-diag * tmd
+    );
+
+    Hamiltonian{
+        u: hamiltonian_u,
+        d: hamiltonian_d,
+    }
+}
+
+//6x6 pdv(H,k_x_i)
+fn hamiltonian_6_dxi(system : &System, kk : Vector2<f64>, xindex : usize) -> Hamiltonian<6>{
+    let param = system.param();
+    let tmd = system.tmd();
+
+    let ed1p = Complex::exp( I * kk.dot(&D1)) * -T;
+    let ed1m = Complex::exp(-I * kk.dot(&D1)) * -T;
+    let ed2p = Complex::exp( I * kk.dot(&D2)) * -T;
+    let ed2m = Complex::exp(-I * kk.dot(&D2)) * -T;
+    let ed3p = Complex::exp( I * kk.dot(&D3)) * -T;
+    let ed3m = Complex::exp(-I * kk.dot(&D3)) * -T;
+
+    let lambda = param.lambda;
+
+    let pludx = {
+        Complex::exp( I * kk.dot(&A1)) * I * A1[xindex] +
+        Complex::exp( I * kk.dot(&A2)) * I * A2[xindex] +
+        Complex::exp( I * kk.dot(&A3)) * I * A3[xindex]
+    } * I * lambda;
+    let mnudx = {
+        Complex::exp(-I * kk.dot(&A1)) * -I * A1[xindex] +
+        Complex::exp(-I * kk.dot(&A2)) * -I * A2[xindex] +
+        Complex::exp(-I * kk.dot(&A3)) * -I * A3[xindex] 
+    } * I * lambda;
+
+    let (hamiltonian_u, hamiltonian_d) = 
+    hamiltonian_6_box(
+        ed1p * I * D1[xindex], ed1m * -I * D1[xindex],
+        ed2p * I * D2[xindex], ed2m * -I * D2[xindex],
+        ed3p * I * D3[xindex], ed3m * -I * D3[xindex],
+        pludx, mnudx,
+        tmd
+    );
+
+    Hamiltonian{
+        u: hamiltonian_u,
+        d: hamiltonian_d,
+    }
+
+    
+}
+
+//2x2 pdv(H,k_x_i,k_x_j)
+fn hamiltonian_2_dxidxj(system : &System, kk : Vector2<f64>, xindex : usize, yindex : usize) -> Hamiltonian<2>{
+    let param = system.param();
+    let tmd = system.tmd();
+
+    let lambda = param.lambda;
+
+    let diag = {
+        -2. * lambda * (
+            kk.dot(&A1).sin() * A1[xindex] * A1[yindex] +
+            kk.dot(&A2).sin() * A2[xindex] * A2[yindex] +
+            kk.dot(&A3).sin() * A3[xindex] * A3[yindex]
+        )
+    } * ONE;
+
+    let off_diag = {
+        Complex::exp( I * kk.dot(&D1)) * -D1[xindex] * D1[yindex] +
+        Complex::exp( I * kk.dot(&D2)) * -D2[xindex] * D2[yindex] +
+        Complex::exp( I * kk.dot(&D3)) * -D3[xindex] * D3[yindex]
+    } * -T;
+
+    let hamiltonian_u = Matrix2::new(
+        diag,off_diag,
+        off_diag.conj(),diag * tmd
+    ) ;
+    let hamiltonian_d = Matrix2::new(
+        -diag,off_diag,
+        off_diag.conj(),-diag * tmd
+    );
+
+    Hamiltonian{
+        u: hamiltonian_u,
+        d: hamiltonian_d,
+    }
+}
+
+//6x6 pdv(H,k_x_i,k_x_j)
+fn hamiltonian_6_dxidxj(system : &System, kk : Vector2<f64>, xindex : usize, yindex : usize) -> Hamiltonian<6>{
+    let param = system.param();
+    let tmd = system.tmd();
+
+    let ed1p = Complex::exp( I * kk.dot(&D1)) * -T;
+    let ed1m = Complex::exp(-I * kk.dot(&D1)) * -T;
+    let ed2p = Complex::exp( I * kk.dot(&D2)) * -T;
+    let ed2m = Complex::exp(-I * kk.dot(&D2)) * -T;
+    let ed3p = Complex::exp( I * kk.dot(&D3)) * -T;
+    let ed3m = Complex::exp(-I * kk.dot(&D3)) * -T;
+
+    let lambda = param.lambda;
+
+    // (±i d_x)(±i d_y) = -d_x d_y なので符号によらない
+    let d1xy = -D1[xindex] * D1[yindex];
+    let d2xy = -D2[xindex] * D2[yindex];
+    let d3xy = -D3[xindex] * D3[yindex];
+
+    let pludxdy = {
+        Complex::exp( I * kk.dot(&A1)) * -A1[xindex] * A1[yindex] +
+        Complex::exp( I * kk.dot(&A2)) * -A2[xindex] * A2[yindex] +
+        Complex::exp( I * kk.dot(&A3)) * -A3[xindex] * A3[yindex]
+    } * I * lambda;
+    let mnudxdy = {
+        Complex::exp(-I * kk.dot(&A1)) * -A1[xindex] * A1[yindex] +
+        Complex::exp(-I * kk.dot(&A2)) * -A2[xindex] * A2[yindex] +
+        Complex::exp(-I * kk.dot(&A3)) * -A3[xindex] * A3[yindex]
+    } * I * lambda;
+
+    let (hamiltonian_u, hamiltonian_d) = 
+    hamiltonian_6_box(
+        ed1p * d1xy, ed1m * d1xy,
+        ed2p * d2xy, ed2m * d2xy,
+        ed3p * d3xy, ed3m * d3xy,
+        pludxdy, mnudxdy,
+        tmd
+    );
+
+    Hamiltonian{
+        u: hamiltonian_u,
+        d: hamiltonian_d,
+    }
+}
+
+//6x6 の重複部分（src の hamiltonian_6_box を Complex<f64> に限ったもの）
+#[allow(clippy::too_many_arguments)]
+fn hamiltonian_6_box(
+    ed1p : Complex<f64>, ed1m : Complex<f64>,
+    ed2p : Complex<f64>, ed2m : Complex<f64>,
+    ed3p : Complex<f64>, ed3m : Complex<f64>,
+    plu  : Complex<f64>, mnu  : Complex<f64>,
+    tmd  : f64
+) -> (Matrix6<Complex<f64>>, Matrix6<Complex<f64>>){
+    let zero = ZERO;
+    let hamiltonian_u = Matrix6::new(
+        zero  ,ed1p,-plu*tmd,ed3p, mnu*tmd,ed2p,
+        ed1m,zero  ,ed2m,-plu,ed3m, mnu,
+        mnu*tmd,ed2p,zero ,ed1p,-plu*tmd, ed3p,
+        ed3m, mnu,ed1m,zero  ,ed2m,-plu,
+        -plu*tmd,ed3p, mnu*tmd,ed2p,zero  ,ed1p,
+        ed2m,-plu,ed3m, mnu,ed1m,zero
+    );
+    let hamiltonian_d = Matrix6::new(
+        zero  ,ed1p, plu*tmd,ed3p,-mnu*tmd,ed2p,
+        ed1m,zero  ,ed2m, plu,ed3m,-mnu,
+        -mnu*tmd,ed2p,zero  ,ed1p, plu*tmd, ed3p,
+        ed3m,-mnu,ed1m,zero  ,ed2m, plu,
+        plu*tmd,ed3p,-mnu*tmd,ed2p,zero  ,ed1p,
+        ed2m, plu,ed3m,-mnu,ed1m,zero
+    );
+    (hamiltonian_u, hamiltonian_d)
+}
+
+#[test]
+fn jet_2_matches_hand_written_derivatives(){
+    let mut rng = Lcg(2);
+
+    for system in systems() {
+        for _ in 0..20 {
+            let kk = rng.next_kk();
+
+            for xindex in 0..2 {
+                for yindex in 0..2 {
+                    let jet = hamiltonian_2_jet(&system, kk, xindex, yindex);
+                    let h = hamiltonian_2(&system, kk);
+                    let dxi = hamiltonian_2_dxi(&system, kk, xindex);
+                    let dxj = hamiltonian_2_dxi(&system, kk, yindex);
+                    let dxidxj = hamiltonian_2_dxidxj(&system, kk, xindex, yindex);
+
+                    for spin in 0..2 {
+                        assert!((jet.h.index(spin) - h.index(spin)).norm() < TOLERANCE, "H {:?}", system);
+                        assert!((jet.dxi.index(spin) - dxi.index(spin)).norm() < TOLERANCE, "dH/dk{} {:?}", xindex, system);
+                        assert!((jet.dxj.index(spin) - dxj.index(spin)).norm() < TOLERANCE, "dH/dk{} {:?}", yindex, system);
+                        assert!((jet.dxidxj.index(spin) - dxidxj.index(spin)).norm() < TOLERANCE, "d2H/dk{}dk{} {:?}", xindex, yindex, system);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn jet_6_matches_hand_written_derivatives(){
+    let mut rng = Lcg(6);
+
+    for system in systems() {
+        for _ in 0..20 {
+            let kk = rng.next_kk();
+
+            for xindex in 0..2 {
+                for yindex in 0..2 {
+                    let jet = hamiltonian_6_jet(&system, kk, xindex, yindex);
+                    let h = hamiltonian_6(&system, kk);
+                    let dxi = hamiltonian_6_dxi(&system, kk, xindex);
+                    let dxj = hamiltonian_6_dxi(&system, kk, yindex);
+                    let dxidxj = hamiltonian_6_dxidxj(&system, kk, xindex, yindex);
+
+                    for spin in 0..2 {
+                        assert!((jet.h.index(spin) - h.index(spin)).norm() < TOLERANCE, "H {:?}", system);
+                        assert!((jet.dxi.index(spin) - dxi.index(spin)).norm() < TOLERANCE, "dH/dk{} {:?}", xindex, system);
+                        assert!((jet.dxj.index(spin) - dxj.index(spin)).norm() < TOLERANCE, "dH/dk{} {:?}", yindex, system);
+                        assert!((jet.dxidxj.index(spin) - dxidxj.index(spin)).norm() < TOLERANCE, "d2H/dk{}dk{} {:?}", xindex, yindex, system);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn jet_6_matches_finite_differences_with_bond_corrections(){
+    let mut rng = Lcg(7);
+    let system = mean_field_with_bonds(&mut rng);
+    let step = 1e-4;
+    let shift = |axis : usize, sign : f64| if axis == 0 { Vector2::new(sign * step, 0.0) } else { Vector2::new(0.0, sign * step) };
+
+    for _ in 0..20 {
+        let kk = rng.next_kk();
+
+        for xindex in 0..2 {
+            for yindex in 0..2 {
+                let jet = hamiltonian_6_jet(&system, kk, xindex, yindex);
+                let h = hamiltonian_6(&system, kk);
+                let at = |kk : Vector2<f64>| hamiltonian_6(&system, kk);
+
+                for spin in 0..2 {
+                    let dxi = (at(kk + shift(xindex, 1.0)).index(spin) - at(kk + shift(xindex, -1.0)).index(spin)).unscale(2.0 * step);
+                    let dxidxj = (
+                        at(kk + shift(xindex, 1.0) + shift(yindex, 1.0)).index(spin) - at(kk + shift(xindex, 1.0) + shift(yindex, -1.0)).index(spin)
+                        - at(kk + shift(xindex, -1.0) + shift(yindex, 1.0)).index(spin) + at(kk + shift(xindex, -1.0) + shift(yindex, -1.0)).index(spin)
+                    ).unscale(4.0 * step * step);
+
+                    assert!((jet.h.index(spin) - h.index(spin)).norm() < TOLERANCE, "H {:?}", system);
+                    assert!((jet.dxi.index(spin) - dxi).norm() < FINITE_DIFFERENCE_TOLERANCE, "dH/dk{} {:?}", xindex, system);
+                    assert!((jet.dxidxj.index(spin) - dxidxj).norm() < FINITE_DIFFERENCE_TOLERANCE, "d2H/dk{}dk{} {:?}", xindex, yindex, system);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,141 @@
+//縮退した多重項のBerry曲率（トレースを等分した値）が多重項内部のユニタリ変換によらないこと、
+//非可換Berry曲率のトレースと一致すること、縮退点が BandInfo に記録されることを確かめる
+
+mod common;
+
+use common::{eigenvalues, Lcg};
+use uuuddd4::{
+    consts::{gamma, k, kp},
+    honeycomb::{
+        cal_berry::{calculate_quantum_metric_from_seud, find_multiplets, non_abelian_berry_curvature, Tensor},
+        honeycomb_grids::Grids,
+        setting::CalcSetting,
+        symmetry::{build_grids_in_wedge, IrreducibleMesh, SymmetryGroup},
+        util::GridInfo,
+    },
+    system::{
+        diag::{diag, SEudEnum},
+        model::{Param, System},
+    },
+};
+
+use nalgebra::{Complex, DMatrix, Vector2};
+
+const TOLERANCE : f64 = 1e-10;
+
+fn setting() -> CalcSetting{
+    CalcSetting { mesh_kx : 10, mesh_ky : 10, height_map_div : 1, threshold_berry : 1e-12, main_mesh : 1 }
+}
+
+//多重項の固有ベクトルを unitary で混ぜる
+fn rotate(seud_enum : &mut SEudEnum, spin : usize, start : usize, unitary : &DMatrix<Complex<f64>>){
+    let n = unitary.nrows();
+    match seud_enum {
+        SEudEnum::SEud2(seud) => {
+            let eigen = if spin == 0 { &mut seud.u } else { &mut seud.d };
+            let rotated = eigen.eigenvectors.columns(start, n) * unitary;
+            eigen.eigenvectors.columns_mut(start, n).copy_from(&rotated);
+        }
+        SEudEnum::SEud6(seud) => {
+            let eigen = if spin == 0 { &mut seud.u } else { &mut seud.d };
+            let rotated = eigen.eigenvectors.columns(start, n) * unitary;
+            eigen.eigenvectors.columns_mut(start, n).copy_from(&rotated);
+        }
+    }
+}
+
+#[test]
+fn traced_curvature_is_invariant_under_rotations_of_a_multiplet(){
+    let mut rng = Lcg(26);
+    let setting = setting();
+    //λ ≠ 0 の6サイトの系では Γ, K, K' に曲率を持つ2重縮退がある
+    let system = System::One1Tmd(Param::new(0.3, 0.5));
+    let points: [Vector2<f64>; 3] = [gamma(6), k(6), kp(6)];
+
+    let mut checked = 0;
+    for kk in points {
+        let seud_enum = diag(&system, kk, false);
+        let berry = calculate_quantum_metric_from_seud(&seud_enum, &system, kk, 1.0, true, Tensor::XY, &setting);
+
+        for spin in 0..2 {
+            let multiplets = find_multiplets(&eigenvalues(&seud_enum, spin), setting.threshold_berry);
+
+            for multiplet in multiplets.iter().filter(|multiplet| multiplet.is_degenerate()) {
+                let mut rotated = seud_enum.clone();
+                rotate(&mut rotated, spin, multiplet.start, &rng.next_unitary(multiplet.len));
+                let rotated_berry = calculate_quantum_metric_from_seud(&rotated, &system, kk, 1.0, true, Tensor::XY, &setting);
+
+                for band_num in 0..system.size() {
+                    assert!(
+                        (berry[spin][band_num] - rotated_berry[spin][band_num]).abs() < TOLERANCE,
+                        "band {} at {:?}: {} vs {}", band_num, kk, berry[spin][band_num], rotated_berry[spin][band_num]
+                    );
+                }
+                let trace: f64 = berry[spin][multiplet.start..multiplet.start + multiplet.len].iter().sum();
+                assert!(trace.abs() > TOLERANCE, "multiplet at {:?} has no curvature", kk);
+                checked += 1;
+            }
+        }
+    }
+    assert!(checked > 0, "no degenerate multiplet found");
+}
+
+#[test]
+fn non_abelian_trace_matches_multiplet_sum(){
+    let mut rng = Lcg(27);
+    let setting = setting();
+    let system = System::One1Tmd(Param::new(0.3, 0.5));
+
+    let mut checked = 0;
+    for kk in [gamma(6), k(6), kp(6)] {
+        let seud_enum = diag(&system, kk, false);
+        let berry = calculate_quantum_metric_from_seud(&seud_enum, &system, kk, 1.0, true, Tensor::XY, &setting);
+
+        for (spin, berry) in berry.iter().enumerate() {
+            let multiplets = find_multiplets(&eigenvalues(&seud_enum, spin), setting.threshold_berry);
+
+            for &multiplet in multiplets.iter().filter(|multiplet| multiplet.is_degenerate()) {
+                let sum: f64 = berry[multiplet.start..multiplet.start + multiplet.len].iter().sum();
+                let curvature = non_abelian_berry_curvature(&seud_enum, &system, kk, spin, multiplet);
+                assert!((curvature.trace().re - sum).abs() < TOLERANCE, "Tr F = {} vs {} at {:?}", curvature.trace().re, sum, kk);
+                assert!(curvature.trace().im.abs() < TOLERANCE);
+
+                //F は共変なのでトレースは回転によらない
+                let mut rotated = seud_enum.clone();
+                rotate(&mut rotated, spin, multiplet.start, &rng.next_unitary(multiplet.len));
+                let rotated_curvature = non_abelian_berry_curvature(&rotated, &system, kk, spin, multiplet);
+                assert!((rotated_curvature.trace() - curvature.trace()).norm() < TOLERANCE);
+                checked += 1;
+            }
+        }
+    }
+    assert!(checked > 0, "no degenerate multiplet found");
+}
+
+#[test]
+fn degenerate_points_are_flagged_in_full_and_wedge_grids(){
+    let calc_setting = CalcSetting { mesh_kx : 12, mesh_ky : 12, height_map_div : 1, threshold_berry : 1e-12, main_mesh : 1 };
+    let system = System::One1Tmd(Param::new(0.3, 0.5));
+
+    let full = Grids::build(calc_setting, system.clone(), GridInfo::no_divide());
+    let group = SymmetryGroup::detect(&system, &calc_setting);
+    let wedge = IrreducibleMesh::build(&group, &calc_setting).expect("the mesh fits the wedge");
+    let reduced = build_grids_in_wedge(calc_setting, system.clone(), &group, &wedge);
+
+    let mut flagged = 0;
+    for spin in 0..2 {
+        for band_num in 0..system.size() {
+            for i in 0..=calc_setting.mesh_kx {
+                for j in 0..=calc_setting.mesh_ky {
+                    let band_info = &full.index(spin)[band_num].0[i][j];
+                    assert_eq!(band_info.multiplet, reduced.index(spin)[band_num].0[i][j].multiplet, "({}, {}) spin {} band {}", i, j, spin, band_num);
+                    if band_info.is_degenerate() {
+                        flagged += 1;
+                    }
+                }
+            }
+        }
+    }
+    //Γ 点の2重縮退は必ず記録される
+    assert!(flagged > 0, "no degenerate k point flagged");
+}
@@ -0,0 +1,45 @@
+//テストで共通に使う疑似乱数と系の一覧
+//（テストごとに使うものが違うので、使わないものの警告は出さない）
+#![allow(dead_code)]
+
+use uuuddd4::system::{
+    diag::SEudEnum,
+    model::{Param, System},
+};
+
+use nalgebra::{Complex, DMatrix, Vector2};
+
+//再現性のある疑似乱数（線形合同法）
+pub struct Lcg(pub u64);
+
+impl Lcg{
+    pub fn next_f64(&mut self) -> f64{
+        self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (self.0 >> 11) as f64 / (1u64 << 53) as f64
+    }
+    pub fn next_kk(&mut self) -> Vector2<f64>{
+        Vector2::new(8.0 * self.next_f64() - 4.0, 8.0 * self.next_f64() - 4.0)
+    }
+    //乱数行列のQR分解で作るランダムな N x N ユニタリ行列
+    pub fn next_unitary(&mut self, n : usize) -> DMatrix<Complex<f64>>{
+        let matrix = DMatrix::from_fn(n, n, |_, _| Complex::new(self.next_f64() - 0.5, self.next_f64() - 0.5));
+        matrix.qr().q()
+    }
+}
+
+//System::from_family で作れる全ての系（2サイトと6サイト）
+pub fn systems() -> Vec<System>{
+    let param = Param::new(0.3, 0.25);
+    let spins = ["para", "fm", "afm", "one1", "one2", "twin", "tri1", "tri2", "uuuddd"];
+    ["original", "tmd", "kanemele"].iter()
+        .flat_map(|family| spins.iter().filter_map(move |spin| System::from_family(family, spin, param)))
+        .collect()
+}
+
+//あるスピンの固有値（昇順）
+pub fn eigenvalues(seud_enum : &SEudEnum, spin : usize) -> Vec<f64>{
+    match seud_enum {
+        SEudEnum::SEud2(seud) => seud.index(spin).eigenvalues.as_slice().to_vec(),
+        SEudEnum::SEud6(seud) => seud.index(spin).eigenvalues.as_slice().to_vec(),
+    }
+}
@@ -0,0 +1,47 @@
+//スピン配置の比較と相図が、候補が空のときにパニックせずエラーを返すこと、
+//各電子数で全エネルギーが最も低い候補を選び、その候補の Tanzaku を返すことを確かめる
+
+use uuuddd4::{
+    honeycomb::{
+        compare::{compare_candidates, CompareOptions},
+        phase_diagram::{PhaseAxis, PhaseDiagram},
+        setting::CalcSetting,
+    },
+    system::model::{Param, System},
+};
+
+fn setting() -> CalcSetting{
+    CalcSetting { mesh_kx : 6, mesh_ky : 6, height_map_div : 1, threshold_berry : 1e-12, main_mesh : 1 }
+}
+
+fn options() -> CompareOptions{
+    CompareOptions { n_div : 6, energy_mesh : 6 }
+}
+
+#[test]
+fn empty_candidates_are_rejected(){
+    assert!(compare_candidates(&[], setting(), options()).is_err());
+    assert!(PhaseDiagram::build(&[], PhaseAxis::Lambda, &[0.1, 0.2], setting(), options()).is_err());
+}
+
+#[test]
+fn winner_has_the_lowest_energy_and_its_own_tanzaku(){
+    let param = Param::new(0.1, 2.0);
+    let candidates = [System::FmTmd(param), System::SatoTmd(param), System::UuudddTmd(param)];
+    let result = compare_candidates(&candidates, setting(), options()).unwrap();
+
+    assert_eq!(result.fillings.len(), options().n_div);
+    for (i, filling) in result.fillings.iter().enumerate() {
+        let lowest = filling.energies.iter().cloned().fold(f64::INFINITY, f64::min);
+        assert_eq!(filling.energies[filling.winner], lowest, "n = {}", filling.n);
+
+        let own = &result.tanzakuss[filling.winner].data[i];
+        assert_eq!(filling.tanzaku.berry, own.berry);
+        assert_eq!(filling.tanzaku.n, own.n);
+    }
+
+    //J が大きい半充填（n = 1）ではハニカム格子の Néel 反強磁性が最安定
+    let half = &result.fillings[options().n_div / 2];
+    assert!((half.n - 1.0).abs() < 1e-12);
+    assert_eq!(result.winner(options().n_div / 2).debug_only_name(), candidates[1].debug_only_name());
+}
@@ -0,0 +1,85 @@
+//逆有効質量テンソルが、対角化したバンドのエネルギーの k についての2階差分と一致することを確かめる
+
+mod common;
+
+use common::eigenvalues;
+use uuuddd4::{
+    consts::{gamma, kp, kpp},
+    honeycomb::{
+        cal_berry::find_multiplets,
+        effective_mass::inverse_effective_mass,
+        setting::CalcSetting,
+    },
+    system::{
+        diag::diag,
+        model::{Param, System},
+    },
+};
+
+use nalgebra::Vector2;
+
+//中心差分の誤差は刻みの2乗程度（丸め誤差は 1e-16 / STEP^2 程度）
+const STEP : f64 = 1e-4;
+const FINITE_DIFFERENCE_TOLERANCE : f64 = 1e-5;
+
+fn setting() -> CalcSetting{
+    CalcSetting { mesh_kx : 10, mesh_ky : 10, height_map_div : 1, threshold_berry : 1e-12, main_mesh : 1 }
+}
+
+//ε_n の2階微分の中心差分 [[∂x∂x, ∂x∂y], [∂y∂x, ∂y∂y]]
+fn finite_difference(system : &System, kk : Vector2<f64>, spin : usize, band_num : usize) -> [[f64; 2]; 2]{
+    let energy = |dx : f64, dy : f64| eigenvalues(&diag(system, kk + Vector2::new(dx * STEP, dy * STEP), false), spin)[band_num];
+    let xx = (energy(1.0, 0.0) - 2.0 * energy(0.0, 0.0) + energy(-1.0, 0.0)) / (STEP * STEP);
+    let yy = (energy(0.0, 1.0) - 2.0 * energy(0.0, 0.0) + energy(0.0, -1.0)) / (STEP * STEP);
+    let xy = (energy(1.0, 1.0) - energy(1.0, -1.0) - energy(-1.0, 1.0) + energy(-1.0, -1.0)) / (4.0 * STEP * STEP);
+    [[xx, xy], [xy, yy]]
+}
+
+//縮退していないバンドについて逆有効質量テンソルを差分と比べ、比べたバンドの数を返す
+fn check_against_finite_difference(system : &System, kk : Vector2<f64>) -> usize{
+    let setting = setting();
+    let seud_enum = diag(system, kk, false);
+    let mut checked = 0;
+
+    for spin in 0..2 {
+        let energies = eigenvalues(&seud_enum, spin);
+        let multiplets = find_multiplets(&energies, 1e-6);
+
+        for band_num in 0..system.size() {
+            if multiplets.iter().any(|multiplet| multiplet.contains(band_num) && multiplet.is_degenerate()) {
+                continue;
+            }
+            let inverse_mass = inverse_effective_mass(&seud_enum, system, kk, spin, band_num, &setting);
+            let expected = finite_difference(system, kk, spin, band_num);
+
+            for (a, row) in expected.iter().enumerate() {
+                for (b, &value) in row.iter().enumerate() {
+                    assert!(
+                        (inverse_mass[(a, b)] - value).abs() < FINITE_DIFFERENCE_TOLERANCE,
+                        "{:?} at {:?}, spin {} band {} ({}, {}): {} vs {}", system, kk, spin, band_num, a, b, inverse_mass[(a, b)], value
+                    );
+                }
+            }
+            checked += 1;
+        }
+    }
+    checked
+}
+
+#[test]
+fn inverse_mass_matches_finite_difference_at_high_symmetry_points(){
+    //λ ≠ 0 で K, K' のギャップが開くので、2サイトの系ではどのバンドも縮退しない
+    let system = System::Tmd(Param::new(0.1, 0.0));
+    for kk in [gamma(2), kp(2), kpp(2)] {
+        assert_eq!(check_against_finite_difference(&system, kk), 4);
+    }
+}
+
+#[test]
+fn inverse_mass_matches_finite_difference_for_six_site_systems(){
+    let param = Param::new(0.3, 0.25);
+    let kk = Vector2::new(0.37, -0.21);
+    for system in [System::One1Tmd(param), System::UuudddKanemele(param), System::Tri1Tmd(param)] {
+        assert!(check_against_finite_difference(&system, kk) > 0, "{:?} has no isolated band", system);
+    }
+}
@@ -0,0 +1,54 @@
+//有限のフレークの局所 Chern マーカーが、Kane-Mele 模型の中心部ではバルクのスピンごとの Chern 数 ±1 になり、
+//弱い乱れでも変わらないこと、フレーク全体の和が 0 になること、自明な絶縁体では 0 になることを確かめる
+
+use uuuddd4::{
+    honeycomb::{
+        chern::chern_numbers,
+        honeycomb_grids::Grids,
+        setting::CalcSetting,
+        util::GridInfo,
+    },
+    system::{
+        flake::{Flake, FlakeShape, LocalChernMarker},
+        model::{Param, System},
+    },
+};
+
+const SHAPE : FlakeShape = FlakeShape::Hexagonal { rings : 6 };
+const BULK_RADIUS : f64 = 2.0;
+const TOLERANCE : f64 = 0.1;
+
+fn bulk_chern(system : &System, e_fermi : f64) -> [f64; 2]{
+    let setting = CalcSetting { mesh_kx : 24, mesh_ky : 24, height_map_div : 1, threshold_berry : 1e-12, main_mesh : 1 };
+    chern_numbers(&Grids::build(setting, system.clone(), GridInfo::no_divide()), e_fermi).map(|c| c.rounded() as f64)
+}
+
+#[test]
+fn kane_mele_marker_matches_the_bulk_chern_number(){
+    //λ が大きいとギャップ 6√3λ が大きく、端の影響が中心まで届かない
+    let system = System::FmKanemele(Param::new(0.3, 0.0));
+    let chern = bulk_chern(&system, 0.0);
+    assert_eq!(chern[0].abs(), 1.0);
+
+    let clean = Flake::new(&system, SHAPE).unwrap();
+    for flake in [clean.clone(), clean.with_anderson(1.0, 7)] {
+        let marker = LocalChernMarker::build(&flake, 0.0);
+        let average = marker.bulk_average(&flake, BULK_RADIUS);
+        for spin in 0..2 {
+            assert!((average[spin] - chern[spin]).abs() < TOLERANCE, "W = {}: {:?} vs {:?}", flake.anderson, average, chern);
+            //端の近くで打ち消し、全体の和は 0 になる
+            let total: f64 = marker.markers[spin].iter().sum();
+            assert!(total.abs() < 1e-8, "{}", total);
+        }
+    }
+}
+
+#[test]
+fn trivial_insulator_has_no_marker(){
+    let system = System::from_family("original", "afm", Param::new(0.1, 1.5)).unwrap();
+    assert_eq!(bulk_chern(&system, 0.0), [0.0, 0.0]);
+
+    let flake = Flake::new(&system, SHAPE).unwrap();
+    let average = LocalChernMarker::build(&flake, 0.0).bulk_average(&flake, BULK_RADIUS);
+    assert!(average.iter().all(|c| c.abs() < TOLERANCE), "{:?}", average);
+}
@@ -0,0 +1,53 @@
+//グラフェン（λ = 0, J = 0）で find_nodes が K と K' の Dirac 点を逆のカイラリティで見つけること、
+//Kane-Mele 模型ではギャップが 6√3λ で開くことを確かめる
+
+use uuuddd4::{
+    consts::{k, kp, PI},
+    honeycomb::gap_finder::{band_gap, chirality_at, find_nodes, global_min_gap, GapSearchSetting},
+    system::model::{Param, System},
+};
+
+const GAP_TOLERANCE : f64 = 1e-6;
+const PHASE_TOLERANCE : f64 = 1e-2;
+
+#[test]
+fn graphene_has_two_dirac_nodes_with_opposite_chirality(){
+    let system = System::Tmd(Param::new(0.0, 0.0));
+    let setting = GapSearchSetting::standard();
+
+    //K, K' は厳密にギャップが閉じ、カイラリティが逆（kp と kpp は同じ K' の点）
+    assert!(band_gap(&system, k(2), 0, 0) < GAP_TOLERANCE);
+    assert!(band_gap(&system, kp(2), 0, 0) < GAP_TOLERANCE);
+    let chirality_k = chirality_at(&system, k(2), 0, 0);
+    assert_eq!(chirality_k.abs(), 1);
+    assert_eq!(chirality_at(&system, kp(2), 0, 0), -chirality_k);
+
+    for spin in 0..2 {
+        let nodes: Vec<_> = find_nodes(&system, spin, 0, &setting).into_iter()
+            .filter(|node| node.gap < GAP_TOLERANCE)
+            .collect();
+        assert_eq!(nodes.len(), 2, "spin {}: {:?}", spin, nodes);
+
+        let mut chiralities: Vec<i32> = nodes.iter().map(|node| node.chirality).collect();
+        chiralities.sort();
+        assert_eq!(chiralities, vec![-1, 1], "spin {}", spin);
+
+        //Dirac 点の周りの Berry 位相は ±π
+        for node in &nodes {
+            assert!((node.berry_phase.abs() - PI).abs() < PHASE_TOLERANCE, "spin {}: {:?}", spin, node);
+        }
+    }
+}
+
+#[test]
+fn intrinsic_spin_orbit_coupling_opens_the_dirac_gap(){
+    //Kane-Mele 模型の K でのギャップは 6√3λ
+    let lambda = 0.05;
+    let system = System::FmKanemele(Param::new(lambda, 0.0));
+    let expected = 6.0 * 3.0_f64.sqrt() * lambda;
+
+    for spin in 0..2 {
+        let node = global_min_gap(&system, spin, 0, &GapSearchSetting::standard()).expect("a gap minimum");
+        assert!((node.gap - expected).abs() < GAP_TOLERANCE, "spin {}: {} vs {}", spin, node.gap, expected);
+    }
+}
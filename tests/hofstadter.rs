@@ -0,0 +1,71 @@
+//弱い磁場の Hofstadter のスペクトルで、グラフェンの Landau 準位の間のギャップの Streda の Chern 数が
+//スピンごとに ±1, ±3, ±5（半整数の量子 Hall 効果）、バンドの下端の近くでは 1, 2, 3 になること、
+//Kane-Mele 模型のバルクのギャップでは chern::chern_numbers の C_σ に戻ることを確かめる
+
+use uuuddd4::{
+    honeycomb::{
+        chern::chern_numbers,
+        hofstadter::{Hofstadter, HofstadterGap},
+        honeycomb_grids::Grids,
+        setting::CalcSetting,
+        util::GridInfo,
+    },
+    system::{
+        model::{Param, System},
+        peierls::Flux,
+    },
+};
+
+const K_POINTS : usize = 2;
+const MIN_GAP : f64 = 0.05;
+
+fn weak_fluxes() -> Vec<Flux>{
+    (30..=32).map(|q| Flux::new(1, q)).collect()
+}
+
+//真ん中の磁束の spin のギャップ（エネルギーの昇順）
+fn middle_gaps(hofstadter : &Hofstadter, spin : usize) -> Vec<&HofstadterGap>{
+    let middle = hofstadter.spectra[1].flux;
+    hofstadter.gaps.iter().filter(|gap| gap.flux == middle && gap.spin == spin).collect()
+}
+
+fn rounded_chern(gap : &HofstadterGap) -> i64{
+    let chern = gap.chern.unwrap_or_else(|| panic!("no Streda Chern number for the gap {} to {}", gap.lower, gap.upper));
+    assert!((chern - chern.round()).abs() < 1e-6, "{}", chern);
+    chern.round() as i64
+}
+
+#[test]
+fn graphene_landau_gaps_follow_the_half_integer_sequence(){
+    let system = System::from_family("original", "afm", Param::new(0.0, 0.0)).unwrap();
+    let hofstadter = Hofstadter::build(&system, &weak_fluxes(), K_POINTS, MIN_GAP).unwrap();
+
+    for spin in 0..2 {
+        let gaps = middle_gaps(&hofstadter, spin);
+        //Dirac 点のまわりの Landau 準位の間では σ_xy = ±(2n + 1)
+        let above: Vec<i64> = gaps.iter().filter(|gap| gap.lower > 0.0).take(4).map(|gap| rounded_chern(gap)).collect();
+        let below: Vec<i64> = gaps.iter().rev().filter(|gap| gap.upper < 0.0).take(4).map(|gap| rounded_chern(gap)).collect();
+        assert_eq!(above, vec![1, 3, 5, 7]);
+        assert_eq!(below, vec![-1, -3, -5, -7]);
+
+        //バンドの下端の近くは通常の2次元電子ガスの Landau 準位で σ_xy = n
+        let bottom: Vec<i64> = gaps.iter().take(3).map(|gap| rounded_chern(gap)).collect();
+        assert_eq!(bottom, vec![1, 2, 3]);
+    }
+}
+
+#[test]
+fn weak_field_limit_recovers_the_bulk_chern_number(){
+    let system = System::FmKanemele(Param::new(0.3, 0.0));
+    let setting = CalcSetting { mesh_kx : 24, mesh_ky : 24, height_map_div : 1, threshold_berry : 1e-12, main_mesh : 1 };
+    let chern = chern_numbers(&Grids::build(setting, system.clone(), GridInfo::no_divide()), 0.0);
+    let hofstadter = Hofstadter::build(&system, &weak_fluxes(), K_POINTS, MIN_GAP).unwrap();
+
+    for (spin, bulk) in chern.iter().enumerate() {
+        assert_eq!(bulk.rounded().abs(), 1);
+        //ゼロエネルギーを含むバルクのギャップ
+        let gap = middle_gaps(&hofstadter, spin).into_iter().find(|gap| gap.lower < 0.0 && gap.upper > 0.0).unwrap();
+        assert!((gap.states - 1.0).abs() < 0.1, "{}", gap.states);
+        assert_eq!(rounded_chern(gap), bulk.rounded(), "spin {}", spin);
+    }
+}
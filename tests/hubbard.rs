@@ -0,0 +1,71 @@
+//Hubbard平均場の解が実空間の格子（ribbon, flake など）に渡せるかの判定と、
+//半充填のハニカム格子で U_c を境に Néel 秩序が現れることを確かめる
+
+use uuuddd4::{
+    interaction::hubbard::{solve, HubbardSetting},
+    system::{
+        hamiltonian::check_real_space,
+        model::{Param, System},
+        ribbon::{Edge, Ribbon},
+    },
+};
+
+fn setting(v : f64, v2 : f64) -> HubbardSetting{
+    HubbardSetting { v, v2, mesh : 12, max_iterations : 100, ..HubbardSetting::standard(2.0, 1.0) }
+}
+
+#[test]
+fn next_nearest_fock_terms_are_rejected_in_real_space(){
+    let seed = System::UuudddKanemele(Param::new(0.1, 0.0));
+    let solution = solve(&seed, false, setting(0.0, 0.5));
+
+    //V = 0 でも V2 の Fock項が結合の補正を作る
+    match &solution.system {
+        System::MeanField(_, fields) => assert!(fields.has_bond_corrections()),
+        _ => panic!("solve should return a mean field"),
+    }
+    assert!(solution.system.debug().contains("V2"), "{}", solution.system.debug());
+    assert!(check_real_space(&solution.system).is_err());
+    assert!(Ribbon::new(&solution.system, Edge::Zigzag, 4).is_err());
+}
+
+#[test]
+fn on_site_mean_field_is_accepted_in_real_space(){
+    let seed = System::UuudddKanemele(Param::new(0.1, 0.0));
+    let solution = solve(&seed, false, setting(0.0, 0.0));
+
+    assert!(check_real_space(&solution.system).is_ok());
+    assert!(Ribbon::new(&solution.system, Edge::Zigzag, 4).is_ok());
+}
+
+//粗いメッシュでは Dirac 点の近くのk点が小さな U でもモーメントを残すので、K 点を含まない細かいメッシュを使う
+fn half_filling(u : f64) -> HubbardSetting{
+    HubbardSetting { mesh : 31, ..HubbardSetting::standard(u, 1.0) }
+}
+
+//ハニカム格子の半充填では U_c ≈ 2.2t を超えると Néel 秩序が現れ、それより小さければ常磁性に戻る
+#[test]
+fn half_filling_orders_above_the_critical_u(){
+    let neel = System::from_family("original", "afm", Param::new(0.0, 0.0)).unwrap();
+    let uuuddd = System::from_family("original", "uuuddd", Param::new(0.0, 0.0)).unwrap();
+
+    let strong = solve(&neel, false, half_filling(4.0));
+    assert!(strong.converged);
+    let size = strong.system.size();
+    let moments = strong.moments();
+    for (site, m) in moments[..size].iter().enumerate() {
+        //副格子ごとに符号が反転し、大きさは揃っている
+        let sign = if site % 2 == 0 { 1.0 } else { -1.0 };
+        assert!((m - sign * moments[0]).abs() < 1e-4, "{:?}", moments);
+    }
+    assert!(moments[0].abs() > 0.2 && moments[0].abs() < 0.5, "{:?}", moments);
+    assert!(strong.charge_order().abs() < 1e-6);
+    let electrons: f64 = strong.densities.iter().flat_map(|spin| &spin[..size]).sum();
+    assert!((electrons - size as f64).abs() < 1e-6, "{}", electrons);
+    //Néel 秩序は uuuddd の配置より平均場のエネルギーが低い
+    assert!(strong.energy < solve(&uuuddd, false, half_filling(4.0)).energy);
+
+    let weak = solve(&neel, false, half_filling(1.0));
+    assert!(weak.converged);
+    assert!(weak.moments().iter().all(|m| m.abs() < 1e-4), "{:?}", weak.moments());
+}
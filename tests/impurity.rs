@@ -0,0 +1,87 @@
+//点不純物の束縛状態がギャップの中で 1 = V G(ω) を満たして T 行列が発散すること、斥力なら価電子帯の上端から、
+//引力なら伝導帯の下端から離れていくこと、時間反転対称な母体では磁性不純物の下向きスピンの束縛状態が
+//-V の非磁性不純物と一致すること、束縛状態の LDOS が不純物のまわりに局在することを確かめる
+
+use uuuddd4::{
+    honeycomb::{
+        honeycomb_grids::Grids,
+        impurity::{BoundStateScan, ImpurityLdos, RealSpaceGreen},
+        qpi::Impurity,
+        setting::CalcSetting,
+        spectral::Broadening,
+        util::GridInfo,
+    },
+    system::model::{Param, System},
+};
+
+use nalgebra::Complex;
+
+const POTENTIALS : [f64; 4] = [0.5, 1.0, 2.0, 4.0];
+
+//Kane-Mele 模型（J = 0）は半充填でギャップ 6√3λ を持つ
+fn green() -> RealSpaceGreen{
+    let setting = CalcSetting { mesh_kx : 18, mesh_ky : 18, height_map_div : 1, threshold_berry : 1e-12, main_mesh : 1 };
+    RealSpaceGreen::new(&Grids::build(setting, System::FmKanemele(Param::new(0.1, 0.0)), GridInfo::no_divide()))
+}
+
+fn energies(scan : &BoundStateScan, spin : usize) -> Vec<f64>{
+    scan.states.iter().map(|state| state.energy[spin].unwrap()).collect()
+}
+
+#[test]
+fn bound_states_leave_the_band_edges_and_solve_the_t_matrix_pole(){
+    let green = green();
+    let repulsive = BoundStateScan::build(&green, 1.0, 0, false, &POTENTIALS, 0.1);
+    let attractive = BoundStateScan::build(&green, 1.0, 0, false, &POTENTIALS.map(|v| -v), 0.1);
+    let (top, bottom) = repulsive.gap[0];
+    assert!((bottom - top - 6.0 * 3f64.sqrt() * 0.1).abs() < 1e-6, "gap {} to {}", top, bottom);
+
+    //斥力が強くなるほど上へ、引力が強くなるほど下へ動く
+    let up = energies(&repulsive, 0);
+    let down = energies(&attractive, 0);
+    for pair in up.windows(2) {
+        assert!(top < pair[0] && pair[0] < pair[1] && pair[1] < bottom, "{:?}", up);
+    }
+    for pair in down.windows(2) {
+        assert!(top < pair[1] && pair[1] < pair[0] && pair[0] < bottom, "{:?}", down);
+    }
+
+    //束縛状態では 1 - V G = 0 なので T 行列が発散する
+    for (state, energy) in repulsive.states.iter().zip(&up) {
+        let impurity = Impurity { site : 0, potential : state.potential, magnetic : false, born : false };
+        let on = green.t_matrix(impurity, Complex::new(energy + repulsive.mu, 1e-9))[0].norm();
+        let off = green.t_matrix(impurity, Complex::new(0.5 * (top + energy) + repulsive.mu, 1e-9))[0].norm();
+        assert!(on > 1e3 * off, "V = {}: {} vs {}", state.potential, on, off);
+    }
+}
+
+#[test]
+fn magnetic_impurity_in_a_time_reversal_symmetric_host(){
+    let green = green();
+    let magnetic = BoundStateScan::build(&green, 1.0, 0, true, &POTENTIALS, 0.1);
+    let repulsive = BoundStateScan::build(&green, 1.0, 0, false, &POTENTIALS, 0.1);
+    let attractive = BoundStateScan::build(&green, 1.0, 0, false, &POTENTIALS.map(|v| -v), 0.1);
+
+    //上向きには V、下向きには -V がかかる
+    for (a, b) in energies(&magnetic, 0).iter().zip(energies(&repulsive, 0)) {
+        assert!((a - b).abs() < 1e-9);
+    }
+    for (a, b) in energies(&magnetic, 1).iter().zip(energies(&attractive, 0)) {
+        assert!((a - b).abs() < 1e-9, "{} vs {}", a, b);
+    }
+}
+
+#[test]
+fn bound_state_is_localized_at_the_impurity(){
+    let green = green();
+    let scan = BoundStateScan::build(&green, 1.0, 0, false, &[2.0], 0.1);
+    let omega = scan.states[0].energy[0].unwrap();
+    let impurity = Impurity { site : 0, potential : 2.0, magnetic : false, born : false };
+    let ldos = ImpurityLdos::build(&green, 1.0, scan.mu, omega, impurity, Broadening { constant : 0.01, quadratic : 0.0 }, 3.0);
+
+    //ギャップの中なので LDOS はほとんど束縛状態のもので、不純物のサイトで最大になり遠くでは小さい
+    let at_impurity = ldos.points.iter().find(|point| point.position.norm() < 1e-9).unwrap().ldos[0];
+    assert!(ldos.points.iter().all(|point| point.ldos[0] <= at_impurity));
+    let far = ldos.points.iter().filter(|point| point.position.norm() > 2.5).map(|point| point.ldos[0]).fold(0.0, f64::max);
+    assert!(far < 0.2 * at_impurity, "{} vs {}", far, at_impurity);
+}
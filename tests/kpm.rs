@@ -0,0 +1,62 @@
+//カーネル多項式法の状態の数が同じ試料の厳密対角化と一致すること、
+//Kane-Mele 模型のギャップの中で Hall 伝導度がスピンごとの Chern 数 ±1 に量子化し、弱い乱れでも保たれることを確かめる
+
+use uuuddd4::{
+    honeycomb::{
+        chern::chern_numbers,
+        honeycomb_grids::Grids,
+        kpm::{DisorderAverage, KpmMoments, KpmSetting},
+        setting::CalcSetting,
+        util::GridInfo,
+    },
+    system::{
+        disorder::{DisorderSetting, DisorderedLattice},
+        model::{Param, System},
+    },
+};
+
+use nalgebra::SymmetricEigen;
+
+const CELLS : [usize; 2] = [12, 12];
+const KPM : KpmSetting = KpmSetting { moments : 128, vectors : 8, block : 32, energy_points : 512 };
+
+//λ が大きいとギャップ 6√3λ が Chebyshev 展開の分解能よりずっと広い
+fn system() -> System{
+    System::FmKanemele(Param::new(0.3, 0.0))
+}
+
+#[test]
+fn kpm_filling_matches_exact_diagonalization(){
+    let setting = DisorderSetting { anderson : 1.0, ..DisorderSetting::clean() };
+    let lattice = DisorderedLattice::new(&system(), CELLS, setting).unwrap();
+    let hamiltonian = lattice.hamiltonian(0);
+    let moments = KpmMoments::build(&hamiltonian, &lattice.velocities(0), KPM, 3);
+    let eigenvalues = SymmetricEigen::new(hamiltonian.to_dense()).eigenvalues;
+
+    for energy in [-2.5, -1.0, 0.0, 1.0, 2.5] {
+        let exact = eigenvalues.iter().filter(|&&e| e < energy).count() as f64 / lattice.size() as f64;
+        assert!((moments.filling(energy) - exact).abs() < 0.02, "E = {}: {} vs {}", energy, moments.filling(energy), exact);
+    }
+    //ギャップの中央には状態がない
+    assert!(moments.density_of_states(0.0) < 0.02, "{}", moments.density_of_states(0.0));
+}
+
+#[test]
+fn hall_conductivity_is_quantized_in_the_gap(){
+    let settings = [DisorderSetting::clean(), DisorderSetting { anderson : 1.0, ..DisorderSetting::clean() }];
+    let average = DisorderAverage::build(&system(), CELLS, &settings, KPM, &[-0.3, 0.0, 0.3]).unwrap();
+
+    //符号も含めてバルクの Chern 数と一致する
+    let setting = CalcSetting { mesh_kx : 24, mesh_ky : 24, height_map_div : 1, threshold_berry : 1e-12, main_mesh : 1 };
+    let chern = chern_numbers(&Grids::build(setting, system(), GridInfo::no_divide()), 0.0).map(|c| c.rounded() as f64);
+    assert_eq!(chern[0], -chern[1]);
+    assert_eq!(chern[0].abs(), 1.0);
+
+    for realization in &average.realizations {
+        for spin in 0..2 {
+            for hall in &realization.hall[spin] {
+                assert!((hall - chern[spin]).abs() < 0.1, "W = {}: {:?} vs {:?}", realization.setting.anderson, realization.hall, chern);
+            }
+        }
+    }
+}
@@ -0,0 +1,57 @@
+//磁気点群から決めた応答テンソルの選択則を、時間反転対称な系と強磁性の系、計算した Tanzakus で確かめる
+
+use uuuddd4::{
+    honeycomb::{
+        magnetic_group::{check_tanzakus, MagneticPointGroup},
+        parallelization::parallel_calculate_tanzaku,
+        setting::CalcSetting,
+    },
+    system::model::{Param, System},
+};
+
+//禁止された成分の許容値。メッシュの対称性は完全だが、バンドの底では丸め誤差だけずれた
+//対称操作の相手（Ω が逆符号）の片方だけがエネルギーの区切りに入ることがあり、1状態分（~1e-7）残る
+const VIOLATION_TOLERANCE : f64 = 1e-6;
+
+fn setting() -> CalcSetting{
+    CalcSetting { mesh_kx : 12, mesh_ky : 12, height_map_div : 30, threshold_berry : 1e-12, main_mesh : 1 }
+}
+
+#[test]
+fn time_reversal_symmetric_system_forbids_hall_response(){
+    //J = 0 では時間反転（スピンの入れ替え）が対称操作になり、灰色群となる
+    let system = System::FmKanemele(Param::new(0.1, 0.0));
+    let group = MagneticPointGroup::from_system(&system, &setting());
+
+    assert_eq!(group.group_type(), 2, "{:?}", group.labels());
+    assert_eq!(group.order(), 2 * group.unitary_order());
+    let allowed = group.allowed_responses();
+    assert!(!allowed.sigma_xy, "{}", allowed.summary());
+    assert!(!allowed.bcd[0] && !allowed.bcd[1], "{}", allowed.summary());
+}
+
+#[test]
+fn ferromagnet_allows_hall_response(){
+    let system = System::FmKanemele(Param::new(0.1, 0.3));
+    let group = MagneticPointGroup::from_system(&system, &setting());
+
+    assert_ne!(group.group_type(), 2, "{:?}", group.labels());
+    let allowed = group.allowed_responses();
+    assert!(allowed.sigma_xy, "{}", allowed.summary());
+
+    //強磁性の Kane-Mele 模型は σ_xy が有限
+    let tanzakus = parallel_calculate_tanzaku(setting(), system);
+    assert!(tanzakus.data.iter().any(|tanzaku| tanzaku.berry.abs() > 1e-3));
+}
+
+#[test]
+fn forbidden_components_vanish_in_computed_tanzakus(){
+    let param = Param::new(0.1, 0.3);
+    for system in [System::FmKanemele(Param::new(0.1, 0.0)), System::FmKanemele(param), System::UuudddKanemele(param), System::One1Tmd(param), System::Tri1Tmd(param)] {
+        let group = MagneticPointGroup::from_system(&system, &setting());
+        let tanzakus = parallel_calculate_tanzaku(setting(), system.clone());
+        let violations = check_tanzakus(&tanzakus, &group.allowed_responses(), VIOLATION_TOLERANCE);
+        assert!(violations.is_empty(), "{:?}: {:?}", system, violations);
+    }
+}
+
@@ -0,0 +1,64 @@
+//相図の各点の最安定なスピン配置が全エネルギーの直接の比較と一致すること、二分法で求めた相境界の両側で
+//最安定な配置が入れ替わること、半充填で J が大きいとハニカム格子の Néel 反強磁性が強磁性に勝つことを確かめる
+
+use uuuddd4::{
+    honeycomb::{
+        compare::{candidate_energies, CompareOptions},
+        phase_diagram::{PhaseAxis, PhaseDiagram},
+        setting::CalcSetting,
+    },
+    system::model::{Param, System},
+};
+
+const BOUNDARY_TOLERANCE : f64 = 1e-3;
+
+fn setting() -> CalcSetting{
+    CalcSetting { mesh_kx : 6, mesh_ky : 6, height_map_div : 20, threshold_berry : 1e-12, main_mesh : 1 }
+}
+
+fn options() -> CompareOptions{
+    CompareOptions { n_div : 10, energy_mesh : 12 }
+}
+
+fn candidates() -> Vec<System>{
+    let param = Param::new(0.1, 0.5);
+    vec![System::FmTmd(param), System::SatoTmd(param), System::UuudddTmd(param)]
+}
+
+//電子数の番号 i で最安定な候補
+fn winner_at(candidates : &[System], param : Param, i : usize) -> usize{
+    let systems: Vec<System> = candidates.iter().map(|system| system.with_param(param)).collect();
+    let energies = candidate_energies(&systems, options());
+    (0..systems.len()).min_by(|&a, &b| energies[a][i].total_cmp(&energies[b][i])).unwrap()
+}
+
+#[test]
+fn phase_diagram_winners_and_boundaries_are_consistent(){
+    let candidates = candidates();
+    let values = [0.2, 0.8, 1.4, 2.0];
+    let diagram = PhaseDiagram::build(&candidates, PhaseAxis::Jj, &values, setting(), options()).unwrap();
+    let base = *candidates[0].param();
+
+    for row in &diagram.points {
+        for (i, point) in row.iter().enumerate() {
+            assert_eq!(point.winner, winner_at(&candidates, PhaseAxis::Jj.with_value(base, point.value), i), "J = {}, n = {}", point.value, point.n);
+            assert!(point.margin >= 0.0);
+        }
+    }
+
+    //半充填（n = 1）で J が大きいと Néel 反強磁性が最安定
+    let half = options().n_div / 2;
+    let strong = diagram.points.last().unwrap();
+    assert!((strong[half].n - 1.0).abs() < 1e-12);
+    assert_eq!(candidates[strong[half].winner].debug_only_name(), candidates[1].debug_only_name(), "J = {}", strong[half].value);
+
+    let boundaries = diagram.boundaries(BOUNDARY_TOLERANCE);
+    assert!(!boundaries.is_empty(), "no phase boundary found");
+    for boundary in &boundaries {
+        let i = (boundary.n * options().n_div as f64 / 2.0).round() as usize;
+        let below = PhaseAxis::Jj.with_value(base, boundary.value - BOUNDARY_TOLERANCE);
+        let above = PhaseAxis::Jj.with_value(base, boundary.value + BOUNDARY_TOLERANCE);
+        assert_eq!(winner_at(&candidates, below, i), boundary.lower, "{:?}", boundary);
+        assert_eq!(winner_at(&candidates, above, i), boundary.upper, "{:?}", boundary);
+    }
+}
@@ -0,0 +1,65 @@
+//準粒子干渉の LDOS の変調が実数の実空間の変調を表すこと（δρ(-q) = δρ(q)^*）、JDOS が q = 0 で最大になること、
+//スピン縮退した母体では磁性不純物の変調が逆向きのスピンで符号だけ反転し、Born 近似では V に比例することを確かめる
+
+use uuuddd4::{
+    honeycomb::{
+        qpi::{Impurity, QpiMap, QpiPoint, QpiSetting},
+        setting::CalcSetting,
+        spectral::Broadening,
+    },
+    system::model::{Param, System},
+};
+
+const TOLERANCE : f64 = 1e-10;
+const OMEGAS : [f64; 2] = [-0.6, 0.4];
+
+fn setting() -> CalcSetting{
+    CalcSetting { mesh_kx : 12, mesh_ky : 12, height_map_div : 1, threshold_berry : 1e-12, main_mesh : 1 }
+}
+
+fn qpi_setting(potential : f64, magnetic : bool, born : bool) -> QpiSetting{
+    QpiSetting {
+        impurity : Impurity { site : 0, potential, magnetic, born },
+        broadening : Broadening { constant : 0.05, quadratic : 0.0 },
+    }
+}
+
+fn point_at(map : &QpiMap, di : i64, dj : i64) -> &QpiPoint{
+    map.points.iter().find(|point| point.di == di && point.dj == dj).unwrap()
+}
+
+#[test]
+fn modulation_is_real_in_space_and_jdos_peaks_at_zero(){
+    let system = System::UuudddTmd(Param::new(0.1, 0.3));
+    for map in QpiMap::build_all(system, setting(), 1.0, &OMEGAS, qpi_setting(0.5, false, false)) {
+        let zero = point_at(&map, 0, 0);
+        for point in &map.points {
+            //メッシュの端の点は -q がメッシュに含まれない
+            if point.di.abs() >= 6 || point.dj.abs() >= 6 {
+                continue;
+            }
+            let opposite = point_at(&map, -point.di, -point.dj);
+            for spin in 0..2 {
+                assert!((point.qpi[spin] - opposite.qpi[spin].conj()).norm() < TOLERANCE, "{:?}", (point.di, point.dj));
+                assert!((point.jdos[spin] - opposite.jdos[spin]).abs() < TOLERANCE);
+                assert!(point.jdos[spin] <= zero.jdos[spin] + TOLERANCE);
+            }
+        }
+    }
+}
+
+#[test]
+fn magnetic_impurity_in_a_paramagnet_flips_the_sign_between_spins(){
+    let system = System::Tmd(Param::new(0.0, 0.0));
+    let magnetic = QpiMap::build_all(system.clone(), setting(), 0.8, &OMEGAS, qpi_setting(0.3, true, true));
+    let doubled = QpiMap::build_all(system, setting(), 0.8, &OMEGAS, qpi_setting(0.6, true, true));
+
+    for (map, double) in magnetic.iter().zip(&doubled) {
+        assert!(map.points.iter().any(|point| point.qpi[0].norm() > 1e-6));
+        for (point, twice) in map.points.iter().zip(&double.points) {
+            assert!((point.qpi[0] + point.qpi[1]).norm() < TOLERANCE, "{:?}", (point.di, point.dj));
+            //Born 近似では V に比例する
+            assert!((twice.qpi[0] - point.qpi[0] * 2.0).norm() < TOLERANCE);
+        }
+    }
+}
@@ -0,0 +1,59 @@
+//ナノリボンのギャップを横切る端の状態の符号つきの数が、ジグザグ端でもアームチェア端でも
+//バルクのスピンごとの Chern 数と一致すること（N_upper = -N_lower = C_σ）を確かめる
+
+use uuuddd4::{
+    honeycomb::{
+        chern::{bulk_gap, chern_numbers},
+        honeycomb_grids::Grids,
+        setting::CalcSetting,
+        util::GridInfo,
+    },
+    system::{
+        model::{Param, System},
+        ribbon::{Edge, Ribbon},
+    },
+};
+
+const WIDTH : usize = 8;
+const K_POINTS : usize = 60;
+const EDGE_DEPTH : f64 = 3.0;
+
+//半充填のギャップの中でのスピンごとの Chern 数が各端の状態の数と一致することを確かめて Chern 数を返す
+fn check_bulk_boundary(system : &System) -> [i64; 2]{
+    let (top, bottom) = bulk_gap(system, 1.0, 24);
+    assert!(bottom - top > 0.1, "{}: gap {} to {}", system.debug(), top, bottom);
+    //粒子正孔対称な系では両端の状態がギャップの中央で交わり、そこでは重みが両端に分かれて bulk に数えられるので中央から少しずらす
+    let e_fermi = top + 0.4 * (bottom - top);
+
+    let setting = CalcSetting { mesh_kx : 24, mesh_ky : 24, height_map_div : 1, threshold_berry : 1e-12, main_mesh : 1 };
+    let chern = chern_numbers(&Grids::build(setting, system.clone(), GridInfo::no_divide()), e_fermi);
+    let chern = chern.map(|c| {
+        assert!(c.gapped && (c.chern - c.rounded() as f64).abs() < 1e-6, "{}: {}", system.debug(), c.chern);
+        c.rounded()
+    });
+
+    for edge in [Edge::Zigzag, Edge::Armchair] {
+        let ribbon = Ribbon::new(system, edge, WIDTH).unwrap();
+        let counts = ribbon.edge_modes(K_POINTS, EDGE_DEPTH, e_fermi);
+        for (count, c) in counts.iter().zip(chern) {
+            assert_eq!((count.upper, count.lower), (c, -c), "{} {}: {:?}", system.debug(), edge.name(), counts);
+            assert_eq!(count.bulk, 0, "{} {}: {:?}", system.debug(), edge.name(), counts);
+        }
+    }
+    chern
+}
+
+#[test]
+fn kane_mele_has_helical_edge_modes(){
+    //スピンごとに Haldane 模型で、逆向きの Chern 数を持つ
+    let chern = check_bulk_boundary(&System::FmKanemele(Param::new(0.1, 0.0)));
+    assert_eq!(chern[0].abs(), 1);
+    assert_eq!(chern[0], -chern[1]);
+}
+
+#[test]
+fn strong_neel_order_has_no_edge_modes(){
+    //Néel の交換場が副格子のポテンシャルとしてスピン軌道のギャップに勝つと自明な絶縁体になる
+    let system = System::from_family("original", "afm", Param::new(0.1, 1.5)).unwrap();
+    assert_eq!(check_bulk_boundary(&system), [0, 0]);
+}
@@ -0,0 +1,46 @@
+//常磁性の母体から求めた RKKY 相互作用が、半充填のハニカム格子（粒子正孔対称な二部格子）で
+//異なる副格子の間では反強磁性、同じ副格子の間では強磁性になること、格子の対称性で
+//等価なサイトの組が同じ J_ij を持つことを確かめる
+
+use uuuddd4::{
+    interaction::rkky::{RkkyHost, RkkySetting},
+    system::model::{Param, System},
+};
+
+fn host() -> RkkyHost{
+    let setting = RkkySetting { mesh : 12, temperature : 0.05, cutoff : 3.0 };
+    RkkyHost::new("kanemele", Param::new(0.0, 1.0), setting).unwrap()
+}
+
+#[test]
+fn half_filling_couplings_follow_the_sublattice_sign_rule(){
+    let couplings = host().couplings(1.0);
+    assert!(couplings.mu.abs() < 1e-9, "mu = {}", couplings.mu);
+
+    for (separation, exchange) in couplings.separations.iter().zip(&couplings.exchange) {
+        let sign = if separation.origin == separation.target { -1.0 } else { 1.0 };
+        assert!(sign * exchange > 0.0, "{:?}: {}", separation, exchange);
+    }
+
+    //Néel 配置の古典的なエネルギーは強磁性より低い
+    let param = Param::new(0.0, 1.0);
+    let neel = System::from_family("kanemele", "afm", param).unwrap();
+    let ferro = System::from_family("kanemele", "fm", param).unwrap();
+    assert!(couplings.classical_energy(&neel) < couplings.classical_energy(&ferro));
+}
+
+#[test]
+fn equivalent_pairs_have_equal_couplings(){
+    let couplings = host().couplings(0.7);
+
+    //近いサイトの組は距離と副格子が同じなら C3 回転と A, B の入れ替えで移り合う
+    //（遠くには距離が同じでも等価でない組がある）
+    for (a, exchange_a) in couplings.separations.iter().zip(&couplings.exchange) {
+        for (b, exchange_b) in couplings.separations.iter().zip(&couplings.exchange) {
+            let same_class = (a.origin == a.target) == (b.origin == b.target) && (a.distance() - b.distance()).abs() < 1e-9;
+            if same_class && a.distance() < 1.5 {
+                assert!((exchange_a - exchange_b).abs() < 1e-9, "{:?}: {} vs {:?}: {}", a, exchange_a, b, exchange_b);
+            }
+        }
+    }
+}
@@ -0,0 +1,77 @@
+//runs/ の実行設定ファイルが全て読み込めること、tanzaku コマンドの出力が
+//ライブラリで直接計算した Tanzakus と一致することを確かめる
+
+use uuuddd4::{
+    honeycomb::{parallelization::parallel_calculate_tanzaku, tanzaku::Tanzakus},
+    run::{
+        command::run,
+        config::{Command, RunConfig},
+    },
+    system::model::{Param, System},
+};
+
+const TOLERANCE : f64 = 1e-12;
+
+//テストごとに別の一時ディレクトリ
+fn temp_dir(name : &str) -> std::path::PathBuf{
+    let dir = std::env::temp_dir().join(format!("uuuddd4_{}_{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn example_run_files_load(){
+    let mut loaded = 0;
+    for entry in std::fs::read_dir("runs").unwrap() {
+        let path = entry.unwrap().path();
+        let path = path.to_str().unwrap();
+        let config = RunConfig::load(path).unwrap_or_else(|e| panic!("{}", e));
+        config.system.build().unwrap_or_else(|e| panic!("{}: {}", path, e));
+        loaded += 1;
+    }
+    assert!(loaded > 0);
+}
+
+#[test]
+fn unknown_keys_are_rejected(){
+    let dir = temp_dir("unknown_keys");
+    let path = dir.join("typo.toml");
+    std::fs::write(&path, "[system]\nfamily = \"kanemele\"\nspin = \"fm\"\nlambda = 0.1\njj = 0.0\n\n[calc]\nmesh_x = 10\n").unwrap();
+
+    assert!(RunConfig::load(path.to_str().unwrap()).is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn tanzaku_command_matches_library(){
+    let dir = temp_dir("tanzaku_command");
+    let out = dir.join("out");
+    let path = dir.join("tanzaku.toml");
+    std::fs::write(&path, format!(
+        "observables = [\"tanzaku\"]\n\n[system]\nfamily = \"kanemele\"\nspin = \"uuuddd\"\nlambda = 0.1\njj = 0.3\n\n\
+         [calc]\nmesh_kx = 12\nmesh_ky = 12\nheight_map_div = 20\nthreshold_berry = 1e-12\nmain_mesh = 1\n\n\
+         [output]\ndir = {:?}\n",
+        out.to_str().unwrap()
+    )).unwrap();
+
+    let config = RunConfig::load(path.to_str().unwrap()).unwrap();
+    run(Command::Tanzaku, &config).unwrap();
+
+    let system = System::UuudddKanemele(Param::new(0.1, 0.3));
+    let expected = parallel_calculate_tanzaku(config.calc_setting(), system.clone());
+    let written = std::fs::read_to_string(Tanzakus::dat_path(&config.output.dir, &system, &config.calc_setting())).unwrap();
+
+    let rows: Vec<Vec<f64>> = written.lines()
+        .filter(|line| !line.starts_with('#'))
+        .map(|line| line.split(',').map(|value| value.parse().unwrap()).collect())
+        .collect();
+    assert_eq!(rows.len(), expected.data.len());
+    for (row, tanzaku) in rows.iter().zip(&expected.data) {
+        let expected_row = [tanzaku.n, tanzaku.energy, tanzaku.berry, tanzaku.bcd.x, tanzaku.bcd.y, tanzaku.qmd.x, tanzaku.qmd.y];
+        for (value, expected) in row.iter().zip(expected_row) {
+            assert!((value - expected).abs() < TOLERANCE, "{:?} vs {:?}", row, expected_row);
+        }
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
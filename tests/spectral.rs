@@ -0,0 +1,57 @@
+//スペクトル関数が和則 ∫ A dω = バンドの数（展開すれば 2）を満たすこと、
+//Néel 秩序ではスピンの反転と副格子の入れ替えで A が移り合うことを確かめる
+
+mod common;
+
+use common::Lcg;
+use uuuddd4::{
+    honeycomb::spectral::{spectral_at, spectral_levels, Broadening},
+    system::model::{Param, System},
+};
+
+const BROADENING : Broadening = Broadening { constant : 0.05, quadratic : 0.0 };
+
+//[-range, range] での台形積分に Lorentz 関数の裾 2η / (π range) を重みを掛けて足した ∫ A_σ dω（副格子の和）
+fn integrated_weight(system : &System, kk : nalgebra::Vector2<f64>, unfold : bool, spin : usize) -> f64{
+    let levels = spectral_levels(system, kk, unfold);
+    let (range, steps) = (20.0, 40000);
+    let step = 2.0 * range / steps as f64;
+    let integral: f64 = (0..=steps)
+        .map(|i| {
+            let omega = -range + i as f64 * step;
+            let a = spectral_at(&levels, omega, 0.0, BROADENING)[spin];
+            let end = if i == 0 || i == steps { 0.5 } else { 1.0 };
+            end * (a[0] + a[1]) * step
+        })
+        .sum();
+    let total: f64 = levels[spin].iter().map(|(_, weights)| weights[0] + weights[1]).sum();
+    integral + total * 2.0 * BROADENING.constant / (std::f64::consts::PI * range)
+}
+
+#[test]
+fn spectral_weight_sums_to_the_number_of_bands(){
+    let mut rng = Lcg(44);
+    let param = Param::new(0.1, 0.3);
+    for system in [System::FmKanemele(param), System::UuudddTmd(param)] {
+        let kk = rng.next_kk();
+        for spin in 0..2 {
+            let weight = integrated_weight(&system, kk, false, spin);
+            assert!((weight - system.size() as f64).abs() < 1e-4, "{}: {}", system.debug(), weight);
+            let unfolded = integrated_weight(&system, kk, true, spin);
+            assert!((unfolded - 2.0).abs() < 1e-4, "{}: {}", system.debug(), unfolded);
+        }
+    }
+}
+
+#[test]
+fn neel_order_maps_spin_up_on_a_to_spin_down_on_b(){
+    let mut rng = Lcg(45);
+    let system = System::from_family("original", "afm", Param::new(0.0, 0.4)).unwrap();
+    for _ in 0..5 {
+        let levels = spectral_levels(&system, rng.next_kk(), false);
+        for omega in [-1.5, -0.4, 0.0, 0.4, 1.5] {
+            let a = spectral_at(&levels, omega, 0.0, BROADENING);
+            assert!((a[0][0] - a[1][1]).abs() < 1e-9 && (a[0][1] - a[1][0]).abs() < 1e-9, "{:?}", a);
+        }
+    }
+}
@@ -0,0 +1,41 @@
+//Supercell::six_site の超格子の模型が hamiltonian_6 とサイトの並びまで一致することを確かめる
+//（ribbon, flake, disorder, peierls は six_site().site_index でバルクの6サイトの値を使う）
+
+mod common;
+
+use common::{systems, Lcg};
+use uuuddd4::system::{
+    hamiltonian::hamiltonian_6,
+    supercell::{Supercell, SupercellSystem},
+};
+
+const TOLERANCE : f64 = 1e-12;
+
+#[test]
+fn six_site_supercell_matches_hamiltonian_6(){
+    let mut rng = Lcg(42);
+
+    //2サイトの系は hamiltonian_6 と tmd の副格子が違うので除く
+    for system in systems().into_iter().filter(|system| system.size() == 6) {
+        let param = *system.param();
+        let spins = system.exchange().iter().map(|exchange| exchange / param.jj).collect();
+        let supercell = SupercellSystem::new("six_site", Supercell::six_site(), param, system.tmd(), spins);
+
+        for _ in 0..20 {
+            let kk = rng.next_kk();
+            let h = hamiltonian_6(&system, kk);
+            let (hamiltonian_u, hamiltonian_d) = supercell.hamiltonian(kk);
+
+            for (spin, hamiltonian) in [hamiltonian_u, hamiltonian_d].iter().enumerate() {
+                for i in 0..6 {
+                    for j in 0..6 {
+                        assert!(
+                            (hamiltonian[(i, j)] - h.index(spin)[(i, j)]).norm() < TOLERANCE,
+                            "H[{}, {}] spin {} {:?}", i, j, spin, system
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,45 @@
+//常磁性の母体の静的スピン感受率が正定値のエルミート行列であること、
+//半充填のハニカム格子では Γ 点の Néel 秩序が最初に不安定になり、RPA がその U に向かって発散することを確かめる
+
+use uuuddd4::interaction::susceptibility::{OrderingPoint, QPoint, Susceptibility, SusceptibilitySetting};
+
+fn setting() -> SusceptibilitySetting{
+    SusceptibilitySetting { mesh : 24, q_mesh : 6, temperature : 0.05 }
+}
+
+#[test]
+fn bare_bubble_is_hermitian_and_positive(){
+    let susceptibility = Susceptibility::new("kanemele", 0.1, 0.8, setting()).unwrap();
+
+    for qpoint in &susceptibility.qpoints {
+        for chi in &qpoint.chi0 {
+            assert!((chi - chi.adjoint()).norm() < 1e-10, "{:?}: {}", qpoint.index, chi);
+            //最小固有値 = trace - 最大固有値
+            let smallest = chi.trace().re - QPoint::largest_eigenvalue(chi);
+            assert!(smallest > -1e-10, "{:?}: {}", qpoint.index, chi);
+        }
+    }
+}
+
+#[test]
+fn half_filling_is_first_unstable_to_neel_order(){
+    let susceptibility = Susceptibility::new("kanemele", 0.0, 1.0, setting()).unwrap();
+    assert!(susceptibility.mu.abs() < 1e-9, "mu = {}", susceptibility.mu);
+
+    let leading = susceptibility.leading();
+    assert_eq!(leading.point, OrderingPoint::Gamma);
+    assert!(leading.point.fits_six_site_cell());
+    assert!(leading.in_phase() < 1e-6, "in phase {}", leading.in_phase());
+    //平均場の U_c ≈ 2.2t（有限温度で少し大きくなる）
+    let critical_u = leading.critical_u();
+    assert!(critical_u > 2.0 && critical_u < 3.0, "U_c = {}", critical_u);
+
+    //U = 0 のRPAは裸の感受率で、U_c に近づくと発散する
+    let bare = leading.chi0_zz();
+    assert!((leading.rpa_zz(0.0).unwrap() - bare).norm() < 1e-12);
+    let enhancement: Vec<f64> = [0.5, 0.9, 0.99].iter()
+        .map(|ratio| QPoint::largest_eigenvalue(&leading.rpa_zz(ratio * critical_u).unwrap()) / QPoint::largest_eigenvalue(&bare))
+        .collect();
+    assert!(enhancement[0] > 1.0 && enhancement[0] < enhancement[1] && enhancement[1] < enhancement[2], "{:?}", enhancement);
+    assert!(enhancement[2] > 10.0, "{:?}", enhancement);
+}
@@ -0,0 +1,167 @@
+//掃引が (λ, J, n) の直積を回り、同じパラメーターの点をまとめて一度だけ計算すること、
+//出力が既にある点を飛ばして再開できること、失敗した点を記録して残りを続けること、
+//tanzaku コマンドの電子数の掃引が各 λ の Tanzakus をその電子数で補間した値を書くことを確かめる
+
+use uuuddd4::{
+    honeycomb::parallelization::parallel_calculate_tanzaku,
+    run::{
+        command::run,
+        config::{Command, RunConfig},
+        sweep::{Axis, JobStatus, Sweep, SweepAxis, SweepJob, SweepPoint},
+    },
+    system::model::{Param, System},
+};
+
+use std::io::{Error, Result as IoResult};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+//各点で λ と n を書き出すだけのジョブ（呼ばれた回数を記録する）
+struct RecordingJob{
+    dir : PathBuf,
+    calls : Mutex<Vec<(f64, usize)>>,   // (λ, 渡された点の数)
+    fail_lambda : Option<f64>,
+}
+
+impl RecordingJob{
+    fn new(dir : PathBuf, fail_lambda : Option<f64>) -> Self{
+        RecordingJob { dir, calls : Mutex::new(Vec::new()), fail_lambda }
+    }
+    fn calls(&self) -> Vec<(f64, usize)>{
+        let mut calls = self.calls.lock().unwrap().clone();
+        calls.sort_by(|a, b| a.0.total_cmp(&b.0));
+        calls
+    }
+}
+
+impl SweepJob for RecordingJob{
+    fn output_path(&self, point : &SweepPoint) -> String{
+        let name = format!("point_{}_{}_{}.dat", point.param.lambda, point.param.jj, point.filling.unwrap_or(f64::NAN));
+        self.dir.join(name).to_str().unwrap().to_string()
+    }
+    fn run(&self, param : Param, points : &[SweepPoint]) -> IoResult<()>{
+        self.calls.lock().unwrap().push((param.lambda, points.len()));
+        if self.fail_lambda == Some(param.lambda) {
+            return Err(Error::other("failed on purpose"));
+        }
+        for point in points {
+            assert_eq!(point.param.lambda, param.lambda);
+            std::fs::write(self.output_path(point), format!("{},{}\n", param.lambda, point.filling.unwrap()))?;
+        }
+        Ok(())
+    }
+}
+
+fn temp_dir(name : &str) -> PathBuf{
+    let dir = std::env::temp_dir().join(format!("uuuddd4_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn sweep() -> Sweep{
+    Sweep::new(Param::new(0.0, 0.25), vec![
+        SweepAxis::new(Axis::Lambda, vec![0.1, 0.2, 0.3]),
+        SweepAxis::linspace(Axis::Filling, 0.5, 1.5, 3),
+    ]).unwrap()
+}
+
+#[test]
+fn points_are_the_product_with_the_last_axis_fastest(){
+    let points = sweep().points();
+    assert_eq!(points.len(), 9);
+    for (i, point) in points.iter().enumerate() {
+        assert_eq!(point.index, i);
+        assert_eq!(point.param.lambda, [0.1, 0.2, 0.3][i / 3]);
+        assert_eq!(point.param.jj, 0.25);
+        assert_eq!(point.filling, Some([0.5, 1.0, 1.5][i % 3]));
+    }
+
+    assert!(Sweep::new(Param::new(0.0, 0.0), vec![SweepAxis::new(Axis::Jj, vec![0.1]), SweepAxis::new(Axis::Jj, vec![0.2])]).is_err());
+}
+
+#[test]
+fn sweep_resumes_from_existing_outputs(){
+    let dir = temp_dir("sweep_resume");
+    let manifest = dir.join("manifest.dat");
+    let manifest = manifest.to_str().unwrap();
+    let sweep = sweep();
+
+    //初回は λ ごとに一度だけ、全ての電子数をまとめて計算する
+    let job = RecordingJob::new(dir.clone(), None);
+    let entries = sweep.run(&job, manifest).unwrap();
+    assert!(entries.iter().all(|entry| entry.status == JobStatus::Computed));
+    assert_eq!(job.calls(), vec![(0.1, 3), (0.2, 3), (0.3, 3)]);
+
+    //一つの出力を消して再開すると、その点だけが計算される
+    let removed = sweep.points()[4];
+    std::fs::remove_file(job.output_path(&removed)).unwrap();
+    let job = RecordingJob::new(dir.clone(), None);
+    let entries = sweep.run(&job, manifest).unwrap();
+    assert_eq!(job.calls(), vec![(0.2, 1)]);
+    for entry in &entries {
+        let expected = if entry.point.index == removed.index { JobStatus::Computed } else { JobStatus::Skipped };
+        assert_eq!(entry.status, expected, "{:?}", entry.point);
+    }
+
+    //マニフェストには全ての点が順に並ぶ
+    let rows: Vec<String> = std::fs::read_to_string(manifest).unwrap().lines().skip(1).map(str::to_string).collect();
+    assert_eq!(rows.len(), 9);
+    assert!(rows[4].contains("computed") && rows[3].contains("skipped"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn failed_points_are_recorded_and_the_rest_continue(){
+    let dir = temp_dir("sweep_failure");
+    let manifest = dir.join("manifest.dat");
+
+    let job = RecordingJob::new(dir.clone(), Some(0.2));
+    let entries = sweep().run(&job, manifest.to_str().unwrap()).unwrap();
+
+    for entry in &entries {
+        let expected = if entry.point.param.lambda == 0.2 { JobStatus::Failed } else { JobStatus::Computed };
+        assert_eq!(entry.status, expected, "{:?}", entry.point);
+    }
+    assert_eq!(entries.iter().filter(|entry| std::path::Path::new(&entry.path).exists()).count(), 6);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn filling_sweep_writes_interpolated_tanzakus(){
+    let dir = temp_dir("sweep_filling");
+    let path = dir.join("sweep.toml");
+    std::fs::write(&path, format!(
+        "[system]\nfamily = \"kanemele\"\nspin = \"fm\"\nlambda = 0.0\njj = 0.3\n\n\
+         [calc]\nmesh_kx = 12\nmesh_ky = 12\nheight_map_div = 40\n\n\
+         [sweep]\ncommand = \"tanzaku\"\n\n\
+         [[sweep.axes]]\nparameter = \"lambda\"\nvalues = [0.05, 0.1]\n\n\
+         [[sweep.axes]]\nparameter = \"filling\"\nvalues = [0.5, 1.0]\n\n\
+         [output]\ndir = {:?}\n",
+        dir.join("out").to_str().unwrap()
+    )).unwrap();
+
+    let config = RunConfig::load(path.to_str().unwrap()).unwrap();
+    run(Command::Sweep, &config).unwrap();
+
+    let manifest = std::fs::read_to_string(dir.join("out").join("sweep_manifest.dat")).unwrap();
+    let rows: Vec<Vec<String>> = manifest.lines().skip(1).map(|line| line.split(',').map(str::to_string).collect()).collect();
+    assert_eq!(rows.len(), 4);
+
+    for row in rows {
+        let lambda : f64 = row[1].parse().unwrap();
+        let n : f64 = row[3].parse().unwrap();
+        assert_eq!(row[4], "computed");
+
+        let expected = parallel_calculate_tanzaku(config.calc_setting(), System::FmKanemele(Param::new(lambda, 0.3)))
+            .linear_interpolate_at_n(n);
+        let written = std::fs::read_to_string(&row[5]).unwrap();
+        let values: Vec<f64> = written.lines().nth(1).unwrap().split(',').map(|value| value.parse().unwrap()).collect();
+        assert!((values[0] - n).abs() < 1e-12, "n {} vs {}", values[0], n);
+        assert!((values[2] - expected.berry).abs() < 1e-12, "berry {} vs {}", values[2], expected.berry);
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
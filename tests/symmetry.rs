@@ -0,0 +1,40 @@
+//既約領域だけを計算して対称操作で展開した Tanzakus が、全メッシュで計算したものと一致することを確かめる
+
+use uuuddd4::{
+    honeycomb::{
+        parallelization::parallel_calculate_tanzaku,
+        setting::CalcSetting,
+        symmetry::{calculate_tanzaku_in_wedge, IrreducibleMesh, SymmetryGroup},
+    },
+    system::model::{Param, System},
+};
+
+const TOLERANCE : f64 = 1e-9;
+
+fn setting() -> CalcSetting{
+    CalcSetting { mesh_kx : 12, mesh_ky : 12, height_map_div : 30, threshold_berry : 1e-12, main_mesh : 1 }
+}
+
+#[test]
+fn irreducible_mesh_reproduces_full_mesh_tanzakus(){
+    let param = Param::new(0.1, 0.3);
+    let calc_setting = setting();
+
+    for system in [System::Tmd(param), System::FmKanemele(param), System::UuudddKanemele(param), System::One1Tmd(param), System::Tri1Kanemele(param)] {
+        let group = SymmetryGroup::detect(&system, &calc_setting);
+        let wedge = IrreducibleMesh::build(&group, &calc_setting).expect("the mesh fits the wedge");
+        //どの系にも恒等操作以外の対称操作がある
+        assert!(group.order() > 1 && wedge.reduction() > 1.0, "{:?}: reduction {}", system, wedge.reduction());
+
+        let full = parallel_calculate_tanzaku(calc_setting, system.clone());
+        let reduced = calculate_tanzaku_in_wedge(calc_setting, system.clone());
+
+        for (a, b) in reduced.data.iter().zip(&full.data) {
+            assert!((a.energy - b.energy).abs() < TOLERANCE, "{:?}", system);
+            assert!((a.n - b.n).abs() < TOLERANCE, "{:?} at {}: n {} vs {}", system, b.energy, a.n, b.n);
+            assert!((a.berry - b.berry).abs() < TOLERANCE, "{:?} at {}: berry {} vs {}", system, b.energy, a.berry, b.berry);
+            assert!((a.bcd - b.bcd).norm() < TOLERANCE, "{:?} at {}: bcd {} vs {}", system, b.energy, a.bcd, b.bcd);
+            assert!((a.qmd - b.qmd).norm() < TOLERANCE, "{:?} at {}: qmd {} vs {}", system, b.energy, a.qmd, b.qmd);
+        }
+    }
+}
@@ -0,0 +1,72 @@
+//グランドポテンシャルと自由エネルギーが熱力学の関係 -∂Ω/∂μ = N, ∂F/∂N = μ を満たすこと、
+//低温の極限で温度 0 の値に近づくこと、Maxwell構成の凸包が min F の下にあることを確かめる
+
+use uuuddd4::{
+    honeycomb::thermodynamics::{ThermoComparison, ThermoSpectrum},
+    system::model::{Param, System},
+};
+
+const STEP : f64 = 1e-4;
+//中心差分の誤差（刻みの2乗程度）
+const FINITE_DIFFERENCE_TOLERANCE : f64 = 1e-6;
+const TEMPERATURE : f64 = 0.05;
+
+#[test]
+fn grand_potential_derivative_is_the_electron_number(){
+    let spectrum = ThermoSpectrum::new(&System::UuudddTmd(Param::new(0.1, 0.5)), 12);
+
+    for mu in [-2.0, -0.5, 0.0, 0.3, 1.2] {
+        let derivative = (spectrum.grand_potential(mu + STEP, TEMPERATURE) - spectrum.grand_potential(mu - STEP, TEMPERATURE)) / (2.0 * STEP);
+        let electrons = spectrum.filling(mu, TEMPERATURE) * spectrum.electrons_per_n();
+        assert!((-derivative - electrons).abs() < FINITE_DIFFERENCE_TOLERANCE, "mu = {}: {} vs {}", mu, -derivative, electrons);
+    }
+}
+
+#[test]
+fn free_energy_derivative_is_the_chemical_potential(){
+    let spectrum = ThermoSpectrum::new(&System::UuudddTmd(Param::new(0.1, 0.5)), 12);
+
+    for n in [0.3, 0.8, 1.0, 1.4] {
+        let derivative = (spectrum.free_energy(n + STEP, TEMPERATURE) - spectrum.free_energy(n - STEP, TEMPERATURE)) / (2.0 * STEP);
+        let mu = spectrum.chemical_potential(n, TEMPERATURE);
+        assert!((spectrum.filling(mu, TEMPERATURE) - n).abs() < 1e-12);
+        assert!((derivative - mu * spectrum.electrons_per_n()).abs() < FINITE_DIFFERENCE_TOLERANCE, "n = {}: {} vs {}", n, derivative, mu * spectrum.electrons_per_n());
+    }
+}
+
+#[test]
+fn low_temperature_limit_matches_zero_temperature(){
+    let spectrum = ThermoSpectrum::new(&System::FmKanemele(Param::new(0.1, 0.3)), 12);
+
+    //F(T) - F(0) ~ -T S は T に比例して小さくなる
+    for n in [0.5, 1.0, 1.5] {
+        let zero = spectrum.free_energy(n, 0.0);
+        let errors: Vec<f64> = [1e-2, 1e-3].iter().map(|&t| (spectrum.free_energy(n, t) - zero).abs()).collect();
+        assert!(errors[1] < 1e-2 && errors[1] < errors[0], "n = {}: {:?}", n, errors);
+    }
+}
+
+#[test]
+fn convex_hull_lies_below_the_minimum_free_energy(){
+    let param = Param::new(0.1, 1.0);
+    let comparison = ThermoComparison::new(&[System::FmTmd(param), System::SatoTmd(param), System::UuudddTmd(param)], 0.0, 12);
+    let points = comparison.at_fillings(40);
+
+    for point in &points {
+        let minimum = point.free_energies[point.winner];
+        assert!(point.free_energies.iter().all(|&f| f >= minimum));
+        assert!(point.hull <= minimum + 1e-12, "n = {}: hull {} above {}", point.n, point.hull, minimum);
+    }
+    //両端は凸包に乗る
+    assert!(!points[0].phase_separated && !points[points.len() - 1].phase_separated);
+
+    //共存する二相は共通接線の上にある: F_low(n_low) - μ N_low = F_high(n_high) - μ N_high
+    let electrons_per_n = comparison.spectra[0].electrons_per_n();
+    let point_at = |n : f64| points.iter().find(|point| (point.n - n).abs() < 1e-12).unwrap();
+    for separation in comparison.phase_separations(&points) {
+        assert!(separation.n_low < separation.n_high);
+        let omega_low = point_at(separation.n_low).free_energies[separation.low] - separation.mu * separation.n_low * electrons_per_n;
+        let omega_high = point_at(separation.n_high).free_energies[separation.high] - separation.mu * separation.n_high * electrons_per_n;
+        assert!((omega_low - omega_high).abs() < 1e-10, "{} vs {}", omega_low, omega_high);
+    }
+}
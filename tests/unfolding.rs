@@ -0,0 +1,60 @@
+//6サイトの単位胞のバンドの展開の重みが各k点で保存すること、基本単位胞の周期を持つ系では
+//重み 1 のバンドが2サイトのバンドと一致し、残りの折り畳まれたバンドの重みが 0 になることを確かめる
+
+mod common;
+
+use common::{eigenvalues, Lcg};
+use uuuddd4::{
+    honeycomb::unfolding::unfold_at,
+    system::{
+        diag::diag,
+        model::{Param, System},
+    },
+};
+
+const TOLERANCE : f64 = 1e-9;
+
+#[test]
+fn weights_are_between_zero_and_one_and_sum_to_two(){
+    let mut rng = Lcg(43);
+    for system in common::systems() {
+        for _ in 0..5 {
+            let (_, weights) = unfold_at(&system, rng.next_kk());
+            for spin_weights in &weights {
+                assert!(spin_weights.iter().all(|&w| (-TOLERANCE..=1.0 + TOLERANCE).contains(&w)), "{}: {:?}", system.debug(), spin_weights);
+                let total: f64 = spin_weights.iter().sum();
+                assert!((total - 2.0).abs() < TOLERANCE, "{}: {}", system.debug(), total);
+            }
+        }
+    }
+}
+
+#[test]
+fn primitive_periodic_systems_unfold_to_their_two_site_bands(){
+    let mut rng = Lcg(44);
+    let param = Param::new(0.1, 0.3);
+    for system in [System::Tmd(param), System::FmKanemele(param)] {
+        assert_eq!(system.size(), 2);
+        for _ in 0..5 {
+            let kk = rng.next_kk();
+            let (energies, weights) = unfold_at(&system, kk);
+            let primitive = diag(&system, kk, false);
+
+            for spin in 0..2 {
+                let mut unfolded = Vec::new();
+                for (energy, weight) in energies[spin].iter().zip(&weights[spin]) {
+                    //重みは 0 か 1 のどちらか
+                    assert!(weight.abs() < TOLERANCE || (weight - 1.0).abs() < TOLERANCE, "{}: {}", system.debug(), weight);
+                    if *weight > 0.5 {
+                        unfolded.push(*energy);
+                    }
+                }
+                let expected = eigenvalues(&primitive, spin);
+                assert_eq!(unfolded.len(), expected.len());
+                for (a, b) in unfolded.iter().zip(&expected) {
+                    assert!((a - b).abs() < TOLERANCE, "{}: {:?} vs {:?}", system.debug(), unfolded, expected);
+                }
+            }
+        }
+    }
+}